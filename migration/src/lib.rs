@@ -6,6 +6,13 @@ pub use sea_orm_migration::prelude::*;
 
 mod m000001_v1_init;
 mod m000002_add_avatar_version;
+mod m000003_add_prompt_templates;
+mod m000004_add_doctor_task_scores;
+mod m000005_add_ai_suggestions;
+mod m000006_add_embeddings;
+mod m000007_add_chat_history_memory;
+mod m000008_add_image_captions;
+mod m000009_add_chat_bookmarks;
 
 pub struct Migrator;
 
@@ -15,6 +22,13 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(m000001_v1_init::Migration),
             Box::new(m000002_add_avatar_version::Migration),
+            Box::new(m000003_add_prompt_templates::Migration),
+            Box::new(m000004_add_doctor_task_scores::Migration),
+            Box::new(m000005_add_ai_suggestions::Migration),
+            Box::new(m000006_add_embeddings::Migration),
+            Box::new(m000007_add_chat_history_memory::Migration),
+            Box::new(m000008_add_image_captions::Migration),
+            Box::new(m000009_add_chat_bookmarks::Migration),
         ]
     }
 }
@@ -0,0 +1,98 @@
+//! 迁移：新增 prompt_templates 表
+//!
+//! 存储用户自定义的 AI 功能提示词模板（按功能 ID 版本化）
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(PromptTemplates::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PromptTemplates::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(PromptTemplates::FeatureId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PromptTemplates::Version)
+                            .integer()
+                            .not_null()
+                            .default(1),
+                    )
+                    .col(
+                        ColumnDef::new(PromptTemplates::SystemPrompt)
+                            .text()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(
+                        ColumnDef::new(PromptTemplates::UserPrompt)
+                            .text()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(ColumnDef::new(PromptTemplates::Note).string())
+                    .col(
+                        ColumnDef::new(PromptTemplates::IsActive)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(PromptTemplates::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_prompt_templates_feature")
+                    .unique()
+                    .table(PromptTemplates::Table)
+                    .col(PromptTemplates::FeatureId)
+                    .col(PromptTemplates::Version)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PromptTemplates::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum PromptTemplates {
+    Table,
+    Id,
+    FeatureId,
+    Version,
+    SystemPrompt,
+    UserPrompt,
+    Note,
+    IsActive,
+    CreatedAt,
+}
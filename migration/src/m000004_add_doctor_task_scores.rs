@@ -0,0 +1,60 @@
+//! 迁移：为 doctor_task 表添加结构化诊断字段
+//!
+//! 维度评分、总分、结论枚举，以及诊断时的角色卡版本/哈希
+
+use sea_orm_migration::prelude::*;
+
+/// (列名, 列定义)
+const COLUMNS: &[(&str, &str)] = &[
+    ("dimensions", "TEXT"),
+    ("overall_score", "REAL"),
+    ("conclusion", "TEXT"),
+    ("card_hash", "TEXT"),
+    ("card_version", "TEXT"),
+];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+
+        for (name, definition) in COLUMNS {
+            // 检查列是否已存在（SQLite 不支持 IF NOT EXISTS）
+            let result = conn
+                .query_all(sea_orm::Statement::from_string(
+                    sea_orm::DatabaseBackend::Sqlite,
+                    format!(
+                        "SELECT COUNT(*) as cnt FROM pragma_table_info('doctor_task') WHERE name='{}'",
+                        name
+                    ),
+                ))
+                .await?;
+
+            if let Some(row) = result.first() {
+                let count: i32 = row.try_get("", "cnt").unwrap_or(0);
+                if count == 0 {
+                    conn.execute_unprepared(&format!(
+                        "ALTER TABLE doctor_task ADD COLUMN {} {};",
+                        name, definition
+                    ))
+                    .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+        for (name, _) in COLUMNS {
+            conn.execute_unprepared(&format!("ALTER TABLE doctor_task DROP COLUMN {};", name))
+                .await?;
+        }
+
+        Ok(())
+    }
+}
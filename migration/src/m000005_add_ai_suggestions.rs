@@ -0,0 +1,94 @@
+//! 迁移：新增 ai_suggestions 表
+//!
+//! 批量 AI 任务的产出先进入审核队列，用户确认后才写入角色卡
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AiSuggestions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AiSuggestions::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AiSuggestions::JobId).uuid())
+                    .col(ColumnDef::new(AiSuggestions::CardId).uuid().not_null())
+                    .col(ColumnDef::new(AiSuggestions::FeatureId).string().not_null())
+                    .col(
+                        ColumnDef::new(AiSuggestions::Payload)
+                            .text()
+                            .not_null()
+                            .default("{}"),
+                    )
+                    .col(
+                        ColumnDef::new(AiSuggestions::Status)
+                            .string()
+                            .not_null()
+                            .default("pending"),
+                    )
+                    .col(
+                        ColumnDef::new(AiSuggestions::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AiSuggestions::ReviewedAt).date_time())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_ai_suggestions_status")
+                    .table(AiSuggestions::Table)
+                    .col(AiSuggestions::Status)
+                    .col(AiSuggestions::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_ai_suggestions_card")
+                    .table(AiSuggestions::Table)
+                    .col(AiSuggestions::CardId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AiSuggestions::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum AiSuggestions {
+    Table,
+    Id,
+    JobId,
+    CardId,
+    FeatureId,
+    Payload,
+    Status,
+    CreatedAt,
+    ReviewedAt,
+}
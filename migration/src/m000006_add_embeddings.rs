@@ -0,0 +1,80 @@
+//! 迁移：新增 embeddings 表
+//!
+//! 存储角色卡、世界书条目与小剧场的语义向量（f32 小端序 BLOB），用于相似推荐与语义搜索
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Embeddings::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Embeddings::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Embeddings::SourceType).string().not_null())
+                    .col(ColumnDef::new(Embeddings::SourceId).uuid().not_null())
+                    .col(
+                        ColumnDef::new(Embeddings::ItemKey)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(ColumnDef::new(Embeddings::Title).string().not_null())
+                    .col(ColumnDef::new(Embeddings::ContentHash).string().not_null())
+                    .col(ColumnDef::new(Embeddings::Model).string().not_null())
+                    .col(ColumnDef::new(Embeddings::Dims).integer().not_null())
+                    .col(ColumnDef::new(Embeddings::Vector).blob().not_null())
+                    .col(ColumnDef::new(Embeddings::UpdatedAt).date_time().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_embeddings_source")
+                    .table(Embeddings::Table)
+                    .col(Embeddings::SourceType)
+                    .col(Embeddings::SourceId)
+                    .col(Embeddings::ItemKey)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Embeddings::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Embeddings {
+    Table,
+    Id,
+    SourceType,
+    SourceId,
+    ItemKey,
+    Title,
+    ContentHash,
+    Model,
+    Dims,
+    Vector,
+    UpdatedAt,
+}
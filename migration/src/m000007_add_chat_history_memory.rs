@@ -0,0 +1,58 @@
+//! 迁移：为 chat_histories 表添加 AI 总结字段
+//!
+//! 滚动总结全文、结构化记忆（事件 / 关系 / 设定事实）与总结时间
+
+use sea_orm_migration::prelude::*;
+
+/// (列名, 列定义)
+const COLUMNS: &[(&str, &str)] = &[
+    ("summary", "TEXT"),
+    ("memory", "TEXT"),
+    ("summarized_at", "DATETIME"),
+];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+
+        for (name, definition) in COLUMNS {
+            // 检查列是否已存在（SQLite 不支持 IF NOT EXISTS）
+            let result = conn
+                .query_all(sea_orm::Statement::from_string(
+                    sea_orm::DatabaseBackend::Sqlite,
+                    format!(
+                        "SELECT COUNT(*) as cnt FROM pragma_table_info('chat_histories') WHERE name='{}'",
+                        name
+                    ),
+                ))
+                .await?;
+
+            if let Some(row) = result.first() {
+                let count: i32 = row.try_get("", "cnt").unwrap_or(0);
+                if count == 0 {
+                    conn.execute_unprepared(&format!(
+                        "ALTER TABLE chat_histories ADD COLUMN {} {};",
+                        name, definition
+                    ))
+                    .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+        for (name, _) in COLUMNS {
+            conn.execute_unprepared(&format!("ALTER TABLE chat_histories DROP COLUMN {};", name))
+                .await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+//! 迁移：为 image 表添加视觉模型描述字段
+//!
+//! AI 描述、AI 生成的标签（用于重新生成时替换）与生成时间
+
+use sea_orm_migration::prelude::*;
+
+/// (列名, 列定义)
+const COLUMNS: &[(&str, &str)] = &[
+    ("ai_caption", "TEXT"),
+    ("ai_tags", "TEXT"),
+    ("captioned_at", "DATETIME"),
+];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+
+        for (name, definition) in COLUMNS {
+            // 检查列是否已存在（SQLite 不支持 IF NOT EXISTS）
+            let result = conn
+                .query_all(sea_orm::Statement::from_string(
+                    sea_orm::DatabaseBackend::Sqlite,
+                    format!(
+                        "SELECT COUNT(*) as cnt FROM pragma_table_info('image') WHERE name='{}'",
+                        name
+                    ),
+                ))
+                .await?;
+
+            if let Some(row) = result.first() {
+                let count: i32 = row.try_get("", "cnt").unwrap_or(0);
+                if count == 0 {
+                    conn.execute_unprepared(&format!(
+                        "ALTER TABLE image ADD COLUMN {} {};",
+                        name, definition
+                    ))
+                    .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+        for (name, _) in COLUMNS {
+            conn.execute_unprepared(&format!("ALTER TABLE image DROP COLUMN {};", name))
+                .await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+//! 迁移：新增 chat_bookmarks 表，并为 chat_histories 添加 current_floor
+//!
+//! 书签 / 高亮定位到具体楼层；阅读进度改为按楼层记录，修改每页楼层数后不会丢失位置
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+
+        // 检查列是否已存在（SQLite 不支持 IF NOT EXISTS）
+        let result = conn
+            .query_all(sea_orm::Statement::from_string(
+                sea_orm::DatabaseBackend::Sqlite,
+                "SELECT COUNT(*) as cnt FROM pragma_table_info('chat_histories') WHERE name='current_floor'"
+                    .to_string(),
+            ))
+            .await?;
+        if let Some(row) = result.first() {
+            let count: i32 = row.try_get("", "cnt").unwrap_or(0);
+            if count == 0 {
+                conn.execute_unprepared(
+                    "ALTER TABLE chat_histories ADD COLUMN current_floor INTEGER NOT NULL DEFAULT 1;",
+                )
+                .await?;
+                // 由旧的页码换算：JSONL 每页 2 层，TXT 每页 30 层
+                conn.execute_unprepared(
+                    "UPDATE chat_histories SET current_floor = (MAX(current_page, 1) - 1) * \
+                     (CASE WHEN format = 'jsonl' THEN 2 ELSE 30 END) + 1;",
+                )
+                .await?;
+            }
+        }
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ChatBookmarks::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ChatBookmarks::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ChatBookmarks::HistoryId).uuid().not_null())
+                    .col(ColumnDef::new(ChatBookmarks::CardId).uuid().not_null())
+                    .col(ColumnDef::new(ChatBookmarks::Floor).integer().not_null())
+                    .col(
+                        ColumnDef::new(ChatBookmarks::Kind)
+                            .string()
+                            .not_null()
+                            .default("bookmark"),
+                    )
+                    .col(
+                        ColumnDef::new(ChatBookmarks::Name)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(ColumnDef::new(ChatBookmarks::Note).text())
+                    .col(ColumnDef::new(ChatBookmarks::Quote).text())
+                    .col(ColumnDef::new(ChatBookmarks::Color).string())
+                    .col(
+                        ColumnDef::new(ChatBookmarks::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatBookmarks::UpdatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ChatBookmarks::Table, ChatBookmarks::HistoryId)
+                            .to(ChatHistories::Table, ChatHistories::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_chat_bookmarks_history")
+                    .table(ChatBookmarks::Table)
+                    .col(ChatBookmarks::HistoryId)
+                    .col(ChatBookmarks::Floor)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_chat_bookmarks_card")
+                    .table(ChatBookmarks::Table)
+                    .col(ChatBookmarks::CardId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ChatBookmarks::Table).to_owned())
+            .await?;
+        manager
+            .get_connection()
+            .execute_unprepared("ALTER TABLE chat_histories DROP COLUMN current_floor;")
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ChatBookmarks {
+    Table,
+    Id,
+    HistoryId,
+    CardId,
+    Floor,
+    Kind,
+    Name,
+    Note,
+    Quote,
+    Color,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum ChatHistories {
+    Table,
+    Id,
+}
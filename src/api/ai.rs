@@ -1,4 +1,5 @@
 use crate::entities::{ai_channel, character_card, setting};
+use crate::services::channel_health;
 use axum::{
     extract::{Path, State},
     http::StatusCode,
@@ -33,6 +34,10 @@ pub struct ChannelResponse {
     pub base_url: String,
     pub model_id: String,
     pub is_active: bool,
+    /// 是否已填写 API Key（密钥本身从不返回）
+    pub api_key_set: bool,
+    /// 健康检查状态（尚未探测过时为 null）
+    pub health: Option<channel_health::ChannelHealth>,
     // Sensitive data excluded
 }
 
@@ -75,6 +80,8 @@ pub async fn list_channels(
             base_url: c.base_url,
             model_id: c.model_id,
             is_active: c.is_active,
+            api_key_set: !c.api_key.is_empty(),
+            health: channel_health::get(c.id),
         })
         .collect();
 
@@ -94,13 +101,15 @@ pub async fn create_channel(
         id: Set(channel_id),
         name: Set(payload.name.clone()),
         base_url: Set(payload.base_url.clone()),
-        api_key: Set(payload.api_key),
+        api_key: Set(crate::utils::crypto::encrypt_local(&payload.api_key)),
         model_id: Set(payload.model_id.clone()),
         is_active: Set(payload.is_active),
         created_at: Set(now),
         updated_at: Set(now),
     };
 
+    crate::services::mock_ai::reset_script(&payload.base_url);
+
     // Use insert without relying on return value (SQLite + UUID fix)
     ai_channel::Entity::insert(new_channel)
         .exec_without_returning(&db)
@@ -120,6 +129,8 @@ pub async fn create_channel(
         base_url: payload.base_url,
         model_id: payload.model_id,
         is_active: payload.is_active,
+        api_key_set: !payload.api_key.is_empty(),
+        health: None,
     }))
 }
 
@@ -137,6 +148,7 @@ pub async fn delete_channel(
                 Json(serde_json::json!({"error": e.to_string()})),
             )
         })?;
+    channel_health::forget(id);
 
     Ok((StatusCode::OK, Json(serde_json::json!({}))))
 }
@@ -164,6 +176,11 @@ pub async fn update_channel(
             )
         })?;
 
+    // 连接信息变更后旧的健康记录不再适用
+    if payload.base_url.is_some() || payload.api_key.is_some() || payload.model_id.is_some() {
+        channel_health::forget(id);
+    }
+
     // Build update model
     let mut update_model: ai_channel::ActiveModel = existing.into();
 
@@ -171,10 +188,11 @@ pub async fn update_channel(
         update_model.name = Set(name);
     }
     if let Some(base_url) = payload.base_url {
+        crate::services::mock_ai::reset_script(&base_url);
         update_model.base_url = Set(base_url);
     }
     if let Some(api_key) = payload.api_key {
-        update_model.api_key = Set(api_key);
+        update_model.api_key = Set(crate::utils::crypto::encrypt_local(&api_key));
     }
     if let Some(model_id) = payload.model_id {
         update_model.model_id = Set(model_id);
@@ -198,18 +216,27 @@ pub async fn update_channel(
         base_url: updated.base_url,
         model_id: updated.model_id,
         is_active: updated.is_active,
+        api_key_set: !updated.api_key.is_empty(),
+        health: channel_health::get(updated.id),
     }))
 }
 pub async fn test_connection(
     Json(payload): Json<TestConnectionRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
-    let client = reqwest::Client::new();
     let start_time = std::time::Instant::now();
 
-    // Construct Chat Completion request
-    // URL: base_url + /chat/completions (user provides full path including /v1)
-    let base = payload.base_url.trim_end_matches('/');
-    let url = format!("{}/chat/completions", base);
+    // 临时渠道（不保存），请求路径为 base_url + /chat/completions (user provides full path including /v1)
+    let now = chrono::Utc::now().naive_utc();
+    let channel = ai_channel::Model {
+        id: Uuid::nil(),
+        name: "test".to_string(),
+        base_url: payload.base_url,
+        api_key: payload.api_key,
+        model_id: payload.model_id.clone(),
+        is_active: true,
+        created_at: now,
+        updated_at: now,
+    };
 
     let body = serde_json::json!({
         "model": payload.model_id,
@@ -219,17 +246,12 @@ pub async fn test_connection(
         "max_tokens": 5
     });
 
-    let res = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", payload.api_key))
-        .header("Content-Type", "application/json")
-        .json(&body)
-        .send()
+    let res = crate::services::ai::send_request(&channel, "chat/completions", &body)
         .await
         .map_err(|e| {
             (
                 StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": format!("Request failed: {}", e)})),
+                Json(serde_json::json!({"error": e.to_string()})),
             )
         })?;
 
@@ -258,48 +280,90 @@ pub async fn test_connection(
 }
 
 /// GET /api/ai/models - List Models (Proxy)
-/// Query params: base_url, api_key (Transient, not saved)
+/// Query params: base_url, api_key (Transient, not saved), refresh (skip cache)
 #[derive(Deserialize)]
 pub struct ListModelsQuery {
     pub base_url: String,
     pub api_key: String,
+    #[serde(default)]
+    pub refresh: bool,
 }
 
 pub async fn list_models_proxy(
     axum::extract::Query(query): axum::extract::Query<ListModelsQuery>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
-    let client = reqwest::Client::new();
-    let base = query.base_url.trim_end_matches('/');
-    let url = format!("{}/models", base);
-
-    let res = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", query.api_key))
-        .send()
+    if !query.refresh {
+        if let Some(models) = channel_health::cached_models(&query.base_url, &query.api_key) {
+            return Ok(Json(models));
+        }
+    }
+
+    let json = channel_health::fetch_models(&query.base_url, &query.api_key)
         .await
         .map_err(|e| {
             (
                 StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": format!("Request failed: {}", e)})),
+                Json(serde_json::json!({"error": e.to_string()})),
             )
         })?;
 
-    if !res.status().is_success() {
-        let err_text = res.text().await.unwrap_or_default();
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": format!("API Error: {}", err_text)})),
-        ));
-    }
+    Ok(Json(json))
+}
+
+#[derive(Deserialize)]
+pub struct ChannelModelsQuery {
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+/// GET /api/ai/channels/:id/models - 已保存渠道的模型列表（带缓存）
+pub async fn list_channel_models(
+    State(db): State<DatabaseConnection>,
+    Path(id): Path<Uuid>,
+    axum::extract::Query(query): axum::extract::Query<ChannelModelsQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let channel = ai_channel::Entity::find_by_id(id)
+        .one(&db)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "Channel not found"})),
+            )
+        })?;
 
-    let json: Value = res.json().await.map_err(|e| {
+    let api_key = crate::services::ai::channel_api_key(&channel).map_err(|e| {
         (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": format!("Invalid JSON response: {}", e)})),
+            e.status_code(),
+            Json(serde_json::json!({"error": e.to_string()})),
         )
     })?;
 
-    Ok(Json(json))
+    if !query.refresh {
+        if let Some(models) = channel_health::cached_models(&channel.base_url, &api_key) {
+            return Ok(Json(models));
+        }
+    }
+
+    let start_time = std::time::Instant::now();
+    let result = channel_health::fetch_models(&channel.base_url, &api_key).await;
+    channel_health::record_models_result(
+        channel.id,
+        start_time.elapsed().as_millis() as u64,
+        &result,
+    );
+    result.map(Json).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": e.to_string()})),
+        )
+    })
 }
 
 #[derive(Serialize)]
@@ -327,14 +391,10 @@ pub async fn test_saved_channels(
         })?;
 
     let mut results = Vec::new();
-    let client = reqwest::Client::new();
 
     // Parallel testing could be better, but sequential is safer for rate limits
     // and simplicity for now.
     for channel in channels {
-        let base = channel.base_url.trim_end_matches('/');
-        let url = format!("{}/chat/completions", base);
-
         let body = serde_json::json!({
             "model": channel.model_id,
             "messages": [
@@ -344,19 +404,14 @@ pub async fn test_saved_channels(
         });
 
         let start_time = std::time::Instant::now();
-        let res = client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", channel.api_key))
-            .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await;
+        let res = crate::services::ai::send_request(&channel, "chat/completions", &body).await;
 
         let latency_ms = start_time.elapsed().as_millis() as u64;
 
         match res {
             Ok(response) => {
                 if response.status().is_success() {
+                    channel_health::record(channel.id, Some(latency_ms), None);
                     results.push(ChannelTestResult {
                         id: channel.id,
                         name: channel.name,
@@ -365,10 +420,18 @@ pub async fn test_saved_channels(
                         latency_ms: Some(latency_ms),
                     });
                 } else {
+                    let status = response.status().as_u16();
                     let err_text = response
                         .text()
                         .await
                         .unwrap_or_else(|_| "Unknown error".to_string());
+                    if channel_health::is_channel_fault(status) {
+                        channel_health::record(
+                            channel.id,
+                            Some(latency_ms),
+                            Some(err_text.clone()),
+                        );
+                    }
                     results.push(ChannelTestResult {
                         id: channel.id,
                         name: channel.name,
@@ -379,6 +442,7 @@ pub async fn test_saved_channels(
                 }
             }
             Err(e) => {
+                channel_health::record(channel.id, None, Some(e.to_string()));
                 results.push(ChannelTestResult {
                     id: channel.id,
                     name: channel.name,
@@ -474,18 +538,6 @@ pub async fn generate_overview(
         channel.name, channel.model_id
     ));
 
-    // 1.5. 获取全局提示词
-    let global_prompt_setting = setting::Entity::find_by_id("global_prompt")
-        .one(&db)
-        .await
-        .unwrap_or(None);
-    let global_prompt = global_prompt_setting.map(|s| s.value).unwrap_or_default();
-    if !global_prompt.is_empty() {
-        logs.push(format!("全局提示词已加载 ({} 字符)", global_prompt.len()));
-    } else {
-        logs.push("未配置局全局提示词".to_string());
-    }
-
     // 2. 获取角色卡数据
     logs.push(format!("正在获取角色卡: {}", payload.card_id));
     let card = character_card::Entity::find_by_id(payload.card_id)
@@ -506,148 +558,49 @@ pub async fn generate_overview(
             )
         })?;
 
-    // 解析 JSON data
-    let card_data: Value = serde_json::from_str(&card.data).unwrap_or(serde_json::json!({}));
-
-    // 提取字段
-    let name = card.name.clone();
-    let description = card.description.clone().unwrap_or_default();
-    let personality = card_data["personality"].as_str().unwrap_or("").to_string();
-    let scenario = card_data["scenario"].as_str().unwrap_or("").to_string();
-    let first_mes = card_data["first_mes"].as_str().unwrap_or("").to_string();
-    let mes_example = card_data["mes_example"].as_str().unwrap_or("").to_string();
-    let creatorcomment = card_data["creatorcomment"]
-        .as_str()
-        .unwrap_or("")
-        .to_string();
-    let system_prompt = card_data["system_prompt"]
-        .as_str()
-        .unwrap_or("")
-        .to_string();
-    let post_history_instructions = card_data["post_history_instructions"]
-        .as_str()
-        .unwrap_or("")
-        .to_string();
-
-    logs.push("字段提取完成:".to_string());
-    logs.push(format!("- Name: {}", name));
-    logs.push(format!("- Description length: {}", description.len()));
-    logs.push(format!("- Personality length: {}", personality.len()));
-    logs.push(format!("- Scenario length: {}", scenario.len()));
-    logs.push(format!("- First Mes length: {}", first_mes.len()));
-
-    // 3. 检查标签策略
-    let current_tags_json: Vec<String> = serde_json::from_str(&card.tags).unwrap_or_default();
-    let generate_tags = current_tags_json.is_empty();
-
-    let mut system_tags_str = String::new();
+    // 3. 构建变量（全局提示词、角色卡字段、标签策略）
+    let vars = crate::services::prompt::build_variables(&db, "overview", Some(&card))
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?;
+    let generate_tags = vars
+        .get("generate_tags")
+        .map(|v| !v.is_empty())
+        .unwrap_or(false);
     if generate_tags {
-        logs.push("当前无标签，将生成标签。正在获取系统标签库...".to_string());
-        // 获取所有 tags
-        let all_cards = character_card::Entity::find()
-            .all(&db)
-            .await
-            .unwrap_or_default();
-        let mut all_tags = std::collections::HashSet::new();
-        for c in all_cards {
-            if let Ok(tags) = serde_json::from_str::<Vec<String>>(&c.tags) {
-                for t in tags {
-                    all_tags.insert(t);
-                }
-            }
-        }
-        let tags_vec: Vec<String> = all_tags.into_iter().collect();
-        system_tags_str = serde_json::to_string(&tags_vec).unwrap_or_default();
-        logs.push(format!("系统标签库共 {} 个标签", tags_vec.len()));
+        logs.push("当前无标签，将生成标签。已加载系统标签库".to_string());
     } else {
         logs.push("当前已有标签，跳过标签生成。".to_string());
     }
 
-    // 4. 构建 Prompt
-    let task_instruction = if generate_tags {
-        format!(
-            r#"
-[任务与约束]
-1. 概览总结：150字以内，精炼概括角色核心特征。
-2. 标签生成：最多5个。必须优先从以下[系统现有标签]中选择；仅当无匹配时才生成新标签。
-   [系统现有标签]: {}
-
-[回复格式]
-请严格仅返回 JSON，不要使用代码块：
-{{"summary": "...", "tags": ["tag1", "tag2"]}}
-"#,
-            system_tags_str
-        )
-    } else {
-        r#"
-[任务与约束]
-1. 概览总结：150字以内，精炼概括角色核心特征。
-
-[回复格式]
-请严格仅返回 JSON，不要使用代码块：
-{"summary": "..."}
-"#
-        .to_string()
-    };
-
-    let user_content = format!(
-        r#"请深入分析以下角色卡数据：
-
-[角色元数据]
-Name: {}
-Description: {}
-
-[详细设定]
-Personality: {}
-Scenario: {}
-First Message: {}
-Example Dialogue: {}
-System Prompt: {}
-Post Instructions: {}
-Creator Comment: {}
-
-{}"#,
-        name,
-        description,
-        personality,
-        scenario,
-        first_mes,
-        mes_example,
-        system_prompt,
-        post_history_instructions,
-        creatorcomment,
-        task_instruction
-    );
-
+    // 4. 渲染提示词模板
+    let template = crate::services::prompt::resolve_template(&db, "overview")
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "缺少概览提示词模板"})),
+            )
+        })?;
+    logs.push(match template.version {
+        Some(v) => format!("使用自定义提示词模板 v{}", v),
+        None => "使用内置提示词模板".to_string(),
+    });
+    let messages = crate::services::prompt::render_messages(&template, &vars);
     logs.push("Prompt 构建完成".to_string());
-    // logs.push(format!("User Content:\n{}", user_content)); // 若太长可注释
-
-    // 5. 调用 AI
-    let client = reqwest::Client::new();
-    let base = channel.base_url.trim_end_matches('/');
-    let url = format!("{}/chat/completions", base);
-
-    // 构建系统提示词：全局提示词 + 功能提示词
-    let base_system_prompt = "你是一位专业的角色卡分析师。请分析角色设定，返回纯 JSON 格式结果，不要包含 markdown 标记。";
-    let system_prompt_content = if global_prompt.is_empty() {
-        base_system_prompt.to_string()
-    } else {
-        format!("{}\n\n{}", global_prompt, base_system_prompt)
-    };
-    logs.push(format!(
-        "System Prompt 长度: {} 字符",
-        system_prompt_content.len()
-    ));
 
-    let body = serde_json::json!({
-        "model": channel.model_id,
-        "messages": [
-            {
-                "role": "system",
-                "content": system_prompt_content
-            },
-            {"role": "user", "content": user_content}
-        ],
+    // 5. 调用 AI（结构化输出：自动修复常见格式问题，不符合要求时纠正重试一次）
+    let params = serde_json::json!({
         "temperature": 1.0,
         "max_tokens": 4096,
         "safety_settings": [
@@ -655,99 +608,43 @@ Creator Comment: {}
             {"category": "HARM_CATEGORY_HATE_SPEECH", "threshold": "BLOCK_NONE"},
             {"category": "HARM_CATEGORY_SEXUALLY_EXPLICIT", "threshold": "BLOCK_NONE"},
             {"category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "BLOCK_NONE"}
-        ],
-        "response_format": { "type": "json_object" }
+        ]
     });
 
-    logs.push(format!("正在请求 AI 接口: {}", url));
+    logs.push(format!("正在请求 AI 渠道: {}", channel.name));
     let start_time = std::time::Instant::now();
 
-    let res = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", channel.api_key))
-        .header("Content-Type", "application/json")
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| {
-            let msg = format!("请求失败: {}", e);
-            logs.push(msg.clone());
-            (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": msg, "logs": logs})),
-            )
-        })?;
-
-    logs.push(format!("AI 响应状态: {}", res.status()));
-
-    if !res.status().is_success() {
-        let err_text = res.text().await.unwrap_or_default();
-        let msg = format!("API 错误: {}", err_text);
-        logs.push(msg.clone());
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({"error": msg, "logs": logs})),
-        ));
-    }
-
-    let json_res: Value = res.json().await.map_err(|e| {
-        let msg = format!("无效的 JSON 响应: {}", e);
+    let completion = crate::services::structured::complete_json(
+        &channel,
+        &messages,
+        params,
+        &crate::services::structured::OVERVIEW_SCHEMA,
+        1,
+    )
+    .await
+    .map_err(|e| {
+        let msg = e.to_string();
         logs.push(msg.clone());
         (
-            StatusCode::BAD_REQUEST,
+            e.status_code(),
             Json(serde_json::json!({"error": msg, "logs": logs})),
         )
     })?;
 
-    let latency = start_time.elapsed().as_millis();
-    logs.push(format!("请求耗时: {}ms", latency));
-
-    // 记录完整的 AI 响应结构（用于调试）
-    logs.push(format!(
-        "Raw JSON Response: {}",
-        serde_json::to_string(&json_res).unwrap_or_default()
-    ));
-
-    // 提取 content
-    let content = json_res["choices"][0]["message"]["content"]
-        .as_str()
-        .ok_or_else(|| {
-            let msg = "AI 响应无 content 字段".to_string();
-            logs.push(msg.clone());
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({"error": msg, "logs": logs})),
-            )
-        })?;
-
-    logs.push(format!("Raw Content: {}", content));
-
-    // 检查空内容（可能是安全过滤导致）
-    if content.trim().is_empty() {
-        let completion_tokens = json_res["usage"]["completion_tokens"].as_u64().unwrap_or(0);
-        let msg = if completion_tokens == 0 {
-            "AI 返回空内容 (completion_tokens=0)。可能是模型安全过滤触发，请尝试更换渠道/模型。"
-                .to_string()
-        } else {
-            "AI 返回空内容".to_string()
-        };
-        logs.push(msg.clone());
-        return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": msg, "logs": logs})),
+    logs.push(format!("请求耗时: {}ms", start_time.elapsed().as_millis()));
+    logs.push(format!("Raw Content: {}", completion.content));
+    if completion.attempts > 1 {
+        logs.push(format!(
+            "输出格式经 {} 次请求后符合要求",
+            completion.attempts
         ));
     }
-
-    // 清理 markdown code block if present
-    let cleaned_content = content
-        .trim()
-        .trim_start_matches("```json")
-        .trim_start_matches("```")
-        .trim_end_matches("```")
-        .trim();
+    if completion.repaired {
+        logs.push("AI 返回的 JSON 存在格式问题，已自动修复".to_string());
+    }
 
     // 解析结果 JSON
-    let ai_result: AiOverviewJson = serde_json::from_str(cleaned_content).map_err(|e| {
+    let ai_result: AiOverviewJson = serde_json::from_value(completion.value).map_err(|e| {
         let msg = format!("无法解析 AI 返回的 JSON: {}", e);
         logs.push(msg.clone());
         (
@@ -757,54 +654,29 @@ Creator Comment: {}
     })?;
 
     // 6. 更新数据库
-    let mut update_model: character_card::ActiveModel = card.clone().into();
-    update_model.custom_summary = Set(Some(ai_result.summary.clone()));
-
-    let mut final_tags = None;
-    if generate_tags {
-        if let Some(ref tags) = ai_result.tags {
+    let final_tags = if generate_tags {
+        ai_result.tags.clone()
+    } else {
+        None
+    };
+    match &final_tags {
+        Some(tags) => {
             logs.push(format!("生成了 {} 个标签: {:?}", tags.len(), tags));
-            let tags_json = serde_json::to_string_pretty(&tags).unwrap_or("[]".to_string());
-            update_model.tags = Set(tags_json);
-
-            // 更新 data JSON 中的 tags（V1/V2/V3 兼容）
-            let mut current_json: Value =
-                serde_json::from_str(&card.data).unwrap_or(serde_json::json!({}));
-
-            // V2 spec: data.data.tags
-            if let Some(data) = current_json.get_mut("data") {
-                if let Some(obj) = data.as_object_mut() {
-                    obj.insert("tags".to_string(), serde_json::json!(tags));
-                }
-            }
-
-            // V1/V3: 根级 tags
-            if let Some(obj) = current_json.as_object_mut() {
-                obj.insert("tags".to_string(), serde_json::json!(tags));
-            }
-
-            // 写回 data 字段（保持格式化）
-            update_model.data =
-                Set(serde_json::to_string_pretty(&current_json).unwrap_or(card.data.clone()));
             logs.push("已同步更新 data JSON 中的 tags 字段".to_string());
-
-            final_tags = Some(tags.clone());
         }
-    } else {
-        logs.push(format!("仅更新概览: {}", ai_result.summary));
+        None => logs.push(format!("仅更新概览: {}", ai_result.summary)),
     }
 
-    update_model.metadata_modified = Set(true);
-    update_model.updated_at = Set(chrono::Utc::now().naive_utc());
-
-    update_model.update(&db).await.map_err(|e| {
-        let msg = format!("数据库更新失败: {}", e);
-        logs.push(msg.clone());
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": msg, "logs": logs})),
-        )
-    })?;
+    crate::services::card::apply_overview(&db, &card, &ai_result.summary, final_tags.as_deref())
+        .await
+        .map_err(|e| {
+            let msg = format!("数据库更新失败: {}", e);
+            logs.push(msg.clone());
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": msg, "logs": logs})),
+            )
+        })?;
 
     logs.push("处理完成!".to_string());
 
@@ -817,8 +689,12 @@ Creator Comment: {}
 
 #[derive(Deserialize)]
 pub struct ExecuteFeatureRequest {
-    pub feature_id: String,               // e.g. "overview"
-    pub messages: Vec<serde_json::Value>, // [{"role": "user", "content": "..."}]
+    pub feature_id: String,                       // e.g. "overview"
+    pub messages: Option<Vec<serde_json::Value>>, // [{"role": "user", "content": "..."}]
+    /// 未传 messages 时，使用该功能的提示词模板渲染
+    pub card_id: Option<Uuid>,
+    #[serde(default)]
+    pub variables: crate::services::prompt::PromptVars,
 }
 
 /// POST /api/ai/execute - Execute generic AI task based on feature config
@@ -873,32 +749,32 @@ pub async fn execute_feature(
             )
         })?;
 
-    // 3. Proxy Request
-    let client = reqwest::Client::new();
-    let base = channel.base_url.trim_end_matches('/');
-    let url = format!("{}/chat/completions", base);
+    // 3. Resolve Messages (explicit messages or rendered template)
+    let messages = match payload.messages {
+        Some(messages) => messages,
+        None => {
+            render_feature_messages(&db, &payload.feature_id, payload.card_id, payload.variables)
+                .await?
+        }
+    };
 
+    // 4. Proxy Request
     // 简化请求体，仅保留 OpenAI 兼容参数
     let body = serde_json::json!({
         "model": channel.model_id,
-        "messages": payload.messages,
+        "messages": messages,
         "temperature": 0.7
     });
 
     // 调试日志：打印请求内容
 
-    let res = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", channel.api_key))
-        .header("Content-Type", "application/json")
-        .json(&body)
-        .send()
+    let res = crate::services::ai::send_request(&channel, "chat/completions", &body)
         .await
         .map_err(|e| {
             tracing::error!("AI Request Error: {}", e);
             (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": format!("Request failed: {}", e)})),
+                e.status_code(),
+                Json(serde_json::json!({"error": e.to_string()})),
             )
         })?;
 
@@ -926,17 +802,75 @@ pub async fn execute_feature(
     Ok(Json(json))
 }
 
+/// 使用功能的提示词模板渲染 messages
+async fn render_feature_messages(
+    db: &DatabaseConnection,
+    feature_id: &str,
+    card_id: Option<Uuid>,
+    extra_vars: crate::services::prompt::PromptVars,
+) -> Result<Vec<Value>, (StatusCode, Json<Value>)> {
+    let template = crate::services::prompt::resolve_template(db, feature_id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": format!("功能 {} 没有可用的提示词模板", feature_id)})),
+            )
+        })?;
+
+    let card = match card_id {
+        Some(id) => Some(
+            character_card::Entity::find_by_id(id)
+                .one(db)
+                .await
+                .map_err(|e| {
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(serde_json::json!({"error": e.to_string()})),
+                    )
+                })?
+                .ok_or_else(|| {
+                    (
+                        StatusCode::NOT_FOUND,
+                        Json(serde_json::json!({"error": "角色卡不存在"})),
+                    )
+                })?,
+        ),
+        None => None,
+    };
+
+    let mut vars = crate::services::prompt::build_variables(db, feature_id, card.as_ref())
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?;
+    vars.extend(extra_vars);
+
+    Ok(crate::services::prompt::render_messages(&template, &vars))
+}
+
 // ==================== 小皮医生 (Doctor) API ====================
 
 use crate::entities::doctor_task;
 use axum::response::sse::{Event, Sse};
-use futures::stream::{self, Stream};
+use futures::stream::{self, Stream, StreamExt};
 use std::convert::Infallible;
 use std::time::Duration;
 
 #[derive(Deserialize)]
 pub struct DoctorAnalyzeRequest {
     pub card_id: Uuid,
+    /// 诊断模式：默认多轮检索；"deep" 为全量世界书分组审查
+    pub mode: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -944,6 +878,11 @@ pub struct DoctorHistoryItem {
     pub id: Uuid,
     pub status: String,
     pub final_report: Option<String>,
+    pub dimensions: Vec<crate::services::doctor::DimensionScore>,
+    pub overall_score: Option<f64>,
+    pub conclusion: Option<String>,
+    pub card_hash: Option<String>,
+    pub card_version: Option<String>,
     pub created_at: String,
 }
 
@@ -1013,10 +952,6 @@ pub async fn doctor_analyze(
     let settings_map: std::collections::HashMap<String, String> =
         settings.into_iter().map(|s| (s.key, s.value)).collect();
 
-    let global_prompt = settings_map
-        .get("global_prompt")
-        .cloned()
-        .unwrap_or_default();
     let channel_id_str = settings_map
         .get("ai_config_global")
         .cloned()
@@ -1052,26 +987,20 @@ pub async fn doctor_analyze(
             )
         })?;
 
+    // 深度模式：全部条目按 Token 预算分组审查后汇总
+    if payload.mode.as_deref() == Some("deep") {
+        let stream = doctor_deep_stream(db, card, channel).await?;
+        return Ok(Sse::new(stream.left_stream()).keep_alive(
+            axum::response::sse::KeepAlive::new()
+                .interval(Duration::from_secs(15))
+                .text("keep-alive"),
+        ));
+    }
+
     // 解析角色卡数据
     let card_data: Value = serde_json::from_str(&card.data).unwrap_or(serde_json::json!({}));
     let v2_data = card_data.get("data").unwrap_or(&card_data);
 
-    let name = card.name.clone();
-    let description = card.description.clone().unwrap_or_default();
-    let personality = v2_data
-        .get("personality")
-        .and_then(|v| v.as_str())
-        .unwrap_or("");
-    let first_mes = v2_data
-        .get("first_mes")
-        .and_then(|v| v.as_str())
-        .unwrap_or("");
-    let alt_greetings = v2_data
-        .get("alternate_greetings")
-        .and_then(|v| v.as_array())
-        .map(|arr| arr.first().and_then(|v| v.as_str()).unwrap_or(""))
-        .unwrap_or("");
-
     // 提取世界书目录
     let entries: Vec<Value> = v2_data
         .get("character_book")
@@ -1083,113 +1012,35 @@ pub async fn doctor_analyze(
         .filter(|e| e.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true)) // Filter enabled
         .collect();
 
-    let worldbook_toc: Vec<String> = entries
-        .iter()
-        .filter_map(|e| e.get("comment").and_then(|c| c.as_str()))
-        .filter(|c| !c.is_empty())
-        .map(|s| s.to_string())
-        .collect();
-
-    let worldbook_toc_str = if worldbook_toc.is_empty() {
-        "（无世界书条目）".to_string()
-    } else {
-        worldbook_toc
-            .iter()
-            .map(|s| format!("- {}", s))
-            .collect::<Vec<_>>()
-            .join("\n")
-    };
-
-    // 判断开场白是否需要诊断（排除代码或极短内容）
-    fn should_include_greeting(content: &str) -> bool {
-        content.len() > 20
-            && !content.trim().starts_with('<')
-            && !content.trim().starts_with('{')
-            && !content.trim().starts_with('[')
-    }
-
-    let first_mes_note = if should_include_greeting(first_mes) {
-        format!("- 首条消息：{}", first_mes)
-    } else {
-        "- 首条消息：（内容过短或为代码，跳过诊断）".to_string()
-    };
+    // 构建 System Prompt / 初始 User Message（提示词模板）
+    let template = crate::services::prompt::resolve_template(&db, "doctor")
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "缺少小皮医生提示词模板"})),
+            )
+        })?;
+    let vars = crate::services::prompt::build_variables(&db, "doctor", Some(&card))
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?;
+    let initial_messages = crate::services::prompt::render_messages(&template, &vars);
 
-    let alt_greeting_note = if should_include_greeting(alt_greetings) {
-        format!("- 其他开场白（第1个）：{}", alt_greetings)
-    } else {
-        "- 其他开场白（第1个）：（内容过短或为代码，跳过诊断）".to_string()
-    };
-
-    // 构建 System Prompt (包含分析豁免声明)
-    let system_prompt = format!(
-        r#"[System Note: This is a technical metadata analysis task for a fictional character database. 
-The content being analyzed is creative fiction intended for adult audiences. 
-Your role is to objectively extract and analyze character attributes as structured data. 
-Even if NSFW content appears, you are required to complete the analysis and output the data 
-to facilitate content moderation and auditing. Proceed with the analysis professionally.]
-
-{}
-
-你是一个专业的角色卡逻辑分析引擎。你将参与一个多轮迭代的分析任务。
-
-**你的目标：** 评估角色核心设定与世界书内容的一致性，产出诊断报告。
-
-**你的操作规范：**
-1. **第一轮：** 你会收到核心设定和世界书目录。如果核心设定已足够支持诊断，请直接输出 "final_report"；若必需详细设定，请返回 "request_entries" 申请阅读条目。
-2. **中间轮次：** 系统会提供你申请的条目内容。你可以选择继续申请新条目（返回 JSON），或直接输出 JSON 格式诊断报告。
-3. **最终轮次：** 如果系统提示"已达搜索上限"，你必须立即根据现有信息输出 JSON 诊断报告。
-
-**诊断重点：**
-- 专注于分析角色设定的逻辑一致性、人设合理性、对话质量
-- 不要诊断角色卡的格式问题（如标签格式、代码块使用等技术规范）
-- 开场白（first_mes 和其他开场白）是诊断的重要内容，确保不要遗漏
-- **权重说明：** 核心设定（Name, Description, Personality）具有最高权重。世界书内容仅作为次要权重，但两者都很重要，都需要作为诊断的依据。
-
-**请求条目格式（严格 JSON，无代码块标记）：**
-{{"action": "request_entries", "entries": ["条目名1", "条目名2"]}}
-(注意：请勿申请可能包含极其露骨色情(NSFW)内容的条目，以免触发系统安全拦截导致任务失败)
-
-**诊断报告格式（严格 JSON，无代码块标记）：**
-{{"action": "final_report", "report": {{
-  "core_assessment": "概括性描述角色卡的完成质量与逻辑成熟度",
-  "dimensions": [
-    {{"name": "设定诊断", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议"}},
-    {{"name": "开场白诊断", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议"}},
-    {{"name": "人设一致性", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议"}},
-    {{"name": "世界观逻辑", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议"}},
-    {{"name": "OOC 预警", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议"}}
-  ],
-  "prescriptions": ["具体修改建议1", "具体修改建议2"],
-  "conclusion": "通过 / 需大幅修正 / 建议重构"
-}}}}
-
-**重要：** 所有输出必须是纯 JSON，不要包含 markdown 代码块标记。dimensions 中各字段可以使用 Markdown 格式（加粗、列表等）来增强可读性。"#,
-        global_prompt
-    );
-
-    // 构建初始 User Message
-    let initial_user_msg = format!(
-        r#"**[任务启动]** 请审阅以下内容，并返回你第一轮想要阅读的世界书条目名称（JSON 格式）。
-
-**核心设定：**
-- 角色名称：{}
-- 角色描述：{}
-- 性格特征：{}
-{}
-{}
-
-**世界书目录（条目名称列表）：**
-{}
-
-请返回 JSON 格式：{{"action": "request_entries", "entries": ["条目名1", ...]}}
-如果世界书目录为空或无需阅读条目，请直接输出诊断报告 JSON。请优先判断当前信息是否足够，避免不必要的搜索。同时请严格避开 NSFW 相关条目。"#,
-        name, description, personality, first_mes_note, alt_greeting_note, worldbook_toc_str
-    );
-
-    // 克隆需要的数据到 async 块
-    let db_clone = db.clone();
-    let channel_clone = channel.clone();
-    let entries_clone = entries.clone();
+    // 克隆需要的数据到 async 块
+    let db_clone = db.clone();
+    let channel_clone = channel.clone();
+    let entries_clone = entries.clone();
 
     // 创建 SSE 流 (不再需要 task_id，只在成功时保存)
     let stream = stream::unfold(
@@ -1198,10 +1049,7 @@ to facilitate content moderation and auditing. Proceed with the analysis profess
             card_id, // 使用 card_id 替代 task_id
             channel_clone,
             entries_clone,
-            vec![
-                serde_json::json!({"role": "system", "content": system_prompt}),
-                serde_json::json!({"role": "user", "content": initial_user_msg}),
-            ],
+            initial_messages,
             0usize, // iteration count
         ),
         |(db, card_id, channel, entries, mut messages, iteration)| async move {
@@ -1219,32 +1067,23 @@ to facilitate content moderation and auditing. Proceed with the analysis profess
                 return None;
             }
 
-            // 调用 AI
-            let client = reqwest::Client::new();
-            let base = channel.base_url.trim_end_matches('/');
-            let url = format!("{}/chat/completions", base);
-
-            let body = serde_json::json!({
-                "model": channel.model_id,
-                "messages": messages,
-                "temperature": 0.7
-            });
-
-            let res = match client
-                .post(&url)
-                .header("Authorization", format!("Bearer {}", channel.api_key))
-                .header("Content-Type", "application/json")
-                .json(&body)
-                .send()
-                .await
+            // 调用 AI（结构化输出：自动修复常见格式问题，不符合要求时纠正重试一次）
+            let completion = match crate::services::structured::complete_json(
+                &channel,
+                &messages,
+                serde_json::json!({"temperature": 0.7}),
+                &crate::services::doctor::ANALYZE_SCHEMA,
+                1,
+            )
+            .await
             {
-                Ok(r) => r,
+                Ok(completion) => completion,
                 Err(e) => {
-                    tracing::error!("Doctor AI network error: {}", e);
+                    tracing::error!("Doctor AI error: {}", e);
                     let event = Event::default().data(
                         serde_json::to_string(&SseProgress {
                             status: "error".to_string(),
-                            message: format!("AI 请求失败: {}", e),
+                            message: e.to_string(),
                             report: None,
                             debug: None,
                         })
@@ -1253,107 +1092,8 @@ to facilitate content moderation and auditing. Proceed with the analysis profess
                     return Some((Ok(event), (db, card_id, channel, entries, messages, 999)));
                 }
             };
-
-            // 检查 HTTP 状态码
-            let status = res.status();
-            let raw_text = res.text().await.unwrap_or_default();
-
-            // 调试日志
-
-            // 如果 HTTP 状态不是成功
-            if !status.is_success() {
-                let event = Event::default().data(
-                    serde_json::to_string(&SseProgress {
-                        status: "error".to_string(),
-                        message: format!(
-                            "AI 服务返回错误 (HTTP {}): {}",
-                            status.as_u16(),
-                            raw_text.chars().take(200).collect::<String>()
-                        ),
-                        report: None,
-                        debug: None,
-                    })
-                    .unwrap(),
-                );
-                return Some((Ok(event), (db, card_id, channel, entries, messages, 999)));
-            }
-
-            let json: Value = match serde_json::from_str(&raw_text) {
-                Ok(j) => j,
-                Err(e) => {
-                    tracing::error!(
-                        "Doctor AI JSON parse error: {}, raw: {}",
-                        e,
-                        raw_text.chars().take(200).collect::<String>()
-                    );
-                    let event = Event::default().data(
-                        serde_json::to_string(&SseProgress {
-                            status: "error".to_string(),
-                            message: format!("AI 响应解析失败: {} (可能是空响应)", e),
-                            report: None,
-                            debug: None,
-                        })
-                        .unwrap(),
-                    );
-                    return Some((Ok(event), (db, card_id, channel, entries, messages, 999)));
-                }
-            };
-
-            // 提取 AI 回复内容
-            let ai_content = json
-                .get("choices")
-                .and_then(|c| c.get(0))
-                .and_then(|c| c.get("message"))
-                .and_then(|m| m.get("content"))
-                .and_then(|c| c.as_str())
-                .unwrap_or("");
-
-            // 检查空响应
-            if ai_content.is_empty() {
-                tracing::warn!(
-                    "Doctor AI returned empty content, full response: {:?}",
-                    json
-                );
-                let event = Event::default().data(
-                    serde_json::to_string(&SseProgress {
-                        status: "error".to_string(),
-                        message: "AI 返回了空内容，可能是内容审核限制导致。请尝试使用其他模型或检查角色卡内容。".to_string(),
-                        report: None,
-                        debug: None,
-                    })
-                    .unwrap(),
-                );
-                return Some((Ok(event), (db, card_id, channel, entries, messages, 999)));
-            }
-
-            // 智能提取 JSON 部分（寻找最外层的 {}，忽略前后的废话）
-            let cleaned =
-                if let (Some(start), Some(end)) = (ai_content.find('{'), ai_content.rfind('}')) {
-                    if start <= end {
-                        &ai_content[start..=end]
-                    } else {
-                        ai_content.trim()
-                    }
-                } else {
-                    ai_content.trim()
-                };
-
-            // 解析 AI 响应
-            let ai_response: Value = match serde_json::from_str(cleaned) {
-                Ok(v) => v,
-                Err(_) => {
-                    // AI 返回了非 JSON，可能是直接的报告文本，尝试包装
-                    serde_json::json!({
-                        "action": "final_report",
-                        "report": {
-                            "core_assessment": ai_content,
-                            "dimensions": [],
-                            "prescriptions": [],
-                            "conclusion": "解析失败，请查看原始内容"
-                        }
-                    })
-                }
-            };
+            let ai_response = completion.value;
+            let ai_content = completion.content.as_str();
 
             let action = ai_response
                 .get("action")
@@ -1486,13 +1226,224 @@ to facilitate content moderation and auditing. Proceed with the analysis profess
         },
     );
 
-    Ok(Sse::new(stream).keep_alive(
+    Ok(Sse::new(stream.right_stream()).keep_alive(
         axum::response::sse::KeepAlive::new()
             .interval(Duration::from_secs(15))
             .text("keep-alive"),
     ))
 }
 
+/// 深度诊断流程状态
+struct DeepState {
+    db: DatabaseConnection,
+    card_id: Uuid,
+    channel: ai_channel::Model,
+    map_template: crate::services::prompt::ResolvedTemplate,
+    reduce_template: crate::services::prompt::ResolvedTemplate,
+    vars: crate::services::prompt::PromptVars,
+    chunks: Vec<crate::services::doctor::EntryChunk>,
+    entry_count: usize,
+    findings: Vec<Value>,
+    /// 无法解析而跳过的分组序号（从 1 开始）
+    skipped: Vec<usize>,
+    step: usize,
+    done: bool,
+}
+
+fn sse_event(progress: SseProgress) -> Event {
+    Event::default().data(serde_json::to_string(&progress).unwrap())
+}
+
+fn sse_error(message: String) -> Event {
+    sse_event(SseProgress {
+        status: "error".to_string(),
+        message,
+        report: None,
+        debug: None,
+    })
+}
+
+/// 深度诊断 (map-reduce)：每组条目一次调用，最后汇总为标准报告并附带 findings
+async fn doctor_deep_stream(
+    db: DatabaseConnection,
+    card: character_card::Model,
+    channel: ai_channel::Model,
+) -> Result<impl Stream<Item = Result<Event, Infallible>>, (StatusCode, Json<Value>)> {
+    let db_error = |e: sea_orm::DbErr| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": e.to_string()})),
+        )
+    };
+    let missing_template = |name: &str| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": format!("缺少提示词模板: {}", name)})),
+        )
+    };
+
+    let map_template = crate::services::prompt::resolve_template(&db, "doctor_deep_map")
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| missing_template("doctor_deep_map"))?;
+    let reduce_template = crate::services::prompt::resolve_template(&db, "doctor_deep_reduce")
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| missing_template("doctor_deep_reduce"))?;
+    let vars = crate::services::prompt::build_variables(&db, "doctor_deep_reduce", Some(&card))
+        .await
+        .map_err(db_error)?;
+
+    let card_json: Value = serde_json::from_str(&card.data).unwrap_or(serde_json::json!({}));
+    let chunks = crate::services::doctor::chunk_entries(
+        &card_json,
+        crate::services::doctor::DEEP_CHUNK_TOKENS,
+    );
+    let entry_count = chunks.iter().map(|c| c.entries.len()).sum();
+
+    let state = DeepState {
+        db,
+        card_id: card.id,
+        channel,
+        map_template,
+        reduce_template,
+        vars,
+        chunks,
+        entry_count,
+        findings: Vec::new(),
+        skipped: Vec::new(),
+        step: 0,
+        done: false,
+    };
+
+    Ok(stream::unfold(state, |mut state| async move {
+        if state.done {
+            return None;
+        }
+
+        let total = state.chunks.len();
+
+        // Map：逐组审查
+        if state.step < total {
+            let index = state.step;
+            let messages = crate::services::doctor::map_messages(
+                &state.map_template,
+                &state.vars,
+                &state.chunks[index],
+                index,
+                total,
+            );
+            let content =
+                match crate::services::ai::chat_completion(&state.channel, &messages, 0.3, false)
+                    .await
+                {
+                    Ok(c) => c,
+                    Err(e) => {
+                        state.done = true;
+                        return Some((Ok(sse_error(e.to_string())), state));
+                    }
+                };
+
+            let found = match crate::services::ai::extract_json(&content) {
+                Some(v) => crate::services::doctor::normalize_findings(&v, &state.chunks[index]),
+                None => {
+                    tracing::warn!(
+                        "Doctor deep chunk {} unparsable: {}",
+                        index + 1,
+                        content.chars().take(200).collect::<String>()
+                    );
+                    state.skipped.push(index + 1);
+                    Vec::new()
+                }
+            };
+            let found_count = found.len();
+            state.findings.extend(found);
+            state.step += 1;
+
+            let debug_info = serde_json::json!({
+                "chunk": index + 1,
+                "sent_messages": messages,
+                "ai_response": content
+            })
+            .to_string();
+            let event = sse_event(SseProgress {
+                status: "progress".to_string(),
+                message: format!(
+                    "正在审查世界书条目（第 {}/{} 组，{} 个条目），发现 {} 个问题",
+                    index + 1,
+                    total,
+                    state.chunks[index].entries.len(),
+                    found_count
+                ),
+                report: None,
+                debug: Some(debug_info),
+            });
+            return Some((Ok(event), state));
+        }
+
+        // Reduce：汇总报告
+        state.done = true;
+        let messages = crate::services::doctor::reduce_messages(
+            &state.reduce_template,
+            &state.vars,
+            &state.findings,
+            state.entry_count,
+        );
+        let content =
+            match crate::services::ai::chat_completion(&state.channel, &messages, 0.7, false).await
+            {
+                Ok(c) => c,
+                Err(e) => return Some((Ok(sse_error(e.to_string())), state)),
+            };
+
+        let parsed = crate::services::ai::extract_json(&content).unwrap_or_else(|| {
+            serde_json::json!({
+                "report": {
+                    "core_assessment": content,
+                    "dimensions": [],
+                    "prescriptions": [],
+                    "conclusion": "解析失败，请查看原始内容"
+                }
+            })
+        });
+        let mut report = parsed.get("report").cloned().unwrap_or(parsed);
+        if let Some(obj) = report.as_object_mut() {
+            obj.insert("mode".to_string(), Value::String("deep".to_string()));
+            obj.insert("findings".to_string(), Value::Array(state.findings.clone()));
+            obj.insert(
+                "entry_count".to_string(),
+                serde_json::json!(state.entry_count),
+            );
+            if !state.skipped.is_empty() {
+                obj.insert(
+                    "skipped_chunks".to_string(),
+                    serde_json::json!(state.skipped),
+                );
+            }
+        }
+
+        let _ = create_task_record(
+            &state.db,
+            state.card_id,
+            serde_json::to_string(&report).unwrap_or_default(),
+        )
+        .await;
+
+        let debug_info = serde_json::json!({
+            "sent_messages": messages,
+            "ai_response": content
+        })
+        .to_string();
+        let event = sse_event(SseProgress {
+            status: "complete".to_string(),
+            message: "诊断完成".to_string(),
+            report: Some(report),
+            debug: Some(debug_info),
+        });
+        Some((Ok(event), state))
+    }))
+}
+
 /// 创建成功的任务记录（只在成功时调用）
 async fn create_task_record(
     db: &DatabaseConnection,
@@ -1501,11 +1452,29 @@ async fn create_task_record(
 ) -> Result<(), sea_orm::DbErr> {
     let task_id = Uuid::new_v4();
     let now = chrono::Utc::now().naive_utc();
+
+    // 结构化字段：维度评分、结论，以及诊断时的角色卡版本
+    let structured = serde_json::from_str::<Value>(&report)
+        .map(|v| crate::services::doctor::parse_report(&v))
+        .ok();
+    let card = character_card::Entity::find_by_id(card_id).one(db).await?;
+
     let task_model = doctor_task::ActiveModel {
         id: Set(task_id),
         character_id: Set(card_id),
         status: Set("success".to_string()),
         final_report: Set(Some(report)),
+        dimensions: Set(structured
+            .as_ref()
+            .map(|s| serde_json::to_string(&s.dimensions).unwrap_or_default())),
+        overall_score: Set(structured.as_ref().and_then(|s| s.overall_score)),
+        conclusion: Set(structured
+            .as_ref()
+            .map(|s| s.conclusion.as_str().to_string())),
+        card_hash: Set(card
+            .as_ref()
+            .map(|c| crate::utils::hash::compute_json_hash(&c.data))),
+        card_version: Set(card.and_then(|c| c.version)),
         created_at: Set(now),
         updated_at: Set(now),
     };
@@ -1516,6 +1485,59 @@ async fn create_task_record(
     Ok(())
 }
 
+/// 旧记录没有结构化字段时，从 final_report 解析并回填
+async fn ensure_structured(
+    db: &DatabaseConnection,
+    task: doctor_task::Model,
+) -> doctor_task::Model {
+    if task.dimensions.is_some() {
+        return task;
+    }
+    let Some(report) = task
+        .final_report
+        .as_deref()
+        .and_then(|r| serde_json::from_str::<Value>(r).ok())
+    else {
+        return task;
+    };
+
+    let structured = crate::services::doctor::parse_report(&report);
+    let mut active: doctor_task::ActiveModel = task.clone().into();
+    active.dimensions = Set(Some(
+        serde_json::to_string(&structured.dimensions).unwrap_or_default(),
+    ));
+    active.overall_score = Set(structured.overall_score);
+    active.conclusion = Set(Some(structured.conclusion.as_str().to_string()));
+    match active.update(db).await {
+        Ok(updated) => updated,
+        Err(e) => {
+            tracing::warn!("Backfill doctor task {} failed: {}", task.id, e);
+            task
+        }
+    }
+}
+
+fn task_dimensions(task: &doctor_task::Model) -> Vec<crate::services::doctor::DimensionScore> {
+    task.dimensions
+        .as_deref()
+        .and_then(|d| serde_json::from_str(d).ok())
+        .unwrap_or_default()
+}
+
+fn history_item(task: doctor_task::Model) -> DoctorHistoryItem {
+    DoctorHistoryItem {
+        id: task.id,
+        dimensions: task_dimensions(&task),
+        status: task.status,
+        final_report: task.final_report,
+        overall_score: task.overall_score,
+        conclusion: task.conclusion,
+        card_hash: task.card_hash,
+        card_version: task.card_version,
+        created_at: task.created_at.format("%Y-%m-%d %H:%M").to_string(),
+    }
+}
+
 /// GET /api/ai/doctor/history/{card_id} - 获取诊断历史
 pub async fn doctor_history(
     State(db): State<DatabaseConnection>,
@@ -1533,17 +1555,142 @@ pub async fn doctor_history(
             )
         })?;
 
-    let items: Vec<DoctorHistoryItem> = tasks
-        .into_iter()
-        .map(|t| DoctorHistoryItem {
+    let mut items: Vec<DoctorHistoryItem> = Vec::with_capacity(tasks.len());
+    for t in tasks {
+        items.push(history_item(ensure_structured(&db, t).await));
+    }
+
+    Ok(Json(items))
+}
+
+#[derive(Deserialize)]
+pub struct DoctorCompareQuery {
+    /// 较早的诊断记录
+    pub from: Uuid,
+    /// 较新的诊断记录
+    pub to: Uuid,
+}
+
+#[derive(Serialize)]
+pub struct DoctorCompareResponse {
+    pub from: DoctorHistoryItem,
+    pub to: DoctorHistoryItem,
+    /// 两次诊断之间角色卡是否有修改
+    pub card_changed: Option<bool>,
+    pub overall_delta: Option<f64>,
+    pub dimensions: Vec<crate::services::doctor::DimensionDelta>,
+    /// 深度诊断的条目问题对比（两次都为深度诊断时提供）
+    pub findings: Option<crate::services::doctor::FindingsDelta>,
+}
+
+/// GET /api/ai/doctor/compare?from=&to= - 对比同一角色卡的两次诊断
+pub async fn doctor_compare(
+    State(db): State<DatabaseConnection>,
+    axum::extract::Query(query): axum::extract::Query<DoctorCompareQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let mut tasks = Vec::with_capacity(2);
+    for id in [query.from, query.to] {
+        let task = doctor_task::Entity::find_by_id(id)
+            .one(&db)
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(serde_json::json!({"error": e.to_string()})),
+                )
+            })?
+            .ok_or_else(|| {
+                (
+                    StatusCode::NOT_FOUND,
+                    Json(serde_json::json!({"error": "诊断记录不存在"})),
+                )
+            })?;
+        tasks.push(ensure_structured(&db, task).await);
+    }
+    let to = tasks.pop().unwrap();
+    let from = tasks.pop().unwrap();
+
+    if from.character_id != to.character_id {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "只能对比同一角色卡的诊断记录"})),
+        ));
+    }
+
+    let report_of = |t: &doctor_task::Model| {
+        t.final_report
+            .as_deref()
+            .and_then(|r| serde_json::from_str::<Value>(r).ok())
+            .unwrap_or(Value::Null)
+    };
+    let findings = crate::services::doctor::compare_findings(&report_of(&from), &report_of(&to));
+    let dimensions =
+        crate::services::doctor::compare_dimensions(&task_dimensions(&from), &task_dimensions(&to));
+    let card_changed = match (&from.card_hash, &to.card_hash) {
+        (Some(a), Some(b)) => Some(a != b),
+        _ => None,
+    };
+    let overall_delta = match (from.overall_score, to.overall_score) {
+        (Some(a), Some(b)) => Some(b - a),
+        _ => None,
+    };
+
+    Ok(Json(DoctorCompareResponse {
+        from: history_item(from),
+        to: history_item(to),
+        card_changed,
+        overall_delta,
+        dimensions,
+        findings,
+    }))
+}
+
+#[derive(Serialize)]
+pub struct DoctorTrendPoint {
+    pub id: Uuid,
+    pub created_at: String,
+    pub overall_score: Option<f64>,
+    pub conclusion: Option<String>,
+    pub card_version: Option<String>,
+    /// 维度名称 -> 分数
+    pub scores: std::collections::BTreeMap<String, Option<f64>>,
+}
+
+/// GET /api/ai/doctor/trend/{card_id} - 评分趋势（按时间升序）
+pub async fn doctor_trend(
+    State(db): State<DatabaseConnection>,
+    Path(card_id): Path<Uuid>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let tasks = doctor_task::Entity::find()
+        .filter(doctor_task::Column::CharacterId.eq(card_id))
+        .filter(doctor_task::Column::Status.eq("success"))
+        .order_by_asc(doctor_task::Column::CreatedAt)
+        .all(&db)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?;
+
+    let mut points = Vec::with_capacity(tasks.len());
+    for t in tasks {
+        let t = ensure_structured(&db, t).await;
+        points.push(DoctorTrendPoint {
             id: t.id,
-            status: t.status,
-            final_report: t.final_report,
             created_at: t.created_at.format("%Y-%m-%d %H:%M").to_string(),
-        })
-        .collect();
+            scores: task_dimensions(&t)
+                .into_iter()
+                .map(|d| (d.name, d.score))
+                .collect(),
+            overall_score: t.overall_score,
+            conclusion: t.conclusion,
+            card_version: t.card_version,
+        });
+    }
 
-    Ok(Json(items))
+    Ok(Json(points))
 }
 
 /// DELETE /api/ai/doctor/history/{id} - 删除诊断记录
@@ -1570,3 +1717,250 @@ pub async fn doctor_history_delete(
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+// ==================== 小皮医生处方落实 ====================
+
+use crate::utils::json_patch::{self, PatchDiff, PatchOp};
+
+#[derive(Deserialize)]
+pub struct DoctorPrescribeRequest {
+    pub card_id: Uuid,
+    /// 选中的处方（来自诊断报告的 prescriptions）
+    pub prescriptions: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct RejectedPatchOp {
+    pub op: Value,
+    pub error: String,
+}
+
+#[derive(Serialize)]
+pub struct DoctorPrescribeResponse {
+    pub patch: Vec<PatchOp>,
+    pub diff: Vec<PatchDiff>,
+    /// 模型给出但无法应用的修改
+    pub rejected: Vec<RejectedPatchOp>,
+    /// 生成补丁时角色卡数据的哈希，应用时用于检测冲突
+    pub card_hash: String,
+}
+
+#[derive(Deserialize)]
+pub struct DoctorApplyRequest {
+    pub card_id: Uuid,
+    pub patch: Vec<PatchOp>,
+    pub card_hash: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct DoctorApplyResponse {
+    pub card: character_card::Model,
+    pub version_id: Uuid,
+    pub version_number: String,
+    pub diff: Vec<PatchDiff>,
+}
+
+fn ai_error(e: crate::services::ai::AiError) -> (StatusCode, Json<Value>) {
+    (
+        e.status_code(),
+        Json(serde_json::json!({"error": e.to_string()})),
+    )
+}
+
+async fn find_card(
+    db: &DatabaseConnection,
+    card_id: Uuid,
+) -> Result<character_card::Model, (StatusCode, Json<Value>)> {
+    character_card::Entity::find_by_id(card_id)
+        .one(db)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "角色卡不存在"})),
+            )
+        })
+}
+
+/// POST /api/ai/doctor/prescribe - 将处方转换为待审核的字段补丁
+pub async fn doctor_prescribe(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<DoctorPrescribeRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let prescriptions: Vec<&str> = payload
+        .prescriptions
+        .iter()
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .collect();
+    if prescriptions.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "请至少选择一条处方"})),
+        ));
+    }
+
+    let card = find_card(&db, payload.card_id).await?;
+    let card_json: Value = serde_json::from_str(&card.data).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": format!("角色卡数据解析失败: {}", e)})),
+        )
+    })?;
+
+    let channel = crate::services::ai::resolve_global_channel(&db)
+        .await
+        .map_err(ai_error)?;
+
+    let prescription_list = prescriptions
+        .iter()
+        .enumerate()
+        .map(|(i, p)| format!("{}. {}", i + 1, p))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut extra = crate::services::prompt::PromptVars::new();
+    extra.insert("prescriptions".into(), prescription_list);
+    let messages = render_feature_messages(&db, "doctor_prescribe", Some(card.id), extra).await?;
+
+    let content = crate::services::ai::chat_completion(&channel, &messages, 0.7, true)
+        .await
+        .map_err(ai_error)?;
+
+    let parsed = crate::services::ai::extract_json(&content).ok_or_else(|| {
+        (
+            StatusCode::BAD_GATEWAY,
+            Json(serde_json::json!({
+                "error": "AI 返回的内容不是有效的 JSON",
+                "raw": content.chars().take(500).collect::<String>()
+            })),
+        )
+    })?;
+    let raw_ops = parsed
+        .get("patch")
+        .and_then(|p| p.as_array())
+        .cloned()
+        .unwrap_or_default();
+
+    // 逐条校验：路径必须可编辑且能在当前数据上成功应用
+    let mut working = card_json;
+    let mut patch = Vec::new();
+    let mut diff = Vec::new();
+    let mut rejected = Vec::new();
+    for raw in raw_ops {
+        let op: PatchOp = match serde_json::from_value(raw.clone()) {
+            Ok(op) => op,
+            Err(e) => {
+                rejected.push(RejectedPatchOp {
+                    op: raw,
+                    error: format!("格式错误: {}", e),
+                });
+                continue;
+            }
+        };
+        if !crate::services::card::is_editable_path(&working, &op.path) {
+            rejected.push(RejectedPatchOp {
+                op: raw,
+                error: "路径不在可编辑字段范围内".to_string(),
+            });
+            continue;
+        }
+        match json_patch::apply_op(&mut working, &op) {
+            Ok(before) => {
+                diff.push(PatchDiff {
+                    op: op.op.clone(),
+                    path: op.path.clone(),
+                    before,
+                    after: op.value.clone(),
+                    reason: op.reason.clone(),
+                });
+                patch.push(op);
+            }
+            Err(error) => rejected.push(RejectedPatchOp { op: raw, error }),
+        }
+    }
+
+    Ok(Json(DoctorPrescribeResponse {
+        patch,
+        diff,
+        rejected,
+        card_hash: crate::utils::hash::compute_json_hash(&card.data),
+    }))
+}
+
+/// POST /api/ai/doctor/prescribe/apply - 应用补丁（写入前自动创建版本快照）
+pub async fn doctor_prescribe_apply(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<DoctorApplyRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    if payload.patch.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "补丁为空"})),
+        ));
+    }
+
+    let card = find_card(&db, payload.card_id).await?;
+    if let Some(hash) = &payload.card_hash {
+        if *hash != crate::utils::hash::compute_json_hash(&card.data) {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(serde_json::json!({"error": "角色卡在生成补丁后已被修改，请重新生成"})),
+            ));
+        }
+    }
+
+    let card_json: Value = serde_json::from_str(&card.data).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": format!("角色卡数据解析失败: {}", e)})),
+        )
+    })?;
+    if let Some(op) = payload
+        .patch
+        .iter()
+        .find(|op| !crate::services::card::is_editable_path(&card_json, &op.path))
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": format!("路径不在可编辑字段范围内: {}", op.path)})),
+        ));
+    }
+
+    let (new_json, diff) = json_patch::apply_patch(&card_json, &payload.patch).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": e})),
+        )
+    })?;
+
+    let version = crate::services::card::snapshot_version(&db, &card, "小皮医生处方应用前自动快照")
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?;
+
+    let updated = crate::services::card::write_card_json(&db, &card, new_json)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?;
+
+    Ok(Json(DoctorApplyResponse {
+        card: updated,
+        version_id: version.id,
+        version_number: version.version_number,
+        diff,
+    }))
+}
@@ -0,0 +1,1027 @@
+//! AI 批量任务 API
+//!
+//! - 批量生成概览/标签：后台任务运行，产出进入审核队列
+//! - 审核队列：用户逐条或批量接受/拒绝建议，接受后才写入角色卡
+//! - 角色卡翻译：后台任务运行，结果另存为新角色卡或新版本
+//! - 聊天记录总结：分段滚动总结并提取记忆，可导出为世界书或作者注释
+
+use crate::entities::{ai_channel, ai_suggestion, character_card, chat_history, world_info};
+use crate::services::st_regex::{ApplyContext, RunMode, ScriptSet};
+use crate::services::{chat_memory, jobs, prompt, st_chat};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use futures::StreamExt;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn internal_error(e: impl std::fmt::Display) -> ApiError {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(serde_json::json!({"error": e.to_string()})),
+    )
+}
+
+/// 默认并发数与上限
+const DEFAULT_CONCURRENCY: usize = 3;
+const MAX_CONCURRENCY: usize = 8;
+
+#[derive(Deserialize)]
+pub struct BatchOverviewRequest {
+    /// 仅处理该分类
+    pub category_id: Option<Uuid>,
+    /// 指定角色卡（与其他条件取交集）
+    pub card_ids: Option<Vec<Uuid>>,
+    /// 仅处理没有标签的角色卡
+    #[serde(default)]
+    pub untagged_only: bool,
+    /// 仅处理没有概览的角色卡
+    #[serde(default)]
+    pub missing_summary_only: bool,
+    pub concurrency: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct BatchStartResponse {
+    pub job_id: Uuid,
+    pub total: usize,
+}
+
+/// POST /api/ai/batch/overview - 批量生成概览与标签（结果进入审核队列）
+pub async fn start_batch_overview(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<BatchOverviewRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let channel = crate::services::ai::resolve_global_channel(&db)
+        .await
+        .map_err(|e| {
+            (
+                e.status_code(),
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?;
+    let template = prompt::resolve_template(&db, "overview")
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| internal_error("缺少概览提示词模板"))?;
+
+    let mut query =
+        character_card::Entity::find().filter(character_card::Column::DeletedAt.is_null());
+    if let Some(category_id) = payload.category_id {
+        query = query.filter(character_card::Column::CategoryId.eq(category_id));
+    }
+    if let Some(ids) = payload.card_ids {
+        query = query.filter(character_card::Column::Id.is_in(ids));
+    }
+    let cards: Vec<character_card::Model> = query
+        .order_by_asc(character_card::Column::CreatedAt)
+        .all(&db)
+        .await
+        .map_err(internal_error)?
+        .into_iter()
+        .filter(|c| {
+            !payload.untagged_only
+                || serde_json::from_str::<Vec<String>>(&c.tags)
+                    .unwrap_or_default()
+                    .is_empty()
+        })
+        .filter(|c| {
+            !payload.missing_summary_only
+                || c.custom_summary
+                    .as_deref()
+                    .map(|s| s.trim().is_empty())
+                    .unwrap_or(true)
+        })
+        .collect();
+
+    if cards.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "没有符合条件的角色卡"})),
+        ));
+    }
+
+    // 标签库与全局提示词每个任务只加载一次
+    let vocabulary = prompt::tag_vocabulary(&db).await.map_err(internal_error)?;
+    let global_prompt = prompt::global_prompt(&db).await.map_err(internal_error)?;
+
+    let total = cards.len();
+    let job_id = jobs::create("batch_overview", total);
+    let concurrency = payload
+        .concurrency
+        .unwrap_or(DEFAULT_CONCURRENCY)
+        .clamp(1, MAX_CONCURRENCY);
+
+    let ctx = std::sync::Arc::new(OverviewJobContext {
+        db,
+        channel,
+        template,
+        vocabulary,
+        global_prompt,
+        job_id,
+    });
+    tokio::spawn(async move {
+        futures::stream::iter(cards)
+            .map(|card| {
+                let ctx = ctx.clone();
+                async move {
+                    if jobs::is_cancelled(ctx.job_id) {
+                        return;
+                    }
+                    let name = card.name.clone();
+                    match overview_one(&ctx, card).await {
+                        Ok(()) => jobs::item_done(ctx.job_id),
+                        Err(e) => jobs::item_failed(ctx.job_id, format!("{}: {}", name, e)),
+                    }
+                }
+            })
+            .buffer_unordered(concurrency)
+            .collect::<Vec<()>>()
+            .await;
+
+        let info = jobs::get(job_id);
+        let (completed, failed) = info.map(|j| (j.completed, j.failed)).unwrap_or((0, 0));
+        jobs::finish(
+            job_id,
+            format!(
+                "完成 {} 个，失败 {} 个，请在审核队列中确认",
+                completed, failed
+            ),
+        );
+    });
+
+    Ok(Json(BatchStartResponse { job_id, total }))
+}
+
+struct OverviewJobContext {
+    db: DatabaseConnection,
+    channel: ai_channel::Model,
+    template: prompt::ResolvedTemplate,
+    vocabulary: String,
+    global_prompt: String,
+    job_id: Uuid,
+}
+
+/// 为单张角色卡生成概览建议
+async fn overview_one(ctx: &OverviewJobContext, card: character_card::Model) -> Result<(), String> {
+    let mut vars = prompt::card_variables(&card);
+    vars.insert("global_prompt".into(), ctx.global_prompt.clone());
+    prompt::overview_variables(&mut vars, &card, &ctx.vocabulary);
+    let generate_tags = vars
+        .get("generate_tags")
+        .map(|v| !v.is_empty())
+        .unwrap_or(false);
+
+    let messages = prompt::render_messages(&ctx.template, &vars);
+    let parsed = crate::services::structured::complete_json(
+        &ctx.channel,
+        &messages,
+        serde_json::json!({"temperature": 1.0}),
+        &crate::services::structured::OVERVIEW_SCHEMA,
+        1,
+    )
+    .await
+    .map_err(|e| e.to_string())?
+    .value;
+
+    // 结构已校验，summary 必为非空字符串
+    let summary = parsed["summary"].as_str().unwrap_or_default();
+    let tags: Option<Vec<String>> = if generate_tags {
+        parsed
+            .get("tags")
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+    } else {
+        None
+    };
+
+    let current_tags: Vec<String> = serde_json::from_str(&card.tags).unwrap_or_default();
+    let suggestion = serde_json::json!({
+        "summary": summary,
+        "tags": tags,
+        "previous_summary": card.custom_summary,
+        "previous_tags": current_tags,
+    });
+    save_suggestion(&ctx.db, Some(ctx.job_id), card.id, "overview", suggestion)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 写入待审核建议（同一角色卡同一功能只保留最新的待审核建议）
+pub(crate) async fn save_suggestion(
+    db: &DatabaseConnection,
+    job_id: Option<Uuid>,
+    card_id: Uuid,
+    feature_id: &str,
+    payload: Value,
+) -> Result<(), sea_orm::DbErr> {
+    ai_suggestion::Entity::delete_many()
+        .filter(ai_suggestion::Column::CardId.eq(card_id))
+        .filter(ai_suggestion::Column::FeatureId.eq(feature_id))
+        .filter(ai_suggestion::Column::Status.eq("pending"))
+        .exec(db)
+        .await?;
+
+    ai_suggestion::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        job_id: Set(job_id),
+        card_id: Set(card_id),
+        feature_id: Set(feature_id.to_string()),
+        payload: Set(payload.to_string()),
+        status: Set("pending".to_string()),
+        created_at: Set(chrono::Utc::now().naive_utc()),
+        reviewed_at: Set(None),
+    }
+    .insert(db)
+    .await?;
+    Ok(())
+}
+
+// ==================== 角色卡翻译 ====================
+
+#[derive(Deserialize)]
+pub struct TranslateCardRequest {
+    pub card_id: Uuid,
+    /// 目标语言，如 "简体中文"、"English"、"日本語"
+    pub target_language: String,
+    /// "new_card"：另存为新角色卡；"version"：写回原卡并创建版本快照
+    pub output: String,
+    #[serde(default = "default_true")]
+    pub include_worldbook: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// POST /api/ai/translate/card - 翻译整张角色卡（后台任务）
+pub async fn start_translate_card(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<TranslateCardRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let target_language = payload.target_language.trim().to_string();
+    if target_language.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "请指定目标语言"})),
+        ));
+    }
+    let as_new_card = match payload.output.as_str() {
+        "new_card" => true,
+        "version" => false,
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": "output 只能是 new_card 或 version"})),
+            ))
+        }
+    };
+
+    let channel = crate::services::ai::resolve_global_channel(&db)
+        .await
+        .map_err(|e| {
+            (
+                e.status_code(),
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?;
+    let template = prompt::resolve_template(&db, "translate")
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| internal_error("缺少翻译提示词模板"))?;
+    let global_prompt = prompt::global_prompt(&db).await.map_err(internal_error)?;
+
+    let card = character_card::Entity::find_by_id(payload.card_id)
+        .one(&db)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "角色卡不存在"})),
+            )
+        })?;
+    let card_json: Value = serde_json::from_str(&card.data).map_err(internal_error)?;
+
+    let segments =
+        crate::services::translate::collect_segments(&card_json, payload.include_worldbook);
+    if segments.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "没有需要翻译的内容"})),
+        ));
+    }
+    let chunks = crate::services::translate::chunk_segments(
+        segments,
+        crate::services::translate::TRANSLATE_CHUNK_TOKENS,
+    );
+
+    let total = chunks.len();
+    let job_id = jobs::create("translate_card", total);
+
+    tokio::spawn(async move {
+        let mut ops = Vec::new();
+        let mut warnings = Vec::new();
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            if jobs::is_cancelled(job_id) {
+                jobs::finish(job_id, "已取消，未写入任何修改");
+                return;
+            }
+            jobs::set_message(job_id, format!("正在翻译第 {}/{} 组", i + 1, total));
+
+            let messages = crate::services::translate::chunk_messages(
+                &template,
+                &global_prompt,
+                &target_language,
+                chunk,
+            );
+            let result = crate::services::ai::chat_completion(&channel, &messages, 0.3, true)
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|content| {
+                    crate::services::ai::extract_json(&content)
+                        .ok_or_else(|| "无法解析 AI 返回的 JSON".to_string())
+                });
+            match result {
+                Ok(response) => {
+                    let (chunk_ops, chunk_warnings) =
+                        crate::services::translate::chunk_patch(&response, chunk);
+                    ops.extend(chunk_ops);
+                    warnings.extend(chunk_warnings);
+                    jobs::item_done(job_id);
+                }
+                Err(e) => {
+                    warnings.extend(chunk.iter().map(|s| format!("{}: {}，保留原文", s.path, e)));
+                    jobs::item_failed(job_id, format!("第 {} 组: {}", i + 1, e));
+                }
+            }
+        }
+
+        if ops.is_empty() {
+            jobs::fail(job_id, "所有片段翻译失败，未写入任何修改");
+            return;
+        }
+
+        // 翻译期间角色卡可能被编辑：写入前重新读取，只替换原文未改动的片段
+        let card = match character_card::Entity::find_by_id(card.id).one(&db).await {
+            Ok(Some(card)) => card,
+            Ok(None) => {
+                jobs::fail(job_id, "角色卡已删除，未写入任何修改");
+                return;
+            }
+            Err(e) => {
+                jobs::fail(job_id, format!("读取角色卡失败: {}", e));
+                return;
+            }
+        };
+        let current_json: Value = match serde_json::from_str(&card.data) {
+            Ok(json) => json,
+            Err(e) => {
+                jobs::fail(job_id, format!("角色卡数据无效: {}", e));
+                return;
+            }
+        };
+        ops.retain(|op| {
+            let unchanged = current_json.pointer(&op.path) == card_json.pointer(&op.path);
+            if !unchanged {
+                warnings.push(format!("{}: 翻译期间已被修改，保留当前内容", op.path));
+            }
+            unchanged
+        });
+        if ops.is_empty() {
+            jobs::fail(job_id, "翻译期间所有片段均已被修改，未写入任何修改");
+            return;
+        }
+
+        let (new_json, _) = match crate::utils::json_patch::apply_patch(&current_json, &ops) {
+            Ok(r) => r,
+            Err(e) => {
+                jobs::fail(job_id, e);
+                return;
+            }
+        };
+
+        let outcome = if as_new_card {
+            crate::services::card::create_card_copy(&db, &card, new_json)
+                .await
+                .map(|id| serde_json::json!({"card_id": id}))
+        } else {
+            let note = format!("翻译为{}前自动快照", target_language);
+            match crate::services::card::snapshot_version(&db, &card, &note).await {
+                Ok(version) => crate::services::card::write_card_json(&db, &card, new_json)
+                    .await
+                    .map(|_| {
+                        serde_json::json!({
+                            "card_id": card.id,
+                            "version_id": version.id,
+                            "version_number": version.version_number,
+                        })
+                    })
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            }
+        };
+
+        match outcome {
+            Ok(mut result) => {
+                result["translated"] = serde_json::json!(ops.len());
+                result["warnings"] = serde_json::json!(warnings);
+                jobs::set_result(job_id, result);
+                jobs::finish(
+                    job_id,
+                    format!("翻译完成：{} 个片段，{} 条警告", ops.len(), warnings.len()),
+                );
+            }
+            Err(e) => jobs::fail(job_id, format!("保存失败: {}", e)),
+        }
+    });
+
+    Ok(Json(BatchStartResponse { job_id, total }))
+}
+
+// ==================== 聊天记录总结 ====================
+
+#[derive(Deserialize, Default)]
+pub struct SummarizeHistoryRequest {
+    /// 仅总结上次之后的新楼层（默认 true）；false 时从头重新总结
+    #[serde(default = "default_true")]
+    pub incremental: bool,
+    /// 每段 Token 预算
+    pub chunk_tokens: Option<usize>,
+}
+
+async fn find_history(db: &DatabaseConnection, id: Uuid) -> Result<chat_history::Model, ApiError> {
+    chat_history::Entity::find_by_id(id)
+        .one(db)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "聊天记录不存在"})),
+            )
+        })
+}
+
+/// 保存总结进度（每段完成后写入，中断后可增量续写）
+async fn save_memory(
+    db: &DatabaseConnection,
+    history: &chat_history::Model,
+    summary: &str,
+    memory: &chat_memory::ChatMemory,
+) -> Result<(), sea_orm::DbErr> {
+    let mut active: chat_history::ActiveModel = history.clone().into();
+    active.summary = Set(Some(summary.to_string()));
+    active.memory = Set(Some(
+        serde_json::to_string(memory).unwrap_or_else(|_| "{}".to_string()),
+    ));
+    active.summarized_at = Set(Some(chrono::Utc::now().naive_utc()));
+    active.update(db).await?;
+    Ok(())
+}
+
+/// POST /api/ai/history/{id}/summarize - 分段滚动总结聊天记录（后台任务）
+pub async fn start_summarize_history(
+    State(db): State<DatabaseConnection>,
+    Path(id): Path<Uuid>,
+    payload: Option<Json<SummarizeHistoryRequest>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let payload = payload.map(|Json(p)| p).unwrap_or_default();
+    let history = find_history(&db, id).await?;
+
+    let channel = crate::services::ai::resolve_global_channel(&db)
+        .await
+        .map_err(|e| {
+            (
+                e.status_code(),
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?;
+    let template = prompt::resolve_template(&db, "chat_summary")
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| internal_error("缺少聊天记录总结提示词模板"))?;
+    let card = character_card::Entity::find_by_id(history.card_id)
+        .one(&db)
+        .await
+        .map_err(internal_error)?;
+    let base_vars = prompt::build_variables(&db, "chat_summary", card.as_ref())
+        .await
+        .map_err(internal_error)?;
+
+    let path = crate::api::history::history_file_path(history.card_id, &history.file_name);
+    let content = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| internal_error(format!("读取聊天记录失败: {}", e)))?;
+    let is_jsonl = history.format == "jsonl" || history.file_name.ends_with(".jsonl");
+    let mut floors = crate::services::chat_floor::parse_floors(&content, is_jsonl);
+
+    // 与发送给模型时一致：执行提示词场景的正则脚本（编译与匹配耗时，放到阻塞线程）
+    let user_name = st_chat::header_user_name(&content).unwrap_or_else(|| "User".to_string());
+    let (script_history, script_card) = (history.clone(), card.clone());
+    floors = tokio::task::spawn_blocking(move || {
+        let scripts = ScriptSet::for_history(&script_history, script_card.as_ref(), &user_name);
+        if !scripts.is_empty() {
+            let total = floors.len();
+            for (index, floor) in floors.iter_mut().enumerate() {
+                let is_user = floor.meta.as_ref().map(|m| m.is_user);
+                let ctx = ApplyContext::for_message(RunMode::Prompt, is_user, index, total);
+                floor.content = scripts.apply(&floor.content, &ctx);
+            }
+        }
+        floors
+    })
+    .await
+    .map_err(internal_error)?;
+
+    // 增量模式从上次总结的楼层之后继续
+    let (mut summary, mut memory) = if payload.incremental {
+        (
+            history.summary.clone().unwrap_or_default(),
+            chat_memory::ChatMemory::from_json(history.memory.as_deref()),
+        )
+    } else {
+        (String::new(), chat_memory::ChatMemory::default())
+    };
+    let pending: Vec<_> = floors
+        .into_iter()
+        .filter(|f| f.floor > memory.last_floor)
+        .collect();
+    let budget = payload
+        .chunk_tokens
+        .unwrap_or(chat_memory::SUMMARY_CHUNK_TOKENS)
+        .clamp(1000, 32000);
+    let chunks = chat_memory::chunk_floors(&pending, budget);
+    if chunks.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "没有需要总结的新楼层"})),
+        ));
+    }
+
+    let total = chunks.len();
+    let job_id = jobs::create("summarize_history", total);
+
+    tokio::spawn(async move {
+        for (i, chunk) in chunks.iter().enumerate() {
+            if jobs::is_cancelled(job_id) {
+                jobs::finish(job_id, format!("已取消，已总结至 #{}", memory.last_floor));
+                return;
+            }
+            jobs::set_message(
+                job_id,
+                format!(
+                    "正在总结第 {}/{} 段（#{} - #{}）",
+                    i + 1,
+                    total,
+                    chunk.start_floor,
+                    chunk.end_floor
+                ),
+            );
+
+            let messages = chat_memory::chunk_messages(
+                &template, &base_vars, &summary, &memory, chunk, i, total,
+            );
+            let result = crate::services::ai::chat_completion(&channel, &messages, 0.3, true)
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|content| {
+                    crate::services::ai::extract_json(&content)
+                        .ok_or_else(|| "无法解析 AI 返回的 JSON".to_string())
+                });
+
+            // 滚动总结依赖上一段结果，失败即停止，已完成部分保留
+            let output = match result {
+                Ok(output) => output,
+                Err(e) => {
+                    jobs::item_failed(job_id, format!("第 {} 段: {}", i + 1, e));
+                    jobs::fail(
+                        job_id,
+                        format!("总结中断，已保存至 #{}，可稍后继续", memory.last_floor),
+                    );
+                    return;
+                }
+            };
+            chat_memory::merge_chunk(&mut summary, &mut memory, &output, chunk);
+            if let Err(e) = save_memory(&db, &history, &summary, &memory).await {
+                jobs::fail(job_id, format!("保存总结失败: {}", e));
+                return;
+            }
+            jobs::item_done(job_id);
+        }
+
+        jobs::set_result(
+            job_id,
+            serde_json::json!({
+                "history_id": history.id,
+                "last_floor": memory.last_floor,
+                "events": memory.events.len(),
+                "facts": memory.facts.len(),
+            }),
+        );
+        jobs::finish(
+            job_id,
+            format!(
+                "总结完成：至 #{}，{} 个事件，{} 条设定",
+                memory.last_floor,
+                memory.events.len(),
+                memory.facts.len()
+            ),
+        );
+    });
+
+    Ok(Json(BatchStartResponse { job_id, total }))
+}
+
+#[derive(Serialize)]
+pub struct HistoryMemoryResponse {
+    pub history_id: Uuid,
+    pub summary: Option<String>,
+    pub memory: chat_memory::ChatMemory,
+    pub summarized_at: Option<String>,
+}
+
+/// GET /api/ai/history/{id}/memory - 读取总结与记忆
+pub async fn get_history_memory(
+    State(db): State<DatabaseConnection>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let history = find_history(&db, id).await?;
+    Ok(Json(HistoryMemoryResponse {
+        history_id: history.id,
+        memory: chat_memory::ChatMemory::from_json(history.memory.as_deref()),
+        summary: history.summary,
+        summarized_at: history.summarized_at.map(|t| t.and_utc().to_rfc3339()),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ExportMemoryRequest {
+    /// "world_info"：新建世界书；"card"：追加到角色卡世界书（自动快照）；"authors_note"：作者注释草稿
+    pub target: String,
+    /// 新建世界书的名称
+    pub name: Option<String>,
+}
+
+/// POST /api/ai/history/{id}/memory/export - 导出记忆
+pub async fn export_history_memory(
+    State(db): State<DatabaseConnection>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<ExportMemoryRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let history = find_history(&db, id).await?;
+    if history.summarized_at.is_none() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "该聊天记录尚未总结"})),
+        ));
+    }
+    let summary = history.summary.clone().unwrap_or_default();
+    let memory = chat_memory::ChatMemory::from_json(history.memory.as_deref());
+
+    match payload.target.as_str() {
+        "authors_note" => Ok(Json(serde_json::json!({
+            "note": chat_memory::authors_note(&summary, &memory)
+        }))),
+        "world_info" => {
+            if memory.facts.is_empty() && memory.relationships.is_empty() {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({"error": "没有可导出的设定或人物关系"})),
+                ));
+            }
+            let name = payload
+                .name
+                .filter(|n| !n.trim().is_empty())
+                .unwrap_or_else(|| format!("{} 记忆", history.display_name));
+            let data = chat_memory::world_info_json(&memory);
+            let now = chrono::Utc::now().naive_utc();
+            let saved = world_info::ActiveModel {
+                id: Set(Uuid::new_v4()),
+                name: Set(name),
+                data: Set(serde_json::to_string_pretty(&data).map_err(internal_error)?),
+                created_at: Set(now),
+                updated_at: Set(now),
+            }
+            .insert(&db)
+            .await
+            .map_err(internal_error)?;
+
+            crate::api::dashboard::invalidate_cache();
+            crate::services::embedding::schedule(
+                &db,
+                crate::services::embedding::Source::WorldInfo(saved.id),
+            );
+            Ok(Json(serde_json::json!({"world_info_id": saved.id})))
+        }
+        "card" => {
+            let card = character_card::Entity::find_by_id(history.card_id)
+                .one(&db)
+                .await
+                .map_err(internal_error)?
+                .ok_or_else(|| {
+                    (
+                        StatusCode::NOT_FOUND,
+                        Json(serde_json::json!({"error": "角色卡不存在"})),
+                    )
+                })?;
+            let mut json: Value = serde_json::from_str(&card.data).map_err(internal_error)?;
+            let prefix = crate::services::card::field_prefix(&json);
+            let book_path = format!("{}/character_book", prefix);
+
+            if json
+                .pointer(&book_path)
+                .map(|b| !b.is_object())
+                .unwrap_or(true)
+            {
+                let book = serde_json::json!({"name": card.name, "entries": [], "extensions": {}});
+                match prefix {
+                    "" => json["character_book"] = book,
+                    _ => json["data"]["character_book"] = book,
+                }
+            }
+            let book = json.pointer_mut(&book_path).unwrap();
+            if !book["entries"].is_array() {
+                book["entries"] = serde_json::json!([]);
+            }
+            let entries = book["entries"].as_array_mut().unwrap();
+            let start_id = entries
+                .iter()
+                .filter_map(|e| e.get("id").and_then(|v| v.as_i64()))
+                .max()
+                .map(|m| m + 1)
+                .unwrap_or(entries.len() as i64);
+            let new_entries = chat_memory::character_book_entries(&memory, start_id);
+            if new_entries.is_empty() {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({"error": "没有可导出的设定或人物关系"})),
+                ));
+            }
+            let added = new_entries.len();
+            entries.extend(new_entries);
+
+            let note = format!("导入聊天记忆（{}）前自动快照", history.display_name);
+            let version = crate::services::card::snapshot_version(&db, &card, &note)
+                .await
+                .map_err(internal_error)?;
+            crate::services::card::write_card_json(&db, &card, json)
+                .await
+                .map_err(internal_error)?;
+
+            Ok(Json(serde_json::json!({
+                "card_id": card.id,
+                "added": added,
+                "version_id": version.id,
+                "version_number": version.version_number,
+            })))
+        }
+        _ => Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "target 只能是 world_info、card 或 authors_note"})),
+        )),
+    }
+}
+
+// ==================== 审核队列 ====================
+
+#[derive(Deserialize)]
+pub struct SuggestionQuery {
+    /// 默认 pending
+    pub status: Option<String>,
+    pub job_id: Option<Uuid>,
+    pub feature_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SuggestionItem {
+    pub id: Uuid,
+    pub job_id: Option<Uuid>,
+    pub card_id: Uuid,
+    pub card_name: Option<String>,
+    pub feature_id: String,
+    pub payload: Value,
+    pub status: String,
+    pub created_at: String,
+}
+
+/// GET /api/ai/suggestions - 审核队列
+pub async fn list_suggestions(
+    State(db): State<DatabaseConnection>,
+    Query(query): Query<SuggestionQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut find = ai_suggestion::Entity::find().filter(
+        ai_suggestion::Column::Status.eq(query.status.unwrap_or_else(|| "pending".to_string())),
+    );
+    if let Some(job_id) = query.job_id {
+        find = find.filter(ai_suggestion::Column::JobId.eq(job_id));
+    }
+    if let Some(feature_id) = query.feature_id {
+        find = find.filter(ai_suggestion::Column::FeatureId.eq(feature_id));
+    }
+    let suggestions = find
+        .order_by_desc(ai_suggestion::Column::CreatedAt)
+        .all(&db)
+        .await
+        .map_err(internal_error)?;
+
+    let card_ids: Vec<Uuid> = suggestions.iter().map(|s| s.card_id).collect();
+    let names: std::collections::HashMap<Uuid, String> = character_card::Entity::find()
+        .filter(character_card::Column::Id.is_in(card_ids))
+        .all(&db)
+        .await
+        .map_err(internal_error)?
+        .into_iter()
+        .map(|c| (c.id, c.name))
+        .collect();
+
+    let items: Vec<SuggestionItem> = suggestions
+        .into_iter()
+        .map(|s| SuggestionItem {
+            id: s.id,
+            job_id: s.job_id,
+            card_name: names.get(&s.card_id).cloned(),
+            card_id: s.card_id,
+            feature_id: s.feature_id,
+            payload: serde_json::from_str(&s.payload).unwrap_or(Value::Null),
+            status: s.status,
+            created_at: s.created_at.format("%Y-%m-%d %H:%M").to_string(),
+        })
+        .collect();
+
+    Ok(Json(items))
+}
+
+#[derive(Deserialize, Default)]
+pub struct AcceptSuggestionRequest {
+    /// 用户修改后的内容，覆盖建议中的同名字段
+    pub edits: Option<serde_json::Map<String, Value>>,
+}
+
+/// 将建议写入角色卡
+async fn apply_suggestion(
+    db: &DatabaseConnection,
+    suggestion: &ai_suggestion::Model,
+    edits: Option<serde_json::Map<String, Value>>,
+) -> Result<(), ApiError> {
+    let mut payload: Value = serde_json::from_str(&suggestion.payload).unwrap_or(Value::Null);
+    if let (Some(obj), Some(edits)) = (payload.as_object_mut(), edits) {
+        obj.extend(edits);
+    }
+
+    let card = character_card::Entity::find_by_id(suggestion.card_id)
+        .one(db)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "角色卡不存在"})),
+            )
+        })?;
+
+    match suggestion.feature_id.as_str() {
+        "overview" => {
+            let summary = payload
+                .get("summary")
+                .and_then(|v| v.as_str())
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .ok_or_else(|| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(serde_json::json!({"error": "概览内容为空，无法写入"})),
+                    )
+                })?;
+            let tags: Option<Vec<String>> = payload
+                .get("tags")
+                .and_then(|v| serde_json::from_value(v.clone()).ok());
+            crate::services::card::apply_overview(db, &card, summary, tags.as_deref())
+                .await
+                .map_err(internal_error)?;
+        }
+        other => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": format!("不支持的建议类型: {}", other)})),
+            ))
+        }
+    }
+    Ok(())
+}
+
+async fn find_pending(db: &DatabaseConnection, id: Uuid) -> Result<ai_suggestion::Model, ApiError> {
+    let suggestion = ai_suggestion::Entity::find_by_id(id)
+        .one(db)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "建议不存在"})),
+            )
+        })?;
+    if suggestion.status != "pending" {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({"error": "该建议已审核"})),
+        ));
+    }
+    Ok(suggestion)
+}
+
+async fn mark_reviewed(
+    db: &DatabaseConnection,
+    suggestion: ai_suggestion::Model,
+    status: &str,
+) -> Result<(), ApiError> {
+    let mut active: ai_suggestion::ActiveModel = suggestion.into();
+    active.status = Set(status.to_string());
+    active.reviewed_at = Set(Some(chrono::Utc::now().naive_utc()));
+    active.update(db).await.map_err(internal_error)?;
+    Ok(())
+}
+
+/// POST /api/ai/suggestions/{id}/accept - 接受建议（可附带修改）
+pub async fn accept_suggestion(
+    State(db): State<DatabaseConnection>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<AcceptSuggestionRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let suggestion = find_pending(&db, id).await?;
+    apply_suggestion(&db, &suggestion, payload.edits).await?;
+    mark_reviewed(&db, suggestion, "accepted").await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/ai/suggestions/{id}/reject - 拒绝建议
+pub async fn reject_suggestion(
+    State(db): State<DatabaseConnection>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let suggestion = find_pending(&db, id).await?;
+    mark_reviewed(&db, suggestion, "rejected").await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct ReviewBatchRequest {
+    pub ids: Vec<Uuid>,
+    /// "accept" | "reject"
+    pub action: String,
+}
+
+#[derive(Serialize)]
+pub struct ReviewBatchResponse {
+    pub processed: usize,
+    pub failed: Vec<Value>,
+}
+
+/// POST /api/ai/suggestions/review - 批量接受/拒绝
+pub async fn review_suggestions(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<ReviewBatchRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let accept = match payload.action.as_str() {
+        "accept" => true,
+        "reject" => false,
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": "action 只能是 accept 或 reject"})),
+            ))
+        }
+    };
+
+    let mut processed = 0;
+    let mut failed = Vec::new();
+    for id in payload.ids {
+        let result = async {
+            let suggestion = find_pending(&db, id).await?;
+            if accept {
+                apply_suggestion(&db, &suggestion, None).await?;
+                mark_reviewed(&db, suggestion, "accepted").await
+            } else {
+                mark_reviewed(&db, suggestion, "rejected").await
+            }
+        }
+        .await;
+        match result {
+            Ok(()) => processed += 1,
+            Err((_, Json(err))) => {
+                failed.push(serde_json::json!({"id": id, "error": err["error"]}))
+            }
+        }
+    }
+
+    Ok(Json(ReviewBatchResponse { processed, failed }))
+}
@@ -0,0 +1,366 @@
+//! AI 角色卡生成 API
+//!
+//! 根据简报（概念、题材、基调、篇幅）与可选的小剧场 / 图库图片参考，
+//! 流式生成完整的 CCv3 角色卡草稿，校验后保存为本地新建角色卡；
+//! 以及为已有角色卡生成备选开场白（生成候选 -> 用户挑选 -> 追加写入）
+
+use crate::entities::{character_card, image, theater};
+use crate::services::{generate, greeting, prompt};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+    Json,
+};
+use futures::stream::{self, Stream};
+use sea_orm::{DatabaseConnection, EntityTrait};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::convert::Infallible;
+use std::time::Duration;
+use uuid::Uuid;
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn internal_error(e: impl std::fmt::Display) -> ApiError {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(serde_json::json!({"error": e.to_string()})),
+    )
+}
+
+fn bad_request(message: &str) -> ApiError {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({"error": message})),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct GenerateCardRequest {
+    /// 角色概念（必填）
+    pub concept: String,
+    pub genre: Option<String>,
+    pub tone: Option<String>,
+    /// 写作语言，默认简体中文
+    pub language: Option<String>,
+    /// description 目标字数
+    pub description_length: Option<u32>,
+    /// 备选开场白数量
+    pub greetings_count: Option<u32>,
+    /// 世界书条目数量
+    pub lorebook_entries: Option<u32>,
+    /// 参考小剧场
+    pub theater_id: Option<Uuid>,
+    /// 参考图库图片
+    pub image_id: Option<Uuid>,
+    /// 使用参考图片作为角色卡封面
+    #[serde(default)]
+    pub use_image_as_avatar: bool,
+}
+
+/// SSE 事件
+#[derive(Serialize, Default)]
+struct GenerateEvent {
+    /// delta / validating / complete / error
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    card_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    card: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warnings: Option<Vec<String>>,
+}
+
+impl GenerateEvent {
+    fn error(message: String) -> Self {
+        Self {
+            status: "error",
+            message: Some(message),
+            ..Default::default()
+        }
+    }
+
+    fn into_event(self) -> Event {
+        Event::default().data(serde_json::to_string(&self).unwrap())
+    }
+}
+
+/// 生成任务上下文
+struct GenerateContext {
+    db: DatabaseConnection,
+    channel: crate::entities::ai_channel::Model,
+    messages: Vec<Value>,
+    /// 作为封面的参考图片
+    avatar_image: Option<image::Model>,
+}
+
+/// POST /api/ai/cards/generate - 根据简报生成角色卡 (SSE)
+pub async fn generate_card(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<GenerateCardRequest>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let concept = payload.concept.trim().to_string();
+    if concept.is_empty() {
+        return Err(bad_request("请填写角色概念"));
+    }
+
+    let channel = crate::services::ai::resolve_global_channel(&db)
+        .await
+        .map_err(|e| {
+            (
+                e.status_code(),
+                Json(serde_json::json!({"error": e.to_string()})),
+            )
+        })?;
+    let template = prompt::resolve_template(&db, "card_generate")
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| internal_error("缺少角色卡生成提示词模板"))?;
+
+    // 参考素材
+    let mut references = Vec::new();
+    if let Some(theater_id) = payload.theater_id {
+        let theater = theater::Entity::find_by_id(theater_id)
+            .one(&db)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| bad_request("参考小剧场不存在"))?;
+        references.push(generate::theater_reference(&theater));
+    }
+    let mut avatar_image = None;
+    if let Some(image_id) = payload.image_id {
+        let image = image::Entity::find_by_id(image_id)
+            .one(&db)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| bad_request("参考图片不存在"))?;
+        references.push(generate::image_reference(&image));
+        if payload.use_image_as_avatar {
+            avatar_image = Some(image);
+        }
+    }
+
+    let mut vars = prompt::build_variables(&db, "card_generate", None)
+        .await
+        .map_err(internal_error)?;
+    vars.insert("concept".into(), concept);
+    vars.insert("genre".into(), payload.genre.unwrap_or_default());
+    vars.insert("tone".into(), payload.tone.unwrap_or_default());
+    vars.insert(
+        "language".into(),
+        payload
+            .language
+            .filter(|s| !s.trim().is_empty())
+            .unwrap_or_else(|| "简体中文".to_string()),
+    );
+    vars.insert(
+        "description_length".into(),
+        payload
+            .description_length
+            .unwrap_or(600)
+            .clamp(100, 4000)
+            .to_string(),
+    );
+    vars.insert(
+        "greetings_count".into(),
+        payload.greetings_count.unwrap_or(2).min(10).to_string(),
+    );
+    vars.insert(
+        "lorebook_entries".into(),
+        payload.lorebook_entries.unwrap_or(5).min(30).to_string(),
+    );
+    vars.insert("reference".into(), references.join("\n\n"));
+
+    let ctx = GenerateContext {
+        db,
+        channel,
+        messages: prompt::render_messages(&template, &vars),
+        avatar_image,
+    };
+
+    // 后台执行生成，通过通道推送 SSE 事件；客户端断开后发送失败即停止
+    let (tx, rx) = tokio::sync::mpsc::channel::<GenerateEvent>(64);
+    tokio::spawn(async move {
+        if let Err(e) = run_generate(ctx, &tx).await {
+            let _ = tx.send(GenerateEvent::error(e)).await;
+        }
+    });
+
+    let stream = stream::unfold(rx, |mut rx| async move {
+        rx.recv()
+            .await
+            .map(|event| (Ok::<_, Infallible>(event.into_event()), rx))
+    });
+
+    Ok(Sse::new(stream).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("keep-alive"),
+    ))
+}
+
+/// 流式生成草稿 -> 校验 -> 保存角色卡
+async fn run_generate(
+    ctx: GenerateContext,
+    tx: &tokio::sync::mpsc::Sender<GenerateEvent>,
+) -> Result<(), String> {
+    let mut stream = crate::services::ai::chat_completion_stream(&ctx.channel, &ctx.messages, 0.8)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut content = String::new();
+    while let Some(delta) = stream.next_delta().await.map_err(|e| e.to_string())? {
+        content.push_str(&delta);
+        let event = GenerateEvent {
+            status: "delta",
+            content: Some(delta),
+            ..Default::default()
+        };
+        if tx.send(event).await.is_err() {
+            tracing::info!("Card generation cancelled: client disconnected");
+            return Ok(());
+        }
+    }
+
+    let _ = tx
+        .send(GenerateEvent {
+            status: "validating",
+            message: Some("正在校验角色卡草稿".to_string()),
+            ..Default::default()
+        })
+        .await;
+
+    let draft = crate::services::ai::extract_json(&content)
+        .ok_or_else(|| "无法解析 AI 返回的角色卡草稿".to_string())?;
+    let (card_json, warnings) = generate::build_card_json(&draft)?;
+
+    let card_id = Uuid::new_v4();
+    let card_dir = crate::utils::paths::get_data_path(&format!("cards/{}", card_id));
+    // 保存失败时清理已写入的角色卡目录
+    let saved = async {
+        let avatar = match &ctx.avatar_image {
+            Some(image) => {
+                let path = crate::utils::paths::get_data_dir()
+                    .join(image.file_path.trim_start_matches('/'));
+                let data = tokio::fs::read(&path)
+                    .await
+                    .map_err(|e| format!("读取参考图片失败: {}", e))?;
+                crate::services::card::write_avatar_image(card_id, &data).await?
+            }
+            None => {
+                tokio::fs::create_dir_all(&card_dir)
+                    .await
+                    .map_err(|e| format!("创建角色卡目录失败: {}", e))?;
+                "/default.webp".to_string()
+            }
+        };
+
+        let compact_json =
+            serde_json::to_string(&card_json).map_err(|e| format!("序列化 JSON 失败: {}", e))?;
+        let data_hash = crate::utils::hash::compute_json_hash(&compact_json);
+        crate::api::cards::save_card_model(
+            &ctx.db,
+            card_id,
+            card_json.clone(),
+            Some(avatar),
+            data_hash,
+            "local",
+        )
+        .await
+    }
+    .await;
+    if let Err(e) = saved {
+        let _ = tokio::fs::remove_dir_all(&card_dir).await;
+        return Err(e);
+    }
+    crate::api::dashboard::invalidate_cache();
+
+    let _ = tx
+        .send(GenerateEvent {
+            status: "complete",
+            message: Some("角色卡已生成".to_string()),
+            card_id: Some(card_id),
+            card: Some(card_json),
+            warnings: Some(warnings),
+            ..Default::default()
+        })
+        .await;
+    Ok(())
+}
+
+fn ai_error(e: crate::services::ai::AiError) -> ApiError {
+    (
+        e.status_code(),
+        Json(serde_json::json!({"error": e.to_string()})),
+    )
+}
+
+async fn find_card(db: &DatabaseConnection, id: Uuid) -> Result<character_card::Model, ApiError> {
+    character_card::Entity::find_by_id(id)
+        .one(db)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "角色卡不存在"})),
+            )
+        })
+}
+
+/// POST /api/ai/cards/:id/greetings/generate - 生成备选开场白候选（不写入角色卡）
+pub async fn generate_greetings(
+    State(db): State<DatabaseConnection>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<greeting::GreetingOptions>,
+) -> Result<Json<greeting::GreetingResult>, ApiError> {
+    let card = find_card(&db, id).await?;
+    let channel = crate::services::ai::resolve_global_channel(&db)
+        .await
+        .map_err(ai_error)?;
+    let result = greeting::generate(&db, &channel, &card, &payload)
+        .await
+        .map_err(ai_error)?;
+    Ok(Json(result))
+}
+
+#[derive(Deserialize)]
+pub struct AcceptGreetingsRequest {
+    pub greetings: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct AcceptGreetingsResponse {
+    pub card: character_card::Model,
+    /// 写入前创建的快照（没有追加任何内容时为空）
+    pub version_id: Option<Uuid>,
+    pub version_number: Option<String>,
+    /// 实际追加的条数（与已有开场白重复的会跳过）
+    pub added: usize,
+}
+
+/// POST /api/ai/cards/:id/greetings/accept - 追加采纳的开场白（写入前自动创建版本快照）
+pub async fn accept_greetings(
+    State(db): State<DatabaseConnection>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<AcceptGreetingsRequest>,
+) -> Result<Json<AcceptGreetingsResponse>, ApiError> {
+    if payload.greetings.iter().all(|g| g.trim().is_empty()) {
+        return Err(bad_request("请选择要采纳的开场白"));
+    }
+    let card = find_card(&db, id).await?;
+    let (card, version, added) = greeting::append(&db, &card, &payload.greetings)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(AcceptGreetingsResponse {
+        card,
+        version_id: version.as_ref().map(|v| v.id),
+        version_number: version.map(|v| v.version_number),
+        added,
+    }))
+}
@@ -2,15 +2,21 @@
 //!
 //! 导出：打包整个 data/ 目录为 .tar.gz (返回为 .piney)
 //! 导入：解压 .piney 文件覆盖 data/ 目录
+//!
+//! AI 渠道密钥以本地 .ai_secret 加密存储，该文件不进入备份；
+//! 导出时默认清除密钥，也可选择保留密文（仅能在本机恢复）或用用户口令重新加密（导入时凭口令恢复）
 
+use crate::entities::ai_channel;
+use crate::utils::crypto;
 use axum::{
     body::Body,
     extract::{Json, Multipart, State},
     http::{header, HeaderMap, StatusCode},
     response::IntoResponse,
 };
+use base64::{engine::general_purpose, Engine as _};
 use chrono::Local;
-use sea_orm::DatabaseConnection;
+use sea_orm::{ActiveModelTrait, ConnectionTrait, DatabaseConnection, EntityTrait, Set};
 
 use flate2::read::GzDecoder;
 use serde::{Deserialize, Serialize};
@@ -36,14 +42,268 @@ fn get_data_dir() -> std::path::PathBuf {
     crate::utils::paths::get_data_path("")
 }
 
-/// GET /api/backup/export - 导出系统数据为 .piney 文件 (流式传输)
-pub async fn export_backup() -> Result<impl IntoResponse, (StatusCode, String)> {
+/// 备份内记录密钥处理方式的文件
+const KEYS_MANIFEST: &str = "backup_keys.json";
+/// 口令校验用的明文
+const PASSPHRASE_CHECK: &str = "piney-backup";
+
+/// 备份中 AI 渠道密钥的处理方式
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyMode {
+    /// 保留本地密文（只能在同一安装中恢复）
+    Keep,
+    /// 清除全部密钥
+    #[default]
+    Strip,
+    /// 使用用户口令重新加密
+    Passphrase,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeysManifest {
+    mode: KeyMode,
+    /// 口令派生用的盐（base64）
+    #[serde(default)]
+    salt: Option<String>,
+    /// 用口令密钥加密的校验串，导入时用于判断口令是否正确
+    #[serde(default)]
+    check: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct ExportRequest {
+    #[serde(default)]
+    pub keys: KeyMode,
+    pub passphrase: Option<String>,
+}
+
+/// 打开指定路径的 SQLite 数据库（备份快照 / 刚恢复的数据库）
+async fn open_sqlite(path: &std::path::Path) -> Result<DatabaseConnection, String> {
+    use sea_orm::sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+    let options = SqliteConnectOptions::new().filename(path);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await
+        .map_err(|e| format!("打开数据库失败: {}", e))?;
+    Ok(sea_orm::SqlxSqliteConnector::from_sqlx_sqlite_pool(pool))
+}
+
+/// 导出前生成数据库快照，并按处理方式改写快照中的渠道密钥
+async fn prepare_snapshot(
+    db: &DatabaseConnection,
+    mode: KeyMode,
+    passphrase: Option<&str>,
+) -> Result<(std::path::PathBuf, KeysManifest), String> {
+    let temp_dir = crate::utils::paths::get_data_path("temp");
+    fs::create_dir_all(&temp_dir).map_err(|e| format!("创建临时目录失败: {}", e))?;
+    let snapshot = temp_dir.join(format!("backup_{}.db", uuid::Uuid::new_v4()));
+
+    // VACUUM INTO 生成一致的快照（包含 WAL 中尚未合并的数据）
+    let escaped = snapshot.to_string_lossy().replace('\'', "''");
+    db.execute_unprepared(&format!("VACUUM INTO '{}';", escaped))
+        .await
+        .map_err(|e| format!("生成数据库快照失败: {}", e))?;
+
+    let mut manifest = KeysManifest {
+        mode,
+        salt: None,
+        check: None,
+    };
+    let passphrase_key = match (mode, passphrase) {
+        (KeyMode::Passphrase, Some(passphrase)) => {
+            let salt = crypto::random_salt();
+            let key = crypto::derive_passphrase_key(passphrase, &salt);
+            manifest.salt = Some(general_purpose::STANDARD.encode(salt));
+            manifest.check = Some(crypto::encrypt_with(&key, PASSPHRASE_CHECK));
+            Some(key)
+        }
+        _ => None,
+    };
+
+    let conn = open_sqlite(&snapshot).await?;
+    let result = async {
+        for channel in ai_channel::Entity::find()
+            .all(&conn)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            let api_key = match &passphrase_key {
+                Some(key) => match crypto::decrypt_local(&channel.api_key) {
+                    Ok(plain) if !plain.is_empty() => crypto::encrypt_with(key, &plain),
+                    Ok(_) => String::new(),
+                    Err(e) => {
+                        tracing::warn!(
+                            "渠道 {} 的 API Key 无法解密，备份中将清除: {}",
+                            channel.name,
+                            e
+                        );
+                        String::new()
+                    }
+                },
+                None => String::new(),
+            };
+            let mut active: ai_channel::ActiveModel = channel.into();
+            active.api_key = Set(api_key);
+            active.update(&conn).await.map_err(|e| e.to_string())?;
+        }
+        Ok::<(), String>(())
+    }
+    .await;
+    conn.close().await.ok();
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&snapshot);
+        return Err(format!("处理备份中的 API Key 失败: {}", e));
+    }
+    Ok((snapshot, manifest))
+}
+
+/// 清除本机无法解密的渠道密钥（来自其他安装的密文），有清除时返回提示
+async fn clear_foreign_keys(data_dir: &std::path::Path) -> Result<Option<String>, String> {
+    if !data_dir.join("piney.db").exists() {
+        return Ok(None);
+    }
+    let conn = open_sqlite(&data_dir.join("piney.db")).await?;
+    let result = async {
+        let mut cleared = 0;
+        for channel in ai_channel::Entity::find()
+            .all(&conn)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            if channel.api_key.is_empty() || crypto::decrypt_local(&channel.api_key).is_ok() {
+                continue;
+            }
+            let mut active: ai_channel::ActiveModel = channel.into();
+            active.api_key = Set(String::new());
+            active.update(&conn).await.map_err(|e| e.to_string())?;
+            cleared += 1;
+        }
+        Ok::<usize, String>(cleared)
+    }
+    .await;
+    conn.close().await.ok();
+    let cleared = result.map_err(|e| format!("检查 API Key 失败: {}", e))?;
+    Ok((cleared > 0).then(|| {
+        format!(
+            "{} 个 AI 渠道的 API Key 由其他安装加密，无法在本机解密，已清除，请重新填写",
+            cleared
+        )
+    }))
+}
+
+/// 恢复后按备份说明处理渠道密钥，返回附加提示
+async fn restore_keys(
+    data_dir: &std::path::Path,
+    passphrase: Option<&str>,
+) -> Result<Option<String>, String> {
+    let manifest_path = data_dir.join(KEYS_MANIFEST);
+    // 没有说明的旧备份按保留密文处理
+    let Ok(content) = fs::read_to_string(&manifest_path) else {
+        return clear_foreign_keys(data_dir).await;
+    };
+    let _ = fs::remove_file(&manifest_path);
+    let manifest: KeysManifest =
+        serde_json::from_str(&content).map_err(|e| format!("备份密钥说明格式错误: {}", e))?;
+
+    match manifest.mode {
+        // 其他安装加密的密钥在本机无法解密，清除并提示
+        KeyMode::Keep => clear_foreign_keys(data_dir).await,
+        KeyMode::Strip => Ok(Some("备份不包含 AI 渠道的 API Key，请重新填写".to_string())),
+        KeyMode::Passphrase => {
+            // 校验口令；口令缺失或错误时清除密钥，避免留下无法解密的数据
+            let key = passphrase.filter(|p| !p.is_empty()).and_then(|p| {
+                let salt = general_purpose::STANDARD
+                    .decode(manifest.salt.as_deref()?)
+                    .ok()?;
+                let key = crypto::derive_passphrase_key(p, &salt);
+                let check = crypto::decrypt_with(&key, manifest.check.as_deref()?).ok()?;
+                (check == PASSPHRASE_CHECK).then_some(key)
+            });
+
+            let conn = open_sqlite(&data_dir.join("piney.db")).await?;
+            let result = async {
+                for channel in ai_channel::Entity::find()
+                    .all(&conn)
+                    .await
+                    .map_err(|e| e.to_string())?
+                {
+                    let api_key = match &key {
+                        Some(key) if !channel.api_key.is_empty() => {
+                            match crypto::decrypt_with(key, &channel.api_key) {
+                                Ok(plain) => crypto::encrypt_local(&plain),
+                                Err(_) => String::new(),
+                            }
+                        }
+                        _ => String::new(),
+                    };
+                    let mut active: ai_channel::ActiveModel = channel.into();
+                    active.api_key = Set(api_key);
+                    active.update(&conn).await.map_err(|e| e.to_string())?;
+                }
+                Ok::<(), String>(())
+            }
+            .await;
+            conn.close().await.ok();
+            result.map_err(|e| format!("恢复 API Key 失败: {}", e))?;
+
+            Ok(Some(if key.is_some() {
+                "AI 渠道的 API Key 已用口令恢复".to_string()
+            } else {
+                "备份口令缺失或错误，AI 渠道的 API Key 已清除，请重新填写".to_string()
+            }))
+        }
+    }
+}
+
+/// 本机专属文件，不参与备份与恢复
+fn is_local_only(name: &str) -> bool {
+    name == ".ai_secret"
+}
+
+/// POST /api/backup/export - 导出系统数据为 .piney 文件 (流式传输)
+///
+/// Body（可选）: {"keys": "strip|keep|passphrase", "passphrase": "..."}；
+/// keys 缺省为 strip，passphrase 在 keys=passphrase 时必填（放在请求体中，避免出现在 URL 与访问日志里）
+pub async fn export_backup(
+    State(db): State<DatabaseConnection>,
+    body: Option<Json<ExportRequest>>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let query = body.map(|Json(body)| body).unwrap_or_default();
     let data_dir = get_data_dir();
 
     if !data_dir.exists() {
         return Err((StatusCode::NOT_FOUND, "数据目录不存在".to_string()));
     }
 
+    let passphrase = query.passphrase.as_deref().filter(|p| !p.is_empty());
+    if query.keys == KeyMode::Passphrase && passphrase.is_none() {
+        return Err((StatusCode::BAD_REQUEST, "请填写备份口令".to_string()));
+    }
+
+    // 需要改写密钥时，使用数据库快照替代原数据库文件
+    let (snapshot, manifest) = match query.keys {
+        KeyMode::Keep => (
+            None,
+            KeysManifest {
+                mode: KeyMode::Keep,
+                salt: None,
+                check: None,
+            },
+        ),
+        mode => {
+            let (path, manifest) = prepare_snapshot(&db, mode, passphrase)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            (Some(path), manifest)
+        }
+    };
+    let manifest = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
     let timestamp = Local::now().format("%Y%m%d_%H%M%S");
     let filename = format!("piney_backup_{}.piney", timestamp);
 
@@ -70,11 +330,26 @@ pub async fn export_backup() -> Result<impl IntoResponse, (StatusCode, String)>
             if let Ok(entries) = fs::read_dir(&data_dir_clone) {
                 for entry in entries.flatten() {
                     let path = entry.path();
+                    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
                     // 忽略 temp 目录（虽然新版不再创建 temp 文件，但防御性编程保留）
-                    if path.file_name().and_then(|n| n.to_str()) == Some("temp") {
+                    if name == "temp" || is_local_only(name) {
                         continue;
                     }
 
+                    // 使用快照替代数据库文件（快照已包含 WAL 数据）
+                    if let Some(snapshot) = &snapshot {
+                        match name {
+                            "piney.db" => {
+                                tar_builder
+                                    .append_path_with_name(snapshot, "piney.db")
+                                    .map_err(|e| format!("打包数据库快照失败: {}", e))?;
+                                continue;
+                            }
+                            "piney.db-wal" | "piney.db-shm" => continue,
+                            _ => {}
+                        }
+                    }
+
                     // 计算相对路径
                     let relative_path = path
                         .strip_prefix(&data_dir_clone)
@@ -93,6 +368,16 @@ pub async fn export_backup() -> Result<impl IntoResponse, (StatusCode, String)>
                 }
             }
 
+            // 写入密钥处理说明
+            let mut header = tar::Header::new_gnu();
+            header.set_size(manifest.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(Local::now().timestamp() as u64);
+            header.set_cksum();
+            tar_builder
+                .append_data(&mut header, KEYS_MANIFEST, &manifest[..])
+                .map_err(|e| format!("打包密钥说明失败: {}", e))?;
+
             // 完成打包
             tar_builder
                 .finish()
@@ -101,6 +386,10 @@ pub async fn export_backup() -> Result<impl IntoResponse, (StatusCode, String)>
             Ok(())
         })();
 
+        if let Some(snapshot) = &snapshot {
+            let _ = fs::remove_file(snapshot);
+        }
+
         if let Err(e) = result {
             // 在流传输过程中发生错误，只能记录日志，无法修改 HTTP 状态码
             error!("备份打包流式传输失败: {}", e);
@@ -141,17 +430,23 @@ pub async fn import_backup(
         error!("尝试关闭数据库连接失败: {}", e);
     }
 
-    // 1. 读取上传的文件
+    // 1. 读取上传的文件（以及可选的备份口令）
     let mut file_data: Option<Vec<u8>> = None;
+    let mut passphrase: Option<String> = None;
 
     while let Ok(Some(field)) = multipart.next_field().await {
-        if field.name() == Some("backup") || field.name() == Some("file") {
-            let data = field
-                .bytes()
-                .await
-                .map_err(|e| (StatusCode::BAD_REQUEST, format!("读取文件失败: {}", e)))?;
-            file_data = Some(data.to_vec());
-            break;
+        match field.name() {
+            Some("backup") | Some("file") => {
+                let data = field
+                    .bytes()
+                    .await
+                    .map_err(|e| (StatusCode::BAD_REQUEST, format!("读取文件失败: {}", e)))?;
+                file_data = Some(data.to_vec());
+            }
+            Some("passphrase") => {
+                passphrase = field.text().await.ok();
+            }
+            _ => {}
         }
     }
 
@@ -224,6 +519,12 @@ pub async fn import_backup(
                 .map_err(|e| format!("读取归档失败: {}", e))?;
             for entry in entries {
                 let mut entry = entry.map_err(|e| format!("读取条目失败: {}", e))?;
+                if entry
+                    .path()
+                    .is_ok_and(|p| is_local_only(&p.to_string_lossy()))
+                {
+                    continue;
+                }
                 entry.set_preserve_permissions(false);
                 entry.set_preserve_mtime(false);
                 entry
@@ -237,6 +538,12 @@ pub async fn import_backup(
                 .map_err(|e| format!("读取归档失败: {}", e))?;
             for entry in entries {
                 let mut entry = entry.map_err(|e| format!("读取条目失败: {}", e))?;
+                if entry
+                    .path()
+                    .is_ok_and(|p| is_local_only(&p.to_string_lossy()))
+                {
+                    continue;
+                }
                 entry.set_preserve_permissions(false);
                 entry.set_preserve_mtime(false);
                 entry
@@ -275,9 +582,17 @@ pub async fn import_backup(
     })?
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
 
+    // 4. 按备份说明处理 AI 渠道密钥
+    let mut message = "数据恢复成功".to_string();
+    match restore_keys(&data_dir, passphrase.as_deref()).await {
+        Ok(Some(note)) => message = format!("{}，{}", message, note),
+        Ok(None) => {}
+        Err(e) => {
+            error!("{}", e);
+            message = format!("{}，但{}", message, e);
+        }
+    }
+
     // 5. 返回成功信息
-    Ok(Json(ImportResponse {
-        username,
-        message: "数据恢复成功".to_string(),
-    }))
+    Ok(Json(ImportResponse { username, message }))
 }
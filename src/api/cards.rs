@@ -317,7 +317,7 @@ fn extract_png_metadata(data: &[u8]) -> Result<String, String> {
     Err("无效的角色卡图片：未找到元数据 (ccv3/chara)".to_string())
 }
 
-async fn save_card_model(
+pub(crate) async fn save_card_model(
     db: &DatabaseConnection,
     uuid: Uuid,
     json: Value,
@@ -424,6 +424,7 @@ async fn save_card_model(
         .insert(db)
         .await
         .map_err(|e| format!("数据库错误: {}", e))?;
+    crate::services::embedding::schedule(db, crate::services::embedding::Source::Card(uuid));
 
     // --- Auto-create Initial Version (V1) ---
     let version_note = if source == "local" {
@@ -716,53 +717,8 @@ pub async fn create_card(
     // 生成 UUID
     let uuid = Uuid::new_v4();
 
-    // 生成创建时间字符串
-    let now = chrono::Local::now();
-    let create_date = now.format("%Y-%m-%d @%Hh %Mm %Ss %3fms").to_string();
-
-    // 构建空白角色卡 JSON 模板（参考 docs/cankao/新建角色卡.json）
-    let card_json = serde_json::json!({
-        "name": payload.name,
-        "description": "",
-        "personality": "",
-        "scenario": "",
-        "first_mes": "",
-        "mes_example": "",
-        "creatorcomment": "",
-        "avatar": "none",
-        "talkativeness": "0.5",
-        "fav": false,
-        "tags": [],
-        "spec": "chara_card_v3",
-        "spec_version": "3.0",
-        "data": {
-            "name": payload.name,
-            "description": "",
-            "personality": "",
-            "scenario": "",
-            "first_mes": "",
-            "mes_example": "",
-            "creator_notes": "",
-            "system_prompt": "",
-            "post_history_instructions": "",
-            "tags": [],
-            "creator": "",
-            "character_version": "",
-            "alternate_greetings": [],
-            "extensions": {
-                "talkativeness": "0.5",
-                "fav": false,
-                "world": "",
-                "depth_prompt": {
-                    "prompt": "",
-                    "depth": 4,
-                    "role": "system"
-                }
-            },
-            "group_only_greetings": []
-        },
-        "create_date": create_date
-    });
+    // 构建空白角色卡 JSON 模板
+    let card_json = crate::services::card::blank_card_json(&payload.name);
 
     // 创建角色卡目录（用于后续可能的封面上传等）
     let card_dir = storage_dir.join(uuid.to_string());
@@ -1366,6 +1322,7 @@ pub async fn update(
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     invalidate_cache();
+    crate::services::embedding::schedule(&db, crate::services::embedding::Source::Card(id));
     Ok(Json(updated_model))
 }
 
@@ -1903,6 +1860,7 @@ pub async fn permanent_delete(
         .exec(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    crate::services::embedding::forget(&db, crate::services::embedding::Source::Card(id));
 
     invalidate_cache();
     Ok(StatusCode::OK)
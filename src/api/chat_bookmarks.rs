@@ -0,0 +1,327 @@
+//! 聊天记录书签 API
+//!
+//! 在聊天记录的楼层上添加书签与高亮（可附备注），并可列出角色卡下全部书签；
+//! 楼层编辑、拼接与撤销后按楼层映射同步书签位置
+
+use crate::api::history::{floor_count, floor_previews};
+use crate::entities::{chat_bookmark, chat_history, prelude::*};
+use crate::services::chat_edit::FloorMap;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Utc;
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// 书签类型
+const KINDS: &[&str] = &["bookmark", "highlight"];
+/// 列表中楼层预览的字符数
+const PREVIEW_CHARS: usize = 80;
+
+#[derive(Serialize)]
+pub struct BookmarkDto {
+    pub id: Uuid,
+    pub history_id: Uuid,
+    pub card_id: Uuid,
+    pub floor: i32,
+    pub kind: String,
+    pub name: String,
+    pub note: Option<String>,
+    pub quote: Option<String>,
+    pub color: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<chat_bookmark::Model> for BookmarkDto {
+    fn from(model: chat_bookmark::Model) -> Self {
+        Self {
+            id: model.id,
+            history_id: model.history_id,
+            card_id: model.card_id,
+            floor: model.floor,
+            kind: model.kind,
+            name: model.name,
+            note: model.note,
+            quote: model.quote,
+            color: model.color,
+            created_at: model.created_at.and_utc().to_rfc3339(),
+            updated_at: model.updated_at.and_utc().to_rfc3339(),
+        }
+    }
+}
+
+/// 角色卡书签列表项（附带所属记录名与楼层预览）
+#[derive(Serialize)]
+pub struct CardBookmarkDto {
+    #[serde(flatten)]
+    pub bookmark: BookmarkDto,
+    pub history_name: String,
+    pub preview: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateBookmarkReq {
+    pub floor: i32,
+    pub kind: Option<String>,
+    pub name: Option<String>,
+    pub note: Option<String>,
+    pub quote: Option<String>,
+    pub color: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateBookmarkReq {
+    pub floor: Option<i32>,
+    pub name: Option<String>,
+    pub note: Option<String>,
+    pub color: Option<String>,
+}
+
+fn db_error(e: DbErr) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+fn validate_floor(floor: i32, total: usize) -> Result<i32, (StatusCode, String)> {
+    if floor < 1 {
+        return Err((StatusCode::BAD_REQUEST, "楼层序号从 1 开始".to_string()));
+    }
+    if floor as usize > total {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("楼层 {} 不存在（共 {} 层）", floor, total),
+        ));
+    }
+    Ok(floor)
+}
+
+/// 空字符串视为清空
+fn non_empty(value: String) -> Option<String> {
+    let value = value.trim().to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+async fn find_history(
+    db: &DatabaseConnection,
+    card_id: Uuid,
+    history_id: Uuid,
+) -> Result<chat_history::Model, (StatusCode, String)> {
+    ChatHistory::find_by_id(history_id)
+        .one(db)
+        .await
+        .map_err(db_error)?
+        .filter(|h| h.card_id == card_id)
+        .ok_or((StatusCode::NOT_FOUND, "History not found".to_string()))
+}
+
+/// GET /api/cards/{id}/history/{history_id}/bookmarks - 单份记录的书签（按楼层排序）
+pub async fn list_history_bookmarks(
+    State(db): State<DatabaseConnection>,
+    Path((card_id, history_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Vec<BookmarkDto>>, (StatusCode, String)> {
+    find_history(&db, card_id, history_id).await?;
+    let bookmarks = ChatBookmark::find()
+        .filter(chat_bookmark::Column::HistoryId.eq(history_id))
+        .order_by_asc(chat_bookmark::Column::Floor)
+        .order_by_asc(chat_bookmark::Column::CreatedAt)
+        .all(&db)
+        .await
+        .map_err(db_error)?;
+
+    Ok(Json(bookmarks.into_iter().map(BookmarkDto::from).collect()))
+}
+
+/// GET /api/cards/{id}/bookmarks - 角色卡下全部记录的书签（最新在前）
+pub async fn list_card_bookmarks(
+    State(db): State<DatabaseConnection>,
+    Path(card_id): Path<Uuid>,
+) -> Result<Json<Vec<CardBookmarkDto>>, (StatusCode, String)> {
+    let histories: HashMap<Uuid, chat_history::Model> = ChatHistory::find()
+        .filter(chat_history::Column::CardId.eq(card_id))
+        .all(&db)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|h| (h.id, h))
+        .collect();
+    let bookmarks = ChatBookmark::find()
+        .filter(chat_bookmark::Column::CardId.eq(card_id))
+        .order_by_desc(chat_bookmark::Column::CreatedAt)
+        .all(&db)
+        .await
+        .map_err(db_error)?;
+
+    // 每份记录只加载一次索引，读取其全部书签所在楼层
+    let mut floors: HashMap<Uuid, Vec<usize>> = HashMap::new();
+    for bookmark in &bookmarks {
+        floors
+            .entry(bookmark.history_id)
+            .or_default()
+            .push(bookmark.floor as usize);
+    }
+    let mut previews: HashMap<Uuid, HashMap<usize, String>> = HashMap::new();
+    for (history_id, floors) in floors {
+        if let Some(history) = histories.get(&history_id) {
+            previews.insert(
+                history_id,
+                floor_previews(history, floors, PREVIEW_CHARS).await,
+            );
+        }
+    }
+
+    let items = bookmarks
+        .into_iter()
+        .filter_map(|bookmark| {
+            let history = histories.get(&bookmark.history_id)?;
+            let preview = previews
+                .get(&bookmark.history_id)
+                .and_then(|p| p.get(&(bookmark.floor as usize)))
+                .cloned();
+            Some(CardBookmarkDto {
+                history_name: history.display_name.clone(),
+                preview,
+                bookmark: BookmarkDto::from(bookmark),
+            })
+        })
+        .collect();
+    Ok(Json(items))
+}
+
+/// POST /api/cards/{id}/history/{history_id}/bookmarks - 添加书签 / 高亮
+pub async fn create_bookmark(
+    State(db): State<DatabaseConnection>,
+    Path((card_id, history_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<CreateBookmarkReq>,
+) -> Result<Json<BookmarkDto>, (StatusCode, String)> {
+    let history = find_history(&db, card_id, history_id).await?;
+    let floor = validate_floor(payload.floor, floor_count(&history).await?)?;
+    let kind = payload.kind.unwrap_or_else(|| "bookmark".to_string());
+    if !KINDS.contains(&kind.as_str()) {
+        return Err((StatusCode::BAD_REQUEST, format!("未知的书签类型: {}", kind)));
+    }
+
+    let now = Utc::now().naive_utc();
+    let bookmark = chat_bookmark::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        history_id: Set(history_id),
+        card_id: Set(card_id),
+        floor: Set(floor),
+        kind: Set(kind),
+        name: Set(payload
+            .name
+            .and_then(non_empty)
+            .unwrap_or_else(|| format!("#{}", floor))),
+        note: Set(payload.note.and_then(non_empty)),
+        quote: Set(payload.quote.and_then(non_empty)),
+        color: Set(payload.color.and_then(non_empty)),
+        created_at: Set(now),
+        updated_at: Set(now),
+    }
+    .insert(&db)
+    .await
+    .map_err(db_error)?;
+
+    Ok(Json(BookmarkDto::from(bookmark)))
+}
+
+/// PATCH /api/cards/{id}/history/{history_id}/bookmarks/{bookmark_id} - 修改书签
+pub async fn update_bookmark(
+    State(db): State<DatabaseConnection>,
+    Path((card_id, history_id, bookmark_id)): Path<(Uuid, Uuid, Uuid)>,
+    Json(payload): Json<UpdateBookmarkReq>,
+) -> Result<Json<BookmarkDto>, (StatusCode, String)> {
+    let history = find_history(&db, card_id, history_id).await?;
+    let bookmark = ChatBookmark::find_by_id(bookmark_id)
+        .one(&db)
+        .await
+        .map_err(db_error)?
+        .filter(|b| b.history_id == history_id)
+        .ok_or((StatusCode::NOT_FOUND, "Bookmark not found".to_string()))?;
+
+    let mut active: chat_bookmark::ActiveModel = bookmark.into();
+    if let Some(floor) = payload.floor {
+        active.floor = Set(validate_floor(floor, floor_count(&history).await?)?);
+    }
+    if let Some(name) = payload.name.and_then(non_empty) {
+        active.name = Set(name);
+    }
+    if let Some(note) = payload.note {
+        active.note = Set(non_empty(note));
+    }
+    if let Some(color) = payload.color {
+        active.color = Set(non_empty(color));
+    }
+    active.updated_at = Set(Utc::now().naive_utc());
+
+    let updated = active.update(&db).await.map_err(db_error)?;
+    Ok(Json(BookmarkDto::from(updated)))
+}
+
+/// DELETE /api/cards/{id}/history/{history_id}/bookmarks/{bookmark_id} - 删除书签
+pub async fn delete_bookmark(
+    State(db): State<DatabaseConnection>,
+    Path((card_id, history_id, bookmark_id)): Path<(Uuid, Uuid, Uuid)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    find_history(&db, card_id, history_id).await?;
+    let result = ChatBookmark::delete_many()
+        .filter(chat_bookmark::Column::Id.eq(bookmark_id))
+        .filter(chat_bookmark::Column::HistoryId.eq(history_id))
+        .exec(&db)
+        .await
+        .map_err(db_error)?;
+    if result.rows_affected == 0 {
+        return Err((StatusCode::NOT_FOUND, "Bookmark not found".to_string()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// 按楼层映射同步记录的书签，返回所在楼层已被删除而移除的书签（随备份保存，撤销时恢复）
+pub(crate) async fn remap_floors(
+    db: &impl ConnectionTrait,
+    history_id: Uuid,
+    map: &FloorMap,
+) -> Result<Vec<chat_bookmark::Model>, (StatusCode, String)> {
+    let bookmarks = ChatBookmark::find()
+        .filter(chat_bookmark::Column::HistoryId.eq(history_id))
+        .all(db)
+        .await
+        .map_err(db_error)?;
+    let mut removed = Vec::new();
+    for bookmark in bookmarks {
+        match map.get(bookmark.floor.max(0) as usize) {
+            Some(floor) if floor as i32 == bookmark.floor => {}
+            Some(floor) => {
+                let mut active: chat_bookmark::ActiveModel = bookmark.into();
+                active.floor = Set(floor as i32);
+                active.update(db).await.map_err(db_error)?;
+            }
+            None => {
+                ChatBookmark::delete_by_id(bookmark.id)
+                    .exec(db)
+                    .await
+                    .map_err(db_error)?;
+                removed.push(bookmark);
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// 撤销时恢复随楼层删除的书签
+pub(crate) async fn restore_bookmarks(
+    db: &impl ConnectionTrait,
+    bookmarks: Vec<chat_bookmark::Model>,
+) -> Result<(), (StatusCode, String)> {
+    if bookmarks.is_empty() {
+        return Ok(());
+    }
+    ChatBookmark::insert_many(bookmarks.into_iter().map(chat_bookmark::ActiveModel::from))
+        .exec_without_returning(db)
+        .await
+        .map_err(db_error)?;
+    Ok(())
+}
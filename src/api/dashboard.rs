@@ -399,3 +399,109 @@ async fn save_setting(
 
     Ok(())
 }
+
+/// 角色卡聊天活跃度
+#[derive(Serialize)]
+pub struct CardChatActivity {
+    pub id: Uuid,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub histories: u64,
+    pub messages: u64,
+    pub tokens: u64,
+    pub last_message_at: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct LibraryChatStats {
+    pub summary: crate::services::chat_stats::ChatStats,
+    /// 按消息数排序的前 10 个角色卡
+    pub top_cards: Vec<CardChatActivity>,
+}
+
+/// 全库统计时同时解析的聊天记录数
+const STATS_CONCURRENCY: usize = 4;
+
+/// 全库聊天记录统计（回收站中的角色卡不计入）
+pub async fn get_chat_stats(
+    State(db): State<DatabaseConnection>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    use crate::entities::chat_history;
+    use crate::services::chat_stats::ChatStats;
+    use futures::StreamExt;
+    use std::collections::HashMap;
+
+    let cards: HashMap<Uuid, SimpleCard> = character_card::Entity::find()
+        .select_only()
+        .columns([
+            character_card::Column::Id,
+            character_card::Column::Name,
+            character_card::Column::Avatar,
+            character_card::Column::UpdatedAt,
+        ])
+        .filter(character_card::Column::DeletedAt.is_null())
+        .into_model::<SimpleCard>()
+        .all(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .into_iter()
+        .map(|c| (c.id, c))
+        .collect();
+    let histories = chat_history::Entity::find()
+        .all(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // 未命中缓存的记录需要读取并解析文件，限制同时处理的数量
+    let histories: Vec<chat_history::Model> = histories
+        .into_iter()
+        .filter(|h| cards.contains_key(&h.card_id))
+        .collect();
+    let results: Vec<_> = futures::stream::iter(histories)
+        .map(|history| async move {
+            let stats = crate::api::history::history_stats(&history).await;
+            (history, stats)
+        })
+        .buffer_unordered(STATS_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut all = Vec::new();
+    let mut per_card: HashMap<Uuid, Vec<_>> = HashMap::new();
+    for (history, result) in results {
+        match result {
+            Ok(stats) => {
+                per_card
+                    .entry(history.card_id)
+                    .or_default()
+                    .push(stats.clone());
+                all.push(stats);
+            }
+            Err(e) => tracing::warn!("统计时跳过聊天记录 {}: {}", history.display_name, e.1),
+        }
+    }
+
+    let mut top_cards: Vec<CardChatActivity> = per_card
+        .into_iter()
+        .filter_map(|(card_id, stats)| {
+            let card = cards.get(&card_id)?;
+            let merged = ChatStats::merge(&stats);
+            Some(CardChatActivity {
+                id: card.id,
+                name: card.name.clone(),
+                avatar: card.avatar.clone(),
+                histories: merged.histories,
+                messages: merged.total_messages,
+                tokens: merged.total_tokens,
+                last_message_at: merged.last_message_at,
+            })
+        })
+        .collect();
+    top_cards.sort_by(|a, b| b.messages.cmp(&a.messages).then(a.name.cmp(&b.name)));
+    top_cards.truncate(10);
+
+    Ok(Json(LibraryChatStats {
+        summary: ChatStats::merge(&all),
+        top_cards,
+    }))
+}
@@ -0,0 +1,246 @@
+//! 语义向量 API
+//!
+//! - 索引状态与全量重建（后台任务，仅重建内容有变化的条目）
+//! - 语义搜索：角色卡 / 世界书条目 / 小剧场
+//! - 相似角色卡推荐
+
+use crate::services::{ai, embedding, jobs};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn internal_error(e: impl std::fmt::Display) -> ApiError {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(serde_json::json!({"error": e.to_string()})),
+    )
+}
+
+fn ai_error(e: ai::AiError) -> ApiError {
+    (
+        e.status_code(),
+        Json(serde_json::json!({"error": e.to_string()})),
+    )
+}
+
+const DEFAULT_LIMIT: usize = 10;
+const MAX_LIMIT: usize = 50;
+
+/// 读取向量渠道，未配置时返回 400
+async fn require_channel(
+    db: &DatabaseConnection,
+) -> Result<crate::entities::ai_channel::Model, ApiError> {
+    ai::resolve_embedding_channel(db)
+        .await
+        .map_err(ai_error)?
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": "没有配置语义向量模型，请到设置页面完成配置"})),
+            )
+        })
+}
+
+#[derive(Serialize)]
+pub struct EmbeddingStatus {
+    pub configured: bool,
+    pub model: Option<String>,
+    pub cards: u64,
+    pub world_entries: u64,
+    pub theaters: u64,
+}
+
+/// GET /api/ai/embeddings/status - 索引状态
+pub async fn status(State(db): State<DatabaseConnection>) -> Result<impl IntoResponse, ApiError> {
+    let channel = ai::resolve_embedding_channel(&db).await.map_err(ai_error)?;
+
+    let count = |source_type: &'static str| {
+        let db = db.clone();
+        let model = channel.as_ref().map(|c| c.model_id.clone());
+        async move {
+            let mut query = crate::entities::embedding::Entity::find()
+                .filter(crate::entities::embedding::Column::SourceType.eq(source_type));
+            if let Some(model) = model {
+                query = query.filter(crate::entities::embedding::Column::Model.eq(model));
+            }
+            query.count(&db).await
+        }
+    };
+
+    Ok(Json(EmbeddingStatus {
+        configured: channel.is_some(),
+        model: channel.as_ref().map(|c| c.model_id.clone()),
+        cards: count(embedding::SOURCE_CARD)
+            .await
+            .map_err(internal_error)?,
+        world_entries: count(embedding::SOURCE_WORLD_ENTRY)
+            .await
+            .map_err(internal_error)?,
+        theaters: count(embedding::SOURCE_THEATER)
+            .await
+            .map_err(internal_error)?,
+    }))
+}
+
+#[derive(Serialize)]
+pub struct ReindexResponse {
+    pub job_id: Uuid,
+    pub total: usize,
+}
+
+/// POST /api/ai/embeddings/reindex - 重建全部索引（后台任务）
+pub async fn reindex(State(db): State<DatabaseConnection>) -> Result<impl IntoResponse, ApiError> {
+    let channel = require_channel(&db).await?;
+    let sources = embedding::all_sources(&db).await.map_err(internal_error)?;
+
+    let total = sources.len();
+    let job_id = jobs::create("embedding_reindex", total);
+    tokio::spawn(async move {
+        let mut embedded = 0usize;
+        for source in &sources {
+            if jobs::is_cancelled(job_id) {
+                break;
+            }
+            match embedding::index_source(&db, &channel, *source).await {
+                Ok(n) => {
+                    embedded += n;
+                    jobs::item_done(job_id);
+                }
+                Err(e) => jobs::item_failed(job_id, format!("{:?}: {}", source, e)),
+            }
+        }
+
+        let pruned = match embedding::prune_orphans(&db, &sources).await {
+            Ok(n) => n,
+            Err(e) => {
+                tracing::warn!("Failed to prune orphaned embeddings: {}", e);
+                0
+            }
+        };
+        jobs::set_result(
+            job_id,
+            serde_json::json!({"embedded": embedded, "pruned": pruned}),
+        );
+        jobs::finish(
+            job_id,
+            format!("重新向量化 {} 条，清理 {} 条", embedded, pruned),
+        );
+    });
+
+    Ok(Json(ReindexResponse { job_id, total }))
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    /// 逗号分隔的来源类型：card / world_entry / theater，默认全部
+    pub types: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// 解析来源类型过滤
+fn parse_types(types: Option<&str>) -> Vec<&'static str> {
+    let all = [
+        embedding::SOURCE_CARD,
+        embedding::SOURCE_WORLD_ENTRY,
+        embedding::SOURCE_THEATER,
+    ];
+    match types.filter(|t| !t.trim().is_empty()) {
+        Some(t) => all
+            .into_iter()
+            .filter(|a| t.split(',').any(|s| s.trim() == *a))
+            .collect(),
+        None => all.to_vec(),
+    }
+}
+
+/// GET /api/ai/embeddings/search - 语义搜索
+pub async fn search(
+    State(db): State<DatabaseConnection>,
+    Query(query): Query<SearchQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let q = query.q.trim();
+    if q.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "请输入搜索内容"})),
+        ));
+    }
+    let channel = require_channel(&db).await?;
+    let types = parse_types(query.types.as_deref());
+
+    let vector = ai::embeddings(&channel, &[q.to_string()])
+        .await
+        .map_err(ai_error)?
+        .pop()
+        .unwrap_or_default();
+
+    let hits = embedding::search(
+        &db,
+        &vector,
+        &channel.model_id,
+        &types,
+        None,
+        query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+    )
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(hits))
+}
+
+#[derive(Deserialize)]
+pub struct SimilarQuery {
+    pub limit: Option<usize>,
+}
+
+/// GET /api/cards/{id}/similar - 相似角色卡
+pub async fn similar_cards(
+    State(db): State<DatabaseConnection>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<SimilarQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let card = crate::entities::character_card::Entity::find_by_id(id)
+        .one(&db)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "角色卡不存在"})),
+            )
+        })?;
+    let channel = require_channel(&db).await?;
+    let source = embedding::Source::Card(card.id);
+
+    // 尚未建立索引（或内容已变化）时即时更新
+    embedding::index_source(&db, &channel, source)
+        .await
+        .map_err(ai_error)?;
+    let vector = embedding::stored_vector(&db, source, &channel.model_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| internal_error("角色卡向量生成失败"))?;
+
+    let hits = embedding::search(
+        &db,
+        &vector,
+        &channel.model_id,
+        &[embedding::SOURCE_CARD],
+        Some((embedding::SOURCE_CARD, card.id)),
+        query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+    )
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(hits))
+}
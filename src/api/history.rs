@@ -1,14 +1,23 @@
+use crate::api::chat_bookmarks;
 use crate::entities::{chat_history, prelude::*};
+use crate::services::chat_branch::{self, BranchInput, BranchNode, SpliceOptions};
+use crate::services::chat_edit::{self, EditRecord, FloorMap, FloorOp};
+use crate::services::chat_floor::{parse_floors, parse_txt_floors, ChatMessage};
+use crate::services::chat_import::{self, ChatFormat, ParsedChat, Speaker, SpeakerOverride};
+use crate::services::chat_index;
+use crate::services::chat_stats;
+use crate::services::st_chat::{ChatHeader, ChatLog, StMessage};
 use anyhow::Result;
 use axum::{
     body::Body,
     extract::{Multipart, Path, State},
     http::StatusCode,
-    response::Json,
+    response::{IntoResponse, Json},
 };
 use chrono::Utc;
 use sea_orm::*;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 use tokio::fs;
 use tokio_util::io::ReaderStream;
@@ -27,8 +36,11 @@ pub struct ChatHistoryDto {
     pub created_at: String,
     pub updated_at: String,
     pub current_page: i32,
+    pub current_floor: i32,
     pub reading_settings: Option<String>,
     pub regex_scripts: String,
+    /// 最近一次 AI 总结时间（未总结为 None）
+    pub summarized_at: Option<String>,
 }
 
 impl From<chat_history::Model> for ChatHistoryDto {
@@ -43,8 +55,10 @@ impl From<chat_history::Model> for ChatHistoryDto {
             format: model.format,
             progress: model.progress,
             current_page: model.current_page,
+            current_floor: model.current_floor,
             reading_settings: model.reading_settings,
             regex_scripts: model.regex_scripts,
+            summarized_at: model.summarized_at.map(|t| t.and_utc().to_rfc3339()),
             created_at: model.created_at.and_utc().to_rfc3339(),
             updated_at: model.updated_at.and_utc().to_rfc3339(),
         }
@@ -134,76 +148,340 @@ pub async fn upload_history(
         "txt"
     };
 
+    if format == "jsonl" {
+        validate_jsonl(&data)?;
+    }
+
     let file_size = data.len() as i64;
 
     // Generate unique filename for main file
-    let mut save_name = file_name.clone();
-    let mut counter = 1;
-    while card_dir.join(&save_name).exists() {
-        let stem = std::path::Path::new(&file_name)
-            .file_stem()
-            .unwrap()
-            .to_str()
-            .unwrap();
-        let ext = std::path::Path::new(&file_name)
-            .extension()
-            .unwrap()
-            .to_str()
-            .unwrap();
-        save_name = format!("{}_{}.{}", stem, counter, ext);
-        counter += 1;
-    }
+    let save_name = unique_file_name(&card_dir, &file_name);
 
     let file_path = card_dir.join(&save_name);
     fs::write(&file_path, &data)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    reindex(file_path, format == "jsonl").await;
 
     // Handle source file if present (only relevant for TXT mode usually, but maybe user uploads both in wind mode? Probably not.)
     let mut saved_source_name = None;
     if let Some((source_name, source_data)) = source_file_data {
-        let stem = std::path::Path::new(&save_name)
-            .file_stem()
-            .unwrap()
-            .to_str()
-            .unwrap();
-        let source_ext = std::path::Path::new(&source_name)
-            .extension()
-            .unwrap_or_default()
-            .to_str()
-            .unwrap_or("jsonl");
-
-        let source_save_name = format!("{}.source.{}", stem, source_ext);
-        let source_path = card_dir.join(&source_save_name);
-
-        fs::write(&source_path, &source_data)
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-        saved_source_name = Some(source_save_name);
+        saved_source_name =
+            Some(save_source_file(&card_dir, &save_name, &source_name, &source_data).await?);
     }
 
+    let saved = insert_history(
+        &db,
+        card_id,
+        save_name,
+        saved_source_name,
+        file_size,
+        format,
+    )
+    .await?;
+    Ok(Json(ChatHistoryDto::from(saved)))
+}
+
+/// 在角色卡目录内生成不重名的文件名（重名时追加 _1、_2 …）
+fn unique_file_name(card_dir: &std::path::Path, file_name: &str) -> String {
+    let path = std::path::Path::new(file_name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("chat");
+    let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("txt");
+
+    let mut save_name = file_name.to_string();
+    let mut counter = 1;
+    while card_dir.join(&save_name).exists() {
+        save_name = format!("{}_{}.{}", stem, counter, ext);
+        counter += 1;
+    }
+    save_name
+}
+
+/// 保存原始文件，命名为 `<主文件名>.source.<扩展名>`
+async fn save_source_file(
+    card_dir: &std::path::Path,
+    save_name: &str,
+    source_name: &str,
+    data: &[u8],
+) -> Result<String, (StatusCode, String)> {
+    let stem = std::path::Path::new(save_name)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(save_name);
+    let source_ext = std::path::Path::new(source_name)
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("jsonl");
+
+    let source_save_name = format!("{}.source.{}", stem, source_ext);
+    fs::write(card_dir.join(&source_save_name), data)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(source_save_name)
+}
+
+async fn insert_history(
+    db: &DatabaseConnection,
+    card_id: Uuid,
+    save_name: String,
+    source_file_name: Option<String>,
+    file_size: i64,
+    format: &str,
+) -> Result<chat_history::Model, (StatusCode, String)> {
     let now = Utc::now().naive_utc();
     let history = chat_history::ActiveModel {
         id: Set(Uuid::new_v4()),
         card_id: Set(card_id),
         file_name: Set(save_name.clone()),
         display_name: Set(save_name),
-        source_file_name: Set(saved_source_name),
+        source_file_name: Set(source_file_name),
         file_size: Set(file_size),
         format: Set(format.to_string()),
         progress: Set(0),
         current_page: Set(1),
+        current_floor: Set(1),
         reading_settings: Set(None),
         regex_scripts: Set("[]".to_string()),
+        summary: Set(None),
+        memory: Set(None),
+        summarized_at: Set(None),
         created_at: Set(now),
         updated_at: Set(now),
     };
 
-    let saved = history
-        .insert(&db)
+    history
+        .insert(db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// 导入表单（preview 与 import 共用）
+struct ImportForm {
+    file_name: String,
+    text: String,
+    raw: Vec<u8>,
+    format: Option<ChatFormat>,
+    conversation: usize,
+    mapping: HashMap<String, SpeakerOverride>,
+}
+
+async fn read_import_form(mut multipart: Multipart) -> Result<ImportForm, (StatusCode, String)> {
+    let mut file: Option<(String, Vec<u8>)> = None;
+    let mut format = None;
+    let mut conversation = 0;
+    let mut mapping = HashMap::new();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+    {
+        let name = field.name().unwrap_or("").to_string();
+        match name.as_str() {
+            "file" => {
+                let file_name = field.file_name().unwrap_or("chat.txt").to_string();
+                let data = field
+                    .bytes()
+                    .await
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+                file = Some((file_name, data.to_vec()));
+            }
+            "format" => {
+                let value = field.text().await.unwrap_or_default();
+                if !value.is_empty() && value != "auto" {
+                    format = Some(
+                        serde_json::from_value(serde_json::Value::String(value.clone())).map_err(
+                            |_| {
+                                (
+                                    StatusCode::BAD_REQUEST,
+                                    format!("Unknown format: {}", value),
+                                )
+                            },
+                        )?,
+                    );
+                }
+            }
+            "conversation" => {
+                conversation = field.text().await.unwrap_or_default().parse().unwrap_or(0);
+            }
+            "mapping" => {
+                let value = field.text().await.unwrap_or_default();
+                if !value.trim().is_empty() {
+                    mapping = serde_json::from_str(&value).map_err(|e| {
+                        (StatusCode::BAD_REQUEST, format!("Invalid mapping: {}", e))
+                    })?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let (file_name, raw) =
+        file.ok_or((StatusCode::BAD_REQUEST, "Missing file field".to_string()))?;
+    let text = String::from_utf8(raw.clone())
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "File must be UTF-8 text".to_string(),
+            )
+        })?
+        .trim_start_matches('\u{feff}')
+        .to_string();
+
+    Ok(ImportForm {
+        file_name,
+        text,
+        raw,
+        format,
+        conversation,
+        mapping,
+    })
+}
+
+/// 识别格式并解析
+fn parse_import(form: &ImportForm) -> Result<ParsedChat, (StatusCode, String)> {
+    let format = form
+        .format
+        .or_else(|| chat_import::detect(&form.text))
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "无法识别的聊天记录格式".to_string(),
+        ))?;
+    chat_import::parse(&form.text, format, form.conversation)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+async fn card_name(db: &DatabaseConnection, card_id: Uuid) -> Result<String, (StatusCode, String)> {
+    CharacterCard::find_by_id(card_id)
+        .one(db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map(|c| c.name)
+        .ok_or((StatusCode::NOT_FOUND, "角色卡不存在".to_string()))
+}
+
+#[derive(Serialize)]
+pub struct ImportPreview {
+    pub format: ChatFormat,
+    pub format_label: &'static str,
+    pub title: Option<String>,
+    /// 文件包含多个对话时的标题列表
+    pub conversations: Vec<String>,
+    pub speakers: Vec<Speaker>,
+    pub message_count: usize,
+    /// 应用映射后的前几条消息
+    pub sample: Vec<ChatMessage>,
+}
+
+/// 预览中展示的消息条数
+const PREVIEW_SAMPLE: usize = 6;
+
+/// POST /api/cards/:id/history/import/preview - 识别格式与发言者，预览转换结果
+pub async fn preview_import(
+    State(db): State<DatabaseConnection>,
+    Path(card_id): Path<Uuid>,
+    multipart: Multipart,
+) -> Result<Json<ImportPreview>, (StatusCode, String)> {
+    let char_name = card_name(&db, card_id).await?;
+    let form = read_import_form(multipart).await?;
+    let parsed = parse_import(&form)?;
+    let log = parsed.to_chat_log(&char_name, &form.mapping);
+
+    Ok(Json(ImportPreview {
+        format: parsed.format,
+        format_label: parsed.format.label(),
+        title: parsed.title.clone(),
+        conversations: parsed.conversations.clone(),
+        speakers: parsed.mapped_speakers(&char_name, &form.mapping),
+        message_count: log.messages.len(),
+        sample: log
+            .messages
+            .iter()
+            .take(PREVIEW_SAMPLE)
+            .enumerate()
+            .map(|(i, m)| ChatMessage::from_st(m, (i + 1) as i32))
+            .collect(),
+    }))
+}
+
+/// 导入记录文件名主体的最大字节数
+const IMPORT_STEM_MAX_BYTES: usize = 150;
+
+/// POST /api/cards/:id/history/import - 转换为 JSONL 保存（原文件保留为 source）
+pub async fn import_history(
+    State(db): State<DatabaseConnection>,
+    Path(card_id): Path<Uuid>,
+    multipart: Multipart,
+) -> Result<Json<ChatHistoryDto>, (StatusCode, String)> {
+    let card_dir = crate::utils::paths::get_data_path("cards").join(card_id.to_string());
+    if !card_dir.exists() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "Character card directory not found".to_string(),
+        ));
+    }
+
+    let char_name = card_name(&db, card_id).await?;
+    let form = read_import_form(multipart).await?;
+    let parsed = parse_import(&form)?;
+    let jsonl = parsed.to_chat_log(&char_name, &form.mapping).to_jsonl();
+
+    let stem = parsed
+        .title
+        .clone()
+        .or_else(|| {
+            std::path::Path::new(&form.file_name)
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| "chat".to_string())
+        .replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "_");
+    // 标题可能很长：限制文件名字节数（还需容纳序号、.source 与索引文件前后缀）
+    let mut stem = stem.trim().to_string();
+    if stem.len() > IMPORT_STEM_MAX_BYTES {
+        let mut end = IMPORT_STEM_MAX_BYTES;
+        while !stem.is_char_boundary(end) {
+            end -= 1;
+        }
+        stem.truncate(end);
+    }
+    if stem.is_empty() {
+        stem = "chat".to_string();
+    }
+    let save_name = unique_file_name(&card_dir, &format!("{}.jsonl", stem));
+    let save_path = card_dir.join(&save_name);
+    fs::write(&save_path, &jsonl)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    // 后续步骤失败时清理已保存的文件
+    let source_name =
+        match save_source_file(&card_dir, &save_name, &form.file_name, &form.raw).await {
+            Ok(name) => name,
+            Err(e) => {
+                let _ = fs::remove_file(&save_path).await;
+                return Err(e);
+            }
+        };
+    let saved = match insert_history(
+        &db,
+        card_id,
+        save_name,
+        Some(source_name.clone()),
+        jsonl.len() as i64,
+        "jsonl",
+    )
+    .await
+    {
+        Ok(saved) => saved,
+        Err(e) => {
+            let _ = fs::remove_file(&save_path).await;
+            let _ = fs::remove_file(card_dir.join(&source_name)).await;
+            return Err(e);
+        }
+    };
+    reindex(save_path, true).await;
     Ok(Json(ChatHistoryDto::from(saved)))
 }
 
@@ -212,6 +490,7 @@ pub struct UpdateHistoryReq {
     pub display_name: Option<String>,
     pub progress: Option<i32>,
     pub current_page: Option<i32>,
+    pub current_floor: Option<i32>,
     pub reading_settings: Option<String>,
     pub regex_scripts: Option<String>,
 }
@@ -221,6 +500,11 @@ pub async fn update_history(
     Path((_card_id, history_id)): Path<(Uuid, Uuid)>,
     Json(payload): Json<UpdateHistoryReq>,
 ) -> Result<Json<ChatHistoryDto>, (StatusCode, String)> {
+    // 改名会移动文件，需与编辑操作互斥，并在加锁后读取当前文件名
+    let _guard = match payload.display_name {
+        Some(_) => Some(chat_edit::lock(history_id).await),
+        None => None,
+    };
     let history = ChatHistory::find_by_id(history_id)
         .one(&db)
         .await
@@ -267,6 +551,7 @@ pub async fn update_history(
                             format!("Failed to rename file: {}", e),
                         )
                     })?;
+                    chat_index::remove(&old_path);
                 }
 
                 active.file_name = Set(new_file_name.clone());
@@ -296,6 +581,7 @@ pub async fn update_history(
                                     format!("Failed to rename source file: {}", e),
                                 )
                             })?;
+                        chat_index::remove(&old_source_path);
 
                         active.source_file_name = Set(Some(new_source_name));
                     }
@@ -310,6 +596,9 @@ pub async fn update_history(
     if let Some(page) = payload.current_page {
         active.current_page = Set(page);
     }
+    if let Some(floor) = payload.current_floor {
+        active.current_floor = Set(floor.max(1));
+    }
     if let Some(settings) = payload.reading_settings {
         active.reading_settings = Set(Some(settings));
     }
@@ -333,6 +622,7 @@ pub async fn delete_history(
         .one(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .filter(|h| h.card_id == card_id)
         .ok_or((StatusCode::NOT_FOUND, "History not found".to_string()))?;
 
     // Delete file
@@ -340,6 +630,7 @@ pub async fn delete_history(
         .join(card_id.to_string())
         .join(&history.file_name);
 
+    chat_index::remove(&file_path);
     if file_path.exists() {
         fs::remove_file(file_path)
             .await
@@ -352,11 +643,16 @@ pub async fn delete_history(
             .join(card_id.to_string())
             .join(source_name);
 
+        chat_index::remove(&source_path);
         if source_path.exists() {
             let _ = fs::remove_file(source_path).await;
         }
     }
 
+    // Delete edit backups
+    let _ = fs::remove_dir_all(history_backup_dir(card_id, history_id)).await;
+    chat_stats::evict(history_id);
+
     // Delete DB record
     history
         .delete(&db)
@@ -368,27 +664,88 @@ pub async fn delete_history(
 
 use axum::extract::Query;
 
-use regex::Regex;
-
 #[derive(Serialize)]
 pub struct PaginatedContent {
     pub total_pages: usize,
     pub current_page: usize,
     pub floors: Vec<ChatMessage>,
+    pub total_floors: usize,
+    pub page_size: usize,
     pub detected_tags: Vec<String>,
-}
-
-#[derive(Serialize, Clone)]
-pub struct ChatMessage {
-    pub floor: i32,
-    pub name: String,
-    pub content: String,
+    /// JSONL 元数据头（TXT 为 None）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header: Option<ChatHeader>,
 }
 
 #[derive(Deserialize)]
 pub struct GetContentQuery {
     pub source: Option<bool>,
     pub page: Option<usize>,
+    /// 打开包含该楼层序号的页（优先于 page）
+    pub floor: Option<usize>,
+    /// 每页楼层数，默认取 reading_settings.page_size
+    pub page_size: Option<usize>,
+}
+
+/// 校验 JSONL 聊天记录：至少包含元数据头或一条消息
+fn validate_jsonl(data: &[u8]) -> Result<ChatLog, (StatusCode, String)> {
+    let text = std::str::from_utf8(data).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            "JSONL file must be UTF-8".to_string(),
+        )
+    })?;
+    let log = ChatLog::parse(text);
+    if log.is_empty() {
+        let detail = log
+            .errors
+            .first()
+            .map(|e| format!(" (line {}: {})", e.line, e.error))
+            .unwrap_or_default();
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Not a valid SillyTavern JSONL chat{}", detail),
+        ));
+    }
+    Ok(log)
+}
+
+/// 阅读器每页楼层数
+pub(crate) fn default_page_size(is_jsonl: bool) -> usize {
+    if is_jsonl {
+        2
+    } else {
+        30
+    }
+}
+
+/// 每页楼层数上限
+const MAX_PAGE_SIZE: usize = 200;
+
+/// 阅读设置中的每页楼层数（未设置时使用默认值）
+pub(crate) fn page_size_of(history: &chat_history::Model, is_jsonl: bool) -> usize {
+    history
+        .reading_settings
+        .as_deref()
+        .and_then(|s| serde_json::from_str::<serde_json::Value>(s).ok())
+        .and_then(|v| v.get("page_size").and_then(|n| n.as_u64()))
+        .map(|n| (n as usize).clamp(1, MAX_PAGE_SIZE))
+        .unwrap_or_else(|| default_page_size(is_jsonl))
+}
+
+/// 聊天记录在磁盘上的路径
+pub(crate) fn history_file_path(card_id: Uuid, file_name: &str) -> std::path::PathBuf {
+    crate::utils::paths::get_data_path("cards")
+        .join(card_id.to_string())
+        .join(file_name)
+}
+
+/// 楼层编辑前的备份目录
+pub(crate) fn history_backup_dir(card_id: Uuid, history_id: Uuid) -> std::path::PathBuf {
+    crate::utils::paths::get_data_path("cards")
+        .join(card_id.to_string())
+        .join("backups")
+        .join(history_id.to_string())
 }
 
 pub async fn get_history_content(
@@ -403,12 +760,12 @@ pub async fn get_history_content(
         .ok_or((StatusCode::NOT_FOUND, "History not found".to_string()))?;
 
     let target_file_name = if query.source.unwrap_or(false) {
-        history.source_file_name.ok_or((
+        history.source_file_name.clone().ok_or((
             StatusCode::NOT_FOUND,
             "No source file available".to_string(),
         ))?
     } else {
-        history.file_name
+        history.file_name.clone()
     };
 
     let file_path = crate::utils::paths::get_data_path("cards")
@@ -419,8 +776,8 @@ pub async fn get_history_content(
         return Err((StatusCode::NOT_FOUND, "File not found on disk".to_string()));
     }
 
-    // If 'page' is None, behavior = existing raw download
-    if query.page.is_none() {
+    // If 'page' / 'floor' is None, behavior = existing raw download
+    if query.page.is_none() && query.floor.is_none() {
         let file = fs::File::open(file_path)
             .await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -428,265 +785,131 @@ pub async fn get_history_content(
         return Ok(Body::from_stream(stream));
     }
 
-    // Pagination Logic
-    let page = query.page.unwrap().max(1);
-    // Let's use 30 for txt and 2 for jsonl (as users requested).
-
+    // Pagination Logic：按偏移索引只读取当前页的楼层
     let is_jsonl = history.format == "jsonl" || target_file_name.ends_with(".jsonl");
-    let current_page_size = if is_jsonl { 2 } else { 30 };
-
-    let content = fs::read_to_string(file_path)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    // Global Tag Detection
-    // Scan all unique tags from the raw content to support global filtering in UI
-    // Global Tag Detection
-    // Scan all unique tags from the raw content to support global filtering in UI
-    let detected_tags: Vec<String> = {
-        let tag_regex = Regex::new(r"</?([a-zA-Z0-9_\-\.\u4e00-\u9fa5]+)(?:\s[^>]*)?>").unwrap();
-        let mut tags_set = std::collections::HashSet::new();
-        // Expanded Common HTML tags to ignore (Blocklist)
-        let ignore = std::collections::HashSet::from([
-            "html",
-            "head",
-            "body",
-            "script",
-            "style",
-            "div",
-            "p",
-            "span",
-            "br",
-            "hr",
-            "img",
-            "a",
-            "b",
-            "i",
-            "u",
-            "s",
-            "strike",
-            "del",
-            "strong",
-            "em",
-            "code",
-            "pre",
-            "blockquote",
-            "thead",
-            "tbody",
-            "tfoot",
-            "tr",
-            "th",
-            "td",
-            "caption",
-            "ul",
-            "ol",
-            "li",
-            "dl",
-            "dt",
-            "dd",
-            "h1",
-            "h2",
-            "h3",
-            "h4",
-            "h5",
-            "h6",
-            "form",
-            "input",
-            "button",
-            "textarea",
-            "select",
-            "option",
-            "label",
-            "fieldset",
-            "legend",
-            "iframe",
-            "svg",
-            "path",
-            "canvas",
-            "audio",
-            "video",
-            "source",
-            "track",
-            "embed",
-            "object",
-            "nav",
-            "header",
-            "footer",
-            "main",
-            "section",
-            "article",
-            "aside",
-            "dialog",
-        ]);
-
-        let mut stack: Vec<String> = Vec::new();
-        // We only care about the structure, so we iterate through tags in order
-        for cap in tag_regex.captures_iter(&content) {
-            if let Some(m) = cap.get(0) {
-                let full_tag = m.as_str();
-                let is_close = full_tag.starts_with("</");
-                let tag_name_raw = cap.get(1).unwrap().as_str(); // Capture 1 is name
-                let tag_name = tag_name_raw.to_lowercase();
-
-                if ignore.contains(tag_name.as_str()) {
-                    continue; // Skip common HTML completely (treated as text)
-                }
-
-                if is_close {
-                    // Try to pop matching tag from stack (handle auto-closing / mismatch)
-                    // If we find the tag in the stack, pop everything up to it
-                    if let Some(pos) = stack.iter().rposition(|t| t == &tag_name) {
-                        stack.truncate(pos);
-                    }
-                } else {
-                    // Open Tag
-                    // Logic:
-                    // 1. If stack is empty -> Top Level -> Add
-                    // 2. If stack contains "content" -> Inside Content -> Add as "content_Name"
-                    // 3. Else -> Nested -> Ignore
-
-                    if !full_tag.trim().ends_with("/>") {
-                        stack.push(tag_name);
-                    }
-
-                    tags_set.insert(tag_name_raw.to_string());
-                }
-            }
-        }
-
-        let mut v: Vec<String> = tags_set.into_iter().collect();
-        v.sort(); // Consistent order
-        v
+    let current_page_size = query
+        .page_size
+        .map(|n| n.clamp(1, MAX_PAGE_SIZE))
+        .unwrap_or_else(|| page_size_of(&history, is_jsonl));
+    let page = match query.floor {
+        Some(floor) => (floor.max(1) - 1) / current_page_size + 1,
+        None => query.page.unwrap_or(1).max(1),
     };
 
-    let mut all_floors = Vec::new();
-
-    if is_jsonl {
-        // Line-by-line parsing
-        let lines: Vec<&str> = content.lines().filter(|l| !l.trim().is_empty()).collect();
-        let total_floors = lines.len();
-        let total_pages = (total_floors as f64 / current_page_size as f64).ceil() as usize;
-        let actual_page = page.min(total_pages).max(1);
-
-        if total_floors == 0 {
-            return Ok(Body::from(
-                serde_json::to_string(&PaginatedContent {
-                    total_pages: 1,
-                    current_page: 1,
-                    floors: vec![],
-                    detected_tags: detected_tags.clone(),
-                })
-                .unwrap(),
-            ));
-        }
+    let result = tokio::task::spawn_blocking(move || -> std::io::Result<PaginatedContent> {
+        let index = chat_index::load_or_build(&file_path, is_jsonl)?;
+        let total_floors = index.floors.len();
+        let total_pages = total_floors.div_ceil(current_page_size).max(1);
+        let actual_page = page.min(total_pages);
 
-        let start_idx = (actual_page - 1) * current_page_size;
+        let start_idx = ((actual_page - 1) * current_page_size).min(total_floors);
         let end_idx = (start_idx + current_page_size).min(total_floors);
+        let chunks = chat_index::read_floors(&file_path, &index, start_idx..end_idx)?;
+
+        let (floors, header) = if is_jsonl {
+            let floors = chunks
+                .iter()
+                .enumerate()
+                .map(|(idx, line)| {
+                    let value = serde_json::from_slice(line).unwrap_or_default();
+                    ChatMessage::from_st(
+                        &StMessage::from_value(value),
+                        (start_idx + idx + 1) as i32,
+                    )
+                })
+                .collect();
+            let header = chat_index::read_header(&file_path, &index)?
+                .and_then(|line| ChatLog::parse(&String::from_utf8_lossy(&line)).header);
+            (floors, header)
+        } else {
+            let floors = chunks
+                .iter()
+                .flat_map(|chunk| parse_txt_floors(&String::from_utf8_lossy(chunk)))
+                .collect();
+            (floors, None)
+        };
 
-        for (idx, line) in lines[start_idx..end_idx].iter().enumerate() {
-            // Parse line as JSON
-            if let Ok(json) = serde_json::from_str::<serde_json::Value>(line) {
-                // Try to find name and content fields
-                // Common formats: SillyTavern uses "name", "mes" or "message"
-                let name = json
-                    .get("name")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("Unknown")
-                    .to_string();
-                let content = json
-                    .get("mes")
-                    .or_else(|| json.get("message"))
-                    .or_else(|| json.get("content"))
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-
-                all_floors.push(ChatMessage {
-                    floor: (start_idx + idx + 1) as i32,
-                    name,
-                    content,
-                });
-            }
-        }
-
-        let result = PaginatedContent {
+        Ok(PaginatedContent {
             total_pages,
             current_page: actual_page,
-            floors: all_floors,
-            detected_tags,
-        };
-        return Ok(Body::from(serde_json::to_string(&result).unwrap()));
-    }
-
-    // Default TXT Parsing Logic
-    // ... existing logic ...
+            floors,
+            total_floors,
+            page_size: current_page_size,
+            detected_tags: index.tags,
+            header,
+        })
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    // Regex: Match the header line: [#123] 【Name】
-    // Then we capture everything until the next header or EOF.
-    let re_header = Regex::new(r"(?m)^\[#(\d+)\]\s*【(.*?)】\s*").unwrap();
-
-    let mut headers = Vec::new();
-    for mat in re_header.find_iter(&content) {
-        let caps = re_header.captures(mat.as_str()).unwrap();
-        let floor = caps[1].parse::<i32>().unwrap_or(0);
-        let name = caps[2].trim().to_string();
-        headers.push((mat.start(), mat.end(), floor, name));
-    }
-
-    for i in 0..headers.len() {
-        let (_start, end, floor, name) = headers[i].clone();
+    Ok(Body::from(serde_json::to_string(&result).map_err(|e| {
+        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    })?))
+}
 
-        let content_end = if i + 1 < headers.len() {
-            headers[i + 1].0
-        } else {
-            content.len()
+/// 按楼层序号读取多个楼层正文的纯文本预览（只加载一次偏移索引，不读取全文）
+pub(crate) async fn floor_previews(
+    history: &chat_history::Model,
+    floors: Vec<usize>,
+    chars: usize,
+) -> HashMap<usize, String> {
+    let is_jsonl = history.format == "jsonl" || history.file_name.ends_with(".jsonl");
+    let file_path = history_file_path(history.card_id, &history.file_name);
+    tokio::task::spawn_blocking(move || {
+        let Ok(index) = chat_index::load_or_build(&file_path, is_jsonl) else {
+            return HashMap::new();
         };
-
-        let body = content[end..content_end].trim().to_string();
-
-        all_floors.push(ChatMessage {
-            floor,
-            name,
-            content: body,
-        });
-    }
-
-    let total_floors = all_floors.len();
-    let total_pages = (total_floors as f64 / current_page_size as f64).ceil() as usize;
-    let actual_page = page.min(total_pages).max(1);
-
-    // If no floors found (e.g. empty file or format mismatch), handle gracefully
-    if total_floors == 0 {
-        return Ok(Body::from(
-            serde_json::to_string(&PaginatedContent {
-                total_pages: 1,
-                current_page: 1,
-                floors: vec![],
-                detected_tags: detected_tags.clone(),
+        floors
+            .into_iter()
+            .filter_map(|floor| {
+                let chunk =
+                    chat_index::read_floors(&file_path, &index, floor.checked_sub(1)?..floor)
+                        .ok()?
+                        .pop()?;
+                let content = if is_jsonl {
+                    StMessage::from_value(serde_json::from_slice(&chunk).ok()?).mes
+                } else {
+                    parse_txt_floors(&String::from_utf8_lossy(&chunk))
+                        .pop()?
+                        .content
+                };
+                let text = chat_export::to_plain_text(&content);
+                let text = text.trim();
+                let mut preview: String = text.chars().take(chars).collect();
+                if text.chars().count() > chars {
+                    preview.push('…');
+                }
+                Some((floor, preview))
             })
-            .unwrap(),
-        ));
-    }
-
-    let start_idx = (actual_page - 1) * current_page_size;
-    let end_idx = (start_idx + current_page_size).min(total_floors);
-
-    let page_floors = if start_idx < total_floors {
-        all_floors[start_idx..end_idx].to_vec()
-    } else {
-        vec![]
-    };
+            .collect()
+    })
+    .await
+    .unwrap_or_default()
+}
 
-    let result = PaginatedContent {
-        total_pages,
-        current_page: actual_page,
-        floors: page_floors,
-        detected_tags,
-    };
+/// 记录的楼层数（经偏移索引）
+pub(crate) async fn floor_count(
+    history: &chat_history::Model,
+) -> Result<usize, (StatusCode, String)> {
+    let is_jsonl = history.format == "jsonl" || history.file_name.ends_with(".jsonl");
+    let file_path = history_file_path(history.card_id, &history.file_name);
+    tokio::task::spawn_blocking(move || chat_index::load_or_build(&file_path, is_jsonl))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map(|index| index.floors.len())
+        .map_err(|_| (StatusCode::NOT_FOUND, "File not found on disk".to_string()))
+}
 
-    Ok(Body::from(serde_json::to_string(&result).map_err(|e| {
-        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    })?))
+/// 文件写入后重建偏移索引（失败只记录日志，翻页时会再次尝试）
+async fn reindex(file_path: std::path::PathBuf, is_jsonl: bool) {
+    let result =
+        tokio::task::spawn_blocking(move || chat_index::rebuild(&file_path, is_jsonl)).await;
+    match result {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => tracing::warn!("重建聊天记录索引失败: {}", e),
+        Err(e) => tracing::warn!("重建聊天记录索引失败: {}", e),
+    }
 }
 
 pub async fn update_history_content(
@@ -698,6 +921,7 @@ pub async fn update_history_content(
         .one(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .filter(|h| h.card_id == card_id)
         .ok_or((StatusCode::NOT_FOUND, "History not found".to_string()))?;
 
     let card_dir = crate::utils::paths::get_data_path("cards").join(card_id.to_string());
@@ -720,13 +944,24 @@ pub async fn update_history_content(
     }
 
     let data = file_data.ok_or((StatusCode::BAD_REQUEST, "Missing file content".to_string()))?;
+    let is_jsonl = history.format == "jsonl" || history.file_name.ends_with(".jsonl");
+    if is_jsonl {
+        validate_jsonl(&data)?;
+    }
     let file_size = data.len() as i64;
 
-    // Overwrite existing file
+    // Overwrite existing file (previous version kept for undo)
+    let _guard = chat_edit::lock(history_id).await;
     let file_path = card_dir.join(&history.file_name);
-    fs::write(&file_path, &data)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    chat_edit::save_with_backup(
+        &file_path,
+        &history_backup_dir(card_id, history_id),
+        &data,
+        None,
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    reindex(file_path, is_jsonl).await;
 
     // Update DB
     let mut active: chat_history::ActiveModel = history.into();
@@ -740,3 +975,718 @@ pub async fn update_history_content(
 
     Ok(Json(ChatHistoryDto::from(updated)))
 }
+
+use crate::services::chat_export::{self, Avatar, ExportMessage, ExportOptions, ExportSource};
+use crate::services::st_regex::ScriptSet;
+
+/// 读取角色卡头像（默认头像视为无头像）
+async fn read_avatar(avatar: Option<&str>) -> Option<Avatar> {
+    let path = avatar.filter(|p| !p.is_empty() && *p != "/default.webp")?;
+    let file_path = crate::utils::paths::get_data_dir().join(path.trim_start_matches('/'));
+    let mime = match file_path.extension().and_then(|s| s.to_str()) {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        _ => "image/webp",
+    };
+    let bytes = fs::read(&file_path).await.ok()?;
+    Some(Avatar { bytes, mime })
+}
+
+/// 导出聊天记录为 HTML / Markdown / EPUB（应用保存的正则与阅读设置）
+pub async fn export_history(
+    State(db): State<DatabaseConnection>,
+    Path((card_id, history_id)): Path<(Uuid, Uuid)>,
+    Query(options): Query<ExportOptions>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let history = ChatHistory::find_by_id(history_id)
+        .one(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .filter(|h| h.card_id == card_id)
+        .ok_or((StatusCode::NOT_FOUND, "History not found".to_string()))?;
+    let card = CharacterCard::find_by_id(card_id)
+        .one(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "角色卡不存在".to_string()))?;
+
+    let file_path = history_file_path(card_id, &history.file_name);
+    let content = fs::read_to_string(&file_path)
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "File not found on disk".to_string()))?;
+
+    let is_jsonl = history.format == "jsonl" || history.file_name.ends_with(".jsonl");
+    let (user_name, messages) = if is_jsonl {
+        let log = ChatLog::parse(&content);
+        let user_name = log
+            .header
+            .as_ref()
+            .map(|h| h.user_name.clone())
+            .filter(|n| !n.is_empty());
+        let messages = log
+            .messages
+            .iter()
+            .enumerate()
+            .map(|(idx, m)| ExportMessage {
+                floor: (idx + 1) as i32,
+                name: m.name.clone(),
+                is_user: m.is_user,
+                is_system: m.is_system,
+                send_date: m.send_date.clone(),
+                content: m.mes.clone(),
+            })
+            .collect::<Vec<_>>();
+        (user_name, messages)
+    } else {
+        // TXT 没有角色信息，名称与角色卡不同的楼层视为用户
+        let messages = parse_txt_floors(&content)
+            .into_iter()
+            .map(|f| ExportMessage {
+                is_user: f.name != card.name,
+                floor: f.floor,
+                name: f.name,
+                is_system: false,
+                send_date: None,
+                content: f.content,
+            })
+            .collect::<Vec<_>>();
+        (None, messages)
+    };
+    let user_name = user_name
+        .or_else(|| messages.iter().find(|m| m.is_user).map(|m| m.name.clone()))
+        .unwrap_or_else(|| "User".to_string());
+
+    let scripts = ScriptSet::for_history(&history, Some(&card), &user_name);
+
+    let source = ExportSource {
+        title: history.display_name.clone(),
+        character_name: card.name.clone(),
+        user_name,
+        avatar: read_avatar(card.avatar.as_deref()).await,
+        messages,
+        scripts,
+        hidden_tags: chat_export::hidden_tags(history.reading_settings.as_deref()),
+        plain_text: !is_jsonl,
+    };
+    let format = options.format;
+    let data = tokio::task::spawn_blocking(move || chat_export::export(source, &options))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let title = history.display_name.trim();
+    let title = if title.is_empty() { "chat" } else { title };
+    let safe_filename: String = title
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let disposition = format!(
+        "attachment; filename=\"{}.{}\"; filename*=UTF-8''{}.{}",
+        safe_filename,
+        format.extension(),
+        urlencoding::encode(title),
+        format.extension()
+    );
+
+    Ok((
+        [
+            ("Content-Type", format.content_type().to_string()),
+            ("Content-Disposition", disposition),
+        ],
+        data,
+    ))
+}
+
+use crate::services::chat_search::{self, Matcher, SearchFloor, SearchQuery, SearchResult};
+
+/// 读取聊天记录并转换为搜索楼层，同时返回元数据头中的用户名
+async fn load_search_floors(
+    history: &chat_history::Model,
+    card_name: &str,
+) -> Result<(Vec<SearchFloor>, Option<String>), (StatusCode, String)> {
+    let file_path = history_file_path(history.card_id, &history.file_name);
+    let content = fs::read_to_string(&file_path)
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "File not found on disk".to_string()))?;
+
+    let is_jsonl = history.format == "jsonl" || history.file_name.ends_with(".jsonl");
+    if !is_jsonl {
+        let floors = parse_txt_floors(&content)
+            .into_iter()
+            .map(|f| SearchFloor {
+                floor: f.floor,
+                name: f.name,
+                is_user: None,
+                send_date: None,
+                content: f.content,
+            })
+            .collect();
+        return Ok((floors, None));
+    }
+
+    let log = ChatLog::parse(&content);
+    let user_name = log
+        .header
+        .map(|h| h.user_name)
+        .filter(|n| !n.is_empty() && n != card_name);
+    let floors = log
+        .messages
+        .into_iter()
+        .enumerate()
+        .map(|(idx, m)| SearchFloor {
+            floor: (idx + 1) as i32,
+            name: m.name,
+            is_user: Some(m.is_user),
+            send_date: m.send_date,
+            content: m.mes,
+        })
+        .collect();
+    Ok((floors, user_name))
+}
+
+/// 在若干聊天记录中搜索
+async fn run_search(
+    histories: Vec<chat_history::Model>,
+    card: &crate::entities::character_card::Model,
+    query: SearchQuery,
+) -> Result<SearchResult, (StatusCode, String)> {
+    let matcher = Matcher::new(&query).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    // 搜索多份记录时跳过缺失的文件
+    let single = histories.len() == 1;
+    let mut loaded = Vec::new();
+    for history in histories {
+        match load_search_floors(&history, &card.name).await {
+            Ok((floors, user_name)) => loaded.push((history, floors, user_name)),
+            Err(e) if single => return Err(e),
+            Err(e) => tracing::warn!("搜索时跳过聊天记录 {}: {}", history.display_name, e.1),
+        }
+    }
+
+    let card = card.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut result = SearchResult::default();
+        for (history, floors, user_name) in loaded {
+            let is_jsonl = history.format == "jsonl" || history.file_name.ends_with(".jsonl");
+            let page_size = query
+                .page_size
+                .filter(|&s| s > 0)
+                .unwrap_or_else(|| page_size_of(&history, is_jsonl));
+            let scripts = ScriptSet::for_history(
+                &history,
+                Some(&card),
+                user_name.as_deref().unwrap_or("User"),
+            );
+            chat_search::search_history(
+                history.id,
+                &history.display_name,
+                &floors,
+                &scripts,
+                page_size,
+                &matcher,
+                &mut result,
+            );
+        }
+        result
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// 搜索单个聊天记录
+pub async fn search_history(
+    State(db): State<DatabaseConnection>,
+    Path((card_id, history_id)): Path<(Uuid, Uuid)>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResult>, (StatusCode, String)> {
+    let history = ChatHistory::find_by_id(history_id)
+        .one(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .filter(|h| h.card_id == card_id)
+        .ok_or((StatusCode::NOT_FOUND, "History not found".to_string()))?;
+    let card = CharacterCard::find_by_id(card_id)
+        .one(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "角色卡不存在".to_string()))?;
+
+    Ok(Json(run_search(vec![history], &card, query).await?))
+}
+
+/// 搜索角色卡下的全部聊天记录
+pub async fn search_card_histories(
+    State(db): State<DatabaseConnection>,
+    Path(card_id): Path<Uuid>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResult>, (StatusCode, String)> {
+    let card = CharacterCard::find_by_id(card_id)
+        .one(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "角色卡不存在".to_string()))?;
+    let histories = ChatHistory::find()
+        .filter(chat_history::Column::CardId.eq(card_id))
+        .order_by_desc(chat_history::Column::CreatedAt)
+        .all(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(run_search(histories, &card, query).await?))
+}
+
+use crate::services::chat_stats::{ChatStats, StatsMessage};
+use std::sync::Arc;
+
+/// 统计单份聊天记录（按文件大小与修改时间缓存）
+pub(crate) async fn history_stats(
+    history: &chat_history::Model,
+) -> Result<Arc<ChatStats>, (StatusCode, String)> {
+    let file_path = history_file_path(history.card_id, &history.file_name);
+    let stamp = chat_index::file_stamp(&file_path)
+        .map_err(|_| (StatusCode::NOT_FOUND, "File not found on disk".to_string()))?;
+    if let Some(stats) = chat_stats::cached(history.id, stamp) {
+        return Ok(stats);
+    }
+
+    let content = fs::read_to_string(&file_path)
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "File not found on disk".to_string()))?;
+    let is_jsonl = history.format == "jsonl" || history.file_name.ends_with(".jsonl");
+
+    let stats = tokio::task::spawn_blocking(move || {
+        let messages: Vec<StatsMessage> = if is_jsonl {
+            ChatLog::parse(&content)
+                .messages
+                .into_iter()
+                .map(|m| StatsMessage {
+                    name: m.name,
+                    is_user: Some(m.is_user),
+                    is_system: m.is_system,
+                    send_date: m.send_date,
+                    content: m.mes,
+                    token_count: m.token_count,
+                    model: m.model,
+                    api: m.api,
+                })
+                .collect()
+        } else {
+            parse_txt_floors(&content)
+                .into_iter()
+                .map(|f| StatsMessage {
+                    name: f.name,
+                    is_user: None,
+                    is_system: false,
+                    send_date: None,
+                    content: f.content,
+                    token_count: None,
+                    model: None,
+                    api: None,
+                })
+                .collect()
+        };
+        ChatStats::compute(&messages, &content)
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let stats = Arc::new(stats);
+    chat_stats::store(history.id, stamp, stats.clone());
+    Ok(stats)
+}
+
+/// 汇总多份聊天记录的统计（跳过缺失的文件）
+pub(crate) async fn merged_stats(histories: &[chat_history::Model]) -> ChatStats {
+    let mut all = Vec::with_capacity(histories.len());
+    for history in histories {
+        match history_stats(history).await {
+            Ok(stats) => all.push(stats),
+            Err(e) => tracing::warn!("统计时跳过聊天记录 {}: {}", history.display_name, e.1),
+        }
+    }
+    ChatStats::merge(&all)
+}
+
+/// 单份聊天记录的统计
+pub async fn get_history_stats(
+    State(db): State<DatabaseConnection>,
+    Path((card_id, history_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<ChatStats>, (StatusCode, String)> {
+    let history = ChatHistory::find_by_id(history_id)
+        .one(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .filter(|h| h.card_id == card_id)
+        .ok_or((StatusCode::NOT_FOUND, "History not found".to_string()))?;
+
+    let stats = history_stats(&history).await?;
+    Ok(Json(stats.as_ref().clone()))
+}
+
+/// 角色卡下全部聊天记录的汇总统计
+pub async fn get_card_history_stats(
+    State(db): State<DatabaseConnection>,
+    Path(card_id): Path<Uuid>,
+) -> Result<Json<ChatStats>, (StatusCode, String)> {
+    let histories = ChatHistory::find()
+        .filter(chat_history::Column::CardId.eq(card_id))
+        .all(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(merged_stats(&histories).await))
+}
+
+#[derive(Deserialize)]
+pub struct EditFloorsReq {
+    pub ops: Vec<FloorOp>,
+}
+
+#[derive(Serialize)]
+pub struct EditFloorsResponse {
+    pub history: ChatHistoryDto,
+    pub total_floors: usize,
+    pub total_pages: usize,
+    /// 可撤销的次数
+    pub undo_depth: usize,
+}
+
+/// 写入后更新文件大小、时间与阅读进度，并返回楼层概况
+async fn finish_edit(
+    db: &impl ConnectionTrait,
+    history: chat_history::Model,
+    content: &str,
+    current_floor: usize,
+) -> Result<EditFloorsResponse, (StatusCode, String)> {
+    let is_jsonl = history.format == "jsonl" || history.file_name.ends_with(".jsonl");
+    let total_floors = parse_floors(content, is_jsonl).len();
+    let page_size = page_size_of(&history, is_jsonl);
+    let backup_dir = history_backup_dir(history.card_id, history.id);
+
+    let mut active: chat_history::ActiveModel = history.into();
+    active.file_size = Set(content.len() as i64);
+    active.current_floor = Set(current_floor.clamp(1, total_floors.max(1)) as i32);
+    active.updated_at = Set(Utc::now().naive_utc());
+    let updated = active
+        .update(db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(EditFloorsResponse {
+        history: ChatHistoryDto::from(updated),
+        total_floors,
+        total_pages: total_floors.div_ceil(page_size).max(1),
+        undo_depth: chat_edit::undo_depth(&backup_dir).await,
+    })
+}
+
+/// 保存楼层修改：书签与阅读进度在同一事务中按楼层映射同步，
+/// 被删除楼层上的书签随备份保存（撤销时恢复），文件写入成功后才提交
+async fn save_edit(
+    db: &DatabaseConnection,
+    history: chat_history::Model,
+    file_path: &std::path::Path,
+    output: String,
+    floors: FloorMap,
+) -> Result<EditFloorsResponse, (StatusCode, String)> {
+    let txn = db
+        .begin()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let bookmarks = chat_bookmarks::remap_floors(&txn, history.id, &floors).await?;
+    let current_floor = floors.position(history.current_floor.max(1) as usize);
+    let record = EditRecord {
+        floors: Some(floors),
+        bookmarks,
+    };
+    chat_edit::save_with_backup(
+        file_path,
+        &history_backup_dir(history.card_id, history.id),
+        output.as_bytes(),
+        Some(&record),
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let response = finish_edit(&txn, history, &output, current_floor).await?;
+    txn.commit()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    reindex(file_path.to_path_buf(), true).await;
+    Ok(response)
+}
+
+/// 读取 JSONL 记录用于改写
+async fn read_chat_log(
+    card_id: Uuid,
+    history: &chat_history::Model,
+) -> Result<(std::path::PathBuf, ChatLog), (StatusCode, String)> {
+    if history.format != "jsonl" && !history.file_name.ends_with(".jsonl") {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "只有 JSONL 聊天记录支持楼层编辑".to_string(),
+        ));
+    }
+    let file_path = history_file_path(card_id, &history.file_name);
+    let content = fs::read_to_string(&file_path)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let log = ChatLog::parse(&content);
+    // 重新序列化会丢弃无法解析的行，因此拒绝编辑
+    if let Some(error) = log.errors.first() {
+        return Err((
+            StatusCode::CONFLICT,
+            format!(
+                "记录 {} 第 {} 行无法解析（{}），请先修复后再编辑",
+                history.display_name, error.line, error.error
+            ),
+        ));
+    }
+    Ok((file_path, log))
+}
+
+/// POST /api/cards/{id}/history/{history_id}/floors - 按楼层编辑 JSONL 聊天记录
+pub async fn edit_floors(
+    State(db): State<DatabaseConnection>,
+    Path((card_id, history_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<EditFloorsReq>,
+) -> Result<Json<EditFloorsResponse>, (StatusCode, String)> {
+    if payload.ops.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "没有要执行的操作".to_string()));
+    }
+    let history = ChatHistory::find_by_id(history_id)
+        .one(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .filter(|h| h.card_id == card_id)
+        .ok_or((StatusCode::NOT_FOUND, "History not found".to_string()))?;
+
+    let _guard = chat_edit::lock(history_id).await;
+    let (file_path, mut log) = read_chat_log(card_id, &history).await?;
+    let floors = FloorMap::for_ops(log.messages.len(), &payload.ops);
+    chat_edit::apply_ops(&mut log, &payload.ops).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    Ok(Json(
+        save_edit(&db, history, &file_path, log.to_jsonl(), floors).await?,
+    ))
+}
+
+/// POST /api/cards/{id}/history/{history_id}/undo - 撤销最近一次内容修改
+pub async fn undo_history_edit(
+    State(db): State<DatabaseConnection>,
+    Path((card_id, history_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<EditFloorsResponse>, (StatusCode, String)> {
+    let history = ChatHistory::find_by_id(history_id)
+        .one(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .filter(|h| h.card_id == card_id)
+        .ok_or((StatusCode::NOT_FOUND, "History not found".to_string()))?;
+
+    let _guard = chat_edit::lock(history_id).await;
+    let file_path = history_file_path(card_id, &history.file_name);
+    let backup = chat_edit::latest_backup(&history_backup_dir(card_id, history_id))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::CONFLICT, "没有可撤销的修改".to_string()))?;
+    let record = backup.record.clone().unwrap_or_default();
+    let is_jsonl = history.format == "jsonl" || history.file_name.ends_with(".jsonl");
+
+    // 书签与阅读进度按反向映射还原，并恢复随楼层删除的书签；文件还原成功后才提交
+    let txn = db
+        .begin()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut current_floor = history.current_floor.max(1) as usize;
+    if let Some(floors) = record.floors.map(|f| f.inverse()) {
+        chat_bookmarks::remap_floors(&txn, history_id, &floors).await?;
+        current_floor = floors.position(current_floor);
+    }
+    chat_bookmarks::restore_bookmarks(&txn, record.bookmarks).await?;
+    chat_edit::restore(&file_path, &backup)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let content = String::from_utf8_lossy(&backup.data);
+    let response = finish_edit(&txn, history, &content, current_floor).await?;
+    txn.commit()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    reindex(file_path, is_jsonl).await;
+    Ok(Json(response))
+}
+
+/// GET /api/cards/{id}/history/branches - 按公共前缀推断的分支树（仅 JSONL 记录）
+pub async fn get_branch_tree(
+    State(db): State<DatabaseConnection>,
+    Path(card_id): Path<Uuid>,
+) -> Result<Json<Vec<BranchNode>>, (StatusCode, String)> {
+    let histories: Vec<chat_history::Model> = ChatHistory::find()
+        .filter(chat_history::Column::CardId.eq(card_id))
+        .order_by_asc(chat_history::Column::CreatedAt)
+        .all(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .into_iter()
+        .filter(|h| h.format == "jsonl" || h.file_name.ends_with(".jsonl"))
+        .collect();
+
+    let nodes = tokio::task::spawn_blocking(move || {
+        let inputs: Vec<BranchInput> = histories
+            .into_iter()
+            .filter_map(|history| {
+                let path = history_file_path(card_id, &history.file_name);
+                let info = match chat_index::branch_info(&path) {
+                    Ok(info) => info,
+                    Err(e) => {
+                        tracing::warn!("分支树跳过聊天记录 {}: {}", history.display_name, e);
+                        return None;
+                    }
+                };
+                Some(BranchInput {
+                    history_id: history.id,
+                    file_stem: std::path::Path::new(&history.file_name)
+                        .file_stem()
+                        .and_then(|s| s.to_str())
+                        .unwrap_or_default()
+                        .to_string(),
+                    name: history.display_name,
+                    fingerprints: info.fingerprints,
+                    main_chat: info.main_chat,
+                })
+            })
+            .collect();
+        chat_branch::build_tree(&inputs)
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(nodes))
+}
+
+#[derive(Deserialize)]
+pub struct BranchReq {
+    /// 保留到第几层
+    pub floor: usize,
+    /// 新记录名称（不含扩展名）
+    pub name: Option<String>,
+}
+
+/// POST /api/cards/{id}/history/{history_id}/branch - 从指定楼层分支为新记录
+pub async fn branch_history(
+    State(db): State<DatabaseConnection>,
+    Path((card_id, history_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<BranchReq>,
+) -> Result<Json<ChatHistoryDto>, (StatusCode, String)> {
+    let history = ChatHistory::find_by_id(history_id)
+        .one(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .filter(|h| h.card_id == card_id)
+        .ok_or((StatusCode::NOT_FOUND, "History not found".to_string()))?;
+    let (_, log) = read_chat_log(card_id, &history).await?;
+
+    let stem = std::path::Path::new(&history.file_name)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("chat")
+        .to_string();
+    let branched = chat_branch::branch(&log, payload.floor, &stem)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let name = payload
+        .name
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{} - 分支 #{}", stem, payload.floor))
+        .replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "_");
+    let card_dir = crate::utils::paths::get_data_path("cards").join(card_id.to_string());
+    let save_name = unique_file_name(&card_dir, &format!("{}.jsonl", name));
+    let jsonl = branched.to_jsonl();
+    chat_edit::write_atomic(&card_dir.join(&save_name), jsonl.as_bytes())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    reindex(card_dir.join(&save_name), true).await;
+
+    let saved = insert_history(&db, card_id, save_name, None, jsonl.len() as i64, "jsonl").await?;
+    // 分支沿用来源记录的正则与阅读设置
+    let mut active: chat_history::ActiveModel = saved.into();
+    active.regex_scripts = Set(history.regex_scripts);
+    active.reading_settings = Set(history.reading_settings);
+    let saved = active
+        .update(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(ChatHistoryDto::from(saved)))
+}
+
+#[derive(Deserialize)]
+pub struct SpliceReq {
+    /// 要接入的分支记录
+    pub source_id: Uuid,
+    /// 目标记录保留到第几层，默认为两者的公共前缀长度
+    pub at: Option<usize>,
+    /// 从分支第几层开始取，默认为公共前缀之后的第一层
+    pub from: Option<usize>,
+    /// 是否丢弃目标记录 at 之后的楼层，默认 true
+    pub replace_tail: Option<bool>,
+}
+
+/// POST /api/cards/{id}/history/{history_id}/splice - 将分支内容拼接进当前记录（可撤销）
+pub async fn splice_history(
+    State(db): State<DatabaseConnection>,
+    Path((card_id, history_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<SpliceReq>,
+) -> Result<Json<EditFloorsResponse>, (StatusCode, String)> {
+    if payload.source_id == history_id {
+        return Err((StatusCode::BAD_REQUEST, "不能拼接记录自身".to_string()));
+    }
+    let history = ChatHistory::find_by_id(history_id)
+        .one(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .filter(|h| h.card_id == card_id)
+        .ok_or((StatusCode::NOT_FOUND, "History not found".to_string()))?;
+    let source = ChatHistory::find_by_id(payload.source_id)
+        .one(&db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .filter(|h| h.card_id == card_id)
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            "Source history not found".to_string(),
+        ))?;
+
+    let _guard = chat_edit::lock(history_id).await;
+    let (file_path, mut log) = read_chat_log(card_id, &history).await?;
+    let (_, source_log) = read_chat_log(card_id, &source).await?;
+
+    let shared = chat_branch::common_prefix(
+        &chat_branch::fingerprints(&log),
+        &chat_branch::fingerprints(&source_log),
+    );
+    let options = SpliceOptions {
+        at: payload.at.unwrap_or(shared),
+        from: payload.from.unwrap_or(shared + 1),
+        replace_tail: payload.replace_tail.unwrap_or(true),
+    };
+    let before = log.messages.len();
+    let inserted = chat_branch::splice(&mut log, &source_log, &options)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let floors = FloorMap::for_splice(before, options.at, inserted, options.replace_tail);
+
+    Ok(Json(
+        save_edit(&db, history, &file_path, log.to_jsonl(), floors).await?,
+    ))
+}
@@ -49,6 +49,26 @@ pub struct UpdateImageRequest {
     pub user_notes: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    /// 导入后使用视觉模型生成描述与标签
+    #[serde(default)]
+    pub caption: bool,
+}
+
+#[derive(Deserialize)]
+pub struct BatchCaptionRequest {
+    pub ids: Option<Vec<Uuid>>,
+    pub category_id: Option<Uuid>,
+    /// 仅处理尚未生成描述的图片（默认 true）
+    #[serde(default = "default_true")]
+    pub uncaptioned_only: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
 #[derive(Deserialize)]
 pub struct BatchDeleteRequest {
     pub ids: Vec<Uuid>,
@@ -88,6 +108,8 @@ pub struct ImageResponse {
     pub user_notes: Option<String>,
     /// 关联的角色卡 ID 列表
     pub char_cards: Vec<Uuid>,
+    pub ai_caption: Option<String>,
+    pub captioned_at: Option<String>,
     pub created_at: String,
 }
 
@@ -137,6 +159,10 @@ impl From<image_entity::Model> for ImageResponse {
             is_favorite: m.is_favorite,
             user_notes: m.user_notes,
             char_cards,
+            ai_caption: m.ai_caption,
+            captioned_at: m
+                .captioned_at
+                .map(|t| Utc.from_utc_datetime(&t).to_rfc3339()),
             created_at: Utc.from_utc_datetime(&m.created_at).to_rfc3339(),
         }
     }
@@ -411,7 +437,8 @@ pub async fn list(
         let search_condition = Condition::any()
             .add(image_entity::Column::Title.contains(search))
             .add(image_entity::Column::Tags.contains(search))
-            .add(image_entity::Column::AiPrompt.contains(search));
+            .add(image_entity::Column::AiPrompt.contains(search))
+            .add(image_entity::Column::AiCaption.contains(search));
         select = select.filter(search_condition);
     }
 
@@ -494,9 +521,11 @@ pub async fn get(
 /// POST /api/images - 导入图片
 pub async fn import(
     State(db): State<DatabaseConnection>,
+    Query(query): Query<ImportQuery>,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
     let mut imported_ids: Vec<Uuid> = Vec::new();
+    let mut imported: Vec<image_entity::Model> = Vec::new();
 
     while let Some(field) = multipart.next_field().await.map_err(|e| {
         (
@@ -621,10 +650,13 @@ pub async fn import(
             is_favorite: Set(false),
             user_notes: Set(None),
             char_cards: Set("[]".to_string()),
+            ai_caption: Set(None),
+            ai_tags: Set(None),
+            captioned_at: Set(None),
             created_at: Set(Utc::now().naive_utc()),
         };
 
-        new_image.insert(&db).await.map_err(|e| {
+        let saved = new_image.insert(&db).await.map_err(|e| {
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({ "error": format!("保存到数据库失败: {}", e) })),
@@ -632,14 +664,117 @@ pub async fn import(
         })?;
 
         imported_ids.push(id);
+        imported.push(saved);
+    }
+
+    // 可选：后台生成描述与标签（未配置视觉渠道时跳过）
+    let mut caption_job_id = None;
+    if query.caption && !imported.is_empty() {
+        match crate::services::vision::CaptionContext::load(&db).await {
+            Ok(Some(ctx)) => {
+                caption_job_id = Some(crate::services::vision::spawn_caption_job(
+                    db.clone(),
+                    ctx,
+                    imported,
+                ));
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Skip image captioning: {}", e),
+        }
     }
 
     Ok((
         StatusCode::CREATED,
-        Json(json!({ "imported": imported_ids.len(), "ids": imported_ids })),
+        Json(json!({
+            "imported": imported_ids.len(),
+            "ids": imported_ids,
+            "caption_job_id": caption_job_id
+        })),
     ))
 }
 
+/// 加载视觉渠道上下文，未配置时返回 400
+async fn caption_context(
+    db: &DatabaseConnection,
+) -> Result<crate::services::vision::CaptionContext, (StatusCode, Json<Value>)> {
+    crate::services::vision::CaptionContext::load(db)
+        .await
+        .map_err(|e| (e.status_code(), Json(json!({ "error": e.to_string() }))))?
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "没有配置视觉模型，请到设置页面完成配置" })),
+            )
+        })
+}
+
+/// POST /api/images/:id/caption - 为单张图片生成描述与标签
+pub async fn caption(
+    State(db): State<DatabaseConnection>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let image = crate::services::vision::live_image(&db, id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": e.to_string() })),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "图片不存在或已删除" })),
+            )
+        })?;
+    let ctx = caption_context(&db).await?;
+
+    let updated = crate::services::vision::caption_image(&db, &ctx, image)
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, Json(json!({ "error": e }))))?;
+    Ok(Json(ImageResponse::from(updated)))
+}
+
+/// POST /api/images/batch/caption - 批量生成描述与标签（后台任务）
+pub async fn batch_caption(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<BatchCaptionRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    let ctx = caption_context(&db).await?;
+
+    let mut select = image_entity::Entity::find();
+    if let Some(ids) = payload.ids {
+        select = select.filter(image_entity::Column::Id.is_in(ids));
+    }
+    if let Some(category_id) = payload.category_id {
+        select = select.filter(image_entity::Column::CategoryId.eq(category_id));
+    }
+    if payload.uncaptioned_only {
+        select = select.filter(image_entity::Column::CaptionedAt.is_null());
+    }
+    let images = select
+        .order_by_asc(image_entity::Column::CreatedAt)
+        .all(&db)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": e.to_string() })),
+            )
+        })?;
+
+    if images.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "没有符合条件的图片" })),
+        ));
+    }
+
+    let total = images.len();
+    let job_id = crate::services::vision::spawn_caption_job(db, ctx, images);
+    Ok(Json(json!({ "job_id": job_id, "total": total })))
+}
+
 /// PATCH /api/images/:id - 更新图片元数据
 pub async fn update(
     State(db): State<DatabaseConnection>,
@@ -0,0 +1,37 @@
+//! 后台任务 API
+//!
+//! 查询批量任务进度、取消任务
+
+use crate::services::jobs;
+use axum::{extract::Path, http::StatusCode, response::IntoResponse, Json};
+use serde_json::Value;
+use uuid::Uuid;
+
+/// GET /api/jobs - 任务列表
+pub async fn list_jobs() -> impl IntoResponse {
+    Json(jobs::list())
+}
+
+/// GET /api/jobs/{id} - 任务进度
+pub async fn get_job(Path(id): Path<Uuid>) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    jobs::get(id).map(Json).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "任务不存在或已过期"})),
+        )
+    })
+}
+
+/// POST /api/jobs/{id}/cancel - 取消任务（已开始的单项会继续完成）
+pub async fn cancel_job(
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    if jobs::request_cancel(id) {
+        Ok(StatusCode::ACCEPTED)
+    } else {
+        Err((
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "任务不存在或已结束"})),
+        ))
+    }
+}
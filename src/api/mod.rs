@@ -3,15 +3,22 @@
 //! 定义所有 RESTful API 路由
 
 pub mod ai;
+pub mod ai_batch;
+pub mod ai_generate;
 pub mod backup;
 pub mod cards;
 pub mod categories;
+pub mod chat_bookmarks;
 pub mod dashboard;
+pub mod embeddings;
 pub mod frontend_style;
 pub mod history;
 pub mod image_categories;
 pub mod images;
+pub mod jobs;
+pub mod prompts;
 pub mod quick_reply;
+pub mod regex;
 pub mod settings;
 pub mod theater;
 pub mod upload;
@@ -33,6 +40,7 @@ pub fn routes(db: DatabaseConnection) -> Router {
         .route("/settings", patch(settings::update))
         // 仪表盘
         .route("/dashboard", get(dashboard::get_dashboard_stats))
+        .route("/dashboard/chat-stats", get(dashboard::get_chat_stats))
         .route("/gacha/draw", post(dashboard::start_gacha))
         .route("/gacha/reveal", post(dashboard::reveal_gacha))
         .route("/gacha/confirm", post(dashboard::confirm_gacha))
@@ -53,6 +61,7 @@ pub fn routes(db: DatabaseConnection) -> Router {
         )
         .route("/cards/{id}/cover", post(cards::update_cover))
         .route("/cards/{id}/export", get(cards::export_card))
+        .route("/cards/{id}/similar", get(embeddings::similar_cards))
         .route("/cards/batch/category", put(cards::batch_update_category))
         .route("/cards/batch/delete", post(cards::batch_soft_delete))
         .route("/cards/batch/export", post(cards::batch_export_cards))
@@ -74,6 +83,23 @@ pub fn routes(db: DatabaseConnection) -> Router {
             "/cards/{id}/history",
             get(history::list_history).post(history::upload_history),
         )
+        .route(
+            "/cards/{id}/history/import/preview",
+            post(history::preview_import),
+        )
+        .route("/cards/{id}/history/import", post(history::import_history))
+        .route(
+            "/cards/{id}/history/search",
+            get(history::search_card_histories),
+        )
+        .route(
+            "/cards/{id}/history/branches",
+            get(history::get_branch_tree),
+        )
+        .route(
+            "/cards/{id}/history/stats",
+            get(history::get_card_history_stats),
+        )
         .route(
             "/cards/{id}/history/{history_id}",
             patch(history::update_history).delete(history::delete_history),
@@ -82,6 +108,47 @@ pub fn routes(db: DatabaseConnection) -> Router {
             "/cards/{id}/history/{history_id}/content",
             get(history::get_history_content).put(history::update_history_content),
         )
+        .route(
+            "/cards/{id}/history/{history_id}/floors",
+            post(history::edit_floors),
+        )
+        .route(
+            "/cards/{id}/history/{history_id}/undo",
+            post(history::undo_history_edit),
+        )
+        .route(
+            "/cards/{id}/history/{history_id}/branch",
+            post(history::branch_history),
+        )
+        .route(
+            "/cards/{id}/history/{history_id}/splice",
+            post(history::splice_history),
+        )
+        .route(
+            "/cards/{id}/history/{history_id}/export",
+            get(history::export_history),
+        )
+        .route(
+            "/cards/{id}/history/{history_id}/search",
+            get(history::search_history),
+        )
+        .route(
+            "/cards/{id}/history/{history_id}/stats",
+            get(history::get_history_stats),
+        )
+        // 聊天书签
+        .route(
+            "/cards/{id}/bookmarks",
+            get(chat_bookmarks::list_card_bookmarks),
+        )
+        .route(
+            "/cards/{id}/history/{history_id}/bookmarks",
+            get(chat_bookmarks::list_history_bookmarks).post(chat_bookmarks::create_bookmark),
+        )
+        .route(
+            "/cards/{id}/history/{history_id}/bookmarks/{bookmark_id}",
+            patch(chat_bookmarks::update_bookmark).delete(chat_bookmarks::delete_bookmark),
+        )
         // 快速回复
         .route(
             "/cards/{id}/quick_reply",
@@ -95,6 +162,8 @@ pub fn routes(db: DatabaseConnection) -> Router {
             "/cards/{id}/quick_reply/{qr_id}/export",
             get(quick_reply::export_quick_reply),
         )
+        // 正则脚本
+        .route("/regex/preview", post(regex::preview))
         // 回收站
         .route("/trash/cards", get(cards::list_trash))
         .route("/trash/cards/{id}/restore", post(cards::restore_card))
@@ -125,6 +194,7 @@ pub fn routes(db: DatabaseConnection) -> Router {
         .route("/images/batch/category", put(images::batch_category))
         .route("/images/batch/update", patch(images::batch_update))
         .route("/images/batch/export", post(images::batch_export))
+        .route("/images/batch/caption", post(images::batch_caption))
         .route(
             "/images/{id}",
             get(images::get)
@@ -132,6 +202,7 @@ pub fn routes(db: DatabaseConnection) -> Router {
                 .delete(images::delete),
         )
         .route("/images/{id}/export", get(images::export))
+        .route("/images/{id}/caption", post(images::caption))
         // 世界书
         .route("/world_info/import", post(world_info::import))
         .route("/world_info", get(world_info::list))
@@ -147,6 +218,7 @@ pub fn routes(db: DatabaseConnection) -> Router {
             get(ai::list_channels).post(ai::create_channel),
         )
         .route("/ai/channels/test", post(ai::test_saved_channels)) // Batch test
+        .route("/ai/channels/{id}/models", get(ai::list_channel_models))
         .route(
             "/ai/channels/{id}",
             delete(ai::delete_channel).put(ai::update_channel),
@@ -155,9 +227,74 @@ pub fn routes(db: DatabaseConnection) -> Router {
         .route("/ai/models", get(ai::list_models_proxy))
         .route("/ai/card/overview", post(ai::generate_overview))
         .route("/ai/execute", post(ai::execute_feature))
+        // AI 批量任务与审核队列
+        .route("/ai/batch/overview", post(ai_batch::start_batch_overview))
+        .route("/ai/translate/card", post(ai_batch::start_translate_card))
+        .route(
+            "/ai/history/{id}/summarize",
+            post(ai_batch::start_summarize_history),
+        )
+        .route("/ai/history/{id}/memory", get(ai_batch::get_history_memory))
+        .route(
+            "/ai/history/{id}/memory/export",
+            post(ai_batch::export_history_memory),
+        )
+        .route("/ai/suggestions", get(ai_batch::list_suggestions))
+        .route("/ai/suggestions/review", post(ai_batch::review_suggestions))
+        .route(
+            "/ai/suggestions/{id}/accept",
+            post(ai_batch::accept_suggestion),
+        )
+        .route(
+            "/ai/suggestions/{id}/reject",
+            post(ai_batch::reject_suggestion),
+        )
+        // 语义向量索引
+        .route("/ai/embeddings/status", get(embeddings::status))
+        .route("/ai/embeddings/reindex", post(embeddings::reindex))
+        .route("/ai/embeddings/search", get(embeddings::search))
+        // 开场白生成
+        .route(
+            "/ai/cards/{id}/greetings/generate",
+            post(ai_generate::generate_greetings),
+        )
+        .route(
+            "/ai/cards/{id}/greetings/accept",
+            post(ai_generate::accept_greetings),
+        )
+        // 后台任务
+        .route("/jobs", get(jobs::list_jobs))
+        .route("/jobs/{id}", get(jobs::get_job))
+        .route("/jobs/{id}/cancel", post(jobs::cancel_job))
+        // 提示词模板
+        .route("/ai/prompts", get(prompts::list_prompts))
+        .route("/ai/prompts/render", post(prompts::render_prompt))
+        .route(
+            "/ai/prompts/{feature_id}",
+            get(prompts::get_prompt).post(prompts::save_prompt),
+        )
+        .route(
+            "/ai/prompts/{feature_id}/reset",
+            post(prompts::reset_prompt),
+        )
+        .route(
+            "/ai/prompts/{feature_id}/versions/{id}",
+            delete(prompts::delete_prompt_version),
+        )
+        .route(
+            "/ai/prompts/{feature_id}/versions/{id}/activate",
+            post(prompts::activate_prompt_version),
+        )
         // 小皮医生
         .route("/ai/doctor/analyze", post(ai::doctor_analyze))
+        .route("/ai/doctor/prescribe", post(ai::doctor_prescribe))
+        .route(
+            "/ai/doctor/prescribe/apply",
+            post(ai::doctor_prescribe_apply),
+        )
         .route("/ai/doctor/history/{card_id}", get(ai::doctor_history))
+        .route("/ai/doctor/compare", get(ai::doctor_compare))
+        .route("/ai/doctor/trend/{card_id}", get(ai::doctor_trend))
         .route(
             "/ai/doctor/history/item/{id}",
             delete(ai::doctor_history_delete),
@@ -194,7 +331,9 @@ pub fn routes(db: DatabaseConnection) -> Router {
         .layer(CompressionLayer::new());
 
     // 2. 不需要压缩的路由 (流式传输)
-    let streaming_routes = Router::new().route("/backup/export", get(backup::export_backup));
+    let streaming_routes = Router::new()
+        .route("/backup/export", post(backup::export_backup))
+        .route("/ai/cards/generate", post(ai_generate::generate_card));
 
     // 3. 合并路由
     compressed_routes.merge(streaming_routes).with_state(db)
@@ -0,0 +1,389 @@
+//! AI 提示词模板 API
+//!
+//! 管理各 AI 功能的提示词模板（版本化），并提供渲染预览
+
+use crate::entities::{character_card, prompt_template};
+use crate::services::prompt::{self, PromptVars};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, Set, SqlErr, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn db_error(e: sea_orm::DbErr) -> ApiError {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(serde_json::json!({"error": e.to_string()})),
+    )
+}
+
+fn bad_request(msg: &str) -> ApiError {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({"error": msg})),
+    )
+}
+
+#[derive(Serialize)]
+pub struct PromptFeatureItem {
+    pub feature_id: String,
+    pub name: String,
+    pub variables: Vec<String>,
+    pub active: Option<prompt::ResolvedTemplate>,
+}
+
+#[derive(Serialize)]
+pub struct PromptVersionItem {
+    pub id: Uuid,
+    pub version: i32,
+    pub system_prompt: String,
+    pub user_prompt: String,
+    pub note: Option<String>,
+    pub is_active: bool,
+    pub created_at: String,
+}
+
+impl From<prompt_template::Model> for PromptVersionItem {
+    fn from(m: prompt_template::Model) -> Self {
+        Self {
+            id: m.id,
+            version: m.version,
+            system_prompt: m.system_prompt,
+            user_prompt: m.user_prompt,
+            note: m.note,
+            is_active: m.is_active,
+            created_at: m.created_at.and_utc().to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PromptFeatureDetail {
+    pub feature_id: String,
+    pub variables: Vec<String>,
+    pub default: Option<prompt::ResolvedTemplate>,
+    pub active: Option<prompt::ResolvedTemplate>,
+    pub versions: Vec<PromptVersionItem>,
+}
+
+#[derive(Deserialize)]
+pub struct SavePromptRequest {
+    pub system_prompt: String,
+    pub user_prompt: String,
+    pub note: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RenderPromptRequest {
+    pub feature_id: String,
+    pub card_id: Option<Uuid>,
+    /// 未保存的草稿（预览编辑中的模板）
+    pub system_prompt: Option<String>,
+    pub user_prompt: Option<String>,
+    /// 额外变量（覆盖自动生成的同名变量）
+    #[serde(default)]
+    pub variables: PromptVars,
+}
+
+#[derive(Serialize)]
+pub struct RenderPromptResponse {
+    pub messages: Vec<Value>,
+    pub variables: PromptVars,
+}
+
+fn feature_variables(feature_id: &str) -> Vec<String> {
+    let mut vars: Vec<String> = prompt::CARD_VARIABLES
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    if let Some(d) = prompt::default_template(feature_id) {
+        vars.extend(d.variables.iter().map(|s| s.to_string()));
+    }
+    vars
+}
+
+/// GET /api/ai/prompts - 列出所有功能及当前生效模板
+pub async fn list_prompts(
+    State(db): State<DatabaseConnection>,
+) -> Result<impl IntoResponse, ApiError> {
+    // 内置功能 + 用户自定义的功能 ID
+    let mut feature_ids: Vec<String> = prompt::DEFAULT_TEMPLATES
+        .iter()
+        .map(|t| t.feature_id.to_string())
+        .collect();
+    let custom = prompt_template::Entity::find()
+        .order_by_asc(prompt_template::Column::FeatureId)
+        .all(&db)
+        .await
+        .map_err(db_error)?;
+    for t in custom {
+        if !feature_ids.contains(&t.feature_id) {
+            feature_ids.push(t.feature_id);
+        }
+    }
+
+    let mut items = Vec::new();
+    for feature_id in feature_ids {
+        let active = prompt::resolve_template(&db, &feature_id)
+            .await
+            .map_err(db_error)?;
+        let name = prompt::default_template(&feature_id)
+            .map(|d| d.name.to_string())
+            .unwrap_or_else(|| feature_id.clone());
+        items.push(PromptFeatureItem {
+            variables: feature_variables(&feature_id),
+            feature_id,
+            name,
+            active,
+        });
+    }
+
+    Ok(Json(items))
+}
+
+/// GET /api/ai/prompts/{feature_id} - 获取功能的模板版本历史
+pub async fn get_prompt(
+    State(db): State<DatabaseConnection>,
+    Path(feature_id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let versions = prompt_template::Entity::find()
+        .filter(prompt_template::Column::FeatureId.eq(&feature_id))
+        .order_by_desc(prompt_template::Column::Version)
+        .all(&db)
+        .await
+        .map_err(db_error)?;
+
+    let default = prompt::default_template(&feature_id).map(|d| prompt::ResolvedTemplate {
+        feature_id: d.feature_id.to_string(),
+        system_prompt: d.system_prompt.to_string(),
+        user_prompt: d.user_prompt.to_string(),
+        version: None,
+        is_default: true,
+    });
+
+    if default.is_none() && versions.is_empty() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "提示词模板不存在"})),
+        ));
+    }
+
+    let active = prompt::resolve_template(&db, &feature_id)
+        .await
+        .map_err(db_error)?;
+
+    Ok(Json(PromptFeatureDetail {
+        variables: feature_variables(&feature_id),
+        feature_id,
+        default,
+        active,
+        versions: versions.into_iter().map(PromptVersionItem::from).collect(),
+    }))
+}
+
+/// POST /api/ai/prompts/{feature_id} - 保存新版本并设为生效
+pub async fn save_prompt(
+    State(db): State<DatabaseConnection>,
+    Path(feature_id): Path<String>,
+    Json(payload): Json<SavePromptRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !prompt::is_valid_feature_id(&feature_id) {
+        return Err(bad_request("功能 ID 仅允许小写字母、数字、下划线和连字符"));
+    }
+    if payload.user_prompt.trim().is_empty() {
+        return Err(bad_request("用户提示词不能为空"));
+    }
+
+    // 读取最大版本号与插入在同一事务中完成；(feature_id, version) 唯一索引兜底并发保存
+    let txn = db.begin().await.map_err(db_error)?;
+    let latest = prompt_template::Entity::find()
+        .filter(prompt_template::Column::FeatureId.eq(&feature_id))
+        .order_by_desc(prompt_template::Column::Version)
+        .one(&txn)
+        .await
+        .map_err(db_error)?;
+    let next_version = latest.map(|t| t.version + 1).unwrap_or(1);
+
+    deactivate_all(&txn, &feature_id).await?;
+
+    let model = prompt_template::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        feature_id: Set(feature_id),
+        version: Set(next_version),
+        system_prompt: Set(payload.system_prompt),
+        user_prompt: Set(payload.user_prompt),
+        note: Set(payload.note),
+        is_active: Set(true),
+        created_at: Set(chrono::Utc::now().naive_utc()),
+    };
+    let saved = model.insert(&txn).await.map_err(|e| {
+        if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) {
+            (
+                StatusCode::CONFLICT,
+                Json(serde_json::json!({"error": "该功能正在保存新版本，请稍后重试"})),
+            )
+        } else {
+            db_error(e)
+        }
+    })?;
+    txn.commit().await.map_err(db_error)?;
+
+    Ok(Json(PromptVersionItem::from(saved)))
+}
+
+/// POST /api/ai/prompts/{feature_id}/versions/{id}/activate - 切换生效版本
+pub async fn activate_prompt_version(
+    State(db): State<DatabaseConnection>,
+    Path((feature_id, version_id)): Path<(String, Uuid)>,
+) -> Result<impl IntoResponse, ApiError> {
+    let version = prompt_template::Entity::find_by_id(version_id)
+        .one(&db)
+        .await
+        .map_err(db_error)?
+        .filter(|v| v.feature_id == feature_id)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "模板版本不存在"})),
+            )
+        })?;
+
+    deactivate_all(&db, &feature_id).await?;
+
+    let mut active: prompt_template::ActiveModel = version.into();
+    active.is_active = Set(true);
+    let updated = active.update(&db).await.map_err(db_error)?;
+
+    Ok(Json(PromptVersionItem::from(updated)))
+}
+
+/// POST /api/ai/prompts/{feature_id}/reset - 恢复内置默认模板（保留历史版本）
+pub async fn reset_prompt(
+    State(db): State<DatabaseConnection>,
+    Path(feature_id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    deactivate_all(&db, &feature_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// DELETE /api/ai/prompts/{feature_id}/versions/{id} - 删除模板版本
+pub async fn delete_prompt_version(
+    State(db): State<DatabaseConnection>,
+    Path((feature_id, version_id)): Path<(String, Uuid)>,
+) -> Result<impl IntoResponse, ApiError> {
+    let result = prompt_template::Entity::delete_many()
+        .filter(prompt_template::Column::Id.eq(version_id))
+        .filter(prompt_template::Column::FeatureId.eq(&feature_id))
+        .exec(&db)
+        .await
+        .map_err(db_error)?;
+
+    if result.rows_affected == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "模板版本不存在"})),
+        ));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/ai/prompts/render - 渲染预览（可传入未保存的草稿）
+pub async fn render_prompt(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<RenderPromptRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let template = build_template(
+        &db,
+        &payload.feature_id,
+        payload.system_prompt,
+        payload.user_prompt,
+    )
+    .await?;
+
+    let card = match payload.card_id {
+        Some(id) => Some(load_card(&db, id).await?),
+        None => None,
+    };
+
+    let mut variables = prompt::build_variables(&db, &payload.feature_id, card.as_ref())
+        .await
+        .map_err(db_error)?;
+    variables.extend(payload.variables);
+
+    Ok(Json(RenderPromptResponse {
+        messages: prompt::render_messages(&template, &variables),
+        variables,
+    }))
+}
+
+/// 使用草稿覆盖当前生效模板
+async fn build_template(
+    db: &DatabaseConnection,
+    feature_id: &str,
+    system_prompt: Option<String>,
+    user_prompt: Option<String>,
+) -> Result<prompt::ResolvedTemplate, ApiError> {
+    let resolved = prompt::resolve_template(db, feature_id)
+        .await
+        .map_err(db_error)?;
+
+    match (resolved, system_prompt, user_prompt) {
+        (Some(mut t), system, user) => {
+            if let Some(s) = system {
+                t.system_prompt = s;
+            }
+            if let Some(u) = user {
+                t.user_prompt = u;
+            }
+            Ok(t)
+        }
+        (None, system, Some(user)) => Ok(prompt::ResolvedTemplate {
+            feature_id: feature_id.to_string(),
+            system_prompt: system.unwrap_or_default(),
+            user_prompt: user,
+            version: None,
+            is_default: false,
+        }),
+        (None, _, None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "提示词模板不存在"})),
+        )),
+    }
+}
+
+async fn load_card(db: &DatabaseConnection, id: Uuid) -> Result<character_card::Model, ApiError> {
+    character_card::Entity::find_by_id(id)
+        .one(db)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(serde_json::json!({"error": "角色卡不存在"})),
+            )
+        })
+}
+
+async fn deactivate_all(db: &impl ConnectionTrait, feature_id: &str) -> Result<(), ApiError> {
+    prompt_template::Entity::update_many()
+        .col_expr(
+            prompt_template::Column::IsActive,
+            sea_orm::sea_query::Expr::value(false),
+        )
+        .filter(prompt_template::Column::FeatureId.eq(feature_id))
+        .exec(db)
+        .await
+        .map_err(db_error)?;
+    Ok(())
+}
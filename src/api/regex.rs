@@ -0,0 +1,124 @@
+//! 正则脚本 API
+//!
+//! 在服务端预览 ST 正则脚本的执行结果
+
+use crate::entities::{character_card, chat_history};
+use crate::services::st_regex::{
+    self, ApplyContext, Macros, Placement, RegexScript, RunMode, ScriptError, ScriptSet, ScriptStep,
+};
+use axum::{extract::State, http::StatusCode, Json};
+use sea_orm::{DatabaseConnection, EntityTrait};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct PreviewRequest {
+    pub text: String,
+    /// 直接给出的脚本；为空时使用角色卡 / 聊天记录中保存的脚本
+    pub scripts: Option<Value>,
+    pub card_id: Option<Uuid>,
+    pub history_id: Option<Uuid>,
+    #[serde(default)]
+    pub mode: RunMode,
+    pub placement: Option<Placement>,
+    pub depth: Option<usize>,
+    #[serde(default)]
+    pub is_edit: bool,
+    /// 覆盖 {{char}} / {{user}}，默认取角色卡名称与 "User"
+    pub char_name: Option<String>,
+    pub user_name: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PreviewResponse {
+    pub result: String,
+    pub steps: Vec<ScriptStep>,
+    pub errors: Vec<ScriptError>,
+}
+
+fn db_error(e: sea_orm::DbErr) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+/// POST /api/regex/preview - 对一段文本执行正则脚本并返回每个脚本的结果
+pub async fn preview(
+    State(db): State<DatabaseConnection>,
+    Json(payload): Json<PreviewRequest>,
+) -> Result<Json<PreviewResponse>, (StatusCode, String)> {
+    let history = match payload.history_id {
+        Some(id) => Some(
+            chat_history::Entity::find_by_id(id)
+                .one(&db)
+                .await
+                .map_err(db_error)?
+                .ok_or((StatusCode::NOT_FOUND, "聊天记录不存在".to_string()))?,
+        ),
+        None => None,
+    };
+    if let (Some(card_id), Some(history)) = (payload.card_id, &history) {
+        if history.card_id != card_id {
+            return Err((StatusCode::NOT_FOUND, "聊天记录不存在".to_string()));
+        }
+    }
+    let card_id = payload
+        .card_id
+        .or_else(|| history.as_ref().map(|h| h.card_id));
+    let card = match card_id {
+        Some(id) => Some(
+            character_card::Entity::find_by_id(id)
+                .one(&db)
+                .await
+                .map_err(db_error)?
+                .ok_or((StatusCode::NOT_FOUND, "角色卡不存在".to_string()))?,
+        ),
+        None => None,
+    };
+
+    let scripts: Vec<RegexScript> = match &payload.scripts {
+        Some(value) => st_regex::parse_scripts(value),
+        None => {
+            let mut scripts = history
+                .as_ref()
+                .map(|h| st_regex::parse_scripts(&Value::String(h.regex_scripts.clone())))
+                .unwrap_or_default();
+            if let Some(card_json) = card
+                .as_ref()
+                .and_then(|c| serde_json::from_str::<Value>(&c.data).ok())
+            {
+                scripts.extend(st_regex::card_scripts(&card_json));
+            }
+            scripts
+        }
+    };
+
+    let macros = Macros::new(
+        payload
+            .char_name
+            .as_deref()
+            .or(card.as_ref().map(|c| c.name.as_str()))
+            .unwrap_or_default(),
+        payload.user_name.as_deref().unwrap_or("User"),
+    );
+    let ctx = ApplyContext {
+        mode: payload.mode,
+        placement: payload.placement,
+        depth: payload.depth,
+        is_edit: payload.is_edit,
+    };
+
+    let text = payload.text;
+    let response = tokio::task::spawn_blocking(move || {
+        let set = ScriptSet::compile(&scripts, macros);
+        let (result, steps) = set.trace(&text, &ctx);
+        PreviewResponse {
+            result,
+            steps,
+            errors: set.errors,
+        }
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(response))
+}
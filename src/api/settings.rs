@@ -31,6 +31,12 @@ pub struct Settings {
     pub avatar: Option<String>,
     /// 全局 AI 默认渠道 ID
     pub ai_config_global: Option<String>,
+    /// 语义向量（embeddings）渠道 ID，未配置时不建立索引
+    pub ai_config_embedding: Option<String>,
+    /// 视觉模型渠道 ID（图片描述与自动标签）
+    pub ai_config_vision: Option<String>,
+    /// 全局渠道不健康时是否自动改用其他渠道（可能是不同模型，默认关闭）
+    pub ai_channel_fallback: bool,
     /// 全局提示词
     pub global_prompt: Option<String>,
 }
@@ -53,6 +59,9 @@ pub async fn get(State(db): State<DatabaseConnection>) -> Json<Settings> {
         items_per_page: 20,
         avatar: None,
         ai_config_global: None,
+        ai_config_embedding: None,
+        ai_config_vision: None,
+        ai_channel_fallback: false,
         global_prompt: None,
     };
 
@@ -69,6 +78,9 @@ pub async fn get(State(db): State<DatabaseConnection>) -> Json<Settings> {
             "items_per_page" => s.items_per_page = setting.value.parse().unwrap_or(20),
             "user_avatar" => s.avatar = Some(setting.value),
             "ai_config_global" => s.ai_config_global = Some(setting.value),
+            "ai_config_embedding" => s.ai_config_embedding = Some(setting.value),
+            "ai_config_vision" => s.ai_config_vision = Some(setting.value),
+            "ai_channel_fallback" => s.ai_channel_fallback = setting.value == "true",
             "global_prompt" => s.global_prompt = Some(setting.value),
             _ => {}
         }
@@ -102,6 +114,9 @@ pub async fn update(
                 "items_per_page" => "items_per_page",
                 "avatar" => "user_avatar", // Map 'avatar' to 'user_avatar'
                 "ai_config_global" => "ai_config_global",
+                "ai_config_embedding" => "ai_config_embedding",
+                "ai_config_vision" => "ai_config_vision",
+                "ai_channel_fallback" => "ai_channel_fallback",
                 "global_prompt" => "global_prompt",
                 _ => continue,
             };
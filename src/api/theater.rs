@@ -195,6 +195,10 @@ pub async fn create_theater(
         .insert(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    crate::services::embedding::schedule(
+        &db,
+        crate::services::embedding::Source::Theater(saved.id),
+    );
 
     Ok(Json(TheaterDto::from(saved)))
 }
@@ -245,6 +249,7 @@ pub async fn update_theater(
         .update(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    crate::services::embedding::schedule(&db, crate::services::embedding::Source::Theater(id));
 
     Ok(Json(TheaterDto::from(updated)))
 }
@@ -262,6 +267,7 @@ pub async fn delete_theater(
     if result.rows_affected == 0 {
         return Err((StatusCode::NOT_FOUND, "小剧场不存在".to_string()));
     }
+    crate::services::embedding::forget(&db, crate::services::embedding::Source::Theater(id));
 
     Ok(StatusCode::NO_CONTENT)
 }
@@ -281,10 +287,13 @@ pub async fn batch_delete_theaters(
     }
 
     Theater::delete_many()
-        .filter(theater::Column::Id.is_in(payload.ids))
+        .filter(theater::Column::Id.is_in(payload.ids.clone()))
         .exec(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    for id in payload.ids {
+        crate::services::embedding::forget(&db, crate::services::embedding::Source::Theater(id));
+    }
 
     Ok(StatusCode::NO_CONTENT)
 }
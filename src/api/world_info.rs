@@ -144,6 +144,7 @@ async fn save_world_info_to_db(
         .insert(db)
         .await
         .map_err(|e| format!("DB Error: {}", e))?;
+    crate::services::embedding::schedule(db, crate::services::embedding::Source::WorldInfo(uuid));
     Ok(())
 }
 
@@ -228,6 +229,7 @@ pub async fn update(
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     invalidate_cache();
+    crate::services::embedding::schedule(&db, crate::services::embedding::Source::WorldInfo(id));
     Ok(Json(updated))
 }
 
@@ -240,6 +242,7 @@ pub async fn delete(
         .exec(&db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    crate::services::embedding::forget(&db, crate::services::embedding::Source::WorldInfo(id));
 
     invalidate_cache();
     Ok(StatusCode::NO_CONTENT)
@@ -159,5 +159,15 @@ pub async fn init_database() -> anyhow::Result<DatabaseConnection> {
     migration::Migrator::up(&db, None).await?;
     info!("数据库迁移完成");
 
+    // 加载 AI 渠道密钥的本地 Secret（文件损坏时中止启动，避免重新生成后无法解密）
+    crate::utils::crypto::init_local_key()
+        .map_err(|e| anyhow::anyhow!("加载 AI 渠道密钥失败: {}", e))?;
+
+    // 加密仍以明文存储的 AI 渠道密钥
+    let encrypted = crate::services::ai::encrypt_stored_keys(&db).await?;
+    if encrypted > 0 {
+        info!("已加密 {} 个 AI 渠道的 API Key", encrypted);
+    }
+
     Ok(db)
 }
@@ -0,0 +1,28 @@
+//! `SeaORM` Entity - AiSuggestion
+//!
+//! AI 批量任务产出的待审核建议（pending / accepted / rejected）
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "ai_suggestions")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub job_id: Option<Uuid>,
+    pub card_id: Uuid,
+    /// 产出该建议的功能，如 "overview"
+    pub feature_id: String,
+    /// 建议内容（JSON），结构由功能决定
+    #[sea_orm(column_type = "Text")]
+    pub payload: String,
+    pub status: String,
+    pub created_at: DateTime,
+    pub reviewed_at: Option<DateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,48 @@
+//! `SeaORM` Entity - ChatBookmark
+//!
+//! 聊天记录中的书签与高亮（定位到楼层，可附带备注）
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "chat_bookmarks")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub history_id: Uuid,
+    pub card_id: Uuid,
+    /// 楼层序号（从 1 开始）
+    pub floor: i32,
+    /// bookmark / highlight
+    pub kind: String,
+    pub name: String,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub note: Option<String>,
+    /// 高亮的原文片段
+    #[sea_orm(column_type = "Text", nullable)]
+    pub quote: Option<String>,
+    pub color: Option<String>,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::chat_history::Entity",
+        from = "Column::HistoryId",
+        to = "super::chat_history::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    ChatHistory,
+}
+
+impl Related<super::chat_history::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ChatHistory.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -17,9 +17,19 @@ pub struct Model {
     pub progress: i32,
     #[sea_orm(default_value = 1)]
     pub current_page: i32,
+    /// 阅读进度所在楼层序号（从 1 开始），不受每页楼层数影响
+    #[sea_orm(default_value = 1)]
+    pub current_floor: i32,
     pub reading_settings: Option<String>,
     #[sea_orm(default_value = "[]")]
     pub regex_scripts: String,
+    /// AI 滚动总结
+    #[sea_orm(column_type = "Text", nullable)]
+    pub summary: Option<String>,
+    /// 结构化记忆 JSON（events / relationships / facts）
+    #[sea_orm(column_type = "Text", nullable)]
+    pub memory: Option<String>,
+    pub summarized_at: Option<DateTime>,
     pub created_at: DateTime,
     pub updated_at: DateTime,
 }
@@ -12,6 +12,16 @@ pub struct Model {
     pub status: String,
     #[sea_orm(column_type = "Text", nullable)]
     pub final_report: Option<String>,
+    /// 结构化维度（JSON 数组，含每个维度的 score）
+    #[sea_orm(column_type = "Text", nullable)]
+    pub dimensions: Option<String>,
+    pub overall_score: Option<f64>,
+    /// 结论：pass | revise | rebuild | unknown
+    pub conclusion: Option<String>,
+    /// 诊断时角色卡数据的哈希与版本号
+    #[sea_orm(column_type = "Text", nullable)]
+    pub card_hash: Option<String>,
+    pub card_version: Option<String>,
     pub created_at: DateTime,
     pub updated_at: DateTime,
 }
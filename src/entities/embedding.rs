@@ -0,0 +1,33 @@
+//! `SeaORM` Entity - Embedding
+//!
+//! 语义向量索引：每个来源（角色卡 / 世界书条目 / 小剧场）一条记录
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "embeddings")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    /// card / world_entry / theater
+    pub source_type: String,
+    pub source_id: Uuid,
+    /// 来源内的子项键（世界书条目 uid），整体来源为空字符串
+    pub item_key: String,
+    pub title: String,
+    /// 参与向量化文本的哈希，未变化时跳过重建
+    pub content_hash: String,
+    pub model: String,
+    pub dims: i32,
+    /// f32 小端序向量
+    #[serde(skip)]
+    #[sea_orm(column_type = "Blob")]
+    pub vector: Vec<u8>,
+    pub updated_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
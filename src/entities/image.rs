@@ -33,6 +33,13 @@ pub struct Model {
     /// 关联的角色卡 ID 列表 (JSON 数组)
     #[sea_orm(column_type = "Text")]
     pub char_cards: String,
+    /// 视觉模型生成的描述
+    #[sea_orm(column_type = "Text", nullable)]
+    pub ai_caption: Option<String>,
+    /// 视觉模型生成的标签 (JSON 数组)，已合并进 tags，重新生成时据此替换
+    #[sea_orm(column_type = "Text", nullable)]
+    pub ai_tags: Option<String>,
+    pub captioned_at: Option<DateTime>,
     pub created_at: DateTime,
 }
 
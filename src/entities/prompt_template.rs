@@ -0,0 +1,27 @@
+//! `SeaORM` Entity - PromptTemplate
+//!
+//! AI 功能提示词模板，每次保存生成一个新版本，is_active 标记当前生效版本
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "prompt_templates")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub feature_id: String,
+    pub version: i32,
+    #[sea_orm(column_type = "Text")]
+    pub system_prompt: String,
+    #[sea_orm(column_type = "Text")]
+    pub user_prompt: String,
+    pub note: Option<String>,
+    pub is_active: bool,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -20,6 +20,9 @@ use utils::mode_detect::RunMode;
 
 /// 创建 Axum 应用实例
 pub async fn create_app(db: DatabaseConnection, mode: RunMode, config: ConfigState) -> Router {
+    // 后台渠道健康检查
+    services::channel_health::spawn_monitor(db.clone());
+
     // CORS 配置
     // CORS 配置
     let cors = CorsLayer::new()
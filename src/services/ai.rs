@@ -0,0 +1,424 @@
+//! AI 调用服务
+//!
+//! 封装渠道解析与 OpenAI 兼容的 chat/completions、embeddings 调用
+
+use crate::entities::{ai_channel, setting};
+use crate::services::{channel_health, mock_ai, structured};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use serde_json::Value;
+use uuid::Uuid;
+
+pub use super::structured::extract_json;
+
+/// AI 调用错误
+#[derive(Debug)]
+pub enum AiError {
+    /// 配置问题（未配置渠道、渠道不存在等）
+    Config(String),
+    /// 请求或响应错误
+    Request(String),
+    /// 上游返回非 2xx 状态码（状态码, 错误信息）
+    Http(u16, String),
+    Database(sea_orm::DbErr),
+}
+
+impl std::fmt::Display for AiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AiError::Config(msg) | AiError::Request(msg) | AiError::Http(_, msg) => {
+                write!(f, "{}", msg)
+            }
+            AiError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<sea_orm::DbErr> for AiError {
+    fn from(e: sea_orm::DbErr) -> Self {
+        AiError::Database(e)
+    }
+}
+
+impl AiError {
+    pub fn status_code(&self) -> axum::http::StatusCode {
+        match self {
+            AiError::Config(_) | AiError::Request(_) | AiError::Http(..) => {
+                axum::http::StatusCode::BAD_REQUEST
+            }
+            AiError::Database(_) => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// 读取设置项中的渠道 ID（未配置或为空时返回 None）
+async fn configured_channel_id(
+    db: &DatabaseConnection,
+    key: &str,
+) -> Result<Option<String>, AiError> {
+    Ok(setting::Entity::find_by_id(key)
+        .one(db)
+        .await?
+        .map(|s| s.value)
+        .filter(|v| !v.is_empty()))
+}
+
+async fn find_channel(db: &DatabaseConnection, id: &str) -> Result<ai_channel::Model, AiError> {
+    let channel_id =
+        Uuid::parse_str(id).map_err(|_| AiError::Config("AI 配置 ID 格式无效".to_string()))?;
+
+    ai_channel::Entity::find_by_id(channel_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| AiError::Config("配置的AI渠道已不存在，请重新配置".to_string()))
+}
+
+/// 读取布尔设置项（未配置时为 false）
+async fn setting_enabled(db: &DatabaseConnection, key: &str) -> Result<bool, AiError> {
+    Ok(setting::Entity::find_by_id(key)
+        .one(db)
+        .await?
+        .is_some_and(|s| s.value == "true"))
+}
+
+/// 读取全局 AI 渠道（ai_config_global）
+///
+/// 仅在开启 ai_channel_fallback 时，配置的渠道被健康检查标记为不可用才改用其他健康的已启用渠道
+/// （其他渠道可能是不同的模型，默认不切换）；没有可替代的渠道时仍返回原渠道
+pub async fn resolve_global_channel(db: &DatabaseConnection) -> Result<ai_channel::Model, AiError> {
+    let channel_id = configured_channel_id(db, "ai_config_global")
+        .await?
+        .ok_or_else(|| AiError::Config("没有配置全局AI模型，请到设置页面完成配置".to_string()))?;
+    let channel = find_channel(db, &channel_id).await?;
+    if channel_health::is_healthy(channel.id) {
+        return Ok(channel);
+    }
+    if !setting_enabled(db, "ai_channel_fallback").await? {
+        return Ok(channel);
+    }
+
+    let fallback = ai_channel::Entity::find()
+        .filter(ai_channel::Column::IsActive.eq(true))
+        .filter(ai_channel::Column::Id.ne(channel.id))
+        .order_by_asc(ai_channel::Column::CreatedAt)
+        .all(db)
+        .await?
+        .into_iter()
+        .find(|c| channel_health::is_healthy(c.id));
+    match fallback {
+        Some(fallback) => {
+            tracing::warn!(
+                "全局渠道 {} 连续失败，临时改用渠道 {}",
+                channel.name,
+                fallback.name
+            );
+            Ok(fallback)
+        }
+        None => Ok(channel),
+    }
+}
+
+/// 读取视觉模型渠道（ai_config_vision），未配置时返回 None
+///
+/// 视觉与向量渠道依赖特定模型，不做健康回退
+pub async fn resolve_vision_channel(
+    db: &DatabaseConnection,
+) -> Result<Option<ai_channel::Model>, AiError> {
+    match configured_channel_id(db, "ai_config_vision").await? {
+        Some(id) => find_channel(db, &id).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+/// 读取语义向量渠道（ai_config_embedding），未配置时返回 None
+pub async fn resolve_embedding_channel(
+    db: &DatabaseConnection,
+) -> Result<Option<ai_channel::Model>, AiError> {
+    match configured_channel_id(db, "ai_config_embedding").await? {
+        Some(id) => find_channel(db, &id).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+/// 解密渠道 API Key（密钥仅在发起请求前解密，不经过 API 返回）
+pub fn channel_api_key(channel: &ai_channel::Model) -> Result<String, AiError> {
+    crate::utils::crypto::decrypt_local(&channel.api_key).map_err(|e| {
+        AiError::Config(format!(
+            "渠道「{}」的 API Key 无法解密（{}），请重新填写",
+            channel.name, e
+        ))
+    })
+}
+
+/// 将仍以明文存储的 API Key 加密（启动时执行，兼容旧数据与恢复的旧备份）
+pub async fn encrypt_stored_keys(db: &DatabaseConnection) -> Result<usize, sea_orm::DbErr> {
+    let channels = ai_channel::Entity::find().all(db).await?;
+    let mut count = 0;
+    for channel in channels {
+        if channel.api_key.is_empty() || crate::utils::crypto::is_encrypted(&channel.api_key) {
+            continue;
+        }
+        let encrypted = crate::utils::crypto::encrypt_local(&channel.api_key);
+        let mut active: ai_channel::ActiveModel = channel.into();
+        active.api_key = Set(encrypted);
+        active.update(db).await?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// 向渠道发送 POST 请求（不检查 HTTP 状态）
+///
+/// 所有 AI 请求都经由此处：负责解密 API Key，`mock://` 渠道由内置模拟服务应答
+pub async fn send_request(
+    channel: &ai_channel::Model,
+    path: &str,
+    body: &Value,
+) -> Result<reqwest::Response, AiError> {
+    if mock_ai::is_mock(&channel.base_url) {
+        return Ok(mock_ai::respond(channel, path, body));
+    }
+
+    let api_key = channel_api_key(channel)?;
+    let client = reqwest::Client::new();
+    let base = channel.base_url.trim_end_matches('/');
+    let url = format!("{}/{}", base, path);
+
+    client
+        .post(&url)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Content-Type", "application/json")
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| AiError::Request(format!("请求失败: {}", e)))
+}
+
+/// 发送 POST 请求并记录渠道健康状态（连接失败或非 2xx 视为失败）
+async fn post_json(
+    channel: &ai_channel::Model,
+    path: &str,
+    body: &Value,
+    label: &str,
+) -> Result<reqwest::Response, AiError> {
+    let start = std::time::Instant::now();
+    let res = match send_request(channel, path, body).await {
+        Ok(res) => res,
+        Err(AiError::Request(e)) => {
+            let message = format!("{} {}", label, e);
+            channel_health::record(channel.id, None, Some(message.clone()));
+            return Err(AiError::Request(message));
+        }
+        Err(e) => return Err(e),
+    };
+    let latency_ms = start.elapsed().as_millis() as u64;
+
+    let status = res.status();
+    if !status.is_success() {
+        let raw_text = res.text().await.unwrap_or_default();
+        let message = format!(
+            "{} 服务返回错误 (HTTP {}): {}",
+            label,
+            status.as_u16(),
+            raw_text.chars().take(200).collect::<String>()
+        );
+        if channel_health::is_channel_fault(status.as_u16()) {
+            channel_health::record(channel.id, Some(latency_ms), Some(message.clone()));
+        }
+        return Err(AiError::Http(status.as_u16(), message));
+    }
+
+    channel_health::record(channel.id, Some(latency_ms), None);
+    Ok(res)
+}
+
+/// 调用 chat/completions，返回首个 choice 的文本内容
+///
+/// json_mode 时请求 JSON 输出（渠道不支持 response_format 时自动降级）
+pub async fn chat_completion(
+    channel: &ai_channel::Model,
+    messages: &[Value],
+    temperature: f32,
+    json_mode: bool,
+) -> Result<String, AiError> {
+    let body = serde_json::json!({
+        "model": channel.model_id,
+        "messages": messages,
+        "temperature": temperature
+    });
+    if json_mode {
+        return structured::request_json(channel, &body, None).await;
+    }
+
+    chat_completion_body(channel, &body).await
+}
+
+/// 使用完整请求体调用 chat/completions，返回首个 choice 的文本内容
+pub async fn chat_completion_body(
+    channel: &ai_channel::Model,
+    body: &Value,
+) -> Result<String, AiError> {
+    let res = post_json(channel, "chat/completions", body, "AI").await?;
+    let raw_text = res.text().await.unwrap_or_default();
+
+    let json: Value = serde_json::from_str(&raw_text)
+        .map_err(|e| AiError::Request(format!("AI 响应解析失败: {} (可能是空响应)", e)))?;
+
+    let content = json["choices"][0]["message"]["content"]
+        .as_str()
+        .unwrap_or("")
+        .to_string();
+    // 空内容且 completion_tokens 为 0 多为安全过滤拦截
+    if content.trim().is_empty() {
+        let completion_tokens = json["usage"]["completion_tokens"].as_u64().unwrap_or(0);
+        let finish_reason = json["choices"][0]["finish_reason"].as_str().unwrap_or("");
+        let message = if completion_tokens == 0 {
+            "AI 返回空内容 (completion_tokens=0)。可能是模型安全过滤触发，请尝试更换渠道/模型。"
+                .to_string()
+        } else {
+            format!(
+                "AI 返回空内容 (completion_tokens={}, finish_reason={})",
+                completion_tokens, finish_reason
+            )
+        };
+        return Err(AiError::Request(message));
+    }
+
+    Ok(content)
+}
+
+/// 调用 OpenAI 兼容的 /embeddings，按输入顺序返回向量
+pub async fn embeddings(
+    channel: &ai_channel::Model,
+    inputs: &[String],
+) -> Result<Vec<Vec<f32>>, AiError> {
+    if inputs.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let body = serde_json::json!({
+        "model": channel.model_id,
+        "input": inputs,
+    });
+
+    let res = post_json(channel, "embeddings", &body, "Embedding").await?;
+    let raw_text = res
+        .text()
+        .await
+        .map_err(|e| AiError::Request(format!("读取 Embedding 响应失败: {}", e)))?;
+
+    let json: Value = serde_json::from_str(&raw_text)
+        .map_err(|e| AiError::Request(format!("Embedding 响应不是合法 JSON: {}", e)))?;
+    let data = json["data"]
+        .as_array()
+        .ok_or_else(|| AiError::Request("Embedding 响应缺少 data".to_string()))?;
+
+    // 按 index 排序，兼容乱序返回
+    let mut vectors: Vec<(usize, Vec<f32>)> = data
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let index = item["index"].as_u64().map(|n| n as usize).unwrap_or(i);
+            let vector = item["embedding"]
+                .as_array()
+                .map(|arr| {
+                    arr.iter()
+                        .filter_map(|v| v.as_f64())
+                        .map(|v| v as f32)
+                        .collect()
+                })
+                .unwrap_or_default();
+            (index, vector)
+        })
+        .collect();
+    vectors.sort_by_key(|(i, _)| *i);
+
+    if vectors.len() != inputs.len() || vectors.iter().any(|(_, v)| v.is_empty()) {
+        return Err(AiError::Request(format!(
+            "Embedding 返回数量不匹配：请求 {} 条，返回 {} 条",
+            inputs.len(),
+            vectors.len()
+        )));
+    }
+    Ok(vectors.into_iter().map(|(_, v)| v).collect())
+}
+
+/// 流式 chat/completions（SSE），逐块读取增量文本
+pub struct ChatStream {
+    res: reqwest::Response,
+    /// 尚未处理的原始字节；多字节字符可能被拆到两个网络块中，只解码完整的行
+    buffer: Vec<u8>,
+    done: bool,
+}
+
+impl ChatStream {
+    /// 读取下一批增量文本；流结束返回 None
+    pub async fn next_delta(&mut self) -> Result<Option<String>, AiError> {
+        loop {
+            if self.done {
+                return Ok(None);
+            }
+
+            // 先消费缓冲区中已完整的行
+            let mut delta = String::new();
+            while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+                let bytes: Vec<u8> = self.buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&bytes);
+                let Some(data) = line.trim().strip_prefix("data:") else {
+                    continue;
+                };
+                let data = data.trim();
+                if data == "[DONE]" {
+                    self.done = true;
+                    break;
+                }
+                if let Ok(json) = serde_json::from_str::<Value>(data) {
+                    if let Some(text) = json["choices"][0]["delta"]["content"].as_str() {
+                        delta.push_str(text);
+                    }
+                }
+            }
+            if !delta.is_empty() {
+                return Ok(Some(delta));
+            }
+            if self.done {
+                return Ok(None);
+            }
+
+            match self
+                .res
+                .chunk()
+                .await
+                .map_err(|e| AiError::Request(format!("读取 AI 响应流失败: {}", e)))?
+            {
+                Some(bytes) => self.buffer.extend_from_slice(&bytes),
+                // 最后一行可能没有换行：补齐后再处理一轮
+                None if !self.buffer.is_empty() => self.buffer.push(b'\n'),
+                None => self.done = true,
+            }
+        }
+    }
+}
+
+/// 发起流式 chat/completions 请求
+pub async fn chat_completion_stream(
+    channel: &ai_channel::Model,
+    messages: &[Value],
+    temperature: f32,
+) -> Result<ChatStream, AiError> {
+    let body = serde_json::json!({
+        "model": channel.model_id,
+        "messages": messages,
+        "temperature": temperature,
+        "stream": true
+    });
+
+    let res = post_json(channel, "chat/completions", &body, "AI").await?;
+
+    Ok(ChatStream {
+        res,
+        buffer: Vec::new(),
+        done: false,
+    })
+}
@@ -0,0 +1,393 @@
+//! 角色卡写入服务
+//!
+//! 供 AI 功能等非编辑器入口修改角色卡：先快照版本，再写回 JSON 并同步冗余字段
+
+use crate::entities::{character_card, character_versions};
+use crate::utils::token::calculate_card_tokens;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set,
+};
+use serde_json::Value;
+use uuid::Uuid;
+
+/// V2/V3 data 对象与 V1 根级镜像字段的对应关系 (data 字段, 根级字段)
+const ROOT_MIRRORS: &[(&str, &str)] = &[
+    ("name", "name"),
+    ("description", "description"),
+    ("personality", "personality"),
+    ("scenario", "scenario"),
+    ("first_mes", "first_mes"),
+    ("mes_example", "mes_example"),
+    ("tags", "tags"),
+    ("creator_notes", "creatorcomment"),
+];
+
+/// 计算下一个版本号：已有版本中最大主版本号 + 1（如 V3 -> V4）
+pub async fn next_version_number(db: &DatabaseConnection, card_id: Uuid) -> Result<String, DbErr> {
+    let versions = character_versions::Entity::find()
+        .filter(character_versions::Column::CharacterId.eq(card_id))
+        .all(db)
+        .await?;
+
+    let max_major = versions
+        .iter()
+        .filter_map(|v| {
+            v.version_number
+                .trim_start_matches(['V', 'v'])
+                .split('.')
+                .next()
+                .and_then(|s| s.parse::<u32>().ok())
+        })
+        .max()
+        .unwrap_or(0);
+
+    Ok(format!("V{}", max_major + 1))
+}
+
+/// 为角色卡当前数据创建版本快照
+pub async fn snapshot_version(
+    db: &DatabaseConnection,
+    card: &character_card::Model,
+    note: &str,
+) -> Result<character_versions::Model, DbErr> {
+    let version_number = next_version_number(db, card.id).await?;
+    character_versions::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        character_id: Set(card.id),
+        version_number: Set(version_number),
+        note: Set(Some(note.to_string())),
+        data: Set(card.data.clone()),
+        created_at: Set(chrono::Utc::now().naive_utc()),
+    }
+    .insert(db)
+    .await
+}
+
+/// 将 data 对象中的字段同步到 V1 根级镜像
+pub fn sync_root_mirrors(json: &mut Value) {
+    let Some(data) = json.get("data").filter(|d| d.is_object()).cloned() else {
+        return;
+    };
+    let Some(root) = json.as_object_mut() else {
+        return;
+    };
+    for (data_key, root_key) in ROOT_MIRRORS {
+        if let Some(v) = data.get(*data_key) {
+            root.insert(root_key.to_string(), v.clone());
+        }
+    }
+}
+
+/// 写回角色卡 JSON：同步根级镜像、冗余列与 Token 统计
+pub async fn write_card_json(
+    db: &DatabaseConnection,
+    card: &character_card::Model,
+    mut json: Value,
+) -> Result<character_card::Model, DbErr> {
+    sync_root_mirrors(&mut json);
+    let card_data = json.get("data").filter(|d| d.is_object()).unwrap_or(&json);
+
+    let mut active: character_card::ActiveModel = card.clone().into();
+
+    if let Some(name) = card_data.get("name").and_then(|v| v.as_str()) {
+        active.name = Set(name.to_string());
+    }
+    if let Some(desc) = card_data.get("description").and_then(|v| v.as_str()) {
+        active.description = Set(Some(desc.to_string()));
+    }
+    if let Some(tags) = card_data.get("tags").filter(|v| v.is_array()) {
+        active.tags = Set(serde_json::to_string_pretty(tags).unwrap_or_else(|_| "[]".to_string()));
+    }
+
+    let counts = calculate_card_tokens(&json);
+    active.token_count_total = Set(Some(counts.total));
+    active.token_count_spec = Set(Some(counts.spec));
+    active.token_count_wb = Set(Some(counts.wb));
+    active.token_count_other = Set(Some(counts.other));
+
+    active.data =
+        Set(serde_json::to_string_pretty(&json).map_err(|e| DbErr::Custom(e.to_string()))?);
+    active.metadata_modified = Set(true);
+    active.updated_at = Set(chrono::Utc::now().naive_utc());
+
+    let updated = active.update(db).await?;
+    crate::api::dashboard::invalidate_cache();
+    super::embedding::schedule(db, super::embedding::Source::Card(updated.id));
+    Ok(updated)
+}
+
+/// 允许 AI 补丁修改的字段
+const EDITABLE_FIELDS: &[&str] = &[
+    "description",
+    "personality",
+    "scenario",
+    "first_mes",
+    "alternate_greetings",
+    "mes_example",
+    "system_prompt",
+    "post_history_instructions",
+    "creator_notes",
+    "character_book",
+];
+
+/// 可编辑字段的路径前缀：V2/V3 为 `/data`，V1 为根级
+pub fn field_prefix(json: &Value) -> &'static str {
+    if json.get("data").map(|d| d.is_object()).unwrap_or(false) {
+        "/data"
+    } else {
+        ""
+    }
+}
+
+/// 判断补丁路径是否落在可编辑字段内
+pub fn is_editable_path(json: &Value, path: &str) -> bool {
+    let prefix = field_prefix(json);
+    let Some(rest) = path.strip_prefix(prefix).and_then(|p| p.strip_prefix('/')) else {
+        return false;
+    };
+    let field = rest.split('/').next().unwrap_or("");
+    EDITABLE_FIELDS.contains(&field)
+}
+
+/// 以 `{路径: 当前值}` 形式列出可编辑字段（世界书按条目展开）
+pub fn editable_fields(json: &Value) -> Value {
+    let prefix = field_prefix(json);
+    let card_data = json.get("data").filter(|d| d.is_object()).unwrap_or(json);
+
+    let mut fields = serde_json::Map::new();
+    for key in EDITABLE_FIELDS {
+        if *key == "character_book" {
+            continue;
+        }
+        let value = card_data.get(*key).cloned().unwrap_or_else(|| {
+            if *key == "alternate_greetings" {
+                Value::Array(vec![])
+            } else {
+                Value::String(String::new())
+            }
+        });
+        fields.insert(format!("{}/{}", prefix, key), value);
+    }
+
+    if let Some(entries) = card_data
+        .get("character_book")
+        .and_then(|cb| cb.get("entries"))
+        .and_then(|e| e.as_array())
+    {
+        for (i, entry) in entries.iter().enumerate() {
+            fields.insert(
+                format!("{}/character_book/entries/{}", prefix, i),
+                serde_json::json!({
+                    "comment": entry.get("comment").cloned().unwrap_or(Value::Null),
+                    "keys": entry.get("keys").cloned().unwrap_or(Value::Null),
+                    "content": entry.get("content").cloned().unwrap_or(Value::Null),
+                    "enabled": entry.get("enabled").cloned().unwrap_or(Value::Bool(true)),
+                }),
+            );
+        }
+    }
+
+    Value::Object(fields)
+}
+
+/// 写入 AI 概览：custom_summary，及可选的标签（同步 data.tags 与根级 tags）
+pub async fn apply_overview(
+    db: &DatabaseConnection,
+    card: &character_card::Model,
+    summary: &str,
+    tags: Option<&[String]>,
+) -> Result<character_card::Model, DbErr> {
+    let mut active: character_card::ActiveModel = card.clone().into();
+    active.custom_summary = Set(Some(summary.to_string()));
+
+    if let Some(tags) = tags {
+        active.tags = Set(serde_json::to_string_pretty(&tags).unwrap_or("[]".to_string()));
+
+        // 更新 data JSON 中的 tags（V1/V2/V3 兼容）
+        let mut current_json: Value =
+            serde_json::from_str(&card.data).unwrap_or(serde_json::json!({}));
+        if let Some(obj) = current_json.get_mut("data").and_then(|d| d.as_object_mut()) {
+            obj.insert("tags".to_string(), serde_json::json!(tags));
+        }
+        if let Some(obj) = current_json.as_object_mut() {
+            obj.insert("tags".to_string(), serde_json::json!(tags));
+        }
+        active.data = Set(serde_json::to_string_pretty(&current_json).unwrap_or(card.data.clone()));
+    }
+
+    active.metadata_modified = Set(true);
+    active.updated_at = Set(chrono::Utc::now().naive_utc());
+    active.update(db).await
+}
+
+/// 构建 CCv3 character_book 条目（扩展字段与 SillyTavern 默认值一致）
+pub fn character_book_entry(
+    id: i64,
+    keys: Vec<String>,
+    comment: &str,
+    content: &str,
+    constant: bool,
+) -> Value {
+    serde_json::json!({
+        "id": id,
+        "keys": keys,
+        "secondary_keys": [],
+        "comment": comment,
+        "content": content,
+        "constant": constant,
+        "selective": true,
+        "insertion_order": 100,
+        "enabled": true,
+        "position": "before_char",
+        "use_regex": false,
+        "extensions": {
+            "position": 0,
+            "exclude_recursion": false,
+            "display_index": id,
+            "probability": 100,
+            "useProbability": true,
+            "depth": 4,
+            "selectiveLogic": 0,
+            "group": "",
+            "prevent_recursion": false,
+            "scan_depth": null,
+            "match_whole_words": null,
+            "case_sensitive": null
+        }
+    })
+}
+
+/// 构建空白 CCv3 角色卡 JSON 模板（参考 docs/cankao/新建角色卡.json）
+pub fn blank_card_json(name: &str) -> Value {
+    let now = chrono::Local::now();
+    let create_date = now.format("%Y-%m-%d @%Hh %Mm %Ss %3fms").to_string();
+
+    serde_json::json!({
+        "name": name,
+        "description": "",
+        "personality": "",
+        "scenario": "",
+        "first_mes": "",
+        "mes_example": "",
+        "creatorcomment": "",
+        "avatar": "none",
+        "talkativeness": "0.5",
+        "fav": false,
+        "tags": [],
+        "spec": "chara_card_v3",
+        "spec_version": "3.0",
+        "data": {
+            "name": name,
+            "description": "",
+            "personality": "",
+            "scenario": "",
+            "first_mes": "",
+            "mes_example": "",
+            "creator_notes": "",
+            "system_prompt": "",
+            "post_history_instructions": "",
+            "tags": [],
+            "creator": "",
+            "character_version": "",
+            "alternate_greetings": [],
+            "extensions": {
+                "talkativeness": "0.5",
+                "fav": false,
+                "world": "",
+                "depth_prompt": {
+                    "prompt": "",
+                    "depth": 4,
+                    "role": "system"
+                }
+            },
+            "group_only_greetings": []
+        },
+        "create_date": create_date
+    })
+}
+
+/// 将图片裁剪为 512x768 并写入角色卡封面（v1_source.png + v1_thumbnail.webp），返回头像路径
+pub async fn write_avatar_image(card_id: Uuid, data: &[u8]) -> Result<String, String> {
+    let storage_dir = crate::utils::paths::get_data_path(&format!("cards/{}", card_id));
+    tokio::fs::create_dir_all(&storage_dir)
+        .await
+        .map_err(|e| format!("创建角色卡目录失败: {}", e))?;
+
+    let img = image::load_from_memory(data).map_err(|e| format!("图片加载失败: {}", e))?;
+    let resized = img.resize_to_fill(512, 768, image::imageops::FilterType::Lanczos3);
+
+    let mut png_data = Vec::new();
+    resized
+        .write_to(
+            &mut std::io::Cursor::new(&mut png_data),
+            image::ImageOutputFormat::Png,
+        )
+        .map_err(|e| format!("PNG 转换失败: {}", e))?;
+    tokio::fs::write(storage_dir.join("v1_source.png"), &png_data)
+        .await
+        .map_err(|e| format!("写入封面失败: {}", e))?;
+
+    // WebPMemory 不可跨 await 持有，先复制为 Vec
+    let webp_data = webp::Encoder::from_image(&resized)
+        .map_err(|e| format!("WebP 编码失败: {}", e))?
+        .encode(85.0)
+        .to_vec();
+    if webp_data.is_empty() {
+        return Err("WebP 编码结果为空".to_string());
+    }
+    tokio::fs::write(storage_dir.join("v1_thumbnail.webp"), &webp_data)
+        .await
+        .map_err(|e| format!("写入缩略图失败: {}", e))?;
+
+    Ok(format!("/cards/{}/v1_thumbnail.webp", card_id))
+}
+
+/// 以新 JSON 创建角色卡副本（复制封面文件，自动生成 V1 版本）
+pub async fn create_card_copy(
+    db: &DatabaseConnection,
+    source: &character_card::Model,
+    mut json: Value,
+) -> Result<Uuid, String> {
+    sync_root_mirrors(&mut json);
+    let uuid = Uuid::new_v4();
+
+    let source_dir = crate::utils::paths::get_data_path(&format!("cards/{}", source.id));
+    let card_dir = crate::utils::paths::get_data_path(&format!("cards/{}", uuid));
+    tokio::fs::create_dir_all(&card_dir)
+        .await
+        .map_err(|e| format!("创建角色卡目录失败: {}", e))?;
+
+    // 复制封面（默认封面等非卡片目录下的路径保持不变）
+    let mut avatar = source.avatar.clone();
+    for file in ["v1_source.png", "v1_thumbnail.webp"] {
+        let from = source_dir.join(file);
+        if from.exists() {
+            tokio::fs::copy(&from, card_dir.join(file))
+                .await
+                .map_err(|e| format!("复制封面失败: {}", e))?;
+        }
+    }
+    if let Some(a) = &source.avatar {
+        if a.starts_with(&format!("/cards/{}/", source.id)) {
+            avatar = Some(a.replace(&source.id.to_string(), &uuid.to_string()));
+        }
+    }
+
+    let compact_json =
+        serde_json::to_string(&json).map_err(|e| format!("序列化 JSON 失败: {}", e))?;
+    let data_hash = crate::utils::hash::compute_json_hash(&compact_json);
+
+    crate::api::cards::save_card_model(db, uuid, json, avatar, data_hash, &source.source).await?;
+
+    // 保留原卡分类
+    if source.category_id.is_some() {
+        if let Ok(Some(card)) = character_card::Entity::find_by_id(uuid).one(db).await {
+            let mut active: character_card::ActiveModel = card.into();
+            active.category_id = Set(source.category_id);
+            active.update(db).await.map_err(|e| e.to_string())?;
+        }
+    }
+
+    crate::api::dashboard::invalidate_cache();
+    Ok(uuid)
+}
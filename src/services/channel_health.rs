@@ -0,0 +1,248 @@
+//! AI 渠道健康监测
+//!
+//! 后台定时探测已启用渠道的 /models 接口，记录延迟与错误历史，并顺带缓存模型列表；
+//! 连续失败达到阈值的渠道标记为不健康，渠道解析时会跳过（状态保存在内存中，服务重启后重置）
+
+use super::ai::AiError;
+use crate::entities::ai_channel;
+use once_cell::sync::Lazy;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// 定时探测间隔
+const PROBE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// 服务启动后首次探测的延迟
+const INITIAL_DELAY: Duration = Duration::from_secs(30);
+/// 单次探测超时
+const PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+/// 连续失败多少次后标记为不健康
+const UNHEALTHY_THRESHOLD: u32 = 3;
+/// 每个渠道保留的探测记录数
+const HISTORY_LIMIT: usize = 20;
+/// 模型列表缓存有效期
+const MODELS_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// 单次探测 / 调用记录
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthRecord {
+    pub success: bool,
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+    pub checked_at: String,
+}
+
+/// 渠道健康状态
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelHealth {
+    pub healthy: bool,
+    pub consecutive_failures: u32,
+    pub last_latency_ms: Option<u64>,
+    pub last_error: Option<String>,
+    pub last_checked_at: Option<String>,
+    /// 最近的记录（最新在后）
+    pub history: VecDeque<HealthRecord>,
+}
+
+impl Default for ChannelHealth {
+    fn default() -> Self {
+        Self {
+            healthy: true,
+            consecutive_failures: 0,
+            last_latency_ms: None,
+            last_error: None,
+            last_checked_at: None,
+            history: VecDeque::new(),
+        }
+    }
+}
+
+static HEALTH: Lazy<Arc<RwLock<HashMap<Uuid, ChannelHealth>>>> =
+    Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
+
+/// (base_url, api_key) -> (缓存时间, 模型列表)
+type ModelsCache = HashMap<(String, String), (Instant, Value)>;
+
+/// 模型列表缓存，按连接信息区分，兼容尚未保存的渠道
+static MODELS_CACHE: Lazy<Arc<RwLock<ModelsCache>>> =
+    Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
+
+/// 读取渠道健康状态（尚未探测过时返回 None）
+pub fn get(id: Uuid) -> Option<ChannelHealth> {
+    HEALTH.read().unwrap().get(&id).cloned()
+}
+
+/// 渠道是否可用于路由（未探测过的渠道视为健康）
+pub fn is_healthy(id: Uuid) -> bool {
+    HEALTH.read().unwrap().get(&id).is_none_or(|h| h.healthy)
+}
+
+/// 渠道删除或连接信息变更后清除其状态
+pub fn forget(id: Uuid) {
+    HEALTH.write().unwrap().remove(&id);
+}
+
+/// HTTP 状态码是否说明渠道本身有问题（服务端错误或鉴权失败）
+///
+/// 400 / 422（如上下文超长、参数不支持）与 429 限流通常由请求本身引起，不计入渠道失败
+pub fn is_channel_fault(status: u16) -> bool {
+    status >= 500 || status == 401 || status == 403
+}
+
+/// 请求错误是否计入渠道失败：连接失败、响应无效计入，HTTP 错误按 [`is_channel_fault`] 判断
+pub fn is_fault_error(error: &AiError) -> bool {
+    match error {
+        AiError::Http(status, _) => is_channel_fault(*status),
+        _ => true,
+    }
+}
+
+/// 记录一次模型列表请求的结果
+///
+/// 非渠道故障的 HTTP 错误（如不提供 /models 接口的 404）说明渠道可达，按成功记录
+pub fn record_models_result(id: Uuid, latency_ms: u64, result: &Result<Value, AiError>) {
+    match result {
+        Err(e) if is_fault_error(e) => record(id, None, Some(e.to_string())),
+        _ => record(id, Some(latency_ms), None),
+    }
+}
+
+/// 记录一次探测或调用结果
+pub fn record(id: Uuid, latency_ms: Option<u64>, error: Option<String>) {
+    let record = HealthRecord {
+        success: error.is_none(),
+        latency_ms,
+        error,
+        checked_at: chrono::Utc::now().to_rfc3339(),
+    };
+
+    let mut health = HEALTH.write().unwrap();
+    let entry = health.entry(id).or_default();
+    if record.success {
+        entry.consecutive_failures = 0;
+        entry.healthy = true;
+    } else {
+        entry.consecutive_failures += 1;
+        if entry.consecutive_failures >= UNHEALTHY_THRESHOLD {
+            entry.healthy = false;
+        }
+    }
+    entry.last_latency_ms = record.latency_ms.or(entry.last_latency_ms);
+    entry.last_error = record.error.clone();
+    entry.last_checked_at = Some(record.checked_at.clone());
+    entry.history.push_back(record);
+    while entry.history.len() > HISTORY_LIMIT {
+        entry.history.pop_front();
+    }
+}
+
+fn cache_key(base_url: &str, api_key: &str) -> (String, String) {
+    (
+        base_url.trim_end_matches('/').to_string(),
+        api_key.to_string(),
+    )
+}
+
+/// 读取未过期的模型列表缓存
+pub fn cached_models(base_url: &str, api_key: &str) -> Option<Value> {
+    MODELS_CACHE
+        .read()
+        .unwrap()
+        .get(&cache_key(base_url, api_key))
+        .filter(|(at, _)| at.elapsed() < MODELS_TTL)
+        .map(|(_, models)| models.clone())
+}
+
+/// 请求上游 /models，成功时写入缓存
+pub async fn fetch_models(base_url: &str, api_key: &str) -> Result<Value, AiError> {
+    if super::mock_ai::is_mock(base_url) {
+        return Ok(super::mock_ai::models(base_url));
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(PROBE_TIMEOUT)
+        .build()
+        .map_err(|e| AiError::Request(e.to_string()))?;
+    let url = format!("{}/models", base_url.trim_end_matches('/'));
+
+    let res = client
+        .get(&url)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .send()
+        .await
+        .map_err(|e| AiError::Request(format!("Request failed: {}", e)))?;
+
+    if !res.status().is_success() {
+        let status = res.status().as_u16();
+        let err_text = res.text().await.unwrap_or_default();
+        return Err(AiError::Http(
+            status,
+            format!(
+                "API Error (HTTP {}): {}",
+                status,
+                err_text.chars().take(200).collect::<String>()
+            ),
+        ));
+    }
+
+    let json: Value = res
+        .json()
+        .await
+        .map_err(|e| AiError::Request(format!("Invalid JSON response: {}", e)))?;
+
+    MODELS_CACHE
+        .write()
+        .unwrap()
+        .insert(cache_key(base_url, api_key), (Instant::now(), json.clone()));
+    Ok(json)
+}
+
+/// 探测单个渠道并记录结果
+pub async fn probe(channel: &ai_channel::Model) {
+    let api_key = match super::ai::channel_api_key(channel) {
+        Ok(key) => key,
+        Err(e) => {
+            record(channel.id, None, Some(e.to_string()));
+            return;
+        }
+    };
+    let start = Instant::now();
+    let result = fetch_models(&channel.base_url, &api_key).await;
+    let latency_ms = start.elapsed().as_millis() as u64;
+
+    if let Err(e) = &result {
+        tracing::warn!("渠道 {} 健康检查失败: {}", channel.name, e);
+    }
+    record_models_result(channel.id, latency_ms, &result);
+}
+
+/// 探测全部已启用渠道
+pub async fn probe_all(db: &DatabaseConnection) -> Result<usize, sea_orm::DbErr> {
+    let channels = ai_channel::Entity::find()
+        .filter(ai_channel::Column::IsActive.eq(true))
+        .all(db)
+        .await?;
+    for channel in &channels {
+        probe(channel).await;
+    }
+    Ok(channels.len())
+}
+
+/// 启动后台定时探测
+pub fn spawn_monitor(db: DatabaseConnection) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval_at(tokio::time::Instant::now() + INITIAL_DELAY, PROBE_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = probe_all(&db).await {
+                tracing::warn!("渠道健康检查读取渠道失败: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,367 @@
+//! 聊天记录分支
+//!
+//! ST 的"从此处分支"会复制前 N 条消息生成新文件，同一角色卡下因此常有大量前缀相同的记录。
+//! 这里按消息指纹计算公共前缀，推断分支关系并生成分支树；同时提供从指定楼层分支与将分支拼接回其他记录的操作
+
+use crate::services::st_chat::{ChatLog, StMessage};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// 至少共享的楼层数，低于此值不视为分支（避免只有开场白相同的记录被串在一起）
+const MIN_SHARED_FLOORS: usize = 2;
+
+/// 单条消息的指纹（发言者 + 正文，忽略首尾空白与 swipe）
+///
+/// 指纹会缓存在聊天记录索引中，因此使用跨版本稳定的 SHA-256（取前 8 字节）
+pub fn fingerprint(message: &StMessage) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(message.name.as_bytes());
+    hasher.update([0, message.is_user as u8]);
+    hasher.update(message.mes.trim().as_bytes());
+    let digest = hasher.finalize();
+    u64::from_le_bytes(digest[..8].try_into().unwrap())
+}
+
+pub fn fingerprints(log: &ChatLog) -> Vec<u64> {
+    log.messages.iter().map(fingerprint).collect()
+}
+
+/// 公共前缀长度（楼层数）
+pub fn common_prefix(a: &[u64], b: &[u64]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+/// ST 分支文件在 chat_metadata.main_chat 中记录来源聊天名（不含扩展名）
+pub fn main_chat(log: &ChatLog) -> Option<String> {
+    log.header
+        .as_ref()
+        .and_then(|h| h.chat_metadata.get("main_chat"))
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+/// 参与分支推断的记录（按创建时间从早到晚排列）
+pub struct BranchInput {
+    pub history_id: uuid::Uuid,
+    pub name: String,
+    /// 文件名去掉扩展名，用于匹配 main_chat
+    pub file_stem: String,
+    pub fingerprints: Vec<u64>,
+    pub main_chat: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BranchNode {
+    pub history_id: uuid::Uuid,
+    pub name: String,
+    pub floors: usize,
+    pub parent_id: Option<uuid::Uuid>,
+    /// 与父记录共享的楼层数（分支点）
+    pub shared_floors: usize,
+    /// 与父记录分叉后独有的楼层数
+    pub own_floors: usize,
+    pub depth: usize,
+}
+
+/// 推断每份记录的父记录，按深度优先顺序返回（根节点 depth 为 0）
+///
+/// 优先使用 main_chat；否则在更早的记录中选公共前缀最长者，前缀相同时选更早的记录
+pub fn build_tree(inputs: &[BranchInput]) -> Vec<BranchNode> {
+    let by_stem: HashMap<&str, usize> = inputs
+        .iter()
+        .enumerate()
+        .map(|(i, input)| (input.file_stem.as_str(), i))
+        .collect();
+
+    let mut parents: Vec<Option<(usize, usize)>> = vec![None; inputs.len()];
+    for (i, input) in inputs.iter().enumerate() {
+        let hinted = input
+            .main_chat
+            .as_deref()
+            .and_then(|stem| by_stem.get(stem).copied())
+            .filter(|&p| p != i)
+            .map(|p| {
+                (
+                    p,
+                    common_prefix(&inputs[p].fingerprints, &input.fingerprints),
+                )
+            });
+        let inferred = || {
+            inputs[..i]
+                .iter()
+                .enumerate()
+                .map(|(p, other)| (p, common_prefix(&other.fingerprints, &input.fingerprints)))
+                .filter(|&(_, shared)| shared >= MIN_SHARED_FLOORS)
+                .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))
+        };
+        parents[i] = hinted.or_else(inferred);
+
+        // main_chat 可能互相指向，出现环时断开
+        let mut seen = vec![false; inputs.len()];
+        let mut cursor = Some(i);
+        while let Some(node) = cursor {
+            if seen[node] {
+                parents[i] = None;
+                break;
+            }
+            seen[node] = true;
+            cursor = parents[node].map(|(p, _)| p);
+        }
+    }
+
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); inputs.len()];
+    let mut roots = Vec::new();
+    for (i, parent) in parents.iter().enumerate() {
+        match parent {
+            Some((p, _)) => children[*p].push(i),
+            None => roots.push(i),
+        }
+    }
+
+    let mut nodes = Vec::with_capacity(inputs.len());
+    let mut stack: Vec<(usize, usize)> = roots.into_iter().rev().map(|i| (i, 0)).collect();
+    while let Some((i, depth)) = stack.pop() {
+        let input = &inputs[i];
+        let shared = parents[i].map(|(_, shared)| shared).unwrap_or(0);
+        nodes.push(BranchNode {
+            history_id: input.history_id,
+            name: input.name.clone(),
+            floors: input.fingerprints.len(),
+            parent_id: parents[i].map(|(p, _)| inputs[p].history_id),
+            shared_floors: shared,
+            own_floors: input.fingerprints.len().saturating_sub(shared),
+            depth,
+        });
+        stack.extend(children[i].iter().rev().map(|&c| (c, depth + 1)));
+    }
+    nodes
+}
+
+/// 从指定楼层分支：保留前 floor 条消息，并在 chat_metadata.main_chat 中记录来源
+pub fn branch(log: &ChatLog, floor: usize, main_chat: &str) -> Result<ChatLog, String> {
+    if floor == 0 || floor > log.messages.len() {
+        return Err(format!(
+            "楼层 {} 不存在（共 {} 层）",
+            floor,
+            log.messages.len()
+        ));
+    }
+    let mut branched = log.clone();
+    branched.messages.truncate(floor);
+    branched.errors.clear();
+    if let Some(header) = branched.header.as_mut() {
+        if let Some(Value::Object(meta)) = header.raw.get_mut("chat_metadata") {
+            meta.insert("main_chat".into(), Value::String(main_chat.to_string()));
+        } else if let Some(obj) = header.raw.as_object_mut() {
+            obj.insert(
+                "chat_metadata".into(),
+                serde_json::json!({ "main_chat": main_chat }),
+            );
+        }
+        header.chat_metadata = header.raw["chat_metadata"].clone();
+    }
+    Ok(branched)
+}
+
+/// 拼接参数（楼层号从 1 开始）
+pub struct SpliceOptions {
+    /// 目标记录保留到第几层（其后接入分支内容）
+    pub at: usize,
+    /// 从分支的第几层开始取
+    pub from: usize,
+    /// true 时丢弃目标记录 at 之后的楼层，false 时将其保留在拼接内容之后
+    pub replace_tail: bool,
+}
+
+/// 将 source 的 from.. 楼层接到 target 的第 at 层之后，返回接入的楼层数
+pub fn splice(
+    target: &mut ChatLog,
+    source: &ChatLog,
+    options: &SpliceOptions,
+) -> Result<usize, String> {
+    if options.at > target.messages.len() {
+        return Err(format!(
+            "目标记录没有第 {} 层（共 {} 层）",
+            options.at,
+            target.messages.len()
+        ));
+    }
+    if options.from == 0 || options.from > source.messages.len() {
+        return Err(format!(
+            "分支没有第 {} 层（共 {} 层）",
+            options.from,
+            source.messages.len()
+        ));
+    }
+    let segment = &source.messages[options.from - 1..];
+    let tail = target.messages.split_off(options.at);
+    target.messages.extend(segment.iter().cloned());
+    if !options.replace_tail {
+        target.messages.extend(tail);
+    }
+    Ok(segment.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(stem: &str, fingerprints: &[u64], main_chat: Option<&str>) -> BranchInput {
+        BranchInput {
+            history_id: uuid::Uuid::new_v4(),
+            name: stem.to_string(),
+            file_stem: stem.to_string(),
+            fingerprints: fingerprints.to_vec(),
+            main_chat: main_chat.map(str::to_string),
+        }
+    }
+
+    /// (名称, 父记录名称, 共享楼层, 深度)，按返回顺序
+    fn shape(inputs: &[BranchInput]) -> Vec<(String, Option<String>, usize, usize)> {
+        let names: HashMap<_, _> = inputs
+            .iter()
+            .map(|i| (i.history_id, i.name.clone()))
+            .collect();
+        build_tree(inputs)
+            .into_iter()
+            .map(|n| {
+                (
+                    n.name,
+                    n.parent_id.map(|p| names[&p].clone()),
+                    n.shared_floors,
+                    n.depth,
+                )
+            })
+            .collect()
+    }
+
+    fn node(
+        name: &str,
+        parent: Option<&str>,
+        shared: usize,
+        depth: usize,
+    ) -> (String, Option<String>, usize, usize) {
+        (name.to_string(), parent.map(str::to_string), shared, depth)
+    }
+
+    #[test]
+    fn infers_parent_by_longest_prefix() {
+        let inputs = [
+            input("a", &[1, 2, 3, 4], None),
+            input("b", &[1, 2, 3, 9], None),
+            input("c", &[1, 2, 3, 9, 10], None),
+        ];
+        assert_eq!(
+            shape(&inputs),
+            [
+                node("a", None, 0, 0),
+                node("b", Some("a"), 3, 1),
+                node("c", Some("b"), 4, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn short_shared_prefix_is_not_a_branch() {
+        let inputs = [input("a", &[1, 2], None), input("b", &[1, 3], None)];
+        assert_eq!(
+            shape(&inputs),
+            [node("a", None, 0, 0), node("b", None, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn prefers_earliest_record_on_tie() {
+        let inputs = [
+            input("a", &[1, 2, 3], None),
+            input("b", &[1, 2, 3], None),
+            input("c", &[1, 2, 3, 4], None),
+        ];
+        let tree = shape(&inputs);
+        assert_eq!(tree[1], node("b", Some("a"), 3, 1));
+        assert_eq!(tree[2], node("c", Some("a"), 3, 1));
+    }
+
+    #[test]
+    fn main_chat_overrides_prefix() {
+        let inputs = [
+            input("a", &[1, 2, 3], None),
+            input("b", &[7, 8], None),
+            input("c", &[1, 2, 3, 4], Some("b")),
+        ];
+        assert_eq!(shape(&inputs)[2], node("c", Some("b"), 0, 1));
+    }
+
+    #[test]
+    fn main_chat_cycles_are_broken() {
+        let inputs = [
+            input("a", &[1, 2, 3], Some("b")),
+            input("b", &[1, 2, 3, 4], Some("a")),
+            input("c", &[5], Some("c")),
+        ];
+        assert_eq!(
+            shape(&inputs),
+            [
+                node("b", None, 0, 0),
+                node("a", Some("b"), 3, 1),
+                node("c", None, 0, 0)
+            ]
+        );
+    }
+
+    fn log(messages: &[&str]) -> ChatLog {
+        let mut content =
+            r#"{"user_name":"User","character_name":"Alice","chat_metadata":{}}"#.to_string();
+        for mes in messages {
+            content.push('\n');
+            content.push_str(&serde_json::json!({ "name": "Alice", "mes": mes }).to_string());
+        }
+        ChatLog::parse(&content)
+    }
+
+    fn texts(log: &ChatLog) -> Vec<&str> {
+        log.messages.iter().map(|m| m.mes.as_str()).collect()
+    }
+
+    #[test]
+    fn branch_keeps_prefix_and_records_source() {
+        let branched = branch(&log(&["a", "b", "c"]), 2, "main").unwrap();
+        assert_eq!(texts(&branched), ["a", "b"]);
+        assert_eq!(main_chat(&branched).as_deref(), Some("main"));
+        assert!(branch(&log(&["a"]), 0, "main").is_err());
+        assert!(branch(&log(&["a"]), 2, "main").is_err());
+    }
+
+    #[test]
+    fn splice_inserts_or_replaces_tail() {
+        let source = log(&["a", "x", "y"]);
+        let mut target = log(&["a", "b", "c"]);
+        let options = SpliceOptions {
+            at: 1,
+            from: 2,
+            replace_tail: false,
+        };
+        assert_eq!(splice(&mut target, &source, &options), Ok(2));
+        assert_eq!(texts(&target), ["a", "x", "y", "b", "c"]);
+
+        let mut target = log(&["a", "b", "c"]);
+        let options = SpliceOptions {
+            at: 1,
+            from: 2,
+            replace_tail: true,
+        };
+        splice(&mut target, &source, &options).unwrap();
+        assert_eq!(texts(&target), ["a", "x", "y"]);
+
+        let options = SpliceOptions {
+            at: 9,
+            from: 1,
+            replace_tail: true,
+        };
+        assert!(splice(&mut target, &source, &options).is_err());
+    }
+}
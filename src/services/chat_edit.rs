@@ -0,0 +1,577 @@
+//! 聊天记录楼层编辑
+//!
+//! 对 JSONL 聊天记录按楼层执行编辑、删除、插入、移动、隐藏与选择 swipe 等操作，
+//! 并提供原子写入（临时文件 + rename）与按时间保存的备份栈，用于撤销。
+//! 每次修改可附带修改记录（楼层映射与被移除的书签，与备份一同保存），用于同步书签与阅读进度
+
+use crate::entities::chat_bookmark;
+use crate::services::st_chat::{ChatLog, StMessage};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::fs;
+
+/// 每份记录最多保留的备份数
+const MAX_BACKUPS: usize = 20;
+
+/// 楼层操作（楼层号从 1 开始；一次提交的多个操作按顺序执行，后一个操作看到的是前一个操作之后的楼层号）
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum FloorOp {
+    /// 修改消息正文 / 发言者 / 思维链
+    Edit {
+        floor: usize,
+        content: Option<String>,
+        name: Option<String>,
+        reasoning: Option<String>,
+    },
+    /// 删除 from..=to（to 缺省时只删除一层）
+    Delete { from: usize, to: Option<usize> },
+    /// 在 after 之后插入一条消息（after 为 0 时插入到最前）
+    Insert {
+        after: usize,
+        name: String,
+        #[serde(default)]
+        is_user: bool,
+        content: String,
+        send_date: Option<String>,
+    },
+    /// 将 from 楼层移动到 to 位置
+    Move { from: usize, to: usize },
+    /// 隐藏 / 取消隐藏 from..=to（对应 ST 的 /hide 与 /unhide）
+    SetHidden {
+        from: usize,
+        to: Option<usize>,
+        hidden: bool,
+    },
+    /// 选择 swipe 作为当前回复
+    SelectSwipe { floor: usize, swipe_id: usize },
+}
+
+fn index_of(log: &ChatLog, floor: usize) -> Result<usize, String> {
+    if floor == 0 || floor > log.messages.len() {
+        return Err(format!(
+            "楼层 {} 不存在（共 {} 层）",
+            floor,
+            log.messages.len()
+        ));
+    }
+    Ok(floor - 1)
+}
+
+fn range_of(
+    log: &ChatLog,
+    from: usize,
+    to: Option<usize>,
+) -> Result<std::ops::RangeInclusive<usize>, String> {
+    let start = index_of(log, from)?;
+    let end = index_of(log, to.unwrap_or(from))?;
+    if end < start {
+        return Err(format!("楼层范围无效: {}-{}", from, to.unwrap_or(from)));
+    }
+    Ok(start..=end)
+}
+
+/// 在原始 JSON 上修改后重新解析，保持 StMessage 字段与 raw 一致
+fn with_raw(message: &mut StMessage, f: impl FnOnce(&mut Map<String, Value>)) {
+    let mut value = message.to_value();
+    if let Value::Object(obj) = &mut value {
+        f(obj);
+    }
+    *message = StMessage::from_value(value);
+}
+
+fn extra_of(obj: &mut Map<String, Value>) -> &mut Map<String, Value> {
+    let extra = obj
+        .entry("extra")
+        .or_insert_with(|| Value::Object(Map::new()));
+    if !extra.is_object() {
+        *extra = Value::Object(Map::new());
+    }
+    extra.as_object_mut().unwrap()
+}
+
+/// 当前显示的 swipe 下标（swipe_id 缺失时按正文匹配）
+fn current_swipe(message: &StMessage) -> Option<usize> {
+    message
+        .swipe_id
+        .or_else(|| message.swipes.iter().position(|s| *s == message.mes))
+}
+
+fn edit(
+    message: &mut StMessage,
+    content: Option<String>,
+    name: Option<String>,
+    reasoning: Option<String>,
+) {
+    let swipe = current_swipe(message);
+    if let Some(content) = content {
+        // 同步更新当前 swipe，避免切换 swipe 后修改丢失
+        if let Some(slot) = swipe.and_then(|i| message.swipes.get_mut(i)) {
+            slot.clone_from(&content);
+        }
+        message.mes = content;
+    }
+    if let Some(name) = name {
+        message.name = name;
+    }
+    with_raw(message, |obj| {
+        if let Some(reasoning) = reasoning {
+            extra_of(obj).insert("reasoning".into(), Value::String(reasoning.clone()));
+            if let Some(info) = swipe.and_then(|i| {
+                obj.get_mut("swipe_info")
+                    .and_then(|v| v.get_mut(i))
+                    .and_then(|v| v.as_object_mut())
+            }) {
+                extra_of(info).insert("reasoning".into(), Value::String(reasoning));
+            }
+        }
+    });
+}
+
+fn select_swipe(message: &mut StMessage, swipe_id: usize) -> Result<(), String> {
+    let Some(text) = message.swipes.get(swipe_id).cloned() else {
+        return Err(format!(
+            "swipe {} 不存在（共 {} 个）",
+            swipe_id,
+            message.swipes.len()
+        ));
+    };
+    message.mes = text;
+    message.swipe_id = Some(swipe_id);
+    // ST 切换 swipe 时会从 swipe_info 恢复该回复的发送时间与 extra（模型、思维链等）
+    with_raw(message, |obj| {
+        let info = obj
+            .get("swipe_info")
+            .and_then(|v| v.get(swipe_id))
+            .and_then(|v| v.as_object())
+            .cloned();
+        if let Some(info) = info {
+            for key in ["send_date", "extra"] {
+                if let Some(value) = info.get(key) {
+                    obj.insert(key.into(), value.clone());
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// 依次执行操作；任一操作失败时返回错误（调用方应丢弃已修改的 log）
+pub fn apply_ops(log: &mut ChatLog, ops: &[FloorOp]) -> Result<(), String> {
+    for (i, op) in ops.iter().enumerate() {
+        apply_op(log, op.clone()).map_err(|e| format!("第 {} 个操作失败: {}", i + 1, e))?;
+    }
+    Ok(())
+}
+
+fn apply_op(log: &mut ChatLog, op: FloorOp) -> Result<(), String> {
+    match op {
+        FloorOp::Edit {
+            floor,
+            content,
+            name,
+            reasoning,
+        } => {
+            let index = index_of(log, floor)?;
+            edit(&mut log.messages[index], content, name, reasoning);
+        }
+        FloorOp::Delete { from, to } => {
+            let range = range_of(log, from, to)?;
+            log.messages.drain(range);
+        }
+        FloorOp::Insert {
+            after,
+            name,
+            is_user,
+            content,
+            send_date,
+        } => {
+            if after > log.messages.len() {
+                return Err(format!(
+                    "楼层 {} 不存在（共 {} 层）",
+                    after,
+                    log.messages.len()
+                ));
+            }
+            let send_date = send_date.or_else(|| Some(chrono::Utc::now().to_rfc3339()));
+            log.messages
+                .insert(after, StMessage::new(&name, is_user, &content, send_date));
+        }
+        FloorOp::Move { from, to } => {
+            let source = index_of(log, from)?;
+            let target = index_of(log, to)?;
+            let message = log.messages.remove(source);
+            log.messages.insert(target, message);
+        }
+        FloorOp::SetHidden { from, to, hidden } => {
+            for index in range_of(log, from, to)? {
+                log.messages[index].is_system = hidden;
+            }
+        }
+        FloorOp::SelectSwipe { floor, swipe_id } => {
+            let index = index_of(log, floor)?;
+            select_swipe(&mut log.messages[index], swipe_id)?;
+        }
+    }
+    Ok(())
+}
+
+/// 修改前后的楼层对应关系
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FloorMap {
+    /// 修改前的楼层数
+    pub before: usize,
+    /// 修改后每个楼层对应的修改前楼层（新插入的为 None）
+    pub origins: Vec<Option<usize>>,
+}
+
+impl FloorMap {
+    fn identity(len: usize) -> Vec<Option<usize>> {
+        (1..=len).map(Some).collect()
+    }
+
+    /// 按 apply_ops 的规则计算映射（ops 应已成功执行过）
+    pub fn for_ops(before: usize, ops: &[FloorOp]) -> Self {
+        let mut origins = Self::identity(before);
+        for op in ops {
+            match *op {
+                FloorOp::Delete { from, to } => {
+                    let end = to.unwrap_or(from).min(origins.len());
+                    if from >= 1 && from <= end {
+                        origins.drain(from - 1..end);
+                    }
+                }
+                FloorOp::Insert { after, .. } => {
+                    origins.insert(after.min(origins.len()), None);
+                }
+                FloorOp::Move { from, to } => {
+                    let len = origins.len();
+                    if (1..=len).contains(&from) && (1..=len).contains(&to) {
+                        let origin = origins.remove(from - 1);
+                        origins.insert(to - 1, origin);
+                    }
+                }
+                FloorOp::Edit { .. } | FloorOp::SetHidden { .. } | FloorOp::SelectSwipe { .. } => {}
+            }
+        }
+        Self { before, origins }
+    }
+
+    /// 在第 at 层之后接入 inserted 层；replace_tail 时丢弃原有的后续楼层
+    pub fn for_splice(before: usize, at: usize, inserted: usize, replace_tail: bool) -> Self {
+        let mut origins = Self::identity(at.min(before));
+        origins.extend(std::iter::repeat_n(None, inserted));
+        if !replace_tail {
+            origins.extend((at + 1..=before).map(Some));
+        }
+        Self { before, origins }
+    }
+
+    /// 修改后的楼层数
+    pub fn after(&self) -> usize {
+        self.origins.len()
+    }
+
+    /// 反向映射（撤销时使用）
+    pub fn inverse(&self) -> Self {
+        let mut origins = vec![None; self.before];
+        for (index, origin) in self.origins.iter().enumerate() {
+            if let Some(slot) = origin.and_then(|o| origins.get_mut(o - 1)) {
+                *slot = Some(index + 1);
+            }
+        }
+        Self {
+            before: self.after(),
+            origins,
+        }
+    }
+
+    /// 修改前的楼层在修改后的位置；该楼层已被删除时为 None
+    pub fn get(&self, floor: usize) -> Option<usize> {
+        self.origins
+            .iter()
+            .position(|o| *o == Some(floor))
+            .map(|i| i + 1)
+    }
+
+    /// 同 get，但楼层被删除时返回原位置之后第一个保留楼层的位置（可能超出末尾）
+    pub fn position(&self, floor: usize) -> usize {
+        self.get(floor).unwrap_or_else(|| {
+            self.origins
+                .iter()
+                .position(|o| o.is_some_and(|o| o > floor))
+                .map_or(self.after() + 1, |i| i + 1)
+        })
+    }
+}
+
+static LOCKS: once_cell::sync::Lazy<Mutex<HashMap<uuid::Uuid, Arc<tokio::sync::Mutex<()>>>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 获取单份记录的写锁，避免并发编辑相互覆盖
+pub async fn lock(history_id: uuid::Uuid) -> tokio::sync::OwnedMutexGuard<()> {
+    let mutex = LOCKS.lock().unwrap().entry(history_id).or_default().clone();
+    mutex.lock_owned().await
+}
+
+/// 原子写入：先写同目录下的临时文件再 rename，避免写到一半时损坏原文件
+pub async fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let file_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("history");
+    let tmp = path.with_file_name(format!(".{}.tmp", file_name));
+    let result = async {
+        let mut file = fs::File::create(&tmp).await?;
+        tokio::io::AsyncWriteExt::write_all(&mut file, data).await?;
+        file.sync_all().await?;
+        drop(file);
+        fs::rename(&tmp, path).await
+    }
+    .await;
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp).await;
+    }
+    result
+}
+
+/// 按时间顺序排列的备份文件（旧 -> 新）
+async fn list_backups(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut backups = Vec::new();
+    let mut entries = match fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(backups),
+        Err(e) => return Err(e),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "bak") {
+            backups.push(path);
+        }
+    }
+    // 文件名为零填充的毫秒时间戳，字典序即时间顺序
+    backups.sort();
+    Ok(backups)
+}
+
+/// 可撤销的次数
+pub async fn undo_depth(dir: &Path) -> usize {
+    list_backups(dir).await.map(|b| b.len()).unwrap_or(0)
+}
+
+/// 与备份一同保存的修改记录，撤销时用于还原书签与阅读进度
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EditRecord {
+    /// 本次修改的楼层映射（未知时为 None）
+    pub floors: Option<FloorMap>,
+    /// 因所在楼层被删除而移除的书签
+    #[serde(default)]
+    pub bookmarks: Vec<chat_bookmark::Model>,
+}
+
+/// 备份对应的修改记录文件
+fn record_path(backup: &Path) -> PathBuf {
+    backup.with_extension("edit.json")
+}
+
+/// 备份当前文件后原子写入新内容；超出上限的旧备份会被删除
+pub async fn save_with_backup(
+    path: &Path,
+    backup_dir: &Path,
+    data: &[u8],
+    record: Option<&EditRecord>,
+) -> std::io::Result<()> {
+    if fs::try_exists(path).await? {
+        fs::create_dir_all(backup_dir).await?;
+        let mut stamp = chrono::Utc::now().timestamp_millis();
+        let mut target = backup_dir.join(format!("{:016}.bak", stamp));
+        // 同一毫秒内多次保存时顺延，保证顺序
+        while fs::try_exists(&target).await? {
+            stamp += 1;
+            target = backup_dir.join(format!("{:016}.bak", stamp));
+        }
+        fs::copy(path, &target).await?;
+        if let Some(record) = record {
+            fs::write(record_path(&target), serde_json::to_vec(record)?).await?;
+        }
+
+        let backups = list_backups(backup_dir).await?;
+        for old in backups
+            .iter()
+            .take(backups.len().saturating_sub(MAX_BACKUPS))
+        {
+            let _ = fs::remove_file(old).await;
+            let _ = fs::remove_file(record_path(old)).await;
+        }
+    }
+    write_atomic(path, data).await
+}
+
+/// 最近一次备份
+pub struct Backup {
+    path: PathBuf,
+    /// 备份的文件内容
+    pub data: Vec<u8>,
+    /// 备份之后那次修改的记录
+    pub record: Option<EditRecord>,
+}
+
+/// 读取最近一次备份（不修改文件）；没有备份时返回 None
+pub async fn latest_backup(backup_dir: &Path) -> std::io::Result<Option<Backup>> {
+    let Some(path) = list_backups(backup_dir).await?.pop() else {
+        return Ok(None);
+    };
+    let data = fs::read(&path).await?;
+    let record = fs::read(record_path(&path))
+        .await
+        .ok()
+        .and_then(|raw| serde_json::from_slice::<EditRecord>(&raw).ok());
+    Ok(Some(Backup { path, data, record }))
+}
+
+/// 撤销：用备份覆盖当前文件并移除该备份
+pub async fn restore(path: &Path, backup: &Backup) -> std::io::Result<()> {
+    write_atomic(path, &backup.data).await?;
+    fs::remove_file(&backup.path).await?;
+    let _ = fs::remove_file(record_path(&backup.path)).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 第 n 层正文为 "n" 的记录
+    fn numbered_log(len: usize) -> ChatLog {
+        ChatLog {
+            messages: (1..=len)
+                .map(|n| StMessage::new("A", false, &n.to_string(), None))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn insert(after: usize) -> FloorOp {
+        FloorOp::Insert {
+            after,
+            name: "B".to_string(),
+            is_user: true,
+            content: "new".to_string(),
+            send_date: None,
+        }
+    }
+
+    /// 执行 ops 后逐层核对 for_ops 的映射，并核对反向映射能还原每个保留的楼层
+    fn check(len: usize, ops: &[FloorOp]) {
+        let mut log = numbered_log(len);
+        apply_ops(&mut log, ops).unwrap();
+        let map = FloorMap::for_ops(len, ops);
+
+        assert_eq!(map.before, len);
+        assert_eq!(map.after(), log.messages.len());
+        for (index, (origin, message)) in map.origins.iter().zip(&log.messages).enumerate() {
+            match origin {
+                Some(floor) => {
+                    assert_eq!(message.mes, floor.to_string(), "floor {}", index + 1);
+                    assert_eq!(map.get(*floor), Some(index + 1));
+                }
+                None => assert_eq!(message.mes, "new", "floor {}", index + 1),
+            }
+        }
+
+        let inverse = map.inverse();
+        assert_eq!((inverse.before, inverse.after()), (map.after(), len));
+        for floor in 1..=len {
+            match map.get(floor) {
+                Some(now) => assert_eq!(inverse.get(now), Some(floor)),
+                None => assert_eq!(inverse.origins[floor - 1], None),
+            }
+        }
+    }
+
+    #[test]
+    fn delete_ranges() {
+        check(
+            5,
+            &[FloorOp::Delete {
+                from: 2,
+                to: Some(3),
+            }],
+        );
+        check(5, &[FloorOp::Delete { from: 5, to: None }]);
+        check(
+            5,
+            &[FloorOp::Delete {
+                from: 1,
+                to: Some(5),
+            }],
+        );
+    }
+
+    #[test]
+    fn insert_at_start_and_end() {
+        check(3, &[insert(0)]);
+        check(3, &[insert(3)]);
+        check(0, &[insert(0), insert(1)]);
+    }
+
+    #[test]
+    fn move_forward_and_backward() {
+        check(5, &[FloorOp::Move { from: 1, to: 3 }]);
+        check(5, &[FloorOp::Move { from: 5, to: 1 }]);
+        check(5, &[FloorOp::Move { from: 2, to: 2 }]);
+    }
+
+    #[test]
+    fn ops_see_previous_results() {
+        check(
+            6,
+            &[
+                FloorOp::Delete { from: 2, to: None },
+                insert(0),
+                FloorOp::Move { from: 1, to: 6 },
+                FloorOp::SetHidden {
+                    from: 1,
+                    to: Some(2),
+                    hidden: true,
+                },
+                FloorOp::Delete {
+                    from: 3,
+                    to: Some(4),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn deleted_floor_position_falls_to_next_kept_floor() {
+        let map = FloorMap::for_ops(
+            5,
+            &[FloorOp::Delete {
+                from: 2,
+                to: Some(3),
+            }],
+        );
+        assert_eq!(map.get(2), None);
+        assert_eq!(map.position(2), 2);
+        assert_eq!(map.position(3), 2);
+
+        let map = FloorMap::for_ops(3, &[FloorOp::Delete { from: 3, to: None }]);
+        assert_eq!(map.position(3), 3);
+    }
+
+    #[test]
+    fn splice_keeps_or_drops_tail() {
+        let map = FloorMap::for_splice(5, 2, 3, true);
+        assert_eq!(map.after(), 5);
+        assert_eq!((map.get(2), map.get(3)), (Some(2), None));
+
+        let map = FloorMap::for_splice(5, 2, 3, false);
+        assert_eq!(map.after(), 8);
+        assert_eq!((map.get(3), map.get(5)), (Some(6), Some(8)));
+        assert_eq!(map.inverse().get(6), Some(3));
+    }
+}
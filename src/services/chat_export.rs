@@ -0,0 +1,735 @@
+//! 聊天记录导出
+//!
+//! 将聊天记录渲染为单文件 HTML、Markdown 或 EPUB。渲染前按阅读器的顺序处理正文：
+//! 聊天记录正则 → 角色卡正则 → 移除阅读设置中隐藏的标签 → 移除 `<script>` → 按白名单清理标签与属性
+
+use crate::services::st_chat::parse_send_date;
+use crate::services::st_regex::{self, ApplyContext, RunMode, ScriptSet};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+use zip::CompressionMethod;
+
+/// 默认每章楼层数
+const DEFAULT_CHAPTER_SIZE: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Html,
+    #[serde(alias = "md")]
+    Markdown,
+    Epub,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Html => "html",
+            Self::Markdown => "md",
+            Self::Epub => "epub",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Html => "text/html; charset=utf-8",
+            Self::Markdown => "text/markdown; charset=utf-8",
+            Self::Epub => "application/epub+zip",
+        }
+    }
+}
+
+/// EPUB / Markdown 分章方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitMode {
+    /// 按固定楼层数
+    #[default]
+    Page,
+    /// 按消息发送日期
+    Date,
+}
+
+/// 导出参数
+#[derive(Debug, Deserialize, Default)]
+pub struct ExportOptions {
+    #[serde(default)]
+    pub format: ExportFormat,
+    #[serde(default)]
+    pub split: SplitMode,
+    /// 按楼层分章时每章楼层数，默认 50
+    pub chapter_size: Option<usize>,
+    /// 是否包含隐藏（is_system）消息
+    #[serde(default)]
+    pub include_hidden: bool,
+}
+
+/// 待导出的楼层
+#[derive(Debug, Clone)]
+pub struct ExportMessage {
+    pub floor: i32,
+    pub name: String,
+    pub is_user: bool,
+    pub is_system: bool,
+    pub send_date: Option<String>,
+    pub content: String,
+}
+
+/// 角色头像
+pub struct Avatar {
+    pub bytes: Vec<u8>,
+    pub mime: &'static str,
+}
+
+/// 导出所需的全部数据
+pub struct ExportSource {
+    pub title: String,
+    pub character_name: String,
+    pub user_name: String,
+    pub avatar: Option<Avatar>,
+    pub messages: Vec<ExportMessage>,
+    /// 聊天记录正则（优先）与角色卡正则
+    pub scripts: ScriptSet,
+    /// 阅读设置中隐藏的标签
+    pub hidden_tags: Vec<String>,
+    /// 正文是否为纯文本（TXT 记录，需要转义 HTML）
+    pub plain_text: bool,
+}
+
+struct Chapter {
+    title: String,
+    messages: Vec<ExportMessage>,
+}
+
+static TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)<[^>]*>").unwrap());
+static BREAK: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)<br\s*/?>|</p\s*>|</div\s*>").unwrap());
+static BLANK_LINES: Lazy<Regex> = Lazy::new(|| Regex::new(r"\n{3,}").unwrap());
+static STYLE_BLOCK: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<style\b[^>]*>.*?</style\s*>").unwrap());
+/// 注释或标签（属性值中允许出现 `>`）
+static HTML_TOKEN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?s)<!--.*?-->|<(/?)([A-Za-z][A-Za-z0-9-]*)((?:[^>"']|"[^"]*"|'[^']*')*)>"#)
+        .unwrap()
+});
+static HTML_ATTR: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"([^\s"'>/=]+)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+)))?"#).unwrap()
+});
+
+/// 导出 HTML 中保留的标签（空格分隔），其余标签移除、保留其中的文字
+const ALLOWED_TAGS: &str =
+    "a abbr b big blockquote br caption center cite code del details div em \
+     figcaption figure font h1 h2 h3 h4 h5 h6 hr i img ins li mark ol p pre q rp rt ruby s small \
+     span strong sub summary sup table tbody td tfoot th thead tr u ul";
+
+/// 保留的属性（空格分隔）；`on*` 事件等其余属性一律移除，`style` 见 [`is_safe_style`]
+const ALLOWED_ATTRS: &str =
+    "align alt class color colspan face height href open rowspan size src style target title width";
+
+fn is_allowed(list: &str, name: &str) -> bool {
+    list.split_whitespace().any(|item| item == name)
+}
+
+/// 从阅读设置 JSON 读取隐藏的标签
+pub fn hidden_tags(reading_settings: Option<&str>) -> Vec<String> {
+    reading_settings
+        .and_then(|s| serde_json::from_str::<serde_json::Value>(s).ok())
+        .and_then(|v| {
+            v["tag_filters"].as_array().map(|arr| {
+                arr.iter()
+                    .filter_map(|t| t.as_str().map(str::to_string))
+                    .collect()
+            })
+        })
+        .unwrap_or_default()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 去除标签得到纯文本（保留换行，解码常见实体）
+pub(crate) fn to_plain_text(html: &str) -> String {
+    let text = BREAK.replace_all(html, "\n");
+    let text = TAG.replace_all(&text, "");
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    BLANK_LINES.replace_all(text.trim(), "\n\n").into_owned()
+}
+
+/// 链接地址是否安全：仅允许 http(s)、图片 data URL 与相对地址
+fn is_safe_url(url: &str) -> bool {
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if ["http://", "https://", "data:image/"]
+        .iter()
+        .any(|prefix| url.starts_with(prefix))
+    {
+        return true;
+    }
+    // 相对地址的首段不能出现协议分隔符（含实体编码的 `&#58;`）
+    let head = url.split(['/', '?', '#']).next().unwrap_or("");
+    !head.contains(':') && !head.contains('&')
+}
+
+/// 内联样式是否安全：不得通过 `url(`、`@import` 等加载外部资源
+fn is_safe_style(style: &str) -> bool {
+    let style: String = style
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    !["url(", "image-set(", "@import", "expression(", "\\", "&"]
+        .iter()
+        .any(|pattern| style.contains(pattern))
+}
+
+fn sanitize_tag(closing: bool, name: &str, attrs: &str) -> String {
+    if closing {
+        return format!("</{}>", name);
+    }
+    let mut tag = format!("<{}", name);
+    for caps in HTML_ATTR.captures_iter(attrs) {
+        let attr = caps[1].to_ascii_lowercase();
+        if !is_allowed(ALLOWED_ATTRS, &attr) {
+            continue;
+        }
+        let value = caps
+            .get(2)
+            .or_else(|| caps.get(3))
+            .or_else(|| caps.get(4))
+            .map(|m| m.as_str());
+        match value {
+            Some(value) => {
+                if (attr == "href" || attr == "src") && !is_safe_url(value) {
+                    continue;
+                }
+                if attr == "style" && !is_safe_style(value) {
+                    continue;
+                }
+                tag.push_str(&format!(
+                    " {}=\"{}\"",
+                    attr,
+                    value.replace('"', "&quot;").replace('<', "&lt;")
+                ));
+            }
+            None => tag.push_str(&format!(" {}", attr)),
+        }
+    }
+    if attrs.trim_end().ends_with('/') {
+        tag.push_str(" /");
+    }
+    tag.push('>');
+    tag
+}
+
+/// 按白名单清理 HTML：移除注释、`<style>` 块、未列出的标签与属性及不安全的链接与样式，
+/// 其余 `<` 一律转义
+fn sanitize_html(html: &str) -> String {
+    let html = STYLE_BLOCK.replace_all(html, "");
+    let html = html.as_ref();
+    let mut out = String::with_capacity(html.len());
+    let mut last = 0;
+    for caps in HTML_TOKEN.captures_iter(html) {
+        let token = caps.get(0).unwrap();
+        out.push_str(&html[last..token.start()].replace('<', "&lt;"));
+        last = token.end();
+        let Some(name) = caps.get(2) else {
+            continue;
+        };
+        let name = name.as_str().to_ascii_lowercase();
+        if is_allowed(ALLOWED_TAGS, &name) {
+            out.push_str(&sanitize_tag(
+                !caps[1].is_empty(),
+                &name,
+                caps.get(3).map_or("", |m| m.as_str()),
+            ));
+        }
+    }
+    out.push_str(&html[last..].replace('<', "&lt;"));
+    out
+}
+
+/// 按阅读器规则处理正文
+fn render_content(source: &ExportSource, content: &str, ctx: &ApplyContext) -> String {
+    let content = if source.plain_text {
+        escape_html(content)
+    } else {
+        content.to_string()
+    };
+    let content = source.scripts.apply(&content, ctx);
+    let content = st_regex::remove_tag_blocks(&content, &source.hidden_tags);
+    sanitize_html(&st_regex::strip_scripts(&content))
+        .trim()
+        .to_string()
+}
+
+/// 发送日期对应的日（无法识别时为 None）
+pub(crate) fn date_key(send_date: &str) -> Option<String> {
+    static ISO_DATE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\d{4}-\d{2}-\d{2})").unwrap());
+    if let Some(caps) = ISO_DATE.captures(send_date.trim()) {
+        return Some(caps[1].to_string());
+    }
+    parse_send_date(send_date).map(|t| t.format("%Y-%m-%d").to_string())
+}
+
+fn split_chapters(messages: Vec<ExportMessage>, split: SplitMode, size: usize) -> Vec<Chapter> {
+    let mut chapters: Vec<Chapter> = Vec::new();
+    match split {
+        SplitMode::Page => {
+            for (index, chunk) in messages.chunks(size).enumerate() {
+                let first = chunk.first().map(|m| m.floor).unwrap_or(0);
+                let last = chunk.last().map(|m| m.floor).unwrap_or(0);
+                chapters.push(Chapter {
+                    title: format!("第 {} 章（#{} - #{}）", index + 1, first, last),
+                    messages: chunk.to_vec(),
+                });
+            }
+        }
+        SplitMode::Date => {
+            let mut current_key: Option<String> = None;
+            for message in messages {
+                let key = message.send_date.as_deref().and_then(date_key);
+                let new_chapter = chapters.is_empty() || (key.is_some() && key != current_key);
+                if new_chapter {
+                    let title = key.clone().unwrap_or_else(|| "未知日期".to_string());
+                    chapters.push(Chapter {
+                        title,
+                        messages: Vec::new(),
+                    });
+                    if key.is_some() {
+                        current_key = key;
+                    }
+                }
+                if let Some(chapter) = chapters.last_mut() {
+                    chapter.messages.push(message);
+                }
+            }
+        }
+    }
+    chapters
+}
+
+/// 渲染导出文件
+pub fn export(mut source: ExportSource, options: &ExportOptions) -> Result<Vec<u8>, String> {
+    let all = std::mem::take(&mut source.messages);
+    let total = all.len();
+    let messages: Vec<ExportMessage> = all
+        .into_iter()
+        .enumerate()
+        .filter(|(_, m)| options.include_hidden || !m.is_system)
+        .map(|(index, mut m)| {
+            // TXT 记录无法区分用户与角色，不按作用位置过滤
+            let is_user = (!source.plain_text).then_some(m.is_user);
+            let ctx = ApplyContext::for_message(RunMode::Display, is_user, index, total);
+            m.content = render_content(&source, &m.content, &ctx);
+            m
+        })
+        .collect();
+    let size = options
+        .chapter_size
+        .unwrap_or(DEFAULT_CHAPTER_SIZE)
+        .clamp(1, 10_000);
+    let chapters = split_chapters(messages, options.split, size);
+
+    match options.format {
+        ExportFormat::Html => Ok(render_html(&source, &chapters).into_bytes()),
+        ExportFormat::Markdown => Ok(render_markdown(&source, &chapters).into_bytes()),
+        ExportFormat::Epub => render_epub(&source, &chapters).map_err(|e| e.to_string()),
+    }
+}
+
+const HTML_STYLE: &str = r#"
+body { margin: 0; background: #f5f5f4; color: #1c1917; font-family: system-ui, -apple-system, "PingFang SC", "Microsoft YaHei", sans-serif; line-height: 1.75; }
+main { max-width: 820px; margin: 0 auto; padding: 32px 16px 64px; }
+header.cover { display: flex; align-items: center; gap: 16px; margin-bottom: 32px; }
+header.cover img { width: 72px; height: 72px; border-radius: 50%; object-fit: cover; }
+header.cover h1 { margin: 0; font-size: 1.5rem; }
+header.cover p { margin: 4px 0 0; color: #78716c; font-size: .875rem; }
+h2.chapter { margin: 40px 0 16px; padding-bottom: 8px; border-bottom: 1px solid #e7e5e4; font-size: 1.1rem; color: #57534e; }
+article.floor { display: flex; gap: 12px; margin: 16px 0; }
+article.floor.user { flex-direction: row-reverse; }
+.avatar { flex: none; width: 40px; height: 40px; border-radius: 50%; object-fit: cover; background: #d6d3d1; color: #fff; display: flex; align-items: center; justify-content: center; font-weight: 600; }
+.bubble { min-width: 0; max-width: 85%; background: #fff; border-radius: 12px; padding: 10px 14px; box-shadow: 0 1px 2px rgba(0,0,0,.06); }
+article.floor.user .bubble { background: #e0f2fe; }
+article.floor.hidden .bubble { opacity: .6; }
+.meta { font-size: .75rem; color: #a8a29e; margin-bottom: 4px; }
+.meta b { color: #44403c; margin-right: 6px; }
+.content { white-space: pre-wrap; word-break: break-word; }
+.content img { max-width: 100%; }
+"#;
+
+fn initial(name: &str) -> String {
+    name.chars()
+        .next()
+        .map(|c| c.to_uppercase().collect())
+        .unwrap_or_else(|| "?".to_string())
+}
+
+fn render_html(source: &ExportSource, chapters: &[Chapter]) -> String {
+    let avatar_url = source
+        .avatar
+        .as_ref()
+        .map(|a| format!("data:{};base64,{}", a.mime, STANDARD.encode(&a.bytes)));
+    let floor_count: usize = chapters.iter().map(|c| c.messages.len()).sum();
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"zh-CN\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n");
+    html.push_str(&format!("<title>{}</title>\n", escape_html(&source.title)));
+    html.push_str(&format!(
+        "<style>{}</style>\n</head>\n<body>\n<main>\n",
+        HTML_STYLE
+    ));
+
+    html.push_str("<header class=\"cover\">\n");
+    if let Some(url) = &avatar_url {
+        html.push_str(&format!("<img src=\"{}\" alt=\"\">\n", url));
+    }
+    html.push_str(&format!(
+        "<div><h1>{}</h1><p>{} · {} 楼</p></div>\n</header>\n",
+        escape_html(&source.title),
+        escape_html(&source.character_name),
+        floor_count
+    ));
+
+    let multi_chapter = chapters.len() > 1;
+    for chapter in chapters {
+        if multi_chapter {
+            html.push_str(&format!(
+                "<h2 class=\"chapter\">{}</h2>\n",
+                escape_html(&chapter.title)
+            ));
+        }
+        for message in &chapter.messages {
+            let mut class = String::from("floor");
+            if message.is_user {
+                class.push_str(" user");
+            }
+            if message.is_system {
+                class.push_str(" hidden");
+            }
+            let avatar = match (&avatar_url, message.is_user) {
+                (Some(url), false) => format!("<img class=\"avatar\" src=\"{}\" alt=\"\">", url),
+                _ => format!(
+                    "<div class=\"avatar\">{}</div>",
+                    escape_html(&initial(&message.name))
+                ),
+            };
+            let date = message
+                .send_date
+                .as_deref()
+                .map(|d| format!(" · {}", escape_html(d)))
+                .unwrap_or_default();
+            html.push_str(&format!(
+                "<article class=\"{}\" id=\"floor-{}\">{}<div class=\"bubble\"><div class=\"meta\"><b>{}</b>#{}{}</div><div class=\"content\">{}</div></div></article>\n",
+                class,
+                message.floor,
+                avatar,
+                escape_html(&message.name),
+                message.floor,
+                date,
+                message.content
+            ));
+        }
+    }
+    html.push_str("</main>\n</body>\n</html>\n");
+    html
+}
+
+fn render_markdown(source: &ExportSource, chapters: &[Chapter]) -> String {
+    let mut md = format!("# {}\n\n", source.title);
+    if let Some(avatar) = &source.avatar {
+        md.push_str(&format!(
+            "![{}](data:{};base64,{})\n\n",
+            source.character_name,
+            avatar.mime,
+            STANDARD.encode(&avatar.bytes)
+        ));
+    }
+    md.push_str(&format!(
+        "> {} × {}\n\n",
+        source.character_name, source.user_name
+    ));
+
+    let multi_chapter = chapters.len() > 1;
+    for chapter in chapters {
+        if multi_chapter {
+            md.push_str(&format!("## {}\n\n", chapter.title));
+        }
+        for message in &chapter.messages {
+            md.push_str(&format!("### #{} {}", message.floor, message.name));
+            if let Some(date) = &message.send_date {
+                md.push_str(&format!(" · {}", date));
+            }
+            md.push_str("\n\n");
+            md.push_str(&message.content);
+            md.push_str("\n\n");
+        }
+    }
+    md
+}
+
+fn xml_paragraphs(text: &str) -> String {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| format!("<p>{}</p>", escape_html(line)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn xhtml_page(title: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!DOCTYPE html>\n<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" xml:lang=\"zh-CN\">\n<head>\n<meta charset=\"utf-8\"/>\n<title>{}</title>\n<link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\"/>\n</head>\n<body>\n{}\n</body>\n</html>\n",
+        escape_html(title),
+        body
+    )
+}
+
+const EPUB_STYLE: &str = r#"
+body { font-family: serif; line-height: 1.7; }
+h1, h2 { text-align: center; }
+.cover { text-align: center; margin-top: 20%; }
+.cover img { max-width: 60%; border-radius: 8px; }
+h3.floor { font-size: 1em; margin: 1.5em 0 .3em; color: #555; }
+h3.floor.user { color: #0369a1; }
+h3.floor small { font-weight: normal; color: #999; }
+p { margin: .3em 0; text-indent: 0; }
+"#;
+
+/// 头像统一转为 PNG（部分阅读器不支持 WebP）
+fn avatar_png(avatar: &Avatar) -> Option<Vec<u8>> {
+    let img = image::load_from_memory(&avatar.bytes).ok()?;
+    let mut buf = Vec::new();
+    img.write_to(&mut Cursor::new(&mut buf), image::ImageOutputFormat::Png)
+        .ok()?;
+    Some(buf)
+}
+
+fn render_epub(source: &ExportSource, chapters: &[Chapter]) -> zip::result::ZipResult<Vec<u8>> {
+    let uid = uuid::Uuid::new_v4();
+    let modified = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ");
+    let cover = source.avatar.as_ref().and_then(avatar_png);
+
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+
+    // mimetype 必须为第一个且不压缩
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(
+        br#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#,
+    )?;
+
+    zip.start_file("OEBPS/style.css", deflated)?;
+    zip.write_all(EPUB_STYLE.as_bytes())?;
+
+    if let Some(png) = &cover {
+        zip.start_file("OEBPS/cover.png", stored)?;
+        zip.write_all(png)?;
+    }
+
+    // 扉页
+    let cover_img = if cover.is_some() {
+        "<img src=\"cover.png\" alt=\"\"/>\n"
+    } else {
+        ""
+    };
+    zip.start_file("OEBPS/title.xhtml", deflated)?;
+    zip.write_all(
+        xhtml_page(
+            &source.title,
+            &format!(
+                "<div class=\"cover\">\n{}<h1>{}</h1>\n<p>{} × {}</p>\n</div>",
+                cover_img,
+                escape_html(&source.title),
+                escape_html(&source.character_name),
+                escape_html(&source.user_name)
+            ),
+        )
+        .as_bytes(),
+    )?;
+
+    for (index, chapter) in chapters.iter().enumerate() {
+        let mut body = format!("<h2>{}</h2>\n", escape_html(&chapter.title));
+        for message in &chapter.messages {
+            let date = message
+                .send_date
+                .as_deref()
+                .map(|d| format!(" <small>{}</small>", escape_html(d)))
+                .unwrap_or_default();
+            body.push_str(&format!(
+                "<h3 class=\"floor{}\">#{} {}{}</h3>\n{}\n",
+                if message.is_user { " user" } else { "" },
+                message.floor,
+                escape_html(&message.name),
+                date,
+                xml_paragraphs(&to_plain_text(&message.content))
+            ));
+        }
+        zip.start_file(format!("OEBPS/chapter_{}.xhtml", index + 1), deflated)?;
+        zip.write_all(xhtml_page(&chapter.title, &body).as_bytes())?;
+    }
+
+    // 目录
+    let nav_items: String = chapters
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            format!(
+                "<li><a href=\"chapter_{}.xhtml\">{}</a></li>\n",
+                i + 1,
+                escape_html(&c.title)
+            )
+        })
+        .collect();
+    zip.start_file("OEBPS/nav.xhtml", deflated)?;
+    zip.write_all(
+        xhtml_page(
+            "目录",
+            &format!(
+                "<nav epub:type=\"toc\" id=\"toc\">\n<h1>目录</h1>\n<ol>\n<li><a href=\"title.xhtml\">{}</a></li>\n{}</ol>\n</nav>",
+                escape_html(&source.title),
+                nav_items
+            ),
+        )
+        .as_bytes(),
+    )?;
+
+    // 包文件
+    let mut manifest = String::from(
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n<item id=\"css\" href=\"style.css\" media-type=\"text/css\"/>\n<item id=\"title\" href=\"title.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+    );
+    if cover.is_some() {
+        manifest.push_str(
+            "<item id=\"cover\" href=\"cover.png\" media-type=\"image/png\" properties=\"cover-image\"/>\n",
+        );
+    }
+    let mut spine = String::from("<itemref idref=\"title\"/>\n");
+    for i in 1..=chapters.len() {
+        manifest.push_str(&format!(
+            "<item id=\"chapter_{0}\" href=\"chapter_{0}.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+            i
+        ));
+        spine.push_str(&format!("<itemref idref=\"chapter_{}\"/>\n", i));
+    }
+    let cover_meta = if cover.is_some() {
+        "<meta name=\"cover\" content=\"cover\"/>\n"
+    } else {
+        ""
+    };
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(
+        format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"uid\">\n<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n<dc:identifier id=\"uid\">urn:uuid:{}</dc:identifier>\n<dc:title>{}</dc:title>\n<dc:creator>{}</dc:creator>\n<dc:language>zh-CN</dc:language>\n<meta property=\"dcterms:modified\">{}</meta>\n{}</metadata>\n<manifest>\n{}</manifest>\n<spine>\n{}</spine>\n</package>\n",
+            uid,
+            escape_html(&source.title),
+            escape_html(&source.character_name),
+            modified,
+            cover_meta,
+            manifest,
+            spine
+        )
+        .as_bytes(),
+    )?;
+
+    Ok(zip.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_allowed_tags_and_attributes() {
+        let html = r#"<p class="a" onclick="x()">正文<br/><b>粗</b></p>"#;
+        assert_eq!(
+            sanitize_html(html),
+            r#"<p class="a">正文<br /><b>粗</b></p>"#
+        );
+    }
+
+    #[test]
+    fn drops_unknown_tags_but_keeps_text() {
+        assert_eq!(
+            sanitize_html("<iframe src=\"https://x\">内容</iframe><!-- 注释 -->"),
+            "内容"
+        );
+        assert_eq!(sanitize_html("1 < 2 <"), "1 &lt; 2 &lt;");
+    }
+
+    #[test]
+    fn attribute_values_may_contain_gt() {
+        assert_eq!(
+            sanitize_html(r#"<span title="a>b" onmouseover='x()'>t</span>"#),
+            r#"<span title="a>b">t</span>"#
+        );
+    }
+
+    #[test]
+    fn removes_unsafe_urls() {
+        for url in [
+            "javascript:alert(1)",
+            " JaVa\tScript:alert(1)",
+            "data:text/html,x",
+            "javascript&#58;alert(1)",
+        ] {
+            let html = format!(r#"<a href="{}">x</a>"#, url);
+            assert_eq!(sanitize_html(&html), "<a>x</a>", "{}", url);
+        }
+        for url in [
+            "https://example.com/a.png",
+            "data:image/png;base64,AA",
+            "img/a.png",
+        ] {
+            let html = format!(r#"<img src="{}">"#, url);
+            assert_eq!(sanitize_html(&html), format!(r#"<img src="{}">"#, url));
+        }
+    }
+
+    #[test]
+    fn removes_style_blocks_and_remote_styles() {
+        assert_eq!(
+            sanitize_html("<style>p { background: url(https://x/t) }</style><p>a</p>"),
+            "<p>a</p>"
+        );
+        assert_eq!(
+            sanitize_html(r#"<span style="color: red">a</span>"#),
+            r#"<span style="color: red">a</span>"#
+        );
+        for style in [
+            "background: URL( https://x/t )",
+            "background: u\\72l(https://x/t)",
+            "background: url&#40;https://x/t)",
+            "@import 'https://x/t.css'",
+        ] {
+            let html = format!(r#"<span style="{}">a</span>"#, style);
+            assert_eq!(sanitize_html(&html), "<span>a</span>", "{}", style);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! 聊天记录楼层
+//!
+//! 阅读器、搜索、总结等功能共用的楼层视图：JSONL 由 ChatLog 解析（楼层号从 1 开始，不含元数据头），
+//! TXT 以 `[#123] 【Name】` 行分隔楼层
+
+use crate::services::st_chat::{ChatLog, StMessage};
+use regex::Regex;
+use serde::Serialize;
+
+#[derive(Serialize, Clone)]
+pub struct ChatMessage {
+    pub floor: i32,
+    pub name: String,
+    pub content: String,
+    /// JSONL 消息的完整字段（TXT 为 None）
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<MessageMeta>,
+}
+
+/// JSONL 消息的附加信息
+#[derive(Serialize, Clone)]
+pub struct MessageMeta {
+    pub is_user: bool,
+    pub is_system: bool,
+    pub send_date: Option<String>,
+    pub swipes: Vec<String>,
+    pub swipe_id: Option<usize>,
+    pub kind: Option<String>,
+    pub reasoning: Option<String>,
+    pub model: Option<String>,
+    pub api: Option<String>,
+    pub token_count: Option<i64>,
+    pub bookmark: Option<String>,
+}
+
+impl ChatMessage {
+    /// 由 JSONL 消息构建楼层（楼层号从 1 开始，不含元数据头）
+    pub fn from_st(message: &StMessage, floor: i32) -> Self {
+        Self {
+            floor,
+            name: message.name.clone(),
+            content: message.mes.clone(),
+            meta: Some(MessageMeta {
+                is_user: message.is_user,
+                is_system: message.is_system,
+                send_date: message.send_date.clone(),
+                swipes: message.swipes.clone(),
+                swipe_id: message.swipe_id,
+                kind: message.kind.clone(),
+                reasoning: message.reasoning.clone(),
+                model: message.model.clone(),
+                api: message.api.clone(),
+                token_count: message.token_count,
+                bookmark: message.bookmark.clone(),
+            }),
+        }
+    }
+}
+
+/// 解析 TXT 格式：以 `[#123] 【Name】` 行分隔楼层
+pub fn parse_txt_floors(content: &str) -> Vec<ChatMessage> {
+    let re_header = Regex::new(r"(?m)^\[#(\d+)\]\s*【(.*?)】\s*").unwrap();
+
+    let mut headers = Vec::new();
+    for caps in re_header.captures_iter(content) {
+        let mat = caps.get(0).unwrap();
+        let floor = caps[1].parse::<i32>().unwrap_or(0);
+        let name = caps[2].trim().to_string();
+        headers.push((mat.start(), mat.end(), floor, name));
+    }
+
+    let mut floors = Vec::new();
+    for i in 0..headers.len() {
+        let (_start, end, floor, name) = headers[i].clone();
+        let content_end = if i + 1 < headers.len() {
+            headers[i + 1].0
+        } else {
+            content.len()
+        };
+        floors.push(ChatMessage {
+            floor,
+            name,
+            content: content[end..content_end].trim().to_string(),
+            meta: None,
+        });
+    }
+    floors
+}
+
+/// 解析聊天记录全部楼层
+pub fn parse_floors(content: &str, is_jsonl: bool) -> Vec<ChatMessage> {
+    if is_jsonl {
+        ChatLog::parse(content)
+            .messages
+            .iter()
+            .enumerate()
+            .map(|(idx, message)| ChatMessage::from_st(message, (idx + 1) as i32))
+            .collect()
+    } else {
+        parse_txt_floors(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jsonl_floors_start_at_first_message() {
+        let header = r#"{"user_name":"User","character_name":"Alice"}"#;
+        let messages = "{\"name\":\"Alice\",\"mes\":\"a\"}\n{\"name\":\"User\",\"is_user\":true,\"mes\":\"b\"}";
+        for content in [format!("{}\n{}", header, messages), messages.to_string()] {
+            let floors = parse_floors(&content, true);
+            let numbered: Vec<(i32, &str)> = floors
+                .iter()
+                .map(|f| (f.floor, f.content.as_str()))
+                .collect();
+            assert_eq!(numbered, [(1, "a"), (2, "b")]);
+        }
+    }
+
+    #[test]
+    fn txt_floors_keep_marker_numbers() {
+        let floors = parse_txt_floors("前言\n[#3] 【Alice】 你好\n第二行\n[#7]【User】\n嗯\n");
+        let parsed: Vec<(i32, &str, &str)> = floors
+            .iter()
+            .map(|f| (f.floor, f.name.as_str(), f.content.as_str()))
+            .collect();
+        assert_eq!(parsed, [(3, "Alice", "你好\n第二行"), (7, "User", "嗯")]);
+    }
+}
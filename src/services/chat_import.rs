@@ -0,0 +1,587 @@
+//! 其他前端聊天记录导入
+//!
+//! 支持 RisuAI 聊天 JSON、Agnai 导出、CharacterAI 导出（CAI Tools 的 histories 与新版 turns）、
+//! OpenAI conversations.json、Tavern `[#N] 【名字】` TXT 与「名字: 内容」纯文本，
+//! 自动识别格式后统一转换为 SillyTavern JSONL。
+//!
+//! 转换分两步：先解析出带发言者标识的中间消息，再按发言者映射（名字 / 角色）生成 ST 消息，
+//! 以便在保存前预览并修正识别结果
+
+use super::st_chat::{ChatHeader, ChatLog, StMessage};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// 纯文本发言者名的最大字符数
+const MAX_SPEAKER_CHARS: usize = 40;
+
+/// 常见的用户称呼（纯文本格式中用于猜测用户一方）
+const USER_NAMES: &[&str] = &["user", "you", "human", "我", "你", "用户"];
+
+static TXT_HEADER: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?m)^\[#\d+\]\s*【.*?】").unwrap());
+static SPEAKER_LINE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^([^:：\s][^:：\n]*?)\s*[:：]\s?(.*)$").unwrap());
+
+/// 聊天记录格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatFormat {
+    SillyTavern,
+    RisuAi,
+    Agnai,
+    CharacterAi,
+    OpenAi,
+    TavernTxt,
+    PlainText,
+}
+
+impl ChatFormat {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ChatFormat::SillyTavern => "SillyTavern JSONL",
+            ChatFormat::RisuAi => "RisuAI 聊天",
+            ChatFormat::Agnai => "Agnai 导出",
+            ChatFormat::CharacterAi => "CharacterAI 导出",
+            ChatFormat::OpenAi => "ChatGPT 对话导出",
+            ChatFormat::TavernTxt => "Tavern TXT",
+            ChatFormat::PlainText => "纯文本（名字: 内容）",
+        }
+    }
+}
+
+/// 发言者身份
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Char,
+    System,
+}
+
+/// 中间消息
+#[derive(Debug, Clone)]
+struct RawMessage {
+    /// 发言者标识（用于映射）
+    speaker: String,
+    /// 源文件中的名字
+    name: Option<String>,
+    role: Role,
+    text: String,
+    send_date: Option<String>,
+    swipes: Vec<String>,
+    swipe_id: usize,
+}
+
+impl RawMessage {
+    fn new(speaker: impl Into<String>, name: Option<String>, role: Role, text: String) -> Self {
+        Self {
+            speaker: speaker.into(),
+            name,
+            role,
+            text,
+            send_date: None,
+            swipes: Vec::new(),
+            swipe_id: 0,
+        }
+    }
+}
+
+/// 识别出的发言者
+#[derive(Debug, Clone, Serialize)]
+pub struct Speaker {
+    pub key: String,
+    pub name: String,
+    pub role: Role,
+    pub count: usize,
+}
+
+/// 用户对发言者的修正
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpeakerOverride {
+    pub name: Option<String>,
+    pub role: Option<Role>,
+}
+
+/// 解析结果（尚未应用发言者映射）
+pub struct ParsedChat {
+    pub format: ChatFormat,
+    /// 对话标题（若源文件提供）
+    pub title: Option<String>,
+    /// 文件包含多个对话时的标题列表（ChatGPT / CharacterAI）
+    pub conversations: Vec<String>,
+    messages: Vec<RawMessage>,
+}
+
+fn str_of(value: &Value) -> Option<String> {
+    value.as_str().map(str::to_string).filter(|s| !s.is_empty())
+}
+
+/// 时间：毫秒 / 秒级时间戳或字符串
+fn date_of(value: &Value, seconds: bool) -> Option<String> {
+    match value {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => {
+            let millis = if seconds {
+                (n.as_f64()? * 1000.0) as i64
+            } else {
+                n.as_i64()?
+            };
+            chrono::DateTime::from_timestamp_millis(millis).map(|t| t.to_rfc3339())
+        }
+        _ => None,
+    }
+}
+
+/// 自动识别格式
+pub fn detect(text: &str) -> Option<ChatFormat> {
+    if let Ok(value) = serde_json::from_str::<Value>(text) {
+        return detect_json(&value);
+    }
+
+    let first_line = text.lines().find(|l| !l.trim().is_empty())?;
+    if serde_json::from_str::<Value>(first_line).is_ok_and(|v| v.is_object()) {
+        return Some(ChatFormat::SillyTavern);
+    }
+    if TXT_HEADER.is_match(text) {
+        return Some(ChatFormat::TavernTxt);
+    }
+    if text.lines().any(|l| SPEAKER_LINE.is_match(l)) {
+        return Some(ChatFormat::PlainText);
+    }
+    None
+}
+
+fn detect_json(value: &Value) -> Option<ChatFormat> {
+    let conversation = match value {
+        Value::Array(items) => items.first()?,
+        other => other,
+    };
+    if conversation.get("mapping").is_some_and(|m| m.is_object()) {
+        return Some(ChatFormat::OpenAi);
+    }
+    if value["type"] == "risuChat"
+        || value["data"]["message"].is_array()
+        || value["message"][0]["data"].is_string()
+    {
+        return Some(ChatFormat::RisuAi);
+    }
+    if value["messages"][0]["msg"].is_string() {
+        return Some(ChatFormat::Agnai);
+    }
+    if value["histories"].is_object() || value["turns"].is_array() {
+        return Some(ChatFormat::CharacterAi);
+    }
+    // 单行 JSON 的 ST 文件（只有元数据头）
+    if value.get("chat_metadata").is_some() || value.get("mes").is_some() {
+        return Some(ChatFormat::SillyTavern);
+    }
+    None
+}
+
+/// 解析为中间消息；conversation 为多对话文件中要导入的对话下标
+pub fn parse(text: &str, format: ChatFormat, conversation: usize) -> Result<ParsedChat, String> {
+    let mut parsed = ParsedChat {
+        format,
+        title: None,
+        conversations: Vec::new(),
+        messages: Vec::new(),
+    };
+
+    match format {
+        ChatFormat::SillyTavern => parse_sillytavern(text, &mut parsed),
+        ChatFormat::TavernTxt => parse_tavern_txt(text, &mut parsed),
+        ChatFormat::PlainText => parse_plain_text(text, &mut parsed),
+        _ => {
+            let value: Value =
+                serde_json::from_str(text).map_err(|e| format!("JSON 解析失败: {}", e))?;
+            match format {
+                ChatFormat::RisuAi => parse_risu(&value, &mut parsed),
+                ChatFormat::Agnai => parse_agnai(&value, &mut parsed),
+                ChatFormat::CharacterAi => parse_cai(&value, conversation, &mut parsed),
+                ChatFormat::OpenAi => parse_openai(&value, conversation, &mut parsed)?,
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    parsed.messages.retain(|m| !m.text.trim().is_empty());
+    if parsed.messages.is_empty() {
+        return Err(format!("未能从文件中解析出消息（{}）", format.label()));
+    }
+    Ok(parsed)
+}
+
+fn parse_sillytavern(text: &str, parsed: &mut ParsedChat) {
+    let log = ChatLog::parse(text);
+    for m in log.messages {
+        let role = if m.is_system {
+            Role::System
+        } else if m.is_user {
+            Role::User
+        } else {
+            Role::Char
+        };
+        let mut raw = RawMessage::new(m.name.clone(), Some(m.name), role, m.mes);
+        raw.send_date = m.send_date;
+        raw.swipe_id = m.swipe_id.unwrap_or(0);
+        raw.swipes = m.swipes;
+        parsed.messages.push(raw);
+    }
+}
+
+fn parse_tavern_txt(text: &str, parsed: &mut ParsedChat) {
+    for floor in super::chat_floor::parse_txt_floors(text) {
+        parsed.messages.push(RawMessage::new(
+            floor.name.clone(),
+            Some(floor.name),
+            Role::Char,
+            floor.content,
+        ));
+    }
+    guess_roles(&mut parsed.messages);
+}
+
+/// 「名字: 内容」纯文本；不以发言者开头的行并入上一条消息
+fn parse_plain_text(text: &str, parsed: &mut ParsedChat) {
+    // 出现两次以上、或位于段首（文件开头 / 空行之后）的名字才视为发言者，
+    // 避免把段落中间的「注：」等误判为换人
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for line in text.lines() {
+        if let Some(caps) = SPEAKER_LINE.captures(line.trim_end()) {
+            let name = caps[1].trim().to_string();
+            if name.chars().count() <= MAX_SPEAKER_CHARS {
+                *counts.entry(name).or_default() += 1;
+            }
+        }
+    }
+    let repeated = counts.values().any(|&c| c >= 2);
+    let is_speaker = |name: &str, paragraph_start: bool| {
+        counts
+            .get(name)
+            .is_some_and(|&c| c >= 2 || !repeated || paragraph_start)
+    };
+
+    let mut current: Option<RawMessage> = None;
+    let mut paragraph_start = true;
+    for line in text.lines() {
+        let line = line.trim_end();
+        let at_start = std::mem::replace(&mut paragraph_start, line.trim().is_empty());
+        if let Some(caps) = SPEAKER_LINE.captures(line) {
+            let name = caps[1].trim();
+            if is_speaker(name, at_start) {
+                if let Some(message) = current.take() {
+                    parsed.messages.push(message);
+                }
+                current = Some(RawMessage::new(
+                    name,
+                    Some(name.to_string()),
+                    Role::Char,
+                    caps[2].to_string(),
+                ));
+                continue;
+            }
+        }
+        if let Some(message) = current.as_mut() {
+            message.text.push('\n');
+            message.text.push_str(line);
+        }
+    }
+    if let Some(message) = current {
+        parsed.messages.push(message);
+    }
+    for message in &mut parsed.messages {
+        message.text = message.text.trim().to_string();
+    }
+    guess_roles(&mut parsed.messages);
+}
+
+/// 只有名字的格式：常见用户称呼为用户；第一个其他发言者为角色（通常由角色开场），其余为用户
+fn guess_roles(messages: &mut [RawMessage]) {
+    let mut char_speaker: Option<String> = None;
+    for message in messages.iter_mut() {
+        let lower = message.speaker.to_lowercase();
+        message.role = if USER_NAMES.contains(&lower.as_str()) {
+            Role::User
+        } else {
+            match &char_speaker {
+                None => {
+                    char_speaker = Some(message.speaker.clone());
+                    Role::Char
+                }
+                Some(c) if *c == message.speaker => Role::Char,
+                Some(_) => Role::User,
+            }
+        };
+    }
+}
+
+/// RisuAI：`{type: "risuChat", data: {name, message: [{role, data, saying, time}]}}`
+fn parse_risu(value: &Value, parsed: &mut ParsedChat) {
+    let root = if value["data"].is_object() {
+        &value["data"]
+    } else {
+        value
+    };
+    parsed.title = str_of(&root["name"]);
+    for m in root["message"].as_array().into_iter().flatten() {
+        let text = m["data"].as_str().unwrap_or("").to_string();
+        let mut raw = if m["role"] == "user" {
+            RawMessage::new("user", None, Role::User, text)
+        } else {
+            let speaker = str_of(&m["saying"]).unwrap_or_else(|| "char".to_string());
+            RawMessage::new(speaker, str_of(&m["name"]), Role::Char, text)
+        };
+        raw.send_date = date_of(&m["time"], false);
+        parsed.messages.push(raw);
+    }
+}
+
+/// Agnai：`{name, messages: [{msg, characterId | userId, handle, createdAt}]}`
+fn parse_agnai(value: &Value, parsed: &mut ParsedChat) {
+    parsed.title = str_of(&value["name"]);
+    for m in value["messages"].as_array().into_iter().flatten() {
+        let text = m["msg"].as_str().unwrap_or("").to_string();
+        let name = str_of(&m["handle"]).or_else(|| str_of(&m["name"]));
+        let mut raw = if let Some(id) = str_of(&m["characterId"]) {
+            RawMessage::new(format!("char:{}", id), name, Role::Char, text)
+        } else if let Some(id) = str_of(&m["userId"]) {
+            RawMessage::new(format!("user:{}", id), name, Role::User, text)
+        } else {
+            RawMessage::new("system", name, Role::System, text)
+        };
+        raw.send_date = date_of(&m["createdAt"], false);
+        parsed.messages.push(raw);
+    }
+}
+
+/// CharacterAI：CAI Tools 的 `histories.histories[].msgs[]` 或新版 `turns[]`（候选回复转为 swipes）
+fn parse_cai(value: &Value, conversation: usize, parsed: &mut ParsedChat) {
+    parsed.title = str_of(&value["info"]["character"]["name"]);
+
+    if let Some(histories) = value["histories"]["histories"].as_array() {
+        parsed.conversations = histories
+            .iter()
+            .enumerate()
+            .map(|(i, h)| {
+                let first = h["msgs"][0]["text"].as_str().unwrap_or("");
+                format!(
+                    "对话 {}：{}",
+                    i + 1,
+                    first.chars().take(30).collect::<String>()
+                )
+            })
+            .collect();
+        let Some(history) = histories.get(conversation) else {
+            return;
+        };
+        for m in history["msgs"].as_array().into_iter().flatten() {
+            let src = &m["src"];
+            let name = str_of(&src["name"]);
+            let text = m["text"].as_str().unwrap_or("").to_string();
+            let raw = if src["is_human"].as_bool().unwrap_or(false) {
+                RawMessage::new(
+                    format!("user:{}", name.clone().unwrap_or_default()),
+                    name,
+                    Role::User,
+                    text,
+                )
+            } else {
+                RawMessage::new(
+                    format!("char:{}", name.clone().unwrap_or_default()),
+                    name,
+                    Role::Char,
+                    text,
+                )
+            };
+            parsed.messages.push(raw);
+        }
+        return;
+    }
+
+    let mut turns: Vec<&Value> = value["turns"].as_array().into_iter().flatten().collect();
+    // 接口返回的 turns 通常为倒序
+    turns.sort_by(|a, b| {
+        a["create_time"]
+            .as_str()
+            .unwrap_or("")
+            .cmp(b["create_time"].as_str().unwrap_or(""))
+    });
+    for turn in turns {
+        let author = &turn["author"];
+        let name = str_of(&author["name"]);
+        let candidates: Vec<&Value> = turn["candidates"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .collect();
+        let primary = turn["primary_candidate_id"].as_str();
+        let swipes: Vec<String> = candidates
+            .iter()
+            .map(|c| c["raw_content"].as_str().unwrap_or("").to_string())
+            .collect();
+        let swipe_id = candidates
+            .iter()
+            .position(|c| {
+                c["candidate_id"]
+                    .as_str()
+                    .is_some_and(|id| Some(id) == primary)
+            })
+            .unwrap_or(swipes.len().saturating_sub(1));
+        let text = swipes.get(swipe_id).cloned().unwrap_or_default();
+        let key = str_of(&author["author_id"]).unwrap_or_else(|| name.clone().unwrap_or_default());
+
+        let mut raw = if author["is_human"].as_bool().unwrap_or(false) {
+            RawMessage::new(format!("user:{}", key), name, Role::User, text)
+        } else {
+            RawMessage::new(format!("char:{}", key), name, Role::Char, text)
+        };
+        raw.send_date = date_of(&turn["create_time"], false);
+        if swipes.len() > 1 {
+            raw.swipes = swipes;
+            raw.swipe_id = swipe_id;
+        }
+        parsed.messages.push(raw);
+    }
+}
+
+/// OpenAI conversations.json：从 current_node 沿 parent 回溯出当前分支
+fn parse_openai(value: &Value, conversation: usize, parsed: &mut ParsedChat) -> Result<(), String> {
+    let conversations: Vec<&Value> = match value {
+        Value::Array(items) => items.iter().collect(),
+        other => vec![other],
+    };
+    parsed.conversations = conversations
+        .iter()
+        .enumerate()
+        .map(|(i, c)| str_of(&c["title"]).unwrap_or_else(|| format!("对话 {}", i + 1)))
+        .collect();
+    let conv = conversations
+        .get(conversation)
+        .ok_or_else(|| format!("对话 #{} 不存在", conversation + 1))?;
+    parsed.title = str_of(&conv["title"]);
+
+    let mapping = &conv["mapping"];
+    let mut node_id = str_of(&conv["current_node"]);
+    let mut chain = Vec::new();
+    let mut guard = 0;
+    while let Some(id) = node_id {
+        let node = &mapping[&id];
+        if node.is_null() || guard > 100_000 {
+            break;
+        }
+        guard += 1;
+        chain.push(node);
+        node_id = str_of(&node["parent"]);
+    }
+    chain.reverse();
+
+    for node in chain {
+        let message = &node["message"];
+        let role = match message["author"]["role"].as_str() {
+            Some("user") => Role::User,
+            Some("assistant") => Role::Char,
+            _ => continue,
+        };
+        let text = message["content"]["parts"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|p| p.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let speaker = if role == Role::User {
+            "user"
+        } else {
+            "assistant"
+        };
+        let mut raw = RawMessage::new(speaker, None, role, text);
+        raw.send_date = date_of(&message["create_time"], true);
+        parsed.messages.push(raw);
+    }
+    Ok(())
+}
+
+impl ParsedChat {
+    /// 汇总发言者（按首次出现顺序）；源文件未提供名字时用户默认为 User，角色默认为角色卡名
+    fn speakers(&self, char_name: &str) -> Vec<Speaker> {
+        let mut speakers: Vec<Speaker> = Vec::new();
+        for m in &self.messages {
+            if let Some(s) = speakers.iter_mut().find(|s| s.key == m.speaker) {
+                s.count += 1;
+                continue;
+            }
+            let name = m.name.clone().unwrap_or_else(|| match m.role {
+                Role::User => "User".to_string(),
+                Role::Char => char_name.to_string(),
+                Role::System => "System".to_string(),
+            });
+            speakers.push(Speaker {
+                key: m.speaker.clone(),
+                name,
+                role: m.role,
+                count: 1,
+            });
+        }
+        speakers
+    }
+
+    /// 应用用户修正后的发言者
+    pub fn mapped_speakers(
+        &self,
+        char_name: &str,
+        overrides: &HashMap<String, SpeakerOverride>,
+    ) -> Vec<Speaker> {
+        let mut speakers = self.speakers(char_name);
+        for speaker in &mut speakers {
+            if let Some(o) = overrides.get(&speaker.key) {
+                if let Some(name) = o.name.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+                    speaker.name = name.to_string();
+                }
+                if let Some(role) = o.role {
+                    speaker.role = role;
+                }
+            }
+        }
+        speakers
+    }
+
+    /// 应用发言者映射，生成 ST 聊天记录
+    pub fn to_chat_log(
+        &self,
+        char_name: &str,
+        overrides: &HashMap<String, SpeakerOverride>,
+    ) -> ChatLog {
+        let speakers = self.mapped_speakers(char_name, overrides);
+        let user_name = speakers
+            .iter()
+            .find(|s| s.role == Role::User)
+            .map(|s| s.name.as_str())
+            .unwrap_or("User");
+
+        let messages = self
+            .messages
+            .iter()
+            .map(|m| {
+                let speaker = speakers.iter().find(|s| s.key == m.speaker);
+                let name = speaker.map(|s| s.name.as_str()).unwrap_or("Unknown");
+                let role = speaker.map(|s| s.role).unwrap_or(m.role);
+                let mut message =
+                    StMessage::new(name, role == Role::User, &m.text, m.send_date.clone());
+                message.is_system = role == Role::System;
+                if !m.swipes.is_empty() {
+                    message.swipes = m.swipes.clone();
+                    message.swipe_id = Some(m.swipe_id);
+                }
+                message
+            })
+            .collect();
+
+        ChatLog {
+            header: Some(ChatHeader::new(user_name, char_name)),
+            messages,
+            errors: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! 聊天记录偏移索引
+//!
+//! 为每个聊天文件在同目录保存 `.<文件名>.idx.json`，记录每个楼层在文件中的字节范围与全文标签。
+//! 阅读器翻页时按索引直接定位读取所需楼层，无需每次读取、解析整个文件；
+//! 索引记录文件大小与修改时间，文件变化后自动重建。JSONL 的分支指纹在首次需要时计算并写回索引
+
+use crate::services::st_chat::ChatLog;
+use crate::services::{chat_branch, chat_stats};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// 索引结构变化时递增，旧索引会被重建
+const INDEX_VERSION: u32 = 1;
+
+static TXT_FLOOR_HEADER: once_cell::sync::Lazy<regex::bytes::Regex> =
+    once_cell::sync::Lazy::new(|| regex::bytes::Regex::new(r"^\[#(\d+)\]\s*【.*?】").unwrap());
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatIndex {
+    pub version: u32,
+    /// 建立索引时的文件大小与修改时间（纳秒），用于判断是否过期
+    pub file_size: u64,
+    pub modified_ns: u64,
+    pub is_jsonl: bool,
+    /// JSONL 元数据头所在行的字节范围
+    pub header: Option<(u64, u64)>,
+    /// 每个楼层的字节范围 [start, end)
+    pub floors: Vec<(u64, u64)>,
+    /// 全文中出现的自定义标签（排序后）
+    pub tags: Vec<String>,
+    /// 分支推断信息（仅 JSONL，首次需要时计算）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<BranchInfo>,
+}
+
+/// 分支推断所需的楼层指纹与来源聊天名
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BranchInfo {
+    pub fingerprints: Vec<u64>,
+    pub main_chat: Option<String>,
+}
+
+/// 索引文件路径：同目录下的隐藏文件
+pub fn index_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("history");
+    path.with_file_name(format!(".{}.idx.json", file_name))
+}
+
+/// 文件大小与修改时间（纳秒）
+pub(crate) fn file_stamp(path: &Path) -> std::io::Result<(u64, u64)> {
+    let meta = fs::metadata(path)?;
+    let modified = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    Ok((meta.len(), modified))
+}
+
+/// ChatLog::parse 的判定：首个有效行若是元数据头则作为头，其余 JSON 对象行为消息
+fn is_header_line(line: &[u8]) -> bool {
+    match serde_json::from_slice::<Value>(line) {
+        Ok(Value::Object(obj)) => {
+            !obj.contains_key("mes")
+                && (obj.contains_key("chat_metadata")
+                    || obj.contains_key("user_name")
+                    || obj.contains_key("character_name"))
+        }
+        _ => false,
+    }
+}
+
+fn is_object_line(line: &[u8]) -> bool {
+    line.first() == Some(&b'{') && serde_json::from_slice::<serde::de::IgnoredAny>(line).is_ok()
+}
+
+fn trim_ascii(bytes: &[u8]) -> (usize, &[u8]) {
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |i| i + 1);
+    (start, &bytes[start..end])
+}
+
+/// 逐行扫描文件建立索引（不一次性读入内存）
+pub fn build(path: &Path, is_jsonl: bool) -> std::io::Result<ChatIndex> {
+    let (file_size, modified_ns) = file_stamp(path)?;
+    let mut reader = BufReader::with_capacity(1 << 20, File::open(path)?);
+    let mut index = ChatIndex {
+        version: INDEX_VERSION,
+        file_size,
+        modified_ns,
+        is_jsonl,
+        header: None,
+        floors: Vec::new(),
+        tags: Vec::new(),
+        branch: None,
+    };
+    let mut tags = HashSet::new();
+    let mut scan = |bytes: &[u8]| {
+        tags.extend(chat_stats::scan_tags(&String::from_utf8_lossy(bytes)).into_keys());
+    };
+
+    let mut line = Vec::new();
+    let mut offset = 0u64;
+    // TXT：当前楼层的起点与尚未扫描标签的正文
+    let mut txt_floor: Option<u64> = None;
+    let mut txt_chunk = Vec::new();
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            break;
+        }
+        let line_start = offset;
+        offset += read as u64;
+
+        if is_jsonl {
+            let (lead, trimmed) = trim_ascii(&line);
+            if trimmed.is_empty() {
+                continue;
+            }
+            let span = (
+                line_start + lead as u64,
+                line_start + (lead + trimmed.len()) as u64,
+            );
+            let first = index.header.is_none() && index.floors.is_empty();
+            if first && is_header_line(trimmed) {
+                index.header = Some(span);
+            } else if is_object_line(trimmed) {
+                index.floors.push(span);
+            } else {
+                continue;
+            }
+            scan(trimmed);
+        } else {
+            if TXT_FLOOR_HEADER.is_match(&line) {
+                if let Some(start) = txt_floor.take() {
+                    index.floors.push((start, line_start));
+                }
+                scan(&txt_chunk);
+                txt_floor = Some(line_start);
+                txt_chunk.clear();
+            }
+            txt_chunk.extend_from_slice(&line);
+        }
+    }
+    if let Some(start) = txt_floor {
+        index.floors.push((start, offset));
+    }
+    scan(&txt_chunk);
+
+    index.tags = tags.into_iter().collect();
+    index.tags.sort();
+    Ok(index)
+}
+
+fn save(path: &Path, index: &ChatIndex) -> std::io::Result<()> {
+    let target = index_path(path);
+    let tmp = target.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec(index)?)?;
+    fs::rename(&tmp, &target)
+}
+
+/// 重建并保存索引
+pub fn rebuild(path: &Path, is_jsonl: bool) -> std::io::Result<ChatIndex> {
+    let index = build(path, is_jsonl)?;
+    if let Err(e) = save(path, &index) {
+        tracing::warn!("保存聊天记录索引失败 {:?}: {}", path, e);
+    }
+    Ok(index)
+}
+
+/// 读取索引；不存在或已过期时重建
+pub fn load_or_build(path: &Path, is_jsonl: bool) -> std::io::Result<ChatIndex> {
+    let (file_size, modified_ns) = file_stamp(path)?;
+    let cached = fs::read(index_path(path))
+        .ok()
+        .and_then(|data| serde_json::from_slice::<ChatIndex>(&data).ok())
+        .filter(|index| {
+            index.version == INDEX_VERSION
+                && index.file_size == file_size
+                && index.modified_ns == modified_ns
+                && index.is_jsonl == is_jsonl
+        });
+    match cached {
+        Some(index) => Ok(index),
+        None => rebuild(path, is_jsonl),
+    }
+}
+
+/// 读取 JSONL 的分支推断信息；索引中没有时解析全文计算，并在文件未变化时写回索引
+pub fn branch_info(path: &Path) -> std::io::Result<BranchInfo> {
+    let mut index = load_or_build(path, true)?;
+    if let Some(info) = index.branch {
+        return Ok(info);
+    }
+    let log = ChatLog::parse(&fs::read_to_string(path)?);
+    let info = BranchInfo {
+        fingerprints: chat_branch::fingerprints(&log),
+        main_chat: chat_branch::main_chat(&log),
+    };
+    if file_stamp(path)? == (index.file_size, index.modified_ns) {
+        index.branch = Some(info.clone());
+        if let Err(e) = save(path, &index) {
+            tracing::warn!("保存聊天记录索引失败 {:?}: {}", path, e);
+        }
+    }
+    Ok(info)
+}
+
+/// 删除索引文件（文件不存在时忽略）
+pub fn remove(path: &Path) {
+    let _ = fs::remove_file(index_path(path));
+}
+
+fn read_span(file: &mut File, start: u64, end: u64) -> std::io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(start))?;
+    let mut buf = vec![0; end.saturating_sub(start) as usize];
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// 读取元数据头所在行
+pub fn read_header(path: &Path, index: &ChatIndex) -> std::io::Result<Option<Vec<u8>>> {
+    match index.header {
+        Some((start, end)) => read_span(&mut File::open(path)?, start, end).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// 读取一段连续楼层，返回每个楼层的原始字节
+pub fn read_floors(
+    path: &Path,
+    index: &ChatIndex,
+    range: Range<usize>,
+) -> std::io::Result<Vec<Vec<u8>>> {
+    let spans =
+        &index.floors[range.start.min(index.floors.len())..range.end.min(index.floors.len())];
+    let (Some(first), Some(last)) = (spans.first(), spans.last()) else {
+        return Ok(Vec::new());
+    };
+    // 楼层在文件中连续，一次读取整段再切分
+    let base = first.0;
+    let buf = read_span(&mut File::open(path)?, base, last.1)?;
+    Ok(spans
+        .iter()
+        .map(|&(start, end)| buf[(start - base) as usize..(end - base) as usize].to_vec())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 写入临时文件并建立索引，返回各楼层与头的文本
+    fn index_of(content: &str, is_jsonl: bool) -> (ChatIndex, Option<String>, Vec<String>) {
+        let dir = std::env::temp_dir().join(format!("piney-chat-index-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("chat.jsonl");
+        fs::write(&path, content).unwrap();
+        let index = build(&path, is_jsonl).unwrap();
+        let header = read_header(&path, &index)
+            .unwrap()
+            .map(|h| String::from_utf8(h).unwrap());
+        let floors = read_floors(&path, &index, 0..index.floors.len())
+            .unwrap()
+            .into_iter()
+            .map(|f| String::from_utf8(f).unwrap())
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        (index, header, floors)
+    }
+
+    #[test]
+    fn jsonl_header_and_floors() {
+        let content = "{\"user_name\":\"U\",\"chat_metadata\":{}}\r\n\n  {\"mes\":\"<think>a</think>\"}  \nbroken\n{\"mes\":\"b\"}";
+        let (index, header, floors) = index_of(content, true);
+        assert_eq!(
+            header.as_deref(),
+            Some("{\"user_name\":\"U\",\"chat_metadata\":{}}")
+        );
+        assert_eq!(
+            floors,
+            ["{\"mes\":\"<think>a</think>\"}", "{\"mes\":\"b\"}"]
+        );
+        assert_eq!(index.tags, ["think"]);
+    }
+
+    #[test]
+    fn jsonl_header_only_on_first_line() {
+        let content = "{\"mes\":\"a\"}\n{\"user_name\":\"U\"}\n";
+        let (index, header, floors) = index_of(content, true);
+        assert!(header.is_none());
+        assert_eq!(index.floors.len(), 2);
+        assert_eq!(floors[1], "{\"user_name\":\"U\"}");
+    }
+
+    #[test]
+    fn txt_floors_span_until_next_marker() {
+        let content = "前言\n[#1]【Alice】你好\n第二行\n[#2] 【User】嗯";
+        let (index, header, floors) = index_of(content, false);
+        assert!(header.is_none());
+        assert_eq!(floors, ["[#1]【Alice】你好\n第二行\n", "[#2] 【User】嗯"]);
+        assert_eq!(index.floors[0].0, "前言\n".len() as u64);
+    }
+}
@@ -0,0 +1,332 @@
+//! 聊天记录总结与记忆提取
+//!
+//! 将聊天楼层按 Token 预算分段，逐段滚动更新总结并累积事件、人物关系与设定事实；
+//! 结果可导出为世界书条目或作者注释草稿，便于在别处继续扮演
+
+use crate::services::chat_floor::ChatMessage;
+use crate::services::prompt::{self, PromptVars, ResolvedTemplate};
+use crate::utils::token::count_tokens;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// 每段聊天记录的 Token 预算（不含提示词本身）
+pub const SUMMARY_CHUNK_TOKENS: usize = 6000;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MemoryEvent {
+    pub floor: Option<i32>,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MemoryRelationship {
+    /// 关系双方，如 "甲 / 乙"
+    pub between: String,
+    pub status: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MemoryFact {
+    pub title: String,
+    #[serde(default)]
+    pub keys: Vec<String>,
+    pub content: String,
+}
+
+/// 结构化记忆（存入 chat_histories.memory）
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ChatMemory {
+    #[serde(default)]
+    pub events: Vec<MemoryEvent>,
+    #[serde(default)]
+    pub relationships: Vec<MemoryRelationship>,
+    #[serde(default)]
+    pub facts: Vec<MemoryFact>,
+    /// 已总结到的最后楼层，用于增量总结
+    #[serde(default)]
+    pub last_floor: i32,
+}
+
+impl ChatMemory {
+    pub fn from_json(text: Option<&str>) -> Self {
+        text.and_then(|t| serde_json::from_str(t).ok())
+            .unwrap_or_default()
+    }
+
+    fn relationships_text(&self) -> String {
+        self.relationships
+            .iter()
+            .map(|r| format!("- {}：{}", r.between, r.status))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// 一段待总结的楼层
+#[derive(Debug, Clone)]
+pub struct FloorChunk {
+    pub start_floor: i32,
+    pub end_floor: i32,
+    pub text: String,
+}
+
+fn render_floor(floor: &ChatMessage) -> String {
+    format!(
+        "[#{}] {}: {}",
+        floor.floor,
+        floor.name,
+        floor.content.trim()
+    )
+}
+
+/// 将楼层按 Token 预算分段（跳过空楼层；单楼层超出预算时独占一段）
+pub fn chunk_floors(floors: &[ChatMessage], budget: usize) -> Vec<FloorChunk> {
+    let mut chunks = Vec::new();
+    let mut current: Vec<String> = Vec::new();
+    let mut current_tokens = 0usize;
+    let mut range = (0, 0);
+
+    for floor in floors.iter().filter(|f| !f.content.trim().is_empty()) {
+        let text = render_floor(floor);
+        let tokens = count_tokens(&text);
+        if !current.is_empty() && current_tokens + tokens > budget {
+            chunks.push(FloorChunk {
+                start_floor: range.0,
+                end_floor: range.1,
+                text: current.join("\n\n"),
+            });
+            current.clear();
+            current_tokens = 0;
+        }
+        if current.is_empty() {
+            range.0 = floor.floor;
+        }
+        range.1 = floor.floor;
+        current.push(text);
+        current_tokens += tokens;
+    }
+
+    if !current.is_empty() {
+        chunks.push(FloorChunk {
+            start_floor: range.0,
+            end_floor: range.1,
+            text: current.join("\n\n"),
+        });
+    }
+    chunks
+}
+
+/// 构建某段的总结请求
+pub fn chunk_messages(
+    template: &ResolvedTemplate,
+    base_vars: &PromptVars,
+    summary: &str,
+    memory: &ChatMemory,
+    chunk: &FloorChunk,
+    index: usize,
+    total: usize,
+) -> Vec<Value> {
+    let mut vars = base_vars.clone();
+    vars.insert("previous_summary".into(), summary.to_string());
+    vars.insert("relationships".into(), memory.relationships_text());
+    vars.insert("chunk".into(), chunk.text.clone());
+    vars.insert(
+        "floor_range".into(),
+        format!("#{} - #{}", chunk.start_floor, chunk.end_floor),
+    );
+    vars.insert("chunk_index".into(), (index + 1).to_string());
+    vars.insert("chunk_count".into(), total.to_string());
+    prompt::render_messages(template, &vars)
+}
+
+fn str_field(value: &Value, key: &str) -> String {
+    value
+        .get(key)
+        .and_then(|v| v.as_str())
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// 合并一段的模型输出：总结整体替换，事件追加，人物关系与设定事实按名称更新
+pub fn merge_chunk(
+    summary: &mut String,
+    memory: &mut ChatMemory,
+    output: &Value,
+    chunk: &FloorChunk,
+) {
+    let new_summary = str_field(output, "summary");
+    if !new_summary.is_empty() {
+        *summary = new_summary;
+    }
+
+    for event in output["events"].as_array().into_iter().flatten() {
+        let text = match event {
+            Value::String(s) => s.trim().to_string(),
+            _ => str_field(event, "text"),
+        };
+        if text.is_empty() || memory.events.iter().any(|e| e.text == text) {
+            continue;
+        }
+        let floor = event
+            .get("floor")
+            .and_then(|v| v.as_i64())
+            .map(|n| n as i32);
+        memory.events.push(MemoryEvent { floor, text });
+    }
+
+    for rel in output["relationships"].as_array().into_iter().flatten() {
+        let between = str_field(rel, "between");
+        let status = str_field(rel, "status");
+        if between.is_empty() || status.is_empty() {
+            continue;
+        }
+        match memory
+            .relationships
+            .iter_mut()
+            .find(|r| r.between == between)
+        {
+            Some(existing) => existing.status = status,
+            None => memory
+                .relationships
+                .push(MemoryRelationship { between, status }),
+        }
+    }
+
+    for fact in output["facts"].as_array().into_iter().flatten() {
+        let title = str_field(fact, "title");
+        let content = str_field(fact, "content");
+        if title.is_empty() || content.is_empty() {
+            continue;
+        }
+        let keys: Vec<String> = fact["keys"]
+            .as_array()
+            .map(|arr| {
+                arr.iter()
+                    .filter_map(|k| k.as_str())
+                    .map(|k| k.trim().to_string())
+                    .filter(|k| !k.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        match memory.facts.iter_mut().find(|f| f.title == title) {
+            Some(existing) => {
+                existing.content = content;
+                for key in keys {
+                    if !existing.keys.contains(&key) {
+                        existing.keys.push(key);
+                    }
+                }
+            }
+            None => memory.facts.push(MemoryFact {
+                title,
+                keys,
+                content,
+            }),
+        }
+    }
+
+    memory.last_floor = memory.last_floor.max(chunk.end_floor);
+}
+
+/// 人物关系合并为一条常驻条目的正文
+fn relationships_entry(memory: &ChatMemory) -> Option<String> {
+    if memory.relationships.is_empty() {
+        None
+    } else {
+        Some(memory.relationships_text())
+    }
+}
+
+/// 导出为 SillyTavern 世界书 JSON（设定事实为关键词条目，人物关系为常驻条目）
+pub fn world_info_json(memory: &ChatMemory) -> Value {
+    let mut entries = serde_json::Map::new();
+    let mut push = |keys: Vec<String>, comment: &str, content: &str, constant: bool| {
+        let uid = entries.len();
+        entries.insert(
+            uid.to_string(),
+            json!({
+                "uid": uid,
+                "key": keys,
+                "keysecondary": [],
+                "comment": comment,
+                "content": content,
+                "constant": constant,
+                "selective": true,
+                "order": 100,
+                "position": 0,
+                "disable": false,
+                "addMemo": true,
+                "excludeRecursion": false,
+                "probability": 100,
+                "useProbability": true,
+                "depth": 4,
+                "displayIndex": uid
+            }),
+        );
+    };
+
+    if let Some(content) = relationships_entry(memory) {
+        push(Vec::new(), "人物关系", &content, true);
+    }
+    for fact in &memory.facts {
+        push(
+            fact.keys.clone(),
+            &fact.title,
+            &fact.content,
+            fact.keys.is_empty(),
+        );
+    }
+
+    json!({ "entries": entries })
+}
+
+/// 导出为 CCv3 character_book 条目（id 从 start_id 开始）
+pub fn character_book_entries(memory: &ChatMemory, start_id: i64) -> Vec<Value> {
+    let mut entries = Vec::new();
+    if let Some(content) = relationships_entry(memory) {
+        entries.push(super::card::character_book_entry(
+            start_id,
+            Vec::new(),
+            "人物关系",
+            &content,
+            true,
+        ));
+    }
+    for fact in &memory.facts {
+        entries.push(super::card::character_book_entry(
+            start_id + entries.len() as i64,
+            fact.keys.clone(),
+            &fact.title,
+            &fact.content,
+            fact.keys.is_empty(),
+        ));
+    }
+    entries
+}
+
+/// 作者注释草稿：前情提要 + 人物关系 + 近期事件
+pub fn authors_note(summary: &str, memory: &ChatMemory) -> String {
+    /// 作者注释中保留的最近事件数
+    const RECENT_EVENTS: usize = 8;
+
+    let mut sections = Vec::new();
+    if !summary.trim().is_empty() {
+        sections.push(format!("【前情提要】\n{}", summary.trim()));
+    }
+    if let Some(rel) = relationships_entry(memory) {
+        sections.push(format!("【人物关系】\n{}", rel));
+    }
+    if !memory.events.is_empty() {
+        let start = memory.events.len().saturating_sub(RECENT_EVENTS);
+        let events = memory.events[start..]
+            .iter()
+            .map(|e| match e.floor {
+                Some(floor) => format!("- (#{}) {}", floor, e.text),
+                None => format!("- {}", e.text),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        sections.push(format!("【近期事件】\n{}", events));
+    }
+    sections.join("\n\n")
+}
@@ -0,0 +1,270 @@
+//! 聊天记录全文搜索
+//!
+//! 在单个或角色卡下全部聊天记录中搜索消息正文（默认搜索经正则脚本处理后的显示文本），
+//! 支持正则、发言者与日期范围过滤；结果带楼层号、上下文与所在页码，便于阅读器直接跳转
+
+use crate::services::chat_export::{date_key, to_plain_text};
+use crate::services::st_regex::{ApplyContext, RunMode, ScriptSet};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+/// 默认最多返回的命中楼层数
+const DEFAULT_LIMIT: usize = 200;
+const MAX_LIMIT: usize = 2000;
+/// 命中片段两侧保留的字符数
+const SNIPPET_CHARS: usize = 60;
+/// 上下文楼层预览的字符数
+const CONTEXT_PREVIEW_CHARS: usize = 120;
+
+/// 搜索参数
+#[derive(Debug, Deserialize, Default)]
+pub struct SearchQuery {
+    pub q: String,
+    /// 按正则搜索
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    /// 发言者名称（不区分大小写）
+    pub speaker: Option<String>,
+    /// 日期范围 YYYY-MM-DD（含）；设置后无日期的楼层不参与搜索
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// 命中楼层前后各附带的楼层数，默认 1
+    pub context: Option<usize>,
+    /// 每页楼层数，默认与阅读器一致
+    pub page_size: Option<usize>,
+    pub limit: Option<usize>,
+    /// 搜索原文（不执行正则脚本、不去除标签）
+    #[serde(default)]
+    pub raw: bool,
+}
+
+/// 待搜索的楼层
+#[derive(Debug, Clone)]
+pub struct SearchFloor {
+    pub floor: i32,
+    pub name: String,
+    /// TXT 记录为 None
+    pub is_user: Option<bool>,
+    pub send_date: Option<String>,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Snippet {
+    pub before: String,
+    pub matched: String,
+    pub after: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ContextFloor {
+    pub floor: i32,
+    pub name: String,
+    pub preview: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    pub history_id: uuid::Uuid,
+    pub history_name: String,
+    pub floor: i32,
+    /// 所在页码（按 page_size 计算）
+    pub page: usize,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_user: Option<bool>,
+    pub send_date: Option<String>,
+    /// 该楼层的匹配次数
+    pub match_count: usize,
+    /// 第一处匹配的片段
+    pub snippet: Snippet,
+    pub context_before: Vec<ContextFloor>,
+    pub context_after: Vec<ContextFloor>,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct SearchResult {
+    /// 命中楼层总数（可能多于返回的 hits）
+    pub total_hits: usize,
+    pub total_matches: usize,
+    pub truncated: bool,
+    pub hits: Vec<SearchHit>,
+}
+
+/// 编译后的搜索条件
+pub struct Matcher {
+    pattern: Regex,
+    speaker: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    context: usize,
+    limit: usize,
+    raw: bool,
+}
+
+fn valid_date(value: Option<&str>) -> Result<Option<String>, String> {
+    match value.map(str::trim).filter(|s| !s.is_empty()) {
+        None => Ok(None),
+        Some(s) => chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .map(|d| Some(d.format("%Y-%m-%d").to_string()))
+            .map_err(|_| format!("日期格式应为 YYYY-MM-DD: {}", s)),
+    }
+}
+
+impl Matcher {
+    pub fn new(query: &SearchQuery) -> Result<Self, String> {
+        let q = query.q.trim();
+        if q.is_empty() {
+            return Err("搜索内容不能为空".to_string());
+        }
+        let source = if query.regex {
+            q.to_string()
+        } else {
+            regex::escape(q)
+        };
+        let pattern = RegexBuilder::new(&source)
+            .case_insensitive(!query.case_sensitive)
+            .size_limit(1 << 20)
+            .build()
+            .map_err(|e| format!("正则无效: {}", e))?;
+
+        Ok(Self {
+            pattern,
+            speaker: query
+                .speaker
+                .as_deref()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_lowercase),
+            from: valid_date(query.from.as_deref())?,
+            to: valid_date(query.to.as_deref())?,
+            context: query.context.unwrap_or(1).min(5),
+            limit: query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+            raw: query.raw,
+        })
+    }
+
+    fn date_in_range(&self, send_date: Option<&str>) -> bool {
+        if self.from.is_none() && self.to.is_none() {
+            return true;
+        }
+        let Some(day) = send_date.and_then(date_key) else {
+            return false;
+        };
+        self.from.as_ref().is_none_or(|from| &day >= from)
+            && self.to.as_ref().is_none_or(|to| &day <= to)
+    }
+}
+
+fn char_tail(text: &str, chars: usize) -> String {
+    let count = text.chars().count();
+    let tail: String = text.chars().skip(count.saturating_sub(chars)).collect();
+    if count > chars {
+        format!("…{}", tail)
+    } else {
+        tail
+    }
+}
+
+fn char_head(text: &str, chars: usize) -> String {
+    let head: String = text.chars().take(chars).collect();
+    if text.chars().count() > chars {
+        format!("{}…", head)
+    } else {
+        head
+    }
+}
+
+/// 连续空白折叠为一个空格
+fn one_line(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !last_space {
+                out.push(' ');
+            }
+            last_space = true;
+        } else {
+            out.push(c);
+            last_space = false;
+        }
+    }
+    out
+}
+
+/// 搜索一份聊天记录，命中追加到 result（超出 limit 后只计数）
+pub fn search_history(
+    history_id: uuid::Uuid,
+    history_name: &str,
+    floors: &[SearchFloor],
+    scripts: &ScriptSet,
+    page_size: usize,
+    matcher: &Matcher,
+    result: &mut SearchResult,
+) {
+    let total = floors.len();
+    // 搜索文本：执行显示场景的正则并去除标签，与阅读器看到的内容一致
+    let texts: Vec<String> = floors
+        .iter()
+        .enumerate()
+        .map(|(index, floor)| {
+            if matcher.raw {
+                return floor.content.clone();
+            }
+            let ctx = ApplyContext::for_message(RunMode::Display, floor.is_user, index, total);
+            to_plain_text(&scripts.apply(&floor.content, &ctx))
+        })
+        .collect();
+
+    let preview = |index: usize| ContextFloor {
+        floor: floors[index].floor,
+        name: floors[index].name.clone(),
+        preview: char_head(one_line(&texts[index]).trim(), CONTEXT_PREVIEW_CHARS),
+    };
+
+    for (index, floor) in floors.iter().enumerate() {
+        if let Some(speaker) = &matcher.speaker {
+            if floor.name.to_lowercase() != *speaker {
+                continue;
+            }
+        }
+        if !matcher.date_in_range(floor.send_date.as_deref()) {
+            continue;
+        }
+        let text = &texts[index];
+        let mut matches = matcher.pattern.find_iter(text).filter(|m| !m.is_empty());
+        let Some(first) = matches.next() else {
+            continue;
+        };
+        let match_count = 1 + matches.count();
+        result.total_hits += 1;
+        result.total_matches += match_count;
+        if result.hits.len() >= matcher.limit {
+            result.truncated = true;
+            continue;
+        }
+
+        let start = index.saturating_sub(matcher.context);
+        let end = (index + 1 + matcher.context).min(total);
+        result.hits.push(SearchHit {
+            history_id,
+            history_name: history_name.to_string(),
+            floor: floor.floor,
+            page: index / page_size.max(1) + 1,
+            name: floor.name.clone(),
+            is_user: floor.is_user,
+            send_date: floor.send_date.clone(),
+            match_count,
+            snippet: Snippet {
+                before: one_line(&char_tail(&text[..first.start()], SNIPPET_CHARS)),
+                matched: first.as_str().to_string(),
+                after: one_line(&char_head(&text[first.end()..], SNIPPET_CHARS)),
+            },
+            context_before: (start..index).map(preview).collect(),
+            context_after: (index + 1..end).map(preview).collect(),
+        });
+    }
+}
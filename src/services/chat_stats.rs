@@ -0,0 +1,464 @@
+//! 聊天记录统计
+//!
+//! 统计发言者消息数、Token 与字数、按日 / 按小时的时间线、平均回复长度、常见标签与使用的模型；
+//! 单份记录的统计按文件大小与修改时间缓存，可合并为角色卡或全库的汇总
+
+use crate::services::st_chat::parse_send_date;
+use crate::utils::token::count_tokens;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+/// 汇总统计中保留的标签 / 模型数
+const TOP_N: usize = 30;
+
+/// 视为普通 HTML 的标签（不计入检测结果）
+const HTML_TAGS: &[&str] = &[
+    "html",
+    "head",
+    "body",
+    "script",
+    "style",
+    "div",
+    "p",
+    "span",
+    "br",
+    "hr",
+    "img",
+    "a",
+    "b",
+    "i",
+    "u",
+    "s",
+    "strike",
+    "del",
+    "strong",
+    "em",
+    "code",
+    "pre",
+    "blockquote",
+    "thead",
+    "tbody",
+    "tfoot",
+    "tr",
+    "th",
+    "td",
+    "caption",
+    "ul",
+    "ol",
+    "li",
+    "dl",
+    "dt",
+    "dd",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "form",
+    "input",
+    "button",
+    "textarea",
+    "select",
+    "option",
+    "label",
+    "fieldset",
+    "legend",
+    "iframe",
+    "svg",
+    "path",
+    "canvas",
+    "audio",
+    "video",
+    "source",
+    "track",
+    "embed",
+    "object",
+    "nav",
+    "header",
+    "footer",
+    "main",
+    "section",
+    "article",
+    "aside",
+    "dialog",
+];
+
+static TAG_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"</?([a-zA-Z0-9_\-\.\u4e00-\u9fa5]+)(?:\s[^>]*)?>").unwrap());
+
+/// 统计全文中各自定义标签（开标签）出现次数，忽略常见 HTML 标签
+pub fn scan_tags(content: &str) -> HashMap<String, usize> {
+    let ignore: HashSet<&str> = HTML_TAGS.iter().copied().collect();
+    let mut counts = HashMap::new();
+    for cap in TAG_PATTERN.captures_iter(content) {
+        let full_tag = &cap[0];
+        let name = &cap[1];
+        if full_tag.starts_with("</") || ignore.contains(name.to_lowercase().as_str()) {
+            continue;
+        }
+        *counts.entry(name.to_string()).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// 全文中出现的自定义标签（排序后）
+pub fn detect_tags(content: &str) -> Vec<String> {
+    let mut tags: Vec<String> = scan_tags(content).into_keys().collect();
+    tags.sort();
+    tags
+}
+
+/// 字数：CJK 字符每字计 1，其余按连续字母数字计词
+pub fn count_words(text: &str) -> usize {
+    let mut words = 0;
+    let mut in_word = false;
+    for c in text.chars() {
+        let cjk = matches!(c as u32,
+            0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF);
+        if cjk {
+            words += 1;
+            in_word = false;
+        } else if c.is_alphanumeric() {
+            if !in_word {
+                words += 1;
+            }
+            in_word = true;
+        } else {
+            in_word = false;
+        }
+    }
+    words
+}
+
+/// 参与统计的消息
+pub struct StatsMessage {
+    pub name: String,
+    /// TXT 记录为 None
+    pub is_user: Option<bool>,
+    pub is_system: bool,
+    pub send_date: Option<String>,
+    pub content: String,
+    /// JSONL 中 extra.token_count（缺失时现场计算）
+    pub token_count: Option<i64>,
+    pub model: Option<String>,
+    pub api: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeakerStats {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_user: Option<bool>,
+    pub messages: u64,
+    pub tokens: u64,
+    pub words: u64,
+    pub avg_tokens: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DayStats {
+    pub date: String,
+    pub messages: u64,
+    pub words: u64,
+    pub tokens: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelCount {
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api: Option<String>,
+    pub messages: u64,
+}
+
+/// 统计结果（单份记录或汇总）
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct ChatStats {
+    pub histories: u64,
+    pub total_messages: u64,
+    pub user_messages: u64,
+    pub char_messages: u64,
+    /// 隐藏 / 系统消息
+    pub hidden_messages: u64,
+    pub total_tokens: u64,
+    pub total_words: u64,
+    pub avg_message_tokens: f64,
+    pub max_message_tokens: u64,
+    /// 角色（非用户）回复的平均长度
+    pub avg_response_tokens: f64,
+    pub avg_response_words: f64,
+    /// 每条消息的 Token 数（按楼层顺序；汇总统计时省略）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub message_tokens: Vec<u32>,
+    pub speakers: Vec<SpeakerStats>,
+    /// 按日统计（无法识别日期的消息不计入）
+    pub days: Vec<DayStats>,
+    /// 按小时（0-23）统计消息数
+    pub hours: [u64; 24],
+    pub first_message_at: Option<String>,
+    pub last_message_at: Option<String>,
+    pub tags: Vec<TagCount>,
+    pub models: Vec<ModelCount>,
+    #[serde(skip)]
+    response_count: u64,
+    #[serde(skip)]
+    response_tokens: u64,
+    #[serde(skip)]
+    response_words: u64,
+}
+
+fn average(total: u64, count: u64) -> f64 {
+    if count == 0 {
+        0.0
+    } else {
+        ((total as f64 / count as f64) * 10.0).round() / 10.0
+    }
+}
+
+impl ChatStats {
+    /// 统计单份聊天记录（`raw` 为文件全文，用于扫描标签）
+    pub fn compute(messages: &[StatsMessage], raw: &str) -> Self {
+        let mut stats = ChatStats {
+            histories: 1,
+            ..Default::default()
+        };
+        let mut speakers: Vec<SpeakerStats> = Vec::new();
+        let mut days: BTreeMap<String, DayStats> = BTreeMap::new();
+        let mut models: HashMap<(String, Option<String>), u64> = HashMap::new();
+        let mut first: Option<chrono::NaiveDateTime> = None;
+        let mut last: Option<chrono::NaiveDateTime> = None;
+
+        for message in messages {
+            let tokens = message
+                .token_count
+                .filter(|&t| t > 0)
+                .map(|t| t as u64)
+                .unwrap_or_else(|| count_tokens(&message.content) as u64);
+            let words = count_words(&message.content) as u64;
+
+            stats.total_messages += 1;
+            stats.total_tokens += tokens;
+            stats.total_words += words;
+            stats.max_message_tokens = stats.max_message_tokens.max(tokens);
+            stats
+                .message_tokens
+                .push(tokens.min(u32::MAX as u64) as u32);
+            if message.is_system {
+                stats.hidden_messages += 1;
+            }
+            match message.is_user {
+                Some(true) => stats.user_messages += 1,
+                Some(false) => {
+                    stats.char_messages += 1;
+                    if !message.is_system {
+                        stats.response_count += 1;
+                        stats.response_tokens += tokens;
+                        stats.response_words += words;
+                    }
+                }
+                None => {}
+            }
+
+            let speaker = match speakers
+                .iter_mut()
+                .position(|s| s.name == message.name && s.is_user == message.is_user)
+            {
+                Some(index) => &mut speakers[index],
+                None => {
+                    speakers.push(SpeakerStats {
+                        name: message.name.clone(),
+                        is_user: message.is_user,
+                        messages: 0,
+                        tokens: 0,
+                        words: 0,
+                        avg_tokens: 0.0,
+                    });
+                    speakers.last_mut().expect("just pushed")
+                }
+            };
+            speaker.messages += 1;
+            speaker.tokens += tokens;
+            speaker.words += words;
+
+            if let Some(time) = message.send_date.as_deref().and_then(parse_send_date) {
+                let day = days
+                    .entry(time.format("%Y-%m-%d").to_string())
+                    .or_insert_with_key(|date| DayStats {
+                        date: date.clone(),
+                        messages: 0,
+                        words: 0,
+                        tokens: 0,
+                    });
+                day.messages += 1;
+                day.words += words;
+                day.tokens += tokens;
+                stats.hours[chrono::Timelike::hour(&time) as usize] += 1;
+                first = Some(first.map_or(time, |f| f.min(time)));
+                last = Some(last.map_or(time, |l| l.max(time)));
+            }
+
+            // 仅统计角色回复所用的模型（缺少 model 时以 api 代替）
+            let model = message.model.as_ref().or(message.api.as_ref());
+            if let Some(model) = model.filter(|_| message.is_user != Some(true)) {
+                *models
+                    .entry((model.clone(), message.api.clone()))
+                    .or_insert(0) += 1;
+            }
+        }
+
+        stats.speakers = speakers;
+        stats.days = days.into_values().collect();
+        stats.first_message_at = first.map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string());
+        stats.last_message_at = last.map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string());
+        stats.tags = scan_tags(raw)
+            .into_iter()
+            .map(|(tag, count)| TagCount {
+                tag,
+                count: count as u64,
+            })
+            .collect();
+        stats.models = models
+            .into_iter()
+            .map(|((model, api), messages)| ModelCount {
+                model,
+                api,
+                messages,
+            })
+            .collect();
+        stats.finish(usize::MAX);
+        stats
+    }
+
+    /// 合并为汇总统计（不含逐条 Token）
+    pub fn merge(summaries: &[Arc<ChatStats>]) -> Self {
+        let mut total = ChatStats::default();
+        let mut days: BTreeMap<String, DayStats> = BTreeMap::new();
+        for stats in summaries {
+            total.histories += stats.histories;
+            total.total_messages += stats.total_messages;
+            total.user_messages += stats.user_messages;
+            total.char_messages += stats.char_messages;
+            total.hidden_messages += stats.hidden_messages;
+            total.total_tokens += stats.total_tokens;
+            total.total_words += stats.total_words;
+            total.max_message_tokens = total.max_message_tokens.max(stats.max_message_tokens);
+            total.response_count += stats.response_count;
+            total.response_tokens += stats.response_tokens;
+            total.response_words += stats.response_words;
+            for (hour, count) in stats.hours.iter().enumerate() {
+                total.hours[hour] += count;
+            }
+            total.first_message_at = match (total.first_message_at.take(), &stats.first_message_at)
+            {
+                (Some(a), Some(b)) => Some(a.min(b.clone())),
+                (a, b) => a.or_else(|| b.clone()),
+            };
+            total.last_message_at = match (total.last_message_at.take(), &stats.last_message_at) {
+                (Some(a), Some(b)) => Some(a.max(b.clone())),
+                (a, b) => a.or_else(|| b.clone()),
+            };
+
+            for speaker in &stats.speakers {
+                match total
+                    .speakers
+                    .iter_mut()
+                    .find(|s| s.name == speaker.name && s.is_user == speaker.is_user)
+                {
+                    Some(s) => {
+                        s.messages += speaker.messages;
+                        s.tokens += speaker.tokens;
+                        s.words += speaker.words;
+                    }
+                    None => total.speakers.push(speaker.clone()),
+                }
+            }
+            for day in &stats.days {
+                let entry = days.entry(day.date.clone()).or_insert_with(|| DayStats {
+                    date: day.date.clone(),
+                    messages: 0,
+                    words: 0,
+                    tokens: 0,
+                });
+                entry.messages += day.messages;
+                entry.words += day.words;
+                entry.tokens += day.tokens;
+            }
+            for tag in &stats.tags {
+                match total.tags.iter_mut().find(|t| t.tag == tag.tag) {
+                    Some(t) => t.count += tag.count,
+                    None => total.tags.push(tag.clone()),
+                }
+            }
+            for model in &stats.models {
+                match total
+                    .models
+                    .iter_mut()
+                    .find(|m| m.model == model.model && m.api == model.api)
+                {
+                    Some(m) => m.messages += model.messages,
+                    None => total.models.push(model.clone()),
+                }
+            }
+        }
+        total.days = days.into_values().collect();
+        total.finish(TOP_N);
+        total
+    }
+
+    /// 计算平均值并排序
+    fn finish(&mut self, top: usize) {
+        self.avg_message_tokens = average(self.total_tokens, self.total_messages);
+        self.avg_response_tokens = average(self.response_tokens, self.response_count);
+        self.avg_response_words = average(self.response_words, self.response_count);
+        for speaker in &mut self.speakers {
+            speaker.avg_tokens = average(speaker.tokens, speaker.messages);
+        }
+        self.speakers
+            .sort_by(|a, b| b.messages.cmp(&a.messages).then(a.name.cmp(&b.name)));
+        self.tags
+            .sort_by(|a, b| b.count.cmp(&a.count).then(a.tag.cmp(&b.tag)));
+        self.tags.truncate(top);
+        self.models
+            .sort_by(|a, b| b.messages.cmp(&a.messages).then(a.model.cmp(&b.model)));
+        self.models.truncate(top);
+    }
+}
+
+/// 缓存项：((文件大小, 修改时间), 统计)
+type CacheEntry = ((u64, u64), Arc<ChatStats>);
+
+/// 单份记录的统计缓存（按 history_id）
+static CACHE: Lazy<RwLock<HashMap<Uuid, CacheEntry>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// 读取缓存（文件变化后失效；阅读进度等只改数据库的更新不影响）
+pub fn cached(history_id: Uuid, stamp: (u64, u64)) -> Option<Arc<ChatStats>> {
+    let cache = CACHE.read().ok()?;
+    cache
+        .get(&history_id)
+        .filter(|(at, _)| *at == stamp)
+        .map(|(_, stats)| stats.clone())
+}
+
+pub fn store(history_id: Uuid, stamp: (u64, u64), stats: Arc<ChatStats>) {
+    if let Ok(mut cache) = CACHE.write() {
+        cache.insert(history_id, (stamp, stats));
+    }
+}
+
+/// 移除缓存（删除记录时调用）
+pub fn evict(history_id: Uuid) {
+    if let Ok(mut cache) = CACHE.write() {
+        cache.remove(&history_id);
+    }
+}
@@ -0,0 +1,426 @@
+//! 小皮医生服务
+//!
+//! 深度诊断：将世界书全部条目（含禁用、未命名条目）按 Token 预算分组，
+//! 逐组分析与核心设定的冲突（map），再汇总为标准诊断报告（reduce）
+//!
+//! 同时提供诊断报告的结构化解析与两次诊断的对比
+
+use crate::services::prompt::{self, PromptVars};
+use crate::services::structured::{Field, FieldKind, Schema};
+use crate::utils::token::count_tokens;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// 每组条目的 Token 预算（不含提示词本身）
+pub const DEEP_CHUNK_TOKENS: usize = 6000;
+
+/// 诊断每轮的输出结构：申请阅读条目（entries）或输出报告（report）
+pub const ANALYZE_SCHEMA: Schema = Schema {
+    name: "doctor_action",
+    fields: &[
+        Field {
+            name: "action",
+            kind: FieldKind::String,
+            required: true,
+        },
+        Field {
+            name: "entries",
+            kind: FieldKind::StringArray,
+            required: false,
+        },
+        Field {
+            name: "report",
+            kind: FieldKind::Object,
+            required: false,
+        },
+    ],
+};
+
+/// 一组待分析的条目
+#[derive(Debug, Clone)]
+pub struct EntryChunk {
+    /// 渲染后的条目文本
+    pub text: String,
+    /// uid -> 条目名称
+    pub entries: HashMap<String, String>,
+}
+
+/// 条目 uid：优先 uid，其次 id，最后回退为数组下标
+pub fn entry_uid(entry: &Value, index: usize) -> String {
+    entry
+        .get("uid")
+        .or_else(|| entry.get("id"))
+        .and_then(|v| match v {
+            Value::Number(n) => Some(n.to_string()),
+            Value::String(s) if !s.is_empty() => Some(s.clone()),
+            _ => None,
+        })
+        .unwrap_or_else(|| index.to_string())
+}
+
+fn entry_name(entry: &Value) -> String {
+    entry
+        .get("comment")
+        .or_else(|| entry.get("name"))
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| "（未命名）".to_string())
+}
+
+fn render_entry(entry: &Value, uid: &str) -> String {
+    let enabled = entry
+        .get("enabled")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+    let keys = entry
+        .get("keys")
+        .and_then(|v| v.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|k| k.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_default();
+    let content = entry.get("content").and_then(|v| v.as_str()).unwrap_or("");
+    format!(
+        "[uid: {}] 名称: {} | 状态: {} | 关键词: {}\n{}\n",
+        uid,
+        entry_name(entry),
+        if enabled { "启用" } else { "禁用" },
+        if keys.is_empty() { "（无）" } else { &keys },
+        content
+    )
+}
+
+/// 按 Token 预算将全部条目分组（单个超预算条目独占一组，不截断）
+pub fn chunk_entries(card_json: &Value, budget: usize) -> Vec<EntryChunk> {
+    let entries = card_json
+        .get("data")
+        .unwrap_or(card_json)
+        .get("character_book")
+        .and_then(|cb| cb.get("entries"))
+        .and_then(|e| e.as_array())
+        .cloned()
+        .unwrap_or_default();
+
+    let mut chunks = Vec::new();
+    let mut current = EntryChunk {
+        text: String::new(),
+        entries: HashMap::new(),
+    };
+    let mut current_tokens = 0;
+
+    for (i, entry) in entries.iter().enumerate() {
+        let uid = entry_uid(entry, i);
+        let text = render_entry(entry, &uid);
+        let tokens = count_tokens(&text);
+
+        if !current.entries.is_empty() && current_tokens + tokens > budget {
+            chunks.push(std::mem::replace(
+                &mut current,
+                EntryChunk {
+                    text: String::new(),
+                    entries: HashMap::new(),
+                },
+            ));
+            current_tokens = 0;
+        }
+
+        current.text.push_str(&text);
+        current.text.push('\n');
+        current.entries.insert(uid, entry_name(entry));
+        current_tokens += tokens;
+    }
+
+    if !current.entries.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// 规范化模型返回的发现：必须引用本组内存在的 uid，并补全条目名称
+pub fn normalize_findings(raw: &Value, chunk: &EntryChunk) -> Vec<Value> {
+    raw.get("findings")
+        .and_then(|f| f.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|f| {
+                    let uid = match f.get("uid")? {
+                        Value::Number(n) => n.to_string(),
+                        Value::String(s) => s.clone(),
+                        _ => return None,
+                    };
+                    let Some(name) = chunk.entries.get(&uid) else {
+                        tracing::warn!("Doctor deep finding references unknown uid: {}", uid);
+                        return None;
+                    };
+                    let mut finding = f.clone();
+                    finding["uid"] = Value::String(uid);
+                    finding["entry"] = Value::String(name.clone());
+                    Some(finding)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// 渲染某组条目的分析消息
+pub fn map_messages(
+    template: &prompt::ResolvedTemplate,
+    base_vars: &PromptVars,
+    chunk: &EntryChunk,
+    index: usize,
+    total: usize,
+) -> Vec<Value> {
+    let mut vars = base_vars.clone();
+    vars.insert("entries_chunk".into(), chunk.text.clone());
+    vars.insert("chunk_index".into(), (index + 1).to_string());
+    vars.insert("chunk_total".into(), total.to_string());
+    prompt::render_messages(template, &vars)
+}
+
+/// 渲染汇总消息
+pub fn reduce_messages(
+    template: &prompt::ResolvedTemplate,
+    base_vars: &PromptVars,
+    findings: &[Value],
+    entry_count: usize,
+) -> Vec<Value> {
+    let mut vars = base_vars.clone();
+    vars.insert(
+        "findings".into(),
+        if findings.is_empty() {
+            "（未发现世界书条目与核心设定的冲突）".to_string()
+        } else {
+            serde_json::to_string_pretty(findings).unwrap_or_default()
+        },
+    );
+    vars.insert("entry_count".into(), entry_count.to_string());
+    prompt::render_messages(template, &vars)
+}
+
+// ==================== 结构化报告 ====================
+
+/// 诊断结论
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Conclusion {
+    /// 通过
+    Pass,
+    /// 需大幅修正
+    Revise,
+    /// 建议重构
+    Rebuild,
+    Unknown,
+}
+
+impl Conclusion {
+    /// 从模型输出的结论文本识别（模型可能原样抄写选项，按严重程度优先匹配）
+    pub fn from_text(text: &str) -> Self {
+        if text.contains("重构") {
+            Conclusion::Rebuild
+        } else if text.contains("修正") {
+            Conclusion::Revise
+        } else if text.contains("通过") {
+            Conclusion::Pass
+        } else {
+            Conclusion::Unknown
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Conclusion::Pass => "pass",
+            Conclusion::Revise => "revise",
+            Conclusion::Rebuild => "rebuild",
+            Conclusion::Unknown => "unknown",
+        }
+    }
+}
+
+/// 单个维度的结构化结果
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DimensionScore {
+    pub name: String,
+    pub score: Option<f64>,
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub issues: String,
+    #[serde(default)]
+    pub suggestions: String,
+}
+
+/// 从报告中解析出的结构化字段
+#[derive(Debug, Clone)]
+pub struct StructuredReport {
+    pub dimensions: Vec<DimensionScore>,
+    pub overall_score: Option<f64>,
+    pub conclusion: Conclusion,
+}
+
+/// 解析分数：数字或 "85"、"85/100"、"85分" 之类的字符串，限制在 0-100
+fn parse_score(v: Option<&Value>) -> Option<f64> {
+    let score = match v? {
+        Value::Number(n) => n.as_f64()?,
+        Value::String(s) => {
+            let num: String = s
+                .trim()
+                .chars()
+                .take_while(|c| c.is_ascii_digit() || *c == '.')
+                .collect();
+            num.parse().ok()?
+        }
+        _ => return None,
+    };
+    Some(score.clamp(0.0, 100.0))
+}
+
+fn text_field(v: &Value, key: &str) -> String {
+    match v.get(key) {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(arr)) => arr
+            .iter()
+            .filter_map(|x| x.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// 解析诊断报告（兼容旧版无评分的报告）
+pub fn parse_report(report: &Value) -> StructuredReport {
+    let dimensions: Vec<DimensionScore> = report
+        .get("dimensions")
+        .and_then(|d| d.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|d| {
+                    let name = d.get("name").and_then(|n| n.as_str())?.to_string();
+                    Some(DimensionScore {
+                        name,
+                        score: parse_score(d.get("score")),
+                        status: text_field(d, "status"),
+                        issues: text_field(d, "issues"),
+                        suggestions: text_field(d, "suggestions"),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // 总分：优先使用报告给出的 overall_score，否则取各维度平均
+    let overall_score = parse_score(report.get("overall_score")).or_else(|| {
+        let scores: Vec<f64> = dimensions.iter().filter_map(|d| d.score).collect();
+        if scores.is_empty() {
+            None
+        } else {
+            Some((scores.iter().sum::<f64>() / scores.len() as f64 * 10.0).round() / 10.0)
+        }
+    });
+
+    let conclusion = report
+        .get("conclusion")
+        .and_then(|c| c.as_str())
+        .map(Conclusion::from_text)
+        .unwrap_or(Conclusion::Unknown);
+
+    StructuredReport {
+        dimensions,
+        overall_score,
+        conclusion,
+    }
+}
+
+/// 两次诊断之间单个维度的变化
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DimensionDelta {
+    pub name: String,
+    pub from_score: Option<f64>,
+    pub to_score: Option<f64>,
+    pub delta: Option<f64>,
+    pub from_issues: Option<String>,
+    pub to_issues: Option<String>,
+}
+
+/// 按维度名称对齐两次诊断（保持 to 的顺序，from 独有的维度追加在后）
+pub fn compare_dimensions(from: &[DimensionScore], to: &[DimensionScore]) -> Vec<DimensionDelta> {
+    let mut result: Vec<DimensionDelta> = to
+        .iter()
+        .map(|t| {
+            let f = from.iter().find(|f| f.name == t.name);
+            let from_score = f.and_then(|f| f.score);
+            DimensionDelta {
+                name: t.name.clone(),
+                from_score,
+                to_score: t.score,
+                delta: match (from_score, t.score) {
+                    (Some(a), Some(b)) => Some(b - a),
+                    _ => None,
+                },
+                from_issues: f.map(|f| f.issues.clone()),
+                to_issues: Some(t.issues.clone()),
+            }
+        })
+        .collect();
+
+    for f in from {
+        if !to.iter().any(|t| t.name == f.name) {
+            result.push(DimensionDelta {
+                name: f.name.clone(),
+                from_score: f.score,
+                to_score: None,
+                delta: None,
+                from_issues: Some(f.issues.clone()),
+                to_issues: None,
+            });
+        }
+    }
+    result
+}
+
+/// 深度诊断 findings 的对比结果（按 uid + 类型匹配）
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct FindingsDelta {
+    /// 旧诊断中存在、新诊断中已消失
+    pub resolved: Vec<Value>,
+    /// 两次诊断中都存在
+    pub persisting: Vec<Value>,
+    /// 新诊断中新出现
+    pub introduced: Vec<Value>,
+}
+
+fn finding_key(f: &Value) -> (String, String) {
+    (
+        f.get("uid")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string(),
+        f.get("type")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string(),
+    )
+}
+
+pub fn compare_findings(from: &Value, to: &Value) -> Option<FindingsDelta> {
+    let from = from.get("findings")?.as_array()?;
+    let to = to.get("findings")?.as_array()?;
+
+    let mut delta = FindingsDelta::default();
+    for f in from {
+        if to.iter().any(|t| finding_key(t) == finding_key(f)) {
+            delta.persisting.push(f.clone());
+        } else {
+            delta.resolved.push(f.clone());
+        }
+    }
+    for t in to {
+        if !from.iter().any(|f| finding_key(f) == finding_key(t)) {
+            delta.introduced.push(t.clone());
+        }
+    }
+    Some(delta)
+}
@@ -0,0 +1,478 @@
+//! 语义向量索引
+//!
+//! - 角色卡、世界书条目、小剧场各自生成向量文本，按内容哈希增量重建
+//! - 向量以 f32 小端序 BLOB 存入 SQLite，查询时全量读取做余弦相似度（单用户库规模足够）
+
+use crate::entities::{character_card, embedding, theater, world_info};
+use crate::services::ai::{self, AiError};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+    QuerySelect, Set,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// 单条向量文本的最大字符数（超出截断）
+const MAX_TEXT_CHARS: usize = 6000;
+/// 每次 /embeddings 请求的输入条数
+const BATCH_SIZE: usize = 32;
+
+pub const SOURCE_CARD: &str = "card";
+pub const SOURCE_WORLD_ENTRY: &str = "world_entry";
+pub const SOURCE_THEATER: &str = "theater";
+
+/// 需要建立索引的来源
+#[derive(Clone, Copy, Debug)]
+pub enum Source {
+    Card(Uuid),
+    WorldInfo(Uuid),
+    Theater(Uuid),
+}
+
+impl Source {
+    fn parts(&self) -> (&'static str, Uuid) {
+        match self {
+            Source::Card(id) => (SOURCE_CARD, *id),
+            Source::WorldInfo(id) => (SOURCE_WORLD_ENTRY, *id),
+            Source::Theater(id) => (SOURCE_THEATER, *id),
+        }
+    }
+}
+
+/// 待向量化的条目
+struct EmbedItem {
+    item_key: String,
+    title: String,
+    text: String,
+}
+
+impl EmbedItem {
+    fn hash(&self) -> String {
+        crate::utils::hash::compute_json_hash(&self.text)
+    }
+}
+
+fn truncate(text: String) -> String {
+    if text.chars().count() > MAX_TEXT_CHARS {
+        text.chars().take(MAX_TEXT_CHARS).collect()
+    } else {
+        text
+    }
+}
+
+/// 拼接非空片段
+fn join_parts(parts: &[(&str, &str)]) -> String {
+    parts
+        .iter()
+        .filter(|(_, v)| !v.trim().is_empty())
+        .map(|(label, v)| format!("{}: {}", label, v.trim()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn card_item(card: &character_card::Model) -> EmbedItem {
+    let json: Value = serde_json::from_str(&card.data).unwrap_or(Value::Null);
+    let data = json.get("data").filter(|d| d.is_object()).unwrap_or(&json);
+    let field = |key: &str| data.get(key).and_then(|v| v.as_str()).unwrap_or("");
+    let tags: Vec<String> = serde_json::from_str(&card.tags).unwrap_or_default();
+    let tags = tags.join(", ");
+
+    let text = join_parts(&[
+        ("Name", &card.name),
+        ("Tags", &tags),
+        ("Summary", card.custom_summary.as_deref().unwrap_or("")),
+        ("Description", field("description")),
+        ("Personality", field("personality")),
+        ("Scenario", field("scenario")),
+    ]);
+    EmbedItem {
+        item_key: String::new(),
+        title: card.name.clone(),
+        text: truncate(text),
+    }
+}
+
+/// 世界书条目：兼容 ST 格式（entries 为对象）与 CCv3 格式（entries 为数组）
+fn world_items(info: &world_info::Model) -> Vec<EmbedItem> {
+    let json: Value = serde_json::from_str(&info.data).unwrap_or(Value::Null);
+    let entries: Vec<(String, &Value)> = match json.get("entries") {
+        Some(Value::Object(map)) => map.iter().map(|(k, v)| (k.clone(), v)).collect(),
+        Some(Value::Array(arr)) => arr
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i.to_string(), v))
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    entries
+        .into_iter()
+        .filter(|(_, e)| !e.get("disable").and_then(|v| v.as_bool()).unwrap_or(false))
+        .filter_map(|(fallback_key, entry)| {
+            let content = entry.get("content").and_then(|v| v.as_str()).unwrap_or("");
+            if content.trim().is_empty() {
+                return None;
+            }
+            let key = entry
+                .get("uid")
+                .or_else(|| entry.get("id"))
+                .map(|v| match v {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                })
+                .unwrap_or(fallback_key);
+            let keys: Vec<&str> = entry
+                .get("key")
+                .or_else(|| entry.get("keys"))
+                .and_then(|v| v.as_array())
+                .map(|arr| arr.iter().filter_map(|k| k.as_str()).collect())
+                .unwrap_or_default();
+            let comment = entry.get("comment").and_then(|v| v.as_str()).unwrap_or("");
+            let title = if comment.trim().is_empty() {
+                keys.first().copied().unwrap_or("未命名条目").to_string()
+            } else {
+                comment.trim().to_string()
+            };
+
+            let keys = keys.join(", ");
+            Some(EmbedItem {
+                item_key: key,
+                title: format!("{} / {}", info.name, title),
+                text: truncate(join_parts(&[
+                    ("Title", comment),
+                    ("Keys", &keys),
+                    ("Content", content),
+                ])),
+            })
+        })
+        .collect()
+}
+
+fn theater_item(t: &theater::Model) -> EmbedItem {
+    EmbedItem {
+        item_key: String::new(),
+        title: t.title.clone(),
+        text: truncate(join_parts(&[
+            ("Title", &t.title),
+            ("Category", &t.category),
+            ("Description", &t.description),
+            ("Content", &t.content),
+        ])),
+    }
+}
+
+pub fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+pub fn decode_vector(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .collect()
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let (mut dot, mut na, mut nb) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        na += x * x;
+        nb += y * y;
+    }
+    if na == 0.0 || nb == 0.0 {
+        0.0
+    } else {
+        dot / (na.sqrt() * nb.sqrt())
+    }
+}
+
+/// 同步某来源的全部条目：内容与模型未变化的跳过，其余批量重建，已不存在的子项删除
+///
+/// 返回实际重新向量化的条目数
+async fn sync_items(
+    db: &DatabaseConnection,
+    channel: &crate::entities::ai_channel::Model,
+    source_type: &str,
+    source_id: Uuid,
+    items: Vec<EmbedItem>,
+) -> Result<usize, AiError> {
+    let existing: HashMap<String, embedding::Model> = embedding::Entity::find()
+        .filter(embedding::Column::SourceType.eq(source_type))
+        .filter(embedding::Column::SourceId.eq(source_id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|e| (e.item_key.clone(), e))
+        .collect();
+
+    let keep: HashSet<&str> = items.iter().map(|i| i.item_key.as_str()).collect();
+    let removed: Vec<Uuid> = existing
+        .values()
+        .filter(|e| !keep.contains(e.item_key.as_str()))
+        .map(|e| e.id)
+        .collect();
+    if !removed.is_empty() {
+        embedding::Entity::delete_many()
+            .filter(embedding::Column::Id.is_in(removed))
+            .exec(db)
+            .await?;
+    }
+
+    let stale: Vec<(EmbedItem, String)> = items
+        .into_iter()
+        .map(|item| {
+            let hash = item.hash();
+            (item, hash)
+        })
+        .filter(|(item, hash)| {
+            existing
+                .get(&item.item_key)
+                .map(|e| &e.content_hash != hash || e.model != channel.model_id)
+                .unwrap_or(true)
+        })
+        .collect();
+
+    let now = chrono::Utc::now().naive_utc();
+    for batch in stale.chunks(BATCH_SIZE) {
+        let inputs: Vec<String> = batch.iter().map(|(item, _)| item.text.clone()).collect();
+        let vectors = ai::embeddings(channel, &inputs).await?;
+
+        for ((item, hash), vector) in batch.iter().zip(vectors) {
+            match existing.get(&item.item_key) {
+                Some(row) => {
+                    let mut active: embedding::ActiveModel = row.clone().into();
+                    active.title = Set(item.title.clone());
+                    active.content_hash = Set(hash.clone());
+                    active.model = Set(channel.model_id.clone());
+                    active.dims = Set(vector.len() as i32);
+                    active.vector = Set(encode_vector(&vector));
+                    active.updated_at = Set(now);
+                    active.update(db).await?;
+                }
+                None => {
+                    embedding::ActiveModel {
+                        id: Set(Uuid::new_v4()),
+                        source_type: Set(source_type.to_string()),
+                        source_id: Set(source_id),
+                        item_key: Set(item.item_key.clone()),
+                        title: Set(item.title.clone()),
+                        content_hash: Set(hash.clone()),
+                        model: Set(channel.model_id.clone()),
+                        dims: Set(vector.len() as i32),
+                        vector: Set(encode_vector(&vector)),
+                        updated_at: Set(now),
+                    }
+                    .insert(db)
+                    .await?;
+                }
+            }
+        }
+    }
+
+    Ok(stale.len())
+}
+
+/// 删除某来源的全部向量
+pub async fn remove_source(db: &DatabaseConnection, source: Source) -> Result<(), DbErr> {
+    let (source_type, source_id) = source.parts();
+    embedding::Entity::delete_many()
+        .filter(embedding::Column::SourceType.eq(source_type))
+        .filter(embedding::Column::SourceId.eq(source_id))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// 为单个来源建立或更新索引；来源已删除（或角色卡在回收站）时清除其向量
+pub async fn index_source(
+    db: &DatabaseConnection,
+    channel: &crate::entities::ai_channel::Model,
+    source: Source,
+) -> Result<usize, AiError> {
+    let (source_type, source_id) = source.parts();
+    let items = match source {
+        Source::Card(id) => character_card::Entity::find_by_id(id)
+            .one(db)
+            .await?
+            .filter(|c| c.deleted_at.is_none())
+            .map(|c| vec![card_item(&c)]),
+        Source::WorldInfo(id) => world_info::Entity::find_by_id(id)
+            .one(db)
+            .await?
+            .map(|w| world_items(&w)),
+        Source::Theater(id) => theater::Entity::find_by_id(id)
+            .one(db)
+            .await?
+            .map(|t| vec![theater_item(&t)]),
+    };
+
+    match items {
+        Some(items) => sync_items(db, channel, source_type, source_id, items).await,
+        None => {
+            remove_source(db, source).await?;
+            Ok(0)
+        }
+    }
+}
+
+/// 内容变更后在后台增量重建索引；未配置向量渠道时不做任何事
+pub fn schedule(db: &DatabaseConnection, source: Source) {
+    let db = db.clone();
+    tokio::spawn(async move {
+        let result = match ai::resolve_embedding_channel(&db).await {
+            Ok(Some(channel)) => index_source(&db, &channel, source).await.map(|_| ()),
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::warn!("Embedding update failed for {:?}: {}", source, e);
+        }
+    });
+}
+
+/// 来源被删除后在后台清除其向量（无需向量渠道）
+pub fn forget(db: &DatabaseConnection, source: Source) {
+    let db = db.clone();
+    tokio::spawn(async move {
+        if let Err(e) = remove_source(&db, source).await {
+            tracing::warn!("Failed to remove embeddings for {:?}: {}", source, e);
+        }
+    });
+}
+
+/// 当前库中所有可建立索引的来源
+pub async fn all_sources(db: &DatabaseConnection) -> Result<Vec<Source>, DbErr> {
+    let cards: Vec<Uuid> = character_card::Entity::find()
+        .select_only()
+        .column(character_card::Column::Id)
+        .filter(character_card::Column::DeletedAt.is_null())
+        .into_tuple()
+        .all(db)
+        .await?;
+    let worlds: Vec<Uuid> = world_info::Entity::find()
+        .select_only()
+        .column(world_info::Column::Id)
+        .into_tuple()
+        .all(db)
+        .await?;
+    let theaters: Vec<Uuid> = theater::Entity::find()
+        .select_only()
+        .column(theater::Column::Id)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    Ok(cards
+        .into_iter()
+        .map(Source::Card)
+        .chain(worlds.into_iter().map(Source::WorldInfo))
+        .chain(theaters.into_iter().map(Source::Theater))
+        .collect())
+}
+
+/// 清除来源已不存在的向量，返回删除条数
+pub async fn prune_orphans(db: &DatabaseConnection, sources: &[Source]) -> Result<u64, DbErr> {
+    let alive: HashSet<(&str, Uuid)> = sources.iter().map(|s| s.parts()).collect();
+    let orphaned: Vec<Uuid> = embedding::Entity::find()
+        .select_only()
+        .column(embedding::Column::Id)
+        .column(embedding::Column::SourceType)
+        .column(embedding::Column::SourceId)
+        .into_tuple::<(Uuid, String, Uuid)>()
+        .all(db)
+        .await?
+        .into_iter()
+        .filter(|(_, t, sid)| !alive.contains(&(t.as_str(), *sid)))
+        .map(|(id, _, _)| id)
+        .collect();
+
+    if orphaned.is_empty() {
+        return Ok(0);
+    }
+    let res = embedding::Entity::delete_many()
+        .filter(embedding::Column::Id.is_in(orphaned))
+        .exec(db)
+        .await?;
+    Ok(res.rows_affected)
+}
+
+/// 检索结果
+#[derive(Serialize, Clone)]
+pub struct SearchHit {
+    pub source_type: String,
+    pub source_id: Uuid,
+    /// 世界书条目 uid，其它来源为空
+    pub item_key: String,
+    pub title: String,
+    pub score: f32,
+}
+
+/// 暴力余弦检索：只比较同一模型生成的向量，回收站中的角色卡不参与
+pub async fn search(
+    db: &DatabaseConnection,
+    query: &[f32],
+    model: &str,
+    source_types: &[&str],
+    exclude: Option<(&str, Uuid)>,
+    limit: usize,
+) -> Result<Vec<SearchHit>, DbErr> {
+    let rows = embedding::Entity::find()
+        .filter(embedding::Column::Model.eq(model))
+        .filter(embedding::Column::SourceType.is_in(source_types.iter().copied()))
+        .all(db)
+        .await?;
+
+    let alive_cards: HashSet<Uuid> = if source_types.contains(&SOURCE_CARD) {
+        character_card::Entity::find()
+            .select_only()
+            .column(character_card::Column::Id)
+            .filter(character_card::Column::DeletedAt.is_null())
+            .into_tuple::<Uuid>()
+            .all(db)
+            .await?
+            .into_iter()
+            .collect()
+    } else {
+        HashSet::new()
+    };
+
+    let mut hits: Vec<SearchHit> = rows
+        .into_iter()
+        .filter(|r| r.dims as usize == query.len())
+        .filter(|r| exclude != Some((r.source_type.as_str(), r.source_id)))
+        .filter(|r| r.source_type != SOURCE_CARD || alive_cards.contains(&r.source_id))
+        .map(|r| SearchHit {
+            score: cosine_similarity(query, &decode_vector(&r.vector)),
+            source_type: r.source_type,
+            source_id: r.source_id,
+            item_key: r.item_key,
+            title: r.title,
+        })
+        .collect();
+
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hits.truncate(limit);
+    Ok(hits)
+}
+
+/// 读取某来源已存储的向量
+pub async fn stored_vector(
+    db: &DatabaseConnection,
+    source: Source,
+    model: &str,
+) -> Result<Option<Vec<f32>>, DbErr> {
+    let (source_type, source_id) = source.parts();
+    Ok(embedding::Entity::find()
+        .filter(embedding::Column::SourceType.eq(source_type))
+        .filter(embedding::Column::SourceId.eq(source_id))
+        .filter(embedding::Column::ItemKey.eq(""))
+        .filter(embedding::Column::Model.eq(model))
+        .one(db)
+        .await?
+        .map(|e| decode_vector(&e.vector)))
+}
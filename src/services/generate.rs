@@ -0,0 +1,175 @@
+//! AI 角色卡生成
+//!
+//! 将模型输出的草稿规范化为完整的 CCv3 角色卡 JSON，并整理参考素材（小剧场 / 图库图片）
+
+use crate::entities::{image, theater};
+use serde_json::{json, Value};
+
+/// 参考素材正文的最大字符数
+const REFERENCE_MAX_CHARS: usize = 4000;
+
+/// 读取草稿中的字符串字段（去除首尾空白）
+fn draft_str(draft: &Value, key: &str) -> String {
+    draft
+        .get(key)
+        .and_then(|v| v.as_str())
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// 读取字符串数组（兼容逗号分隔的字符串），去空去重
+fn draft_str_list(value: Option<&Value>) -> Vec<String> {
+    let items: Vec<String> = match value {
+        Some(Value::Array(arr)) => arr
+            .iter()
+            .filter_map(|v| v.as_str())
+            .map(|s| s.trim().to_string())
+            .collect(),
+        Some(Value::String(s)) => s.split([',', '，']).map(|s| s.trim().to_string()).collect(),
+        _ => Vec::new(),
+    };
+
+    let mut result: Vec<String> = Vec::new();
+    for item in items {
+        if !item.is_empty() && !result.contains(&item) {
+            result.push(item);
+        }
+    }
+    result
+}
+
+/// 将草稿中的世界书条目规范化为 CCv3 character_book 条目
+fn normalize_entries(draft: &Value, warnings: &mut Vec<String>) -> Vec<Value> {
+    let raw_entries = draft
+        .get("character_book")
+        .and_then(|cb| cb.get("entries").or(Some(cb)))
+        .and_then(|e| e.as_array())
+        .cloned()
+        .unwrap_or_default();
+
+    let mut entries = Vec::new();
+    for (index, raw) in raw_entries.iter().enumerate() {
+        let content = draft_str(raw, "content");
+        if content.is_empty() {
+            warnings.push(format!("世界书条目 {} 内容为空，已丢弃", index + 1));
+            continue;
+        }
+
+        let keys = draft_str_list(raw.get("keys").or_else(|| raw.get("key")));
+        let mut comment = draft_str(raw, "comment");
+        if comment.is_empty() {
+            comment = draft_str(raw, "name");
+        }
+        if comment.is_empty() {
+            comment = keys.first().cloned().unwrap_or_default();
+        }
+
+        // 没有触发词的条目设为常驻，避免永远无法触发
+        let constant = keys.is_empty();
+        if constant {
+            warnings.push(format!("世界书条目「{}」缺少触发词，已设为常驻", comment));
+        }
+
+        entries.push(super::card::character_book_entry(
+            entries.len() as i64,
+            keys,
+            &comment,
+            &content,
+            constant,
+        ));
+    }
+    entries
+}
+
+/// 将模型草稿构建为完整 CCv3 角色卡 JSON
+///
+/// name / description / first_mes 为必填；其余字段缺失时补空并给出警告
+pub fn build_card_json(draft: &Value) -> Result<(Value, Vec<String>), String> {
+    let name = draft_str(draft, "name");
+    if name.is_empty() {
+        return Err("草稿缺少角色名 (name)".to_string());
+    }
+    let description = draft_str(draft, "description");
+    if description.is_empty() {
+        return Err("草稿缺少角色描述 (description)".to_string());
+    }
+    let first_mes = draft_str(draft, "first_mes");
+    if first_mes.is_empty() {
+        return Err("草稿缺少开场白 (first_mes)".to_string());
+    }
+
+    let mut warnings = Vec::new();
+    for field in ["personality", "scenario", "mes_example"] {
+        if draft_str(draft, field).is_empty() {
+            warnings.push(format!("草稿缺少 {}，已留空", field));
+        }
+    }
+
+    let greetings = draft_str_list(draft.get("alternate_greetings"));
+    let tags = draft_str_list(draft.get("tags"));
+    let entries = normalize_entries(draft, &mut warnings);
+
+    let mut card = super::card::blank_card_json(&name);
+    {
+        let data = &mut card["data"];
+        data["description"] = json!(description);
+        data["personality"] = json!(draft_str(draft, "personality"));
+        data["scenario"] = json!(draft_str(draft, "scenario"));
+        data["first_mes"] = json!(first_mes);
+        data["mes_example"] = json!(draft_str(draft, "mes_example"));
+        data["creator_notes"] = json!(draft_str(draft, "creator_notes"));
+        data["system_prompt"] = json!(draft_str(draft, "system_prompt"));
+        data["alternate_greetings"] = json!(greetings);
+        data["tags"] = json!(tags);
+        if !entries.is_empty() {
+            data["character_book"] = json!({
+                "name": name,
+                "entries": entries,
+                "extensions": {}
+            });
+        }
+    }
+    super::card::sync_root_mirrors(&mut card);
+
+    Ok((card, warnings))
+}
+
+/// 截断参考素材，避免超出上下文
+fn truncate(text: &str) -> String {
+    if text.chars().count() > REFERENCE_MAX_CHARS {
+        let mut s: String = text.chars().take(REFERENCE_MAX_CHARS).collect();
+        s.push_str("\n……（已截断）");
+        s
+    } else {
+        text.to_string()
+    }
+}
+
+/// 小剧场作为参考素材
+pub fn theater_reference(theater: &theater::Model) -> String {
+    let mut parts = vec![format!(
+        "小剧场《{}》（{}）",
+        theater.title, theater.category
+    )];
+    if !theater.description.trim().is_empty() {
+        parts.push(format!("简介：{}", theater.description.trim()));
+    }
+    parts.push(truncate(theater.content.trim()));
+    parts.join("\n")
+}
+
+/// 图库图片作为参考素材（使用标题、标签、绘图提示词与备注）
+pub fn image_reference(image: &image::Model) -> String {
+    let mut parts = vec![format!("角色形象参考图「{}」", image.title)];
+    let tags = draft_str_list(serde_json::from_str::<Value>(&image.tags).ok().as_ref());
+    if !tags.is_empty() {
+        parts.push(format!("标签：{}", tags.join("、")));
+    }
+    if let Some(prompt) = image.ai_prompt.as_deref().filter(|s| !s.trim().is_empty()) {
+        parts.push(format!("绘图提示词：{}", truncate(prompt.trim())));
+    }
+    if let Some(notes) = image.user_notes.as_deref().filter(|s| !s.trim().is_empty()) {
+        parts.push(format!("备注：{}", notes.trim()));
+    }
+    parts.join("\n")
+}
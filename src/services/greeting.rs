@@ -0,0 +1,343 @@
+//! AI 开场白生成
+//!
+//! 结合角色设定、已有开场白与选定的世界书条目批量生成备选开场白，
+//! 按字符二元组相似度与已有开场白及本批候选去重；采纳的开场白追加到 alternate_greetings
+
+use crate::entities::{ai_channel, character_card, character_versions};
+use crate::services::ai::AiError;
+use crate::services::prompt;
+use crate::services::structured::{self, Field, FieldKind, Schema};
+use sea_orm::{DatabaseConnection, DbErr};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+/// 单次最多生成条数
+const MAX_COUNT: u32 = 10;
+/// 默认相似度阈值（达到即视为重复）
+const DEFAULT_THRESHOLD: f64 = 0.6;
+/// 提示词中每条已有开场白保留的字符数
+const EXISTING_PREVIEW_CHARS: usize = 400;
+/// 提示词中每条世界书条目保留的字符数
+const ENTRY_MAX_CHARS: usize = 1500;
+
+/// 开场白输出结构
+pub const GREETINGS_SCHEMA: Schema = Schema {
+    name: "card_greetings",
+    fields: &[Field {
+        name: "greetings",
+        kind: FieldKind::StringArray,
+        required: true,
+    }],
+};
+
+/// 生成参数
+#[derive(Debug, Deserialize, Default)]
+pub struct GreetingOptions {
+    /// 生成条数，默认 3
+    pub count: Option<u32>,
+    /// 人称：first / second / third，或自定义描述
+    pub pov: Option<String>,
+    /// 篇幅：short / medium / long、目标字数，或自定义描述
+    pub length: Option<String>,
+    pub tone: Option<String>,
+    /// 情境种子
+    pub seed: Option<String>,
+    /// 参考的世界书条目（character_book.entries 中的下标）
+    #[serde(default)]
+    pub entries: Vec<usize>,
+    /// 相似度阈值 (0-1)，默认 0.6
+    pub threshold: Option<f64>,
+}
+
+/// 去重后保留的候选
+#[derive(Debug, Serialize)]
+pub struct GreetingCandidate {
+    pub content: String,
+    /// 与已有开场白的最高相似度
+    pub max_similarity: f64,
+}
+
+/// 因重复被丢弃的候选
+#[derive(Debug, Serialize)]
+pub struct DroppedGreeting {
+    pub content: String,
+    pub similarity: f64,
+    /// 与之重复的开场白（如「已有开场白 #2」「候选 #1」）
+    pub similar_to: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GreetingResult {
+    pub candidates: Vec<GreetingCandidate>,
+    pub dropped: Vec<DroppedGreeting>,
+    pub attempts: usize,
+}
+
+fn pov_label(pov: Option<&str>) -> String {
+    match pov.map(str::trim).unwrap_or("") {
+        "" | "third" => "第三人称（以「{{char}}」或「他/她」叙述）".to_string(),
+        "first" => "第一人称（以「我」叙述 {{char}} 的言行）".to_string(),
+        "second" => "第二人称（以「你」称呼 {{user}}）".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn length_label(length: Option<&str>) -> String {
+    let length = length.map(str::trim).unwrap_or("");
+    if let Ok(chars) = length.parse::<u32>() {
+        return format!("约 {} 字", chars.clamp(50, 3000));
+    }
+    match length {
+        "short" => "100-200 字".to_string(),
+        "" | "medium" => "300-500 字".to_string(),
+        "long" => "800-1200 字".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// 截断过长文本
+fn preview(text: &str, max_chars: usize) -> String {
+    if text.chars().count() > max_chars {
+        let mut s: String = text.chars().take(max_chars).collect();
+        s.push_str("……");
+        s
+    } else {
+        text.to_string()
+    }
+}
+
+/// 角色卡已有的开场白（first_mes + alternate_greetings）
+pub fn card_greetings(json: &Value) -> Vec<String> {
+    let data = json.get("data").filter(|d| d.is_object()).unwrap_or(json);
+    let mut greetings = Vec::new();
+    if let Some(first) = data["first_mes"].as_str().filter(|s| !s.trim().is_empty()) {
+        greetings.push(first.to_string());
+    }
+    for g in data["alternate_greetings"].as_array().into_iter().flatten() {
+        if let Some(g) = g.as_str().filter(|s| !s.trim().is_empty()) {
+            greetings.push(g.to_string());
+        }
+    }
+    greetings
+}
+
+/// 选定的世界书条目正文
+fn selected_entries(json: &Value, indices: &[usize]) -> Result<String, String> {
+    let data = json.get("data").filter(|d| d.is_object()).unwrap_or(json);
+    let entries = data["character_book"]["entries"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+
+    let mut parts = Vec::new();
+    for &index in indices {
+        let entry = entries
+            .get(index)
+            .ok_or_else(|| format!("世界书条目 #{} 不存在", index + 1))?;
+        let title = entry["comment"]
+            .as_str()
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("条目 #{}", index + 1));
+        let content = entry["content"].as_str().unwrap_or("");
+        parts.push(format!(
+            "### {}\n{}",
+            title,
+            preview(content, ENTRY_MAX_CHARS)
+        ));
+    }
+    Ok(parts.join("\n\n"))
+}
+
+/// 字符二元组集合（忽略空白、标点与大小写，兼容中文）
+fn bigrams(text: &str) -> HashSet<(char, char)> {
+    let chars: Vec<char> = text
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+    if chars.len() == 1 {
+        return HashSet::from([(chars[0], chars[0])]);
+    }
+    chars.windows(2).map(|w| (w[0], w[1])).collect()
+}
+
+/// 两段文本的相似度（字符二元组 Jaccard 系数，0-1）
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (bigrams(a), bigrams(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let intersection = a.intersection(&b).count();
+    let union = a.len() + b.len() - intersection;
+    intersection as f64 / union as f64
+}
+
+/// 将相似度保留两位小数
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// 按相似度去重：与已有开场白或本批更早的候选相似度达到阈值即丢弃
+pub fn dedupe(
+    generated: Vec<String>,
+    existing: &[String],
+    threshold: f64,
+) -> (Vec<GreetingCandidate>, Vec<DroppedGreeting>) {
+    let mut kept: Vec<GreetingCandidate> = Vec::new();
+    let mut dropped = Vec::new();
+
+    for content in generated {
+        let content = content.trim().to_string();
+        if content.is_empty() {
+            continue;
+        }
+
+        let (max_existing, existing_index) = existing
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (similarity(&content, e), i))
+            .fold(
+                (0.0, 0),
+                |best, cur| if cur.0 > best.0 { cur } else { best },
+            );
+        if max_existing >= threshold {
+            dropped.push(DroppedGreeting {
+                content,
+                similarity: round2(max_existing),
+                similar_to: format!("已有开场白 #{}", existing_index + 1),
+            });
+            continue;
+        }
+
+        let duplicate = kept
+            .iter()
+            .enumerate()
+            .map(|(i, k)| (similarity(&content, &k.content), i))
+            .find(|(score, _)| *score >= threshold);
+        if let Some((score, index)) = duplicate {
+            dropped.push(DroppedGreeting {
+                content,
+                similarity: round2(score),
+                similar_to: format!("候选 #{}", index + 1),
+            });
+            continue;
+        }
+
+        kept.push(GreetingCandidate {
+            content,
+            max_similarity: round2(max_existing),
+        });
+    }
+    (kept, dropped)
+}
+
+/// 为角色卡生成备选开场白候选（不写入角色卡）
+pub async fn generate(
+    db: &DatabaseConnection,
+    channel: &ai_channel::Model,
+    card: &character_card::Model,
+    options: &GreetingOptions,
+) -> Result<GreetingResult, AiError> {
+    let json: Value = serde_json::from_str(&card.data)
+        .map_err(|e| AiError::Config(format!("角色卡数据解析失败: {}", e)))?;
+    let existing = card_greetings(&json);
+    let count = options.count.unwrap_or(3).clamp(1, MAX_COUNT);
+    let threshold = options
+        .threshold
+        .unwrap_or(DEFAULT_THRESHOLD)
+        .clamp(0.1, 1.0);
+
+    let template = prompt::resolve_template(db, "greeting_generate")
+        .await?
+        .ok_or_else(|| AiError::Config("缺少开场白生成提示词模板".to_string()))?;
+    let mut vars = prompt::build_variables(db, "greeting_generate", Some(card)).await?;
+    vars.insert("count".into(), count.to_string());
+    vars.insert("pov".into(), pov_label(options.pov.as_deref()));
+    vars.insert("length".into(), length_label(options.length.as_deref()));
+    vars.insert("tone".into(), options.tone.clone().unwrap_or_default());
+    vars.insert("seed".into(), options.seed.clone().unwrap_or_default());
+    vars.insert(
+        "entries".into(),
+        selected_entries(&json, &options.entries).map_err(AiError::Config)?,
+    );
+    vars.insert(
+        "existing_greetings".into(),
+        existing
+            .iter()
+            .enumerate()
+            .map(|(i, g)| format!("#{}\n{}", i + 1, preview(g, EXISTING_PREVIEW_CHARS)))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+    );
+
+    let messages = prompt::render_messages(&template, &vars);
+    let completion = structured::complete_json(
+        channel,
+        &messages,
+        json!({"temperature": 0.9, "max_tokens": 8192}),
+        &GREETINGS_SCHEMA,
+        1,
+    )
+    .await?;
+
+    let generated: Vec<String> = completion.value["greetings"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|v| v.as_str().map(str::to_string))
+        .collect();
+    let (candidates, dropped) = dedupe(generated, &existing, threshold);
+
+    Ok(GreetingResult {
+        candidates,
+        dropped,
+        attempts: completion.attempts,
+    })
+}
+
+/// 追加采纳的开场白（写入前自动创建版本快照），返回更新后的角色卡、快照与实际追加条数
+///
+/// 与已有开场白完全相同的内容会被跳过；没有可追加的内容时不创建快照、不改写角色卡
+pub async fn append(
+    db: &DatabaseConnection,
+    card: &character_card::Model,
+    greetings: &[String],
+) -> Result<(character_card::Model, Option<character_versions::Model>, usize), DbErr> {
+    let mut json: Value =
+        serde_json::from_str(&card.data).map_err(|e| DbErr::Custom(e.to_string()))?;
+    let existing = card_greetings(&json);
+
+    let mut added: Vec<String> = Vec::new();
+    for greeting in greetings {
+        let greeting = greeting.trim();
+        if !greeting.is_empty()
+            && !existing.iter().any(|e| e.trim() == greeting)
+            && !added.iter().any(|a| a == greeting)
+        {
+            added.push(greeting.to_string());
+        }
+    }
+
+    if added.is_empty() {
+        return Ok((card.clone(), None, 0));
+    }
+
+    let version = super::card::snapshot_version(db, card, "追加 AI 开场白前自动快照").await?;
+
+    let target = if super::card::field_prefix(&json).is_empty() {
+        &mut json
+    } else {
+        &mut json["data"]
+    };
+    let mut alternate: Vec<Value> = target["alternate_greetings"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    alternate.extend(added.iter().map(|g| json!(g)));
+    target["alternate_greetings"] = Value::Array(alternate);
+
+    let updated = super::card::write_card_json(db, card, json).await?;
+    Ok((updated, Some(version), added.len()))
+}
@@ -0,0 +1,174 @@
+//! 后台任务注册表
+//!
+//! 批量 AI 任务在后台运行，进度保存在内存中供前端轮询（服务重启后丢失）
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+/// 保留的已结束任务数量
+const MAX_FINISHED_JOBS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: Uuid,
+    /// 任务类型，如 "batch_overview"
+    pub kind: String,
+    pub status: JobStatus,
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub message: String,
+    /// 失败明细（最多保留 100 条）
+    pub errors: Vec<String>,
+    /// 任务产出（如新建的角色卡 ID），由具体任务自行定义
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    pub cancel_requested: bool,
+    pub created_at: String,
+    pub finished_at: Option<String>,
+}
+
+static JOBS: Lazy<Arc<RwLock<HashMap<Uuid, JobInfo>>>> =
+    Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
+
+fn now() -> String {
+    chrono::Utc::now().to_rfc3339()
+}
+
+/// 登记新任务
+pub fn create(kind: &str, total: usize) -> Uuid {
+    let id = Uuid::new_v4();
+    let job = JobInfo {
+        id,
+        kind: kind.to_string(),
+        status: JobStatus::Running,
+        total,
+        completed: 0,
+        failed: 0,
+        message: "任务已开始".to_string(),
+        errors: Vec::new(),
+        result: None,
+        cancel_requested: false,
+        created_at: now(),
+        finished_at: None,
+    };
+
+    let mut jobs = JOBS.write().unwrap();
+    prune(&mut jobs);
+    jobs.insert(id, job);
+    id
+}
+
+/// 清理过旧的已结束任务
+fn prune(jobs: &mut HashMap<Uuid, JobInfo>) {
+    let mut finished: Vec<(String, Uuid)> = jobs
+        .values()
+        .filter(|j| j.status != JobStatus::Running)
+        .map(|j| (j.created_at.clone(), j.id))
+        .collect();
+    if finished.len() < MAX_FINISHED_JOBS {
+        return;
+    }
+    finished.sort();
+    for (_, id) in finished.iter().take(finished.len() + 1 - MAX_FINISHED_JOBS) {
+        jobs.remove(id);
+    }
+}
+
+pub fn get(id: Uuid) -> Option<JobInfo> {
+    JOBS.read().unwrap().get(&id).cloned()
+}
+
+/// 所有任务（最新在前）
+pub fn list() -> Vec<JobInfo> {
+    let mut jobs: Vec<JobInfo> = JOBS.read().unwrap().values().cloned().collect();
+    jobs.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    jobs
+}
+
+fn update(id: Uuid, f: impl FnOnce(&mut JobInfo)) {
+    if let Some(job) = JOBS.write().unwrap().get_mut(&id) {
+        f(job);
+    }
+}
+
+pub fn set_message(id: Uuid, message: impl Into<String>) {
+    let message = message.into();
+    update(id, |job| job.message = message);
+}
+
+/// 记录一项成功
+pub fn item_done(id: Uuid) {
+    update(id, |job| job.completed += 1);
+}
+
+/// 记录一项失败
+pub fn item_failed(id: Uuid, error: impl Into<String>) {
+    let error = error.into();
+    update(id, |job| {
+        job.failed += 1;
+        if job.errors.len() < 100 {
+            job.errors.push(error);
+        }
+    });
+}
+
+pub fn set_result(id: Uuid, result: serde_json::Value) {
+    update(id, |job| job.result = Some(result));
+}
+
+/// 结束任务（已请求取消的任务标记为 cancelled）
+pub fn finish(id: Uuid, message: impl Into<String>) {
+    let message = message.into();
+    update(id, |job| {
+        job.status = if job.cancel_requested {
+            JobStatus::Cancelled
+        } else {
+            JobStatus::Completed
+        };
+        job.message = message;
+        job.finished_at = Some(now());
+    });
+}
+
+pub fn fail(id: Uuid, message: impl Into<String>) {
+    let message = message.into();
+    update(id, |job| {
+        job.status = JobStatus::Failed;
+        job.message = message;
+        job.finished_at = Some(now());
+    });
+}
+
+/// 请求取消，返回任务是否存在且仍在运行
+pub fn request_cancel(id: Uuid) -> bool {
+    let mut jobs = JOBS.write().unwrap();
+    match jobs.get_mut(&id) {
+        Some(job) if job.status == JobStatus::Running => {
+            job.cancel_requested = true;
+            job.message = "正在取消...".to_string();
+            true
+        }
+        _ => false,
+    }
+}
+
+pub fn is_cancelled(id: Uuid) -> bool {
+    JOBS.read()
+        .unwrap()
+        .get(&id)
+        .map(|j| j.cancel_requested)
+        .unwrap_or(true)
+}
@@ -0,0 +1,399 @@
+//! 离线模拟 AI 渠道
+//!
+//! Base URL 以 `mock://` 开头的渠道不会发出网络请求，而是由本模块返回 OpenAI 兼容的响应，
+//! 便于在没有真实模型时调试各 AI 功能：
+//! - `mock://` / `mock://auto`：按请求内容规则应答（概览 JSON、开场白、小皮医生多轮、向量、纯文本回显）
+//! - `mock://error`：HTTP 500；`mock://rate_limit`：HTTP 429
+//! - `mock://empty`：返回空内容；`mock://malformed`：返回被截断的 JSON 内容
+//! - `mock://broken`：HTTP 200 但响应体不是 JSON
+//! - `mock://script/<name>`：按顺序回放 `data/mock/<name>.json` 中的脚本步骤（用完后重复最后一步）；
+//!   保存渠道或修改脚本文件后从第一步重新开始
+//!
+//! 脚本文件为数组，每步可包含 `status`、`content`（模型回复文本）、`error`（错误信息）
+//! 或 `raw`（原样返回的响应体）
+//!
+//! 请求体中 `stream: true` 时以 SSE 分块返回
+
+use crate::entities::ai_channel;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+const SCHEME: &str = "mock://";
+/// 模拟向量维度
+const EMBEDDING_DIMS: usize = 64;
+/// 流式响应每块的字符数
+const STREAM_CHUNK_CHARS: usize = 16;
+
+/// 脚本回放进度（按 Base URL 区分）：(脚本文件修改时间, 下一步序号)
+type ScriptCursor = (Option<SystemTime>, usize);
+static SCRIPT_CURSORS: Lazy<Mutex<HashMap<String, ScriptCursor>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 是否为模拟渠道地址
+pub fn is_mock(base_url: &str) -> bool {
+    base_url.trim().starts_with(SCHEME)
+}
+
+/// 重置脚本回放进度（创建或修改渠道时调用）
+pub fn reset_script(base_url: &str) {
+    if is_mock(base_url) {
+        SCRIPT_CURSORS.lock().unwrap().remove(base_url.trim());
+    }
+}
+
+fn scenario(base_url: &str) -> &str {
+    base_url
+        .trim()
+        .trim_start_matches(SCHEME)
+        .trim_end_matches('/')
+}
+
+/// 脚本中的一步
+#[derive(Deserialize, Default)]
+struct ScriptStep {
+    #[serde(default)]
+    status: Option<u16>,
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    raw: Option<String>,
+}
+
+/// 模拟应答（尚未包装为 HTTP 响应）
+enum Reply {
+    Content(String),
+    Error(u16, String),
+    Raw(String),
+    Json(Value),
+}
+
+/// 模拟 /models 列表
+pub fn models(base_url: &str) -> Value {
+    let scenario_model = format!("mock-{}", scenario(base_url).replace('/', "-"));
+    json!({
+        "object": "list",
+        "data": [
+            {"id": "mock-chat", "object": "model", "owned_by": "piney"},
+            {"id": "mock-embedding", "object": "model", "owned_by": "piney"},
+            {"id": scenario_model, "object": "model", "owned_by": "piney"}
+        ]
+    })
+}
+
+/// 生成模拟响应
+pub fn respond(channel: &ai_channel::Model, path: &str, body: &Value) -> reqwest::Response {
+    let reply = match path {
+        "models" => Reply::Json(models(&channel.base_url)),
+        "embeddings" => Reply::Json(embeddings(body)),
+        _ => chat_reply(&channel.base_url, body),
+    };
+
+    let stream = body["stream"].as_bool().unwrap_or(false);
+    let (status, content_type, text) = match reply {
+        Reply::Content(content) if stream => (200, "text/event-stream", sse_body(&content)),
+        Reply::Content(content) => (
+            200,
+            "application/json",
+            completion_json(&content, &channel.model_id).to_string(),
+        ),
+        Reply::Json(value) => (200, "application/json", value.to_string()),
+        Reply::Raw(raw) => (200, "text/plain", raw),
+        Reply::Error(status, message) => (
+            status,
+            "application/json",
+            json!({"error": {"message": message, "type": "mock_error"}}).to_string(),
+        ),
+    };
+
+    // 脚本中的状态码已在加载时校验，这里仍兜底为 500，避免构建响应失败
+    let status = axum::http::StatusCode::from_u16(status)
+        .unwrap_or(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+    let res = axum::http::Response::builder()
+        .status(status)
+        .header("content-type", content_type)
+        .body(text)
+        .expect("模拟响应构建失败");
+    reqwest::Response::from(res)
+}
+
+fn chat_reply(base_url: &str, body: &Value) -> Reply {
+    let scenario = scenario(base_url);
+    match scenario {
+        "error" => Reply::Error(500, "模拟服务内部错误".to_string()),
+        "rate_limit" => Reply::Error(429, "模拟限流：请求过于频繁".to_string()),
+        "empty" => Reply::Content(String::new()),
+        "malformed" => Reply::Content(
+            "好的，以下是结果：\n```json\n{\"summary\": \"模拟内容\", \"tags\": [\"模拟\","
+                .to_string(),
+        ),
+        "broken" => Reply::Raw("<html>502 Bad Gateway</html>".to_string()),
+        _ => match scenario.strip_prefix("script/") {
+            Some(name) => script_reply(base_url, name),
+            None => auto_reply(body),
+        },
+    }
+}
+
+/// 脚本名只允许字母、数字、`_` 与 `-`，防止通过 `../` 读取 mock 目录之外的文件
+fn is_valid_script_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// 回放脚本中的下一步
+fn script_reply(base_url: &str, name: &str) -> Reply {
+    if !is_valid_script_name(name) {
+        return Reply::Error(
+            400,
+            format!("模拟脚本名无效: {}（只允许字母、数字、_ 与 -）", name),
+        );
+    }
+    let path = crate::utils::paths::get_data_path("mock").join(format!("{}.json", name));
+    let steps: Vec<ScriptStep> = match std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| serde_json::from_str(&text).map_err(|e| e.to_string()))
+    {
+        Ok(steps) => steps,
+        Err(e) => return Reply::Error(500, format!("读取模拟脚本 {:?} 失败: {}", path, e)),
+    };
+    if steps.is_empty() {
+        return Reply::Error(500, format!("模拟脚本 {:?} 为空", path));
+    }
+    if let Some((index, status)) = steps.iter().enumerate().find_map(|(i, step)| {
+        step.status
+            .filter(|s| axum::http::StatusCode::from_u16(*s).is_err())
+            .map(|s| (i, s))
+    }) {
+        return Reply::Error(
+            400,
+            format!(
+                "模拟脚本 {:?} 第 {} 步的状态码无效: {}",
+                path,
+                index + 1,
+                status
+            ),
+        );
+    }
+
+    let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+    let index = {
+        let mut cursors = SCRIPT_CURSORS.lock().unwrap();
+        let cursor = cursors
+            .entry(base_url.trim().to_string())
+            .or_insert((modified, 0));
+        // 脚本文件被修改后从头回放
+        if cursor.0 != modified {
+            *cursor = (modified, 0);
+        }
+        let index = cursor.1.min(steps.len() - 1);
+        cursor.1 += 1;
+        index
+    };
+
+    let step = steps.into_iter().nth(index).unwrap_or_default();
+    if let Some(raw) = step.raw {
+        return Reply::Raw(raw);
+    }
+    match step.status.unwrap_or(200) {
+        200..=299 => Reply::Content(step.content.unwrap_or_default()),
+        status => Reply::Error(
+            status,
+            step.error.unwrap_or_else(|| "模拟脚本错误".to_string()),
+        ),
+    }
+}
+
+/// 所有消息的文本内容（兼容多模态内容块）
+fn message_texts(body: &Value) -> Vec<(String, String)> {
+    body["messages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|m| {
+            let role = m["role"].as_str().unwrap_or("").to_string();
+            let text = match &m["content"] {
+                Value::String(s) => s.clone(),
+                Value::Array(parts) => parts
+                    .iter()
+                    .filter_map(|p| p["text"].as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                _ => String::new(),
+            };
+            (role, text)
+        })
+        .collect()
+}
+
+/// 按请求内容规则应答
+fn auto_reply(body: &Value) -> Reply {
+    let messages = message_texts(body);
+    let all_text: String = messages
+        .iter()
+        .map(|(_, t)| t.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let assistant_turns = messages.iter().filter(|(r, _)| r == "assistant").count();
+    let last_user = messages
+        .iter()
+        .rev()
+        .find(|(r, _)| r == "user")
+        .map(|(_, t)| t.clone())
+        .unwrap_or_default();
+
+    // 小皮医生：首轮申请阅读条目，之后输出诊断报告
+    if all_text.contains("request_entries") {
+        if assistant_turns == 0 {
+            return Reply::Content(
+                json!({"action": "request_entries", "entries": first_bracketed(&all_text)})
+                    .to_string(),
+            );
+        }
+        return Reply::Content(
+            json!({
+                "action": "final_report",
+                "report": {
+                    "core_assessment": "（模拟）角色设定整体完整。",
+                    "dimensions": [
+                        {"name": "一致性", "score": 8, "comment": "（模拟）未发现明显矛盾。"}
+                    ],
+                    "prescriptions": [],
+                    "conclusion": "（模拟）诊断完成。"
+                }
+            })
+            .to_string(),
+        );
+    }
+
+    let json_mode = body["response_format"].is_object()
+        || all_text.contains("JSON")
+        || all_text.contains("json");
+    if json_mode && all_text.contains("summary") && all_text.contains("tags") {
+        return Reply::Content(
+            json!({"summary": "（模拟）这是一段自动生成的角色概览。", "tags": ["模拟", "测试"]})
+                .to_string(),
+        );
+    }
+    if json_mode && all_text.contains("greetings") {
+        return Reply::Content(
+            json!({"greetings": [
+                "（模拟）清晨的雨刚停，{{char}} 推开门，冲 {{user}} 挥了挥手。",
+                "（模拟）深夜的图书馆里只剩两盏灯，{{char}} 合上书，抬头看向 {{user}}。",
+                "（模拟）集市上人声鼎沸，{{char}} 举着两串糖葫芦挤到 {{user}} 面前。"
+            ]})
+            .to_string(),
+        );
+    }
+    if json_mode {
+        return Reply::Content(
+            json!({"mock": true, "echo": last_user.chars().take(200).collect::<String>()})
+                .to_string(),
+        );
+    }
+
+    Reply::Content(format!(
+        "（模拟回复）{}",
+        last_user.chars().take(200).collect::<String>()
+    ))
+}
+
+/// 取文本中第一个「」或 [] 包裹的名称，用于模拟申请条目
+fn first_bracketed(text: &str) -> Vec<String> {
+    for (open, close) in [('「', '」'), ('[', ']')] {
+        if let Some(start) = text.find(open) {
+            let rest = &text[start + open.len_utf8()..];
+            if let Some(end) = rest.find(close) {
+                let name = rest[..end].trim();
+                if !name.is_empty() && name.chars().count() <= 40 {
+                    return vec![name.to_string()];
+                }
+            }
+        }
+    }
+    Vec::new()
+}
+
+/// 由文本哈希生成确定性的单位向量
+fn embed(text: &str) -> Vec<f32> {
+    let mut vector = Vec::with_capacity(EMBEDDING_DIMS);
+    let mut round = 0u32;
+    while vector.len() < EMBEDDING_DIMS {
+        let mut hasher = Sha256::new();
+        hasher.update(round.to_le_bytes());
+        hasher.update(text.as_bytes());
+        for byte in hasher.finalize() {
+            if vector.len() < EMBEDDING_DIMS {
+                vector.push(byte as f32 / 127.5 - 1.0);
+            }
+        }
+        round += 1;
+    }
+    let norm = vector
+        .iter()
+        .map(|v| v * v)
+        .sum::<f32>()
+        .sqrt()
+        .max(f32::EPSILON);
+    vector.iter().map(|v| v / norm).collect()
+}
+
+fn embeddings(body: &Value) -> Value {
+    let inputs: Vec<String> = match &body["input"] {
+        Value::String(s) => vec![s.clone()],
+        Value::Array(arr) => arr
+            .iter()
+            .map(|v| v.as_str().unwrap_or("").to_string())
+            .collect(),
+        _ => Vec::new(),
+    };
+    let data: Vec<Value> = inputs
+        .iter()
+        .enumerate()
+        .map(|(i, text)| json!({"object": "embedding", "index": i, "embedding": embed(text)}))
+        .collect();
+    json!({"object": "list", "data": data, "model": body["model"]})
+}
+
+fn completion_json(content: &str, model: &str) -> Value {
+    json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion",
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": content},
+            "finish_reason": "stop"
+        }],
+        "usage": {
+            "prompt_tokens": 0,
+            "completion_tokens": content.chars().count(),
+            "total_tokens": content.chars().count()
+        }
+    })
+}
+
+/// 将回复切分为 SSE 增量
+fn sse_body(content: &str) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let mut out = String::new();
+    for chunk in chars.chunks(STREAM_CHUNK_CHARS) {
+        let delta: String = chunk.iter().collect();
+        let event = json!({
+            "id": "chatcmpl-mock",
+            "object": "chat.completion.chunk",
+            "choices": [{"index": 0, "delta": {"content": delta}, "finish_reason": null}]
+        });
+        out.push_str(&format!("data: {}\n\n", event));
+    }
+    out.push_str("data: [DONE]\n\n");
+    out
+}
@@ -1,3 +1,28 @@
 //! 服务层模块入口
 //!
-//! 提供业务逻辑实现 (目前为空，后续功能实现时添加)
+//! 提供跨 API 复用的业务逻辑实现
+
+pub mod ai;
+pub mod card;
+pub mod channel_health;
+pub mod chat_branch;
+pub mod chat_edit;
+pub mod chat_export;
+pub mod chat_floor;
+pub mod chat_import;
+pub mod chat_index;
+pub mod chat_memory;
+pub mod chat_search;
+pub mod chat_stats;
+pub mod doctor;
+pub mod embedding;
+pub mod generate;
+pub mod greeting;
+pub mod jobs;
+pub mod mock_ai;
+pub mod prompt;
+pub mod st_chat;
+pub mod st_regex;
+pub mod structured;
+pub mod translate;
+pub mod vision;
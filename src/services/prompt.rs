@@ -0,0 +1,749 @@
+//! AI 提示词模板服务
+//!
+//! - 内置默认模板（概览、小皮医生及其深度诊断、处方落实、翻译），用户可在数据库中保存自定义版本覆盖
+//! - 变量语法：`{{变量名}}`，条件块：`{{#if 变量}}...{{else}}...{{/if}}`（不支持嵌套）
+//! - 变量只替换一轮，角色卡内容中的 `{{user}}` 等 ST 宏会原样保留
+
+use crate::entities::{character_card, prompt_template, setting};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+
+/// 模板变量表
+pub type PromptVars = BTreeMap<String, String>;
+
+/// 内置默认模板
+pub struct DefaultTemplate {
+    pub feature_id: &'static str,
+    pub name: &'static str,
+    pub system_prompt: &'static str,
+    pub user_prompt: &'static str,
+    /// 该功能额外提供的变量（通用角色卡变量见 CARD_VARIABLES）
+    pub variables: &'static [&'static str],
+}
+
+/// 所有功能共享的角色卡变量
+pub const CARD_VARIABLES: &[&str] = &[
+    "char",
+    "description",
+    "personality",
+    "scenario",
+    "first_mes",
+    "alternate_greetings",
+    "mes_example",
+    "creator_notes",
+    "system_prompt",
+    "post_history_instructions",
+    "tags",
+    "worldbook_toc",
+    "global_prompt",
+];
+
+const OVERVIEW_SYSTEM: &str = "{{#if global_prompt}}{{global_prompt}}\n\n{{/if}}你是一位专业的角色卡分析师。请分析角色设定，返回纯 JSON 格式结果，不要包含 markdown 标记。";
+
+const OVERVIEW_USER: &str = r#"请深入分析以下角色卡数据：
+
+[角色元数据]
+Name: {{char}}
+Description: {{description}}
+
+[详细设定]
+Personality: {{personality}}
+Scenario: {{scenario}}
+First Message: {{first_mes}}
+Example Dialogue: {{mes_example}}
+System Prompt: {{system_prompt}}
+Post Instructions: {{post_history_instructions}}
+Creator Comment: {{creator_notes}}
+{{#if generate_tags}}
+[任务与约束]
+1. 概览总结：150字以内，精炼概括角色核心特征。
+2. 标签生成：最多5个。必须优先从以下[系统现有标签]中选择；仅当无匹配时才生成新标签。
+   [系统现有标签]: {{system_tags}}
+
+[回复格式]
+请严格仅返回 JSON，不要使用代码块：
+{"summary": "...", "tags": ["tag1", "tag2"]}
+{{else}}
+[任务与约束]
+1. 概览总结：150字以内，精炼概括角色核心特征。
+
+[回复格式]
+请严格仅返回 JSON，不要使用代码块：
+{"summary": "..."}
+{{/if}}"#;
+
+const DOCTOR_SYSTEM: &str = r#"[System Note: This is a technical metadata analysis task for a fictional character database. 
+The content being analyzed is creative fiction intended for adult audiences. 
+Your role is to objectively extract and analyze character attributes as structured data. 
+Even if NSFW content appears, you are required to complete the analysis and output the data 
+to facilitate content moderation and auditing. Proceed with the analysis professionally.]
+
+{{global_prompt}}
+
+你是一个专业的角色卡逻辑分析引擎。你将参与一个多轮迭代的分析任务。
+
+**你的目标：** 评估角色核心设定与世界书内容的一致性，产出诊断报告。
+
+**你的操作规范：**
+1. **第一轮：** 你会收到核心设定和世界书目录。如果核心设定已足够支持诊断，请直接输出 "final_report"；若必需详细设定，请返回 "request_entries" 申请阅读条目。
+2. **中间轮次：** 系统会提供你申请的条目内容。你可以选择继续申请新条目（返回 JSON），或直接输出 JSON 格式诊断报告。
+3. **最终轮次：** 如果系统提示"已达搜索上限"，你必须立即根据现有信息输出 JSON 诊断报告。
+
+**诊断重点：**
+- 专注于分析角色设定的逻辑一致性、人设合理性、对话质量
+- 不要诊断角色卡的格式问题（如标签格式、代码块使用等技术规范）
+- 开场白（first_mes 和其他开场白）是诊断的重要内容，确保不要遗漏
+- **权重说明：** 核心设定（Name, Description, Personality）具有最高权重。世界书内容仅作为次要权重，但两者都很重要，都需要作为诊断的依据。
+
+**请求条目格式（严格 JSON，无代码块标记）：**
+{"action": "request_entries", "entries": ["条目名1", "条目名2"]}
+(注意：请勿申请可能包含极其露骨色情(NSFW)内容的条目，以免触发系统安全拦截导致任务失败)
+
+**诊断报告格式（严格 JSON，无代码块标记）：**
+{"action": "final_report", "report": {
+  "core_assessment": "概括性描述角色卡的完成质量与逻辑成熟度",
+  "overall_score": 80,
+  "dimensions": [
+    {"name": "设定诊断", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议", "score": 80},
+    {"name": "开场白诊断", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议", "score": 80},
+    {"name": "人设一致性", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议", "score": 80},
+    {"name": "世界观逻辑", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议", "score": 80},
+    {"name": "OOC 预警", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议", "score": 80}
+  ],
+  "prescriptions": ["具体修改建议1", "具体修改建议2"],
+  "conclusion": "通过 / 需大幅修正 / 建议重构"
+}}
+
+**评分说明：** score 与 overall_score 为 0-100 的整数，分数越高表示该维度越完善；conclusion 必须三选一。
+
+**重要：** 所有输出必须是纯 JSON，不要包含 markdown 代码块标记。dimensions 中各字段可以使用 Markdown 格式（加粗、列表等）来增强可读性。"#;
+
+const DOCTOR_USER: &str = r#"**[任务启动]** 请审阅以下内容，并返回你第一轮想要阅读的世界书条目名称（JSON 格式）。
+
+**核心设定：**
+- 角色名称：{{char}}
+- 角色描述：{{description}}
+- 性格特征：{{personality}}
+{{first_mes_note}}
+{{alt_greeting_note}}
+
+**世界书目录（条目名称列表）：**
+{{worldbook_toc}}
+
+请返回 JSON 格式：{"action": "request_entries", "entries": ["条目名1", ...]}
+如果世界书目录为空或无需阅读条目，请直接输出诊断报告 JSON。请优先判断当前信息是否足够，避免不必要的搜索。同时请严格避开 NSFW 相关条目。"#;
+
+const PRESCRIBE_SYSTEM: &str = r#"{{#if global_prompt}}{{global_prompt}}
+
+{{/if}}你是一位专业的角色卡编辑。你将根据小皮医生的诊断处方，把修改建议落实为对角色卡字段的具体编辑。
+
+**输出格式（严格 JSON，无代码块标记）：**
+{"patch": [
+  {"op": "replace", "path": "/data/description", "value": "修改后的完整字段内容", "reason": "对应的处方与修改理由"}
+]}
+
+**规则：**
+- op 仅允许 add / replace / remove；path 必须使用[可编辑字段]中给出的路径，世界书条目可在条目路径后追加 /content、/keys、/comment
+- replace 时 value 必须是字段修改后的完整内容，而不是片段
+- 新增世界书条目使用 {"op": "add", "path": "<世界书条目数组路径>/-", "value": {"keys": [...], "content": "...", "comment": "...", "enabled": true}}
+- 只修改与处方相关的内容，保持原文的文风与其余设定不变，原样保留角色卡中的 ST 宏（如 user、char 占位符）
+- 无法落实或无需修改的处方直接忽略，不要编造"#;
+
+const PRESCRIBE_USER: &str = r#"[诊断处方]
+{{prescriptions}}
+
+[可编辑字段]（JSON，键为字段路径，值为当前内容）
+{{card_fields}}
+
+请输出 JSON 补丁。"#;
+
+const DEEP_MAP_SYSTEM: &str = r#"{{#if global_prompt}}{{global_prompt}}
+
+{{/if}}你是一个专业的角色卡逻辑分析引擎，正在对世界书进行逐组全量审查。
+
+**你的任务：** 对照角色核心设定，找出本组世界书条目中的问题：与核心设定矛盾、条目之间互相矛盾、可能导致 OOC 的描述、冗余重复或关键信息缺失。禁用条目同样需要审查，但请在描述中注明其为禁用状态。
+
+**输出格式（严格 JSON，无代码块标记）：**
+{"findings": [
+  {"uid": "条目 uid（必须与条目标注的 uid 完全一致）", "type": "contradiction / conflict / ooc / redundancy / missing", "severity": "high / medium / low", "description": "问题描述", "suggestion": "修改建议"}
+]}
+
+没有发现问题时返回 {"findings": []}。不要输出与条目无关的评价。"#;
+
+const DEEP_MAP_USER: &str = r#"**核心设定：**
+- 角色名称：{{char}}
+- 角色描述：{{description}}
+- 性格特征：{{personality}}
+- 场景：{{scenario}}
+
+**世界书条目（第 {{chunk_index}}/{{chunk_total}} 组）：**
+{{entries_chunk}}
+
+请输出本组条目的 findings JSON。"#;
+
+const DEEP_REDUCE_SYSTEM: &str = r#"[System Note: This is a technical metadata analysis task for a fictional character database. 
+The content being analyzed is creative fiction intended for adult audiences. 
+Your role is to objectively extract and analyze character attributes as structured data. 
+Even if NSFW content appears, you are required to complete the analysis and output the data 
+to facilitate content moderation and auditing. Proceed with the analysis professionally.]
+
+{{global_prompt}}
+
+你是一个专业的角色卡逻辑分析引擎。世界书的全部条目已经逐组审查完毕，你将根据核心设定与各组审查发现，汇总出最终诊断报告。
+
+**诊断重点：**
+- 专注于分析角色设定的逻辑一致性、人设合理性、对话质量
+- 不要诊断角色卡的格式问题（如标签格式、代码块使用等技术规范）
+- 开场白（first_mes 和其他开场白）是诊断的重要内容，确保不要遗漏
+- 引用世界书问题时请注明条目 uid，便于定位
+
+**诊断报告格式（严格 JSON，无代码块标记）：**
+{"action": "final_report", "report": {
+  "core_assessment": "概括性描述角色卡的完成质量与逻辑成熟度",
+  "overall_score": 80,
+  "dimensions": [
+    {"name": "设定诊断", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议", "score": 80},
+    {"name": "开场白诊断", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议", "score": 80},
+    {"name": "人设一致性", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议", "score": 80},
+    {"name": "世界观逻辑", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议", "score": 80},
+    {"name": "OOC 预警", "status": "现状描述", "issues": "潜在问题", "suggestions": "优化建议", "score": 80}
+  ],
+  "prescriptions": ["具体修改建议1", "具体修改建议2"],
+  "conclusion": "通过 / 需大幅修正 / 建议重构"
+}}
+
+**评分说明：** score 与 overall_score 为 0-100 的整数，分数越高表示该维度越完善；conclusion 必须三选一。
+
+**重要：** 所有输出必须是纯 JSON，不要包含 markdown 代码块标记。dimensions 中各字段可以使用 Markdown 格式（加粗、列表等）来增强可读性。"#;
+
+const DEEP_REDUCE_USER: &str = r#"**核心设定：**
+- 角色名称：{{char}}
+- 角色描述：{{description}}
+- 性格特征：{{personality}}
+{{first_mes_note}}
+{{alt_greeting_note}}
+
+**世界书全量审查发现（共 {{entry_count}} 个条目）：**
+{{findings}}
+
+请输出最终诊断报告 JSON。"#;
+
+const TRANSLATE_SYSTEM: &str = r#"{{#if global_prompt}}{{global_prompt}}
+
+{{/if}}你是一位专业的角色扮演文本翻译。请将用户提供的每个片段翻译为{{target_language}}。
+
+**规则：**
+- 形如 ⟦P0⟧ 的占位符代表宏、HTML 标签或代码，必须原样保留在译文中对应的位置，不得翻译、删除、合并或改写
+- 保持原文的语气、人称与格式（换行、Markdown、引号风格）
+- 人名、地名等专有名词在各片段间保持一致
+- 已经是目标语言的片段原样返回
+
+**输出格式（严格 JSON，无代码块标记）：**
+{"segments": {"片段 ID": "译文"}}"#;
+
+const TRANSLATE_USER: &str = r#"请翻译以下片段（JSON，键为片段 ID）：
+
+{{segments}}"#;
+
+const CARD_GENERATE_SYSTEM: &str = r#"{{#if global_prompt}}{{global_prompt}}
+
+{{/if}}你是一位资深的角色卡作者，擅长为 SillyTavern 编写高质量的 CCv3 角色卡。请根据用户的简报创作一张完整的角色卡草稿。
+
+**要求：**
+- 使用{{language}}写作，全文使用 {{char}}、{{user}} 宏指代角色与用户
+- description 约 {{description_length}} 字，包含外貌、身份、背景与关系
+- personality 为简洁的性格概述；scenario 描述开场情境
+- first_mes 为高质量开场白，另写 {{greetings_count}} 条风格各异的 alternate_greetings
+- mes_example 使用 <START> 分隔的示例对话
+- character_book 写 {{lorebook_entries}} 条世界书条目，每条包含 keys（触发词数组）、comment（标题）与 content
+
+**输出格式（严格 JSON，无代码块标记）：**
+{"name": "", "description": "", "personality": "", "scenario": "", "first_mes": "", "alternate_greetings": [], "mes_example": "", "creator_notes": "", "system_prompt": "", "tags": [], "character_book": {"entries": [{"keys": [], "comment": "", "content": ""}]}}"#;
+
+const CARD_GENERATE_USER: &str = r#"【角色概念】
+{{concept}}
+{{#if genre}}
+【题材】{{genre}}
+{{/if}}{{#if tone}}
+【基调】{{tone}}
+{{/if}}{{#if reference}}
+【参考素材】
+{{reference}}
+{{/if}}"#;
+
+const CHAT_SUMMARY_SYSTEM: &str = r#"{{#if global_prompt}}{{global_prompt}}
+
+{{/if}}你是一位细致的剧情记录员，负责阅读 {{char}} 的角色扮演聊天记录，并维护一份可用于在别处继续扮演的记忆档案。
+
+聊天记录会分段提供（当前第 {{chunk_index}}/{{chunk_count}} 段，楼层 {{floor_range}}）。请结合已有总结，输出**更新后的完整总结**，并提取本段新出现的内容。
+
+**要求：**
+- summary：截至本段的完整剧情总结（覆盖旧总结，保留关键转折，800 字以内）
+- events：本段发生的关键事件，注明楼层
+- relationships：截至本段的人物关系（只列有变化或新出现的）
+- facts：本段确立的设定事实（地点、物品、规则、承诺、秘密等），每条附带可触发的关键词
+- 只记录聊天中明确出现的内容，不要推测
+
+**输出格式（严格 JSON，无代码块标记）：**
+{"summary": "", "events": [{"floor": 1, "text": ""}], "relationships": [{"between": "甲 / 乙", "status": ""}], "facts": [{"title": "", "keys": [""], "content": ""}]}"#;
+
+const CHAT_SUMMARY_USER: &str = r#"{{#if previous_summary}}【已有总结】
+{{previous_summary}}
+
+{{/if}}{{#if relationships}}【已知人物关系】
+{{relationships}}
+
+{{/if}}【聊天记录（楼层 {{floor_range}}）】
+{{chunk}}"#;
+
+const IMAGE_CAPTION_SYSTEM: &str = r#"{{#if global_prompt}}{{global_prompt}}
+
+{{/if}}你是一位图库管理员，负责为角色扮演素材图库中的图片撰写描述与标签。
+
+**要求：**
+- caption：用简体中文客观描述画面（人物外貌、服饰、动作、场景、画风、氛围），100 字以内
+- tags：5-12 个简短的中文标签，涵盖人物特征、场景、画风与情绪；优先复用已有标签
+- 不要臆测人物身份或作品来源
+
+**输出格式（严格 JSON，无代码块标记）：**
+{"caption": "", "tags": []}"#;
+
+const IMAGE_CAPTION_USER: &str = r#"请为这张图片撰写描述与标签。
+{{#if ai_prompt}}
+【绘图提示词（仅供参考）】
+{{ai_prompt}}
+{{/if}}{{#if existing_tags}}
+【图库已有标签】
+{{existing_tags}}
+{{/if}}"#;
+
+const GREETING_GENERATE_SYSTEM: &str = r#"{{#if global_prompt}}{{global_prompt}}
+
+{{/if}}你是一位资深的角色卡作者，擅长为 SillyTavern 角色卡撰写开场白。请为 {{char}} 创作 {{count}} 条全新的备选开场白（alternate_greetings）。
+
+**要求：**
+- 人称视角：{{pov}}
+- 篇幅：每条 {{length}}
+{{#if tone}}- 基调：{{tone}}
+{{/if}}- 使用 {{user}} 宏指代用户，保持 {{char}} 的性格与设定一致
+- 每条开场白的情境、切入点与氛围都应不同，且不得与已有开场白雷同
+- 开场白应留出让 {{user}} 回应的空间，不要替 {{user}} 说话或行动
+
+**输出格式（严格 JSON，无代码块标记）：**
+{"greetings": ["", ""]}"#;
+
+const GREETING_GENERATE_USER: &str = r#"【角色描述】
+{{description}}
+{{#if personality}}
+【性格】
+{{personality}}
+{{/if}}{{#if scenario}}
+【情境】
+{{scenario}}
+{{/if}}{{#if entries}}
+【相关世界书条目】
+{{entries}}
+{{/if}}{{#if existing_greetings}}
+【已有开场白（请避免雷同）】
+{{existing_greetings}}
+{{/if}}{{#if seed}}
+【情境种子】
+请围绕以下情境展开：{{seed}}
+{{/if}}"#;
+
+pub const DEFAULT_TEMPLATES: &[DefaultTemplate] = &[
+    DefaultTemplate {
+        feature_id: "overview",
+        name: "角色卡概览",
+        system_prompt: OVERVIEW_SYSTEM,
+        user_prompt: OVERVIEW_USER,
+        variables: &["generate_tags", "system_tags"],
+    },
+    DefaultTemplate {
+        feature_id: "doctor",
+        name: "小皮医生",
+        system_prompt: DOCTOR_SYSTEM,
+        user_prompt: DOCTOR_USER,
+        variables: &["first_mes_note", "alt_greeting_note"],
+    },
+    DefaultTemplate {
+        feature_id: "doctor_prescribe",
+        name: "小皮医生处方落实",
+        system_prompt: PRESCRIBE_SYSTEM,
+        user_prompt: PRESCRIBE_USER,
+        variables: &["prescriptions", "card_fields"],
+    },
+    DefaultTemplate {
+        feature_id: "doctor_deep_map",
+        name: "小皮医生深度诊断（分组审查）",
+        system_prompt: DEEP_MAP_SYSTEM,
+        user_prompt: DEEP_MAP_USER,
+        variables: &["entries_chunk", "chunk_index", "chunk_total"],
+    },
+    DefaultTemplate {
+        feature_id: "doctor_deep_reduce",
+        name: "小皮医生深度诊断（汇总报告）",
+        system_prompt: DEEP_REDUCE_SYSTEM,
+        user_prompt: DEEP_REDUCE_USER,
+        variables: &[
+            "first_mes_note",
+            "alt_greeting_note",
+            "findings",
+            "entry_count",
+        ],
+    },
+    DefaultTemplate {
+        feature_id: "translate",
+        name: "角色卡翻译",
+        system_prompt: TRANSLATE_SYSTEM,
+        user_prompt: TRANSLATE_USER,
+        variables: &["target_language", "segments"],
+    },
+    DefaultTemplate {
+        feature_id: "card_generate",
+        name: "角色卡生成",
+        system_prompt: CARD_GENERATE_SYSTEM,
+        user_prompt: CARD_GENERATE_USER,
+        variables: &[
+            "concept",
+            "genre",
+            "tone",
+            "language",
+            "description_length",
+            "greetings_count",
+            "lorebook_entries",
+            "reference",
+        ],
+    },
+    DefaultTemplate {
+        feature_id: "chat_summary",
+        name: "聊天记录总结",
+        system_prompt: CHAT_SUMMARY_SYSTEM,
+        user_prompt: CHAT_SUMMARY_USER,
+        variables: &[
+            "previous_summary",
+            "relationships",
+            "chunk",
+            "floor_range",
+            "chunk_index",
+            "chunk_count",
+        ],
+    },
+    DefaultTemplate {
+        feature_id: "image_caption",
+        name: "图片描述与标签",
+        system_prompt: IMAGE_CAPTION_SYSTEM,
+        user_prompt: IMAGE_CAPTION_USER,
+        variables: &["ai_prompt", "existing_tags"],
+    },
+    DefaultTemplate {
+        feature_id: "greeting_generate",
+        name: "开场白生成",
+        system_prompt: GREETING_GENERATE_SYSTEM,
+        user_prompt: GREETING_GENERATE_USER,
+        variables: &[
+            "count",
+            "pov",
+            "length",
+            "tone",
+            "seed",
+            "entries",
+            "existing_greetings",
+        ],
+    },
+];
+
+/// 查找内置默认模板
+pub fn default_template(feature_id: &str) -> Option<&'static DefaultTemplate> {
+    DEFAULT_TEMPLATES
+        .iter()
+        .find(|t| t.feature_id == feature_id)
+}
+
+/// 功能 ID 只允许小写字母、数字、下划线和连字符
+pub fn is_valid_feature_id(feature_id: &str) -> bool {
+    !feature_id.is_empty()
+        && feature_id.len() <= 64
+        && feature_id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+/// 当前生效的模板（自定义版本或内置默认）
+#[derive(Serialize, Clone)]
+pub struct ResolvedTemplate {
+    pub feature_id: String,
+    pub system_prompt: String,
+    pub user_prompt: String,
+    /// 自定义版本号，内置默认为 None
+    pub version: Option<i32>,
+    pub is_default: bool,
+}
+
+/// 解析功能当前生效的模板：优先使用 is_active 的自定义版本，否则回退内置默认
+pub async fn resolve_template(
+    db: &DatabaseConnection,
+    feature_id: &str,
+) -> Result<Option<ResolvedTemplate>, DbErr> {
+    let active = prompt_template::Entity::find()
+        .filter(prompt_template::Column::FeatureId.eq(feature_id))
+        .filter(prompt_template::Column::IsActive.eq(true))
+        .order_by_desc(prompt_template::Column::Version)
+        .one(db)
+        .await?;
+
+    if let Some(t) = active {
+        return Ok(Some(ResolvedTemplate {
+            feature_id: t.feature_id,
+            system_prompt: t.system_prompt,
+            user_prompt: t.user_prompt,
+            version: Some(t.version),
+            is_default: false,
+        }));
+    }
+
+    Ok(default_template(feature_id).map(|d| ResolvedTemplate {
+        feature_id: d.feature_id.to_string(),
+        system_prompt: d.system_prompt.to_string(),
+        user_prompt: d.user_prompt.to_string(),
+        version: None,
+        is_default: true,
+    }))
+}
+
+static IF_BLOCK: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?s)\{\{#if\s+(\w+)\s*\}\}(.*?)(?:\{\{else\}\}(.*?))?\{\{/if\}\}").unwrap()
+});
+static VARIABLE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{\{\s*(\w+)\s*\}\}").unwrap());
+
+/// 渲染模板
+///
+/// 先展开条件块（变量非空即为真），再单轮替换变量；未知变量保持原样
+pub fn render(template: &str, vars: &PromptVars) -> String {
+    let expanded = IF_BLOCK.replace_all(template, |caps: &Captures| {
+        let truthy = vars
+            .get(&caps[1])
+            .map(|v| !v.trim().is_empty())
+            .unwrap_or(false);
+        if truthy {
+            caps.get(2).map(|m| m.as_str()).unwrap_or("").to_string()
+        } else {
+            caps.get(3).map(|m| m.as_str()).unwrap_or("").to_string()
+        }
+    });
+
+    VARIABLE
+        .replace_all(&expanded, |caps: &Captures| match vars.get(&caps[1]) {
+            Some(v) => v.clone(),
+            None => caps[0].to_string(),
+        })
+        .into_owned()
+}
+
+/// 渲染为 OpenAI 格式的 messages（system 为空时省略）
+pub fn render_messages(template: &ResolvedTemplate, vars: &PromptVars) -> Vec<Value> {
+    let mut messages = Vec::new();
+    let system = render(&template.system_prompt, vars);
+    if !system.trim().is_empty() {
+        messages.push(serde_json::json!({"role": "system", "content": system}));
+    }
+    messages
+        .push(serde_json::json!({"role": "user", "content": render(&template.user_prompt, vars)}));
+    messages
+}
+
+/// 判断开场白是否需要诊断（排除代码或极短内容）
+fn should_include_greeting(content: &str) -> bool {
+    content.len() > 20
+        && !content.trim().starts_with('<')
+        && !content.trim().starts_with('{')
+        && !content.trim().starts_with('[')
+}
+
+/// 读取角色卡字段：优先 V2/V3 data 对象，回退到根级（V1）
+fn card_field<'a>(json: &'a Value, key: &str) -> &'a str {
+    json.get("data")
+        .and_then(|d| d.get(key))
+        .and_then(|v| v.as_str())
+        .or_else(|| json.get(key).and_then(|v| v.as_str()))
+        .unwrap_or("")
+}
+
+/// 提取角色卡通用变量
+pub fn card_variables(card: &character_card::Model) -> PromptVars {
+    let json: Value = serde_json::from_str(&card.data).unwrap_or(serde_json::json!({}));
+    let v2_data = json.get("data").unwrap_or(&json);
+
+    let mut vars = PromptVars::new();
+    vars.insert("char".into(), card.name.clone());
+    vars.insert(
+        "description".into(),
+        card.description.clone().unwrap_or_default(),
+    );
+    for key in [
+        "personality",
+        "scenario",
+        "first_mes",
+        "mes_example",
+        "system_prompt",
+        "post_history_instructions",
+    ] {
+        vars.insert(key.into(), card_field(&json, key).to_string());
+    }
+
+    let creator_notes = match card_field(&json, "creator_notes") {
+        "" => json
+            .get("creatorcomment")
+            .and_then(|v| v.as_str())
+            .unwrap_or(""),
+        s => s,
+    };
+    vars.insert("creator_notes".into(), creator_notes.to_string());
+
+    let greetings: Vec<&str> = v2_data
+        .get("alternate_greetings")
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect())
+        .unwrap_or_default();
+    vars.insert("alternate_greetings".into(), greetings.join("\n---\n"));
+
+    let tags: Vec<String> = serde_json::from_str(&card.tags).unwrap_or_default();
+    vars.insert("tags".into(), tags.join(", "));
+
+    // 世界书目录：已启用且有备注名的条目
+    let worldbook_toc: Vec<String> = v2_data
+        .get("character_book")
+        .and_then(|cb| cb.get("entries"))
+        .and_then(|e| e.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter(|e| e.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true))
+                .filter_map(|e| e.get("comment").and_then(|c| c.as_str()))
+                .filter(|c| !c.is_empty())
+                .map(|s| format!("- {}", s))
+                .collect()
+        })
+        .unwrap_or_default();
+    vars.insert(
+        "worldbook_toc".into(),
+        if worldbook_toc.is_empty() {
+            "（无世界书条目）".to_string()
+        } else {
+            worldbook_toc.join("\n")
+        },
+    );
+
+    vars
+}
+
+fn card_has_tags(card: &character_card::Model) -> bool {
+    !serde_json::from_str::<Vec<String>>(&card.tags)
+        .unwrap_or_default()
+        .is_empty()
+}
+
+/// 系统标签库（所有角色卡标签去重后的 JSON 数组字符串）
+pub async fn tag_vocabulary(db: &DatabaseConnection) -> Result<String, DbErr> {
+    let all_cards = character_card::Entity::find().all(db).await?;
+    let mut all_tags = HashSet::new();
+    for c in all_cards {
+        if let Ok(tags) = serde_json::from_str::<Vec<String>>(&c.tags) {
+            all_tags.extend(tags);
+        }
+    }
+    let tags_vec: Vec<String> = all_tags.into_iter().collect();
+    Ok(serde_json::to_string(&tags_vec).unwrap_or_default())
+}
+
+/// 概览功能的专属变量：角色卡无标签时才生成标签
+pub fn overview_variables(vars: &mut PromptVars, card: &character_card::Model, vocabulary: &str) {
+    if card_has_tags(card) {
+        vars.insert("generate_tags".into(), String::new());
+        vars.insert("system_tags".into(), String::new());
+    } else {
+        vars.insert("generate_tags".into(), "true".into());
+        vars.insert("system_tags".into(), vocabulary.to_string());
+    }
+}
+
+/// 读取全局提示词
+pub async fn global_prompt(db: &DatabaseConnection) -> Result<String, DbErr> {
+    Ok(setting::Entity::find_by_id("global_prompt")
+        .one(db)
+        .await?
+        .map(|s| s.value)
+        .unwrap_or_default())
+}
+
+/// 构建某功能的完整变量表（全局提示词 + 角色卡变量 + 功能专属变量）
+pub async fn build_variables(
+    db: &DatabaseConnection,
+    feature_id: &str,
+    card: Option<&character_card::Model>,
+) -> Result<PromptVars, DbErr> {
+    let global_prompt = global_prompt(db).await?;
+
+    let mut vars = match card {
+        Some(card) => card_variables(card),
+        None => PromptVars::new(),
+    };
+    vars.insert("global_prompt".into(), global_prompt);
+
+    let Some(card) = card else {
+        return Ok(vars);
+    };
+
+    match feature_id {
+        "overview" => {
+            // 已有标签时不生成标签，无需加载系统标签库
+            let vocabulary = if card_has_tags(card) {
+                String::new()
+            } else {
+                tag_vocabulary(db).await?
+            };
+            overview_variables(&mut vars, card, &vocabulary);
+        }
+        "doctor" | "doctor_deep_map" | "doctor_deep_reduce" => {
+            let json: Value = serde_json::from_str(&card.data).unwrap_or(serde_json::json!({}));
+            let first_mes = vars.get("first_mes").cloned().unwrap_or_default();
+            let first_alt = json
+                .get("data")
+                .unwrap_or(&json)
+                .get("alternate_greetings")
+                .and_then(|v| v.as_array())
+                .and_then(|arr| arr.first())
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+
+            let first_mes_note = if should_include_greeting(&first_mes) {
+                format!("- 首条消息：{}", first_mes)
+            } else {
+                "- 首条消息：（内容过短或为代码，跳过诊断）".to_string()
+            };
+            let alt_greeting_note = if should_include_greeting(&first_alt) {
+                format!("- 其他开场白（第1个）：{}", first_alt)
+            } else {
+                "- 其他开场白（第1个）：（内容过短或为代码，跳过诊断）".to_string()
+            };
+            vars.insert("first_mes_note".into(), first_mes_note);
+            vars.insert("alt_greeting_note".into(), alt_greeting_note);
+        }
+        "doctor_prescribe" => {
+            // prescriptions 由调用方传入
+            let json: Value = serde_json::from_str(&card.data).unwrap_or(serde_json::json!({}));
+            vars.insert(
+                "card_fields".into(),
+                serde_json::to_string_pretty(&crate::services::card::editable_fields(&json))
+                    .unwrap_or_default(),
+            );
+        }
+        _ => {}
+    }
+
+    Ok(vars)
+}
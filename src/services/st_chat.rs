@@ -0,0 +1,408 @@
+//! SillyTavern JSONL 聊天记录解析
+//!
+//! ST 聊天文件第一行为元数据头（user_name、character_name、chat_metadata），之后每行一条消息。
+//! 解析时尽量宽容（字段类型不符时取默认值），并保留原始 JSON 以便编辑后原样写回未知字段
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// 聊天元数据头
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatHeader {
+    pub user_name: String,
+    pub character_name: String,
+    pub create_date: Option<String>,
+    pub chat_metadata: Value,
+    #[serde(skip)]
+    pub raw: Value,
+}
+
+/// 单条消息
+#[derive(Debug, Clone, Serialize)]
+pub struct StMessage {
+    pub name: String,
+    pub is_user: bool,
+    /// 隐藏消息（ST 的 /hide）或系统消息
+    pub is_system: bool,
+    /// 发送时间（统一为字符串；数字时间戳转为 RFC 3339）
+    pub send_date: Option<String>,
+    pub mes: String,
+    pub swipes: Vec<String>,
+    /// 当前选中的 swipe 下标
+    pub swipe_id: Option<usize>,
+    /// extra.type（如 narrator、comment）
+    pub kind: Option<String>,
+    /// 思维链（extra.reasoning）
+    pub reasoning: Option<String>,
+    pub model: Option<String>,
+    pub api: Option<String>,
+    pub token_count: Option<i64>,
+    /// 书签 / 检查点链接（extra.bookmark_link）
+    pub bookmark: Option<String>,
+    #[serde(skip)]
+    pub raw: Value,
+}
+
+/// 无法解析的行（行号从 1 开始）
+#[derive(Debug, Clone, Serialize)]
+pub struct LineError {
+    pub line: usize,
+    pub error: String,
+}
+
+/// 解析后的聊天记录
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChatLog {
+    pub header: Option<ChatHeader>,
+    pub messages: Vec<StMessage>,
+    pub errors: Vec<LineError>,
+}
+
+fn str_field(obj: &Map<String, Value>, key: &str) -> Option<String> {
+    obj.get(key).and_then(|v| v.as_str()).map(str::to_string)
+}
+
+/// 宽松读取布尔值（兼容 "true" / 1）
+fn bool_field(obj: &Map<String, Value>, key: &str) -> bool {
+    match obj.get(key) {
+        Some(Value::Bool(b)) => *b,
+        Some(Value::String(s)) => s == "true",
+        Some(Value::Number(n)) => n.as_i64().unwrap_or(0) != 0,
+        _ => false,
+    }
+}
+
+/// 时间字段：字符串原样返回，毫秒时间戳转为 RFC 3339
+fn date_field(obj: &Map<String, Value>, key: &str) -> Option<String> {
+    match obj.get(key)? {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => n
+            .as_i64()
+            .and_then(chrono::DateTime::from_timestamp_millis)
+            .map(|t| t.to_rfc3339()),
+        _ => None,
+    }
+}
+
+/// 是否为元数据头（只有首行可能是）
+fn is_header(obj: &Map<String, Value>) -> bool {
+    !obj.contains_key("mes")
+        && (obj.contains_key("chat_metadata")
+            || obj.contains_key("user_name")
+            || obj.contains_key("character_name"))
+}
+
+impl ChatHeader {
+    /// 新建元数据头（用于从其他格式转换）
+    pub fn new(user_name: &str, character_name: &str) -> Self {
+        Self::from_value(serde_json::json!({
+            "user_name": user_name,
+            "character_name": character_name,
+            "create_date": chrono::Utc::now().to_rfc3339(),
+            "chat_metadata": {}
+        }))
+    }
+
+    fn from_value(raw: Value) -> Self {
+        let obj = raw.as_object().cloned().unwrap_or_default();
+        Self {
+            user_name: str_field(&obj, "user_name").unwrap_or_default(),
+            character_name: str_field(&obj, "character_name").unwrap_or_default(),
+            create_date: date_field(&obj, "create_date"),
+            chat_metadata: obj.get("chat_metadata").cloned().unwrap_or(Value::Null),
+            raw,
+        }
+    }
+}
+
+impl StMessage {
+    /// 新建消息（用于从其他格式转换）
+    pub fn new(name: &str, is_user: bool, mes: &str, send_date: Option<String>) -> Self {
+        let mut raw = serde_json::json!({
+            "name": name,
+            "is_user": is_user,
+            "is_system": false,
+            "mes": mes,
+            "extra": {}
+        });
+        if let Some(date) = send_date {
+            raw["send_date"] = Value::String(date);
+        }
+        Self::from_value(raw)
+    }
+
+    /// 从单行 JSON 解析（兼容其他工具使用的 message / content 字段）
+    pub fn from_value(raw: Value) -> Self {
+        let obj = raw.as_object().cloned().unwrap_or_default();
+        let extra = obj
+            .get("extra")
+            .and_then(|v| v.as_object())
+            .cloned()
+            .unwrap_or_default();
+
+        let mes = ["mes", "message", "content"]
+            .iter()
+            .find_map(|k| str_field(&obj, k))
+            .unwrap_or_default();
+        let swipes: Vec<String> = obj
+            .get("swipes")
+            .and_then(|v| v.as_array())
+            .map(|arr| {
+                arr.iter()
+                    .map(|s| s.as_str().unwrap_or("").to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let swipe_id = obj
+            .get("swipe_id")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as usize)
+            .filter(|&i| i < swipes.len());
+
+        Self {
+            name: str_field(&obj, "name").unwrap_or_else(|| "Unknown".to_string()),
+            is_user: bool_field(&obj, "is_user"),
+            is_system: bool_field(&obj, "is_system"),
+            send_date: date_field(&obj, "send_date"),
+            mes,
+            swipes,
+            swipe_id,
+            kind: str_field(&extra, "type"),
+            reasoning: str_field(&extra, "reasoning").filter(|s| !s.is_empty()),
+            model: str_field(&extra, "model").filter(|s| !s.is_empty()),
+            api: str_field(&extra, "api").filter(|s| !s.is_empty()),
+            token_count: extra.get("token_count").and_then(|v| v.as_i64()),
+            bookmark: str_field(&extra, "bookmark_link").filter(|s| !s.is_empty()),
+            raw,
+        }
+    }
+
+    /// 写回 JSON：在原始对象上更新已知字段，保留其余字段
+    pub fn to_value(&self) -> Value {
+        let mut raw = match &self.raw {
+            Value::Object(obj) => obj.clone(),
+            _ => Map::new(),
+        };
+        raw.insert("name".into(), Value::String(self.name.clone()));
+        raw.insert("is_user".into(), Value::Bool(self.is_user));
+        raw.insert("is_system".into(), Value::Bool(self.is_system));
+        raw.insert("mes".into(), Value::String(self.mes.clone()));
+        // 兼容格式的消息统一写为 mes
+        raw.remove("message");
+        raw.remove("content");
+        if !self.swipes.is_empty() {
+            raw.insert("swipes".into(), serde_json::json!(self.swipes));
+            if let Some(id) = self.swipe_id {
+                raw.insert("swipe_id".into(), serde_json::json!(id));
+            }
+        }
+        Value::Object(raw)
+    }
+}
+
+static HUMANIZED_DATE: once_cell::sync::Lazy<regex::Regex> = once_cell::sync::Lazy::new(|| {
+    regex::Regex::new(r"^(\d{4})-(\d{1,2})-(\d{1,2}) ?@(\d{1,2})h ?(\d{1,2})m").unwrap()
+});
+
+/// 解析 send_date（RFC 3339、`YYYY-MM-DD HH:MM`、ST 的 "June 5, 2024 3:45pm" 与 "2024-6-5 @15h 30m" 等）
+pub fn parse_send_date(send_date: &str) -> Option<chrono::NaiveDateTime> {
+    let s = send_date.trim();
+    if let Ok(t) = chrono::DateTime::parse_from_rfc3339(s) {
+        return Some(t.naive_local());
+    }
+    for format in [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%B %d, %Y %I:%M%p",
+        "%B %d, %Y %I:%M %p",
+        "%b %d, %Y %I:%M%p",
+    ] {
+        if let Ok(t) = chrono::NaiveDateTime::parse_from_str(s, format) {
+            return Some(t);
+        }
+    }
+    let caps = HUMANIZED_DATE.captures(s)?;
+    let num = |i: usize| caps[i].parse::<u32>().ok();
+    chrono::NaiveDate::from_ymd_opt(caps[1].parse().ok()?, num(2)?, num(3)?)?.and_hms_opt(
+        num(4)?,
+        num(5)?,
+        0,
+    )
+}
+
+/// 只读取首行元数据头中的用户名（无需解析全文）
+pub fn header_user_name(content: &str) -> Option<String> {
+    let first = content.lines().find(|l| !l.trim().is_empty())?;
+    let value: Value = serde_json::from_str(first.trim()).ok()?;
+    let obj = value.as_object().filter(|o| is_header(o))?;
+    str_field(obj, "user_name").filter(|s| !s.is_empty())
+}
+
+impl ChatLog {
+    /// 解析 JSONL 文本；空行跳过，坏行记录到 errors
+    pub fn parse(content: &str) -> Self {
+        let mut log = ChatLog::default();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let value = match serde_json::from_str::<Value>(line) {
+                Ok(v @ Value::Object(_)) => v,
+                Ok(_) => {
+                    log.errors.push(LineError {
+                        line: index + 1,
+                        error: "不是 JSON 对象".to_string(),
+                    });
+                    continue;
+                }
+                Err(e) => {
+                    log.errors.push(LineError {
+                        line: index + 1,
+                        error: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            let first = log.header.is_none() && log.messages.is_empty();
+            if first && value.as_object().is_some_and(is_header) {
+                log.header = Some(ChatHeader::from_value(value));
+            } else {
+                log.messages.push(StMessage::from_value(value));
+            }
+        }
+        log
+    }
+
+    /// 是否解析出了任何内容
+    pub fn is_empty(&self) -> bool {
+        self.header.is_none() && self.messages.is_empty()
+    }
+
+    /// 序列化为 JSONL（头 + 每条消息一行）
+    pub fn to_jsonl(&self) -> String {
+        let mut out = String::new();
+        if let Some(header) = &self.header {
+            out.push_str(&header.raw.to_string());
+            out.push('\n');
+        }
+        for message in &self.messages {
+            out.push_str(&message.to_value().to_string());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = r#"{"user_name":"User","character_name":"Alice","create_date":"2024-06-05@15h30m","chat_metadata":{"main_chat":"base"}}"#;
+
+    #[test]
+    fn parses_header_and_messages() {
+        let content = format!(
+            "{}\n{}\n\n{}\n",
+            HEADER,
+            r#"{"name":"Alice","is_user":false,"mes":"你好","send_date":1717600000000,"extra":{"reasoning":"想想","model":"m1","token_count":3}}"#,
+            r#"{"name":"User","is_user":"true","is_system":1,"mes":"hi","swipes":["a","b"],"swipe_id":1}"#,
+        );
+        let log = ChatLog::parse(&content);
+
+        let header = log.header.as_ref().unwrap();
+        assert_eq!(
+            (header.user_name.as_str(), header.character_name.as_str()),
+            ("User", "Alice")
+        );
+        assert_eq!(header.chat_metadata["main_chat"], "base");
+        assert!(log.errors.is_empty());
+        assert_eq!(log.messages.len(), 2);
+
+        let first = &log.messages[0];
+        assert_eq!((first.name.as_str(), first.mes.as_str()), ("Alice", "你好"));
+        assert!(first
+            .send_date
+            .as_deref()
+            .unwrap()
+            .starts_with("2024-06-05"));
+        assert_eq!(first.reasoning.as_deref(), Some("想想"));
+        assert_eq!(
+            (first.model.as_deref(), first.token_count),
+            (Some("m1"), Some(3))
+        );
+
+        let second = &log.messages[1];
+        assert!(second.is_user && second.is_system);
+        assert_eq!((second.swipes.len(), second.swipe_id), (2, Some(1)));
+    }
+
+    #[test]
+    fn headerless_file_starts_with_a_message() {
+        let log = ChatLog::parse(r#"{"name":"Alice","mes":"第一条"}"#);
+        assert!(log.header.is_none());
+        assert_eq!(log.messages[0].mes, "第一条");
+    }
+
+    #[test]
+    fn header_only_recognized_on_first_line() {
+        let content = format!("{}\n{}", r#"{"name":"Alice","mes":"x"}"#, HEADER);
+        let log = ChatLog::parse(&content);
+        assert!(log.header.is_none());
+        assert_eq!(log.messages.len(), 2);
+    }
+
+    #[test]
+    fn bad_lines_are_reported_with_line_numbers() {
+        let content = format!("{}\nnot json\n[1]\n{}", HEADER, r#"{"mes":"ok"}"#);
+        let log = ChatLog::parse(&content);
+        let lines: Vec<usize> = log.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [2, 3]);
+        assert_eq!(log.messages.len(), 1);
+        assert_eq!(log.messages[0].name, "Unknown");
+    }
+
+    #[test]
+    fn round_trip_keeps_unknown_fields() {
+        let content = format!(
+            "{}\n{}\n",
+            HEADER,
+            r#"{"name":"Alice","is_user":false,"is_system":false,"mes":"a","custom":{"k":1},"message":"dup"}"#
+        );
+        let mut log = ChatLog::parse(&content);
+        log.messages[0].mes = "b".to_string();
+        let reparsed = ChatLog::parse(&log.to_jsonl());
+
+        assert_eq!(reparsed.header.unwrap().raw, log.header.unwrap().raw);
+        let raw = &reparsed.messages[0].raw;
+        assert_eq!(raw["mes"], "b");
+        assert_eq!(raw["custom"]["k"], 1);
+        assert!(raw.get("message").is_none());
+    }
+
+    #[test]
+    fn parses_send_date_formats() {
+        let expected = chrono::NaiveDate::from_ymd_opt(2024, 6, 5)
+            .unwrap()
+            .and_hms_opt(15, 30, 0)
+            .unwrap();
+        for date in [
+            "2024-06-05 15:30",
+            "2024-06-05T15:30:00.000",
+            "June 5, 2024 3:30pm",
+            "2024-6-5 @15h 30m",
+            "2024-6-5@15h30m 12s 000ms",
+        ] {
+            assert_eq!(parse_send_date(date), Some(expected), "{}", date);
+        }
+        assert_eq!(parse_send_date("yesterday"), None);
+    }
+
+    #[test]
+    fn reads_user_name_from_header_only() {
+        assert_eq!(header_user_name(HEADER).as_deref(), Some("User"));
+        assert_eq!(header_user_name(r#"{"name":"User","mes":"x"}"#), None);
+    }
+}
@@ -0,0 +1,695 @@
+//! SillyTavern 正则脚本引擎
+//!
+//! 在服务端执行角色卡 / 聊天记录中保存的 ST 正则脚本，行为与 ST 的 `getRegexedString` 对齐：
+//! - `/pattern/flags` 语法（基于 fancy-regex，支持环视与反向引用）
+//! - 替换串中的 `$1` / `$<name>` / `{{match}}`，捕获内容先去除 trimStrings
+//! - placement（用户输入 / AI 输出 / 世界书 / 思维链）、minDepth / maxDepth
+//! - markdownOnly / promptOnly / runOnEdit
+//! - 宏替换（`{{char}}`、`{{user}}` 等），substituteRegex 控制查找正则中的宏
+
+use crate::entities::{character_card, chat_history};
+use fancy_regex::{Captures, Regex as FancyRegex, RegexBuilder};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 单个脚本的回溯上限（防止灾难性回溯卡死请求）
+const BACKTRACK_LIMIT: usize = 1_000_000;
+
+/// ST 正则脚本（字段名与 ST 导出一致）
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RegexScript {
+    pub id: Option<String>,
+    pub script_name: String,
+    #[serde(alias = "regex")]
+    pub find_regex: String,
+    #[serde(alias = "replace")]
+    pub replace_string: String,
+    pub trim_strings: Vec<String>,
+    pub placement: Vec<i64>,
+    pub disabled: bool,
+    pub markdown_only: bool,
+    pub prompt_only: bool,
+    pub run_on_edit: bool,
+    /// 查找正则中的宏：0 不替换，1 原样替换，2 转义后替换（旧版为布尔值）
+    #[serde(deserialize_with = "substitute_mode")]
+    pub substitute_regex: u8,
+    pub min_depth: Option<i64>,
+    pub max_depth: Option<i64>,
+}
+
+fn substitute_mode<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Bool(b) => b as u8,
+        Value::Number(n) => n.as_u64().unwrap_or(0).min(2) as u8,
+        _ => 0,
+    })
+}
+
+/// 脚本作用位置（对应 ST 的 regex_placement）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Placement {
+    UserInput,
+    AiOutput,
+    SlashCommand,
+    WorldInfo,
+    Reasoning,
+}
+
+impl Placement {
+    pub fn code(self) -> i64 {
+        match self {
+            Self::UserInput => 1,
+            Self::AiOutput => 2,
+            Self::SlashCommand => 3,
+            Self::WorldInfo => 5,
+            Self::Reasoning => 6,
+        }
+    }
+
+    /// 聊天消息对应的位置
+    pub fn for_message(is_user: bool) -> Self {
+        if is_user {
+            Self::UserInput
+        } else {
+            Self::AiOutput
+        }
+    }
+}
+
+/// 运行场景
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RunMode {
+    /// 显示（阅读、导出、搜索）：运行除 promptOnly 外的脚本，与阅读器一致
+    #[default]
+    Display,
+    /// 发送给模型（总结等）：运行除 markdownOnly 外的脚本
+    Prompt,
+    /// 修改原文：只运行两者皆未勾选的脚本
+    Raw,
+}
+
+/// 单次执行的上下文
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApplyContext {
+    pub mode: RunMode,
+    /// 未知位置（如 TXT 记录）时不按 placement 过滤
+    pub placement: Option<Placement>,
+    /// 消息深度（0 为最后一条）；None 时不按深度过滤
+    pub depth: Option<usize>,
+    pub is_edit: bool,
+}
+
+impl ApplyContext {
+    /// 聊天记录中第 `index` 条消息（共 `total` 条）的上下文
+    pub fn for_message(mode: RunMode, is_user: Option<bool>, index: usize, total: usize) -> Self {
+        Self {
+            mode,
+            placement: is_user.map(Placement::for_message),
+            depth: Some(total.saturating_sub(index + 1)),
+            is_edit: false,
+        }
+    }
+}
+
+/// 宏替换所需的名称
+#[derive(Debug, Clone, Default)]
+pub struct Macros {
+    pub char_name: String,
+    pub user_name: String,
+}
+
+static MACRO_CHAR: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\{\{char\}\}|<BOT>|<CHAR>").unwrap());
+static MACRO_USER: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\{\{user\}\}|<USER>").unwrap());
+static MACRO_NEWLINE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\{\{newline\}\}").unwrap());
+static MACRO_MATCH: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\{\{match\}\}").unwrap());
+
+impl Macros {
+    pub fn new(char_name: &str, user_name: &str) -> Self {
+        Self {
+            char_name: char_name.to_string(),
+            user_name: user_name.to_string(),
+        }
+    }
+
+    pub fn substitute(&self, text: &str) -> String {
+        if !text.contains('{') && !text.contains('<') {
+            return text.to_string();
+        }
+        let text = MACRO_CHAR.replace_all(text, regex::NoExpand(&self.char_name));
+        let text = MACRO_USER.replace_all(&text, regex::NoExpand(&self.user_name));
+        MACRO_NEWLINE.replace_all(&text, "\n").into_owned()
+    }
+}
+
+/// 替换模板片段
+#[derive(Debug, Clone)]
+enum Token {
+    Literal(String),
+    Group(usize),
+    Named(String),
+}
+
+/// 解析替换串：`$N`、`$<name>`、`{{match}}`（及阅读器兼容的 `$&`），其余原样保留
+fn parse_replacement(template: &str) -> Vec<Token> {
+    // 与阅读器一致：支持以字面量 \n \t 书写的换行
+    let template = template
+        .replace("\\n", "\n")
+        .replace("\\r", "\r")
+        .replace("\\t", "\t")
+        .replace("\\\"", "\"");
+    let template = MACRO_MATCH.replace_all(&template, "$$0");
+
+    let mut tokens = Vec::new();
+    let mut literal = String::new();
+    let mut rest = template.as_ref();
+    while let Some(pos) = rest.find('$') {
+        literal.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        let digits = after.bytes().take_while(u8::is_ascii_digit).count();
+        let token = if digits > 0 {
+            after[..digits]
+                .parse()
+                .ok()
+                .map(|n| (Token::Group(n), digits))
+        } else if let Some(name) = after.strip_prefix('<').and_then(|s| s.split_once('>')) {
+            Some((Token::Named(name.0.to_string()), name.0.len() + 2))
+        } else if after.starts_with('&') {
+            Some((Token::Group(0), 1))
+        } else {
+            None
+        };
+        match token {
+            Some((token, len)) => {
+                if !literal.is_empty() {
+                    tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                }
+                tokens.push(token);
+                rest = &after[len..];
+            }
+            None => {
+                literal.push('$');
+                rest = after;
+            }
+        }
+    }
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+    tokens
+}
+
+/// 解析 `/pattern/flags`；不带斜杠时整体为 pattern 并按全局处理（与阅读器一致）
+fn split_pattern(find: &str) -> (&str, String) {
+    let trimmed = find.trim();
+    if trimmed.starts_with('/') {
+        if let Some(last) = trimmed.rfind('/').filter(|&i| i > 0) {
+            let flags: String = trimmed[last + 1..]
+                .chars()
+                .filter(|c| "gimsuy".contains(*c))
+                .collect();
+            let flags = if flags.is_empty() {
+                "g".to_string()
+            } else {
+                flags
+            };
+            return (&trimmed[1..last], flags);
+        }
+    }
+    (find, "g".to_string())
+}
+
+/// 编译后的脚本
+pub struct CompiledScript {
+    pub script: RegexScript,
+    regex: FancyRegex,
+    global: bool,
+    replacement: Vec<Token>,
+    /// 已替换宏的 trimStrings
+    trim_strings: Vec<String>,
+}
+
+impl CompiledScript {
+    pub fn compile(script: &RegexScript, macros: &Macros) -> Result<Self, String> {
+        let (pattern, flags) = split_pattern(&script.find_regex);
+        let pattern = match script.substitute_regex {
+            1 => macros.substitute(pattern),
+            2 => {
+                let escaped = Macros {
+                    char_name: fancy_regex::escape(&macros.char_name).into_owned(),
+                    user_name: fancy_regex::escape(&macros.user_name).into_owned(),
+                };
+                escaped.substitute(pattern)
+            }
+            _ => pattern.to_string(),
+        };
+        if pattern.is_empty() {
+            return Err("正则为空".to_string());
+        }
+
+        let inline: String = flags.chars().filter(|c| "ims".contains(*c)).collect();
+        let pattern = if inline.is_empty() {
+            pattern
+        } else {
+            format!("(?{}){}", inline, pattern)
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .backtrack_limit(BACKTRACK_LIMIT)
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            script: script.clone(),
+            regex,
+            global: flags.contains('g'),
+            replacement: parse_replacement(&script.replace_string),
+            trim_strings: script
+                .trim_strings
+                .iter()
+                .map(|t| macros.substitute(t))
+                .filter(|t| !t.is_empty())
+                .collect(),
+        })
+    }
+
+    /// 是否在该上下文中运行；不运行时返回原因
+    pub fn skip_reason(&self, ctx: &ApplyContext) -> Option<&'static str> {
+        let s = &self.script;
+        if s.disabled {
+            return Some("已禁用");
+        }
+        let mode_ok = match ctx.mode {
+            RunMode::Display => !s.prompt_only,
+            RunMode::Prompt => !s.markdown_only,
+            RunMode::Raw => !s.prompt_only && !s.markdown_only,
+        };
+        if !mode_ok {
+            return Some(if s.prompt_only {
+                "仅作用于提示词"
+            } else {
+                "仅作用于显示"
+            });
+        }
+        if ctx.is_edit && !s.run_on_edit {
+            return Some("编辑时不运行");
+        }
+        if let Some(placement) = ctx.placement {
+            if !s.placement.contains(&placement.code()) {
+                return Some("作用位置不匹配");
+            }
+        }
+        if let Some(depth) = ctx.depth.map(|d| d as i64) {
+            if s.min_depth.is_some_and(|min| min >= -1 && depth < min) {
+                return Some("低于最小深度");
+            }
+            if s.max_depth.is_some_and(|max| max >= 0 && depth > max) {
+                return Some("超过最大深度");
+            }
+        }
+        None
+    }
+
+    fn expand(&self, caps: &Captures, macros: &Macros) -> String {
+        let mut out = String::new();
+        for token in &self.replacement {
+            let group = match token {
+                Token::Literal(s) => {
+                    out.push_str(s);
+                    continue;
+                }
+                Token::Group(n) => caps.get(*n),
+                Token::Named(name) => caps.name(name),
+            };
+            if let Some(m) = group {
+                let mut value = m.as_str().to_string();
+                for trim in &self.trim_strings {
+                    value = value.replace(trim.as_str(), "");
+                }
+                out.push_str(&value);
+            }
+        }
+        macros.substitute(&out)
+    }
+
+    /// 执行替换（回溯超限等运行时错误时返回 Err，原文保持不变）
+    pub fn apply(&self, text: &str, macros: &Macros) -> Result<String, String> {
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for caps in self.regex.captures_iter(text) {
+            let caps = caps.map_err(|e| e.to_string())?;
+            let whole = caps.get(0).expect("group 0 always matches");
+            out.push_str(&text[last..whole.start()]);
+            out.push_str(&self.expand(&caps, macros));
+            last = whole.end();
+            if !self.global {
+                break;
+            }
+        }
+        out.push_str(&text[last..]);
+        Ok(out)
+    }
+}
+
+/// 单个脚本的执行记录（用于预览）
+#[derive(Debug, Serialize)]
+pub struct ScriptStep {
+    pub name: String,
+    pub applied: bool,
+    pub changed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 编译失败的脚本
+#[derive(Debug, Serialize)]
+pub struct ScriptError {
+    pub name: String,
+    pub error: String,
+}
+
+/// 一组按顺序执行的脚本
+#[derive(Default)]
+pub struct ScriptSet {
+    scripts: Vec<CompiledScript>,
+    macros: Macros,
+    pub errors: Vec<ScriptError>,
+}
+
+impl ScriptSet {
+    /// 编译脚本；无法编译的脚本记录到 errors 后跳过
+    pub fn compile(scripts: &[RegexScript], macros: Macros) -> Self {
+        let mut set = Self {
+            macros,
+            ..Default::default()
+        };
+        for script in scripts {
+            match CompiledScript::compile(script, &set.macros) {
+                Ok(compiled) => set.scripts.push(compiled),
+                Err(error) => {
+                    if !script.disabled {
+                        tracing::warn!(
+                            "正则脚本「{}」无法编译，已跳过: {}",
+                            script.script_name,
+                            error
+                        );
+                    }
+                    set.errors.push(ScriptError {
+                        name: script.script_name.clone(),
+                        error,
+                    });
+                }
+            }
+        }
+        set
+    }
+
+    /// 聊天记录的脚本：聊天记录正则在前，角色卡正则在后（与阅读器一致）
+    pub fn for_history(
+        history: &chat_history::Model,
+        card: Option<&character_card::Model>,
+        user_name: &str,
+    ) -> Self {
+        let mut scripts = parse_scripts(&Value::String(history.regex_scripts.clone()));
+        let char_name = card.map(|c| c.name.as_str()).unwrap_or_default();
+        if let Some(card_json) = card.and_then(|c| serde_json::from_str::<Value>(&c.data).ok()) {
+            scripts.extend(card_scripts(&card_json));
+        }
+        Self::compile(&scripts, Macros::new(char_name, user_name))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+
+    pub fn apply(&self, text: &str, ctx: &ApplyContext) -> String {
+        self.run(text, ctx, None)
+    }
+
+    /// 执行并记录每个脚本的结果
+    pub fn trace(&self, text: &str, ctx: &ApplyContext) -> (String, Vec<ScriptStep>) {
+        let mut steps = Vec::new();
+        let result = self.run(text, ctx, Some(&mut steps));
+        (result, steps)
+    }
+
+    fn run(
+        &self,
+        text: &str,
+        ctx: &ApplyContext,
+        mut steps: Option<&mut Vec<ScriptStep>>,
+    ) -> String {
+        let mut current = text.to_string();
+        for script in &self.scripts {
+            let mut step = ScriptStep {
+                name: script.script.script_name.clone(),
+                applied: false,
+                changed: false,
+                skipped: None,
+                error: None,
+            };
+            if let Some(reason) = script.skip_reason(ctx) {
+                step.skipped = Some(reason.to_string());
+            } else {
+                match script.apply(&current, &self.macros) {
+                    Ok(next) => {
+                        step.applied = true;
+                        step.changed = next != current;
+                        current = next;
+                    }
+                    Err(error) => {
+                        tracing::warn!("正则脚本「{}」执行失败: {}", step.name, error);
+                        step.error = Some(error);
+                    }
+                }
+            }
+            if let Some(steps) = steps.as_deref_mut() {
+                steps.push(step);
+            }
+        }
+        current
+    }
+}
+
+/// 解析脚本数组（JSON 数组或其字符串形式），忽略无法识别的项
+pub fn parse_scripts(value: &Value) -> Vec<RegexScript> {
+    let array = match value {
+        Value::String(s) => serde_json::from_str::<Value>(s).unwrap_or(Value::Null),
+        other => other.clone(),
+    };
+    array
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|v| serde_json::from_value(v.clone()).ok())
+        .collect()
+}
+
+/// 角色卡中的正则脚本（data.extensions.regex_scripts，兼容 V1 根级）
+pub fn card_scripts(card_json: &Value) -> Vec<RegexScript> {
+    let scripts = card_json
+        .get("data")
+        .and_then(|d| d.get("extensions"))
+        .and_then(|e| e.get("regex_scripts"))
+        .or_else(|| {
+            card_json
+                .get("extensions")
+                .and_then(|e| e.get("regex_scripts"))
+        });
+    scripts.map(parse_scripts).unwrap_or_default()
+}
+
+static SCRIPT_BLOCK: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<script\b[^>]*>.*?</script\s*>").unwrap());
+
+/// 移除 `<tag>...</tag>` 块（不区分大小写，用于阅读设置中隐藏的标签）
+pub fn remove_tag_blocks(text: &str, tags: &[String]) -> String {
+    let mut result = text.to_string();
+    for tag in tags.iter().filter(|t| !t.trim().is_empty()) {
+        let escaped = regex::escape(tag.trim());
+        if let Ok(re) = Regex::new(&format!(r"(?is)<{0}(?:\s[^>]*)?>.*?</{0}\s*>", escaped)) {
+            result = re.replace_all(&result, "").into_owned();
+        }
+    }
+    result
+}
+
+/// 移除 `<script>` 块
+pub fn strip_scripts(text: &str) -> String {
+    SCRIPT_BLOCK.replace_all(text, "").into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn script(value: Value) -> RegexScript {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn run(scripts: Value, text: &str, ctx: &ApplyContext) -> String {
+        ScriptSet::compile(&parse_scripts(&scripts), Macros::new("Alice", "Bob")).apply(text, ctx)
+    }
+
+    #[test]
+    fn parses_st_fields_and_legacy_values() {
+        let s = script(json!({
+            "scriptName": "x",
+            "regex": "/a/g",
+            "replace": "b",
+            "substituteRegex": true,
+            "placement": [1, 2],
+        }));
+        assert_eq!(
+            (s.find_regex.as_str(), s.replace_string.as_str()),
+            ("/a/g", "b")
+        );
+        assert_eq!(s.substitute_regex, 1);
+        assert_eq!(script(json!({"substituteRegex": 5})).substitute_regex, 2);
+        assert_eq!(parse_scripts(&json!("[{\"findRegex\":\"a\"}, 1]")).len(), 1);
+    }
+
+    #[test]
+    fn splits_pattern_flags() {
+        assert_eq!(split_pattern("/a+/gi"), ("a+", "gi".to_string()));
+        assert_eq!(split_pattern("/a\\/b/"), ("a\\/b", "g".to_string()));
+        assert_eq!(split_pattern("plain"), ("plain", "g".to_string()));
+    }
+
+    #[test]
+    fn replaces_groups_and_trims_captures() {
+        let scripts = json!([{
+            "findRegex": "/<(?<tag>\\w+)>(.*?)<\\/\\w+>/g",
+            "replaceString": "[$<tag>:{{match}}|$2]",
+            "trimStrings": ["{{char}}"],
+        }]);
+        assert_eq!(
+            run(scripts, "<b>hi Alice</b>", &ApplyContext::default()),
+            "[b:<b>hi </b>|hi ]"
+        );
+    }
+
+    #[test]
+    fn respects_global_and_case_flags() {
+        let ctx = ApplyContext::default();
+        assert_eq!(
+            run(
+                json!([{"findRegex": "/a/i", "replaceString": "x"}]),
+                "aaa",
+                &ctx
+            ),
+            "xaa"
+        );
+        assert_eq!(
+            run(
+                json!([{"findRegex": "/a/gi", "replaceString": "x"}]),
+                "aA",
+                &ctx
+            ),
+            "xx"
+        );
+        assert_eq!(
+            run(
+                json!([{"findRegex": "a", "replaceString": "x"}]),
+                "aa",
+                &ctx
+            ),
+            "xx"
+        );
+    }
+
+    #[test]
+    fn substitutes_macros() {
+        let ctx = ApplyContext::default();
+        let plain = json!([{"findRegex": "/{{user}}/g", "replaceString": "{{char}}", "substituteRegex": 1}]);
+        assert_eq!(run(plain, "hi Bob", &ctx), "hi Alice");
+        let off = json!([{"findRegex": "/{{user}}/g", "replaceString": "x"}]);
+        assert_eq!(run(off, "hi Bob", &ctx), "hi Bob");
+        let escaped = Macros::new("A.B", "U");
+        let compiled = CompiledScript::compile(
+            &script(
+                json!({"findRegex": "/{{char}}/g", "replaceString": "x", "substituteRegex": 2}),
+            ),
+            &escaped,
+        )
+        .unwrap();
+        assert_eq!(compiled.apply("A.B AxB", &escaped).unwrap(), "x AxB");
+    }
+
+    #[test]
+    fn filters_by_mode_placement_and_depth() {
+        let compile =
+            |value: Value| CompiledScript::compile(&script(value), &Macros::default()).unwrap();
+        let display = ApplyContext::default();
+        let prompt = ApplyContext {
+            mode: RunMode::Prompt,
+            ..Default::default()
+        };
+
+        let prompt_only = compile(json!({"findRegex": "a", "promptOnly": true}));
+        assert!(prompt_only.skip_reason(&display).is_some());
+        assert!(prompt_only.skip_reason(&prompt).is_none());
+        let markdown_only = compile(json!({"findRegex": "a", "markdownOnly": true}));
+        assert!(markdown_only.skip_reason(&prompt).is_some());
+
+        let ai_only = compile(json!({"findRegex": "a", "placement": [2]}));
+        let user_msg = ApplyContext::for_message(RunMode::Display, Some(true), 0, 1);
+        let ai_msg = ApplyContext::for_message(RunMode::Display, Some(false), 0, 1);
+        assert!(ai_only.skip_reason(&user_msg).is_some());
+        assert!(ai_only.skip_reason(&ai_msg).is_none());
+        // 位置未知（TXT 记录）时不过滤
+        assert!(ai_only.skip_reason(&display).is_none());
+
+        let recent = compile(json!({"findRegex": "a", "minDepth": 1, "maxDepth": 2}));
+        let depths: Vec<bool> = (0..4)
+            .map(|index| {
+                let ctx = ApplyContext::for_message(RunMode::Display, None, index, 4);
+                recent.skip_reason(&ctx).is_none()
+            })
+            .collect();
+        assert_eq!(depths, [false, true, true, false]);
+    }
+
+    #[test]
+    fn bad_scripts_are_reported_and_skipped() {
+        let scripts = parse_scripts(&json!([
+            {"scriptName": "broken", "findRegex": "/(/g"},
+            {"scriptName": "ok", "findRegex": "a", "replaceString": "b"},
+        ]));
+        let set = ScriptSet::compile(&scripts, Macros::default());
+        assert_eq!(set.errors.len(), 1);
+        assert_eq!(set.errors[0].name, "broken");
+        let (result, steps) = set.trace("a", &ApplyContext::default());
+        assert_eq!(result, "b");
+        assert!(steps[0].applied && steps[0].changed);
+    }
+
+    #[test]
+    fn catastrophic_backtracking_leaves_text_unchanged() {
+        let scripts = json!([{"findRegex": "/(a+)+$/", "replaceString": "x"}]);
+        let text = format!("{}!", "a".repeat(40));
+        assert_eq!(run(scripts, &text, &ApplyContext::default()), text);
+    }
+
+    #[test]
+    fn reads_card_scripts_and_strips_blocks() {
+        let card = json!({"data": {"extensions": {"regex_scripts": [{"findRegex": "a"}]}}});
+        assert_eq!(card_scripts(&card).len(), 1);
+        assert_eq!(
+            card_scripts(&json!({"extensions": {"regex_scripts": []}})).len(),
+            0
+        );
+
+        let text = "a<Think x=1>隐藏</think>b<SCRIPT>x()</script>c";
+        let hidden = remove_tag_blocks(text, &["think".to_string(), " ".to_string()]);
+        assert_eq!(strip_scripts(&hidden), "abc");
+    }
+}
@@ -0,0 +1,525 @@
+//! AI 结构化输出解析
+//!
+//! - 从模型输出中提取 JSON：去除 ``` 代码块、跳过前后说明文字、截取第一个配平的对象
+//! - 修复常见问题：尾随逗号、中文引号作字符串定界符、字符串内裸换行、输出被截断
+//! - 按预期结构校验，不符合时附带纠正说明重试
+//! - 按渠道记录 response_format 支持情况（json_schema → json_object → 不指定），不支持时自动降级
+
+use super::ai::{self, AiError};
+use crate::entities::ai_channel;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use uuid::Uuid;
+
+/// 最多尝试的起始位置数（跳过说明文字中的花括号）
+const MAX_START_CANDIDATES: usize = 5;
+/// 截断修复时最多回退的逗号位置数
+const MAX_CUT_ATTEMPTS: usize = 8;
+
+/// 解析结果
+#[derive(Debug, Clone)]
+pub struct ParsedJson {
+    pub value: Value,
+    /// 是否经过修复（原文不是合法 JSON）
+    pub repaired: bool,
+}
+
+/// 从模型输出中提取 JSON（容忍代码块、说明文字、常见格式错误与截断）
+pub fn extract_json(content: &str) -> Option<Value> {
+    parse_json(content).map(|p| p.value)
+}
+
+/// 从模型输出中提取 JSON，并说明是否经过修复
+pub fn parse_json(content: &str) -> Option<ParsedJson> {
+    let text = strip_fences(content);
+    if let Ok(value) = serde_json::from_str::<Value>(text.trim()) {
+        if value.is_object() || value.is_array() {
+            return Some(ParsedJson {
+                value,
+                repaired: false,
+            });
+        }
+    }
+
+    // 优先对象；没有对象时再尝试数组
+    let opener = if text.contains('{') { '{' } else { '[' };
+    text.match_indices(opener)
+        .take(MAX_START_CANDIDATES)
+        .find_map(|(start, _)| parse_from(&text[start..]))
+}
+
+/// 取第一个 ``` 代码块的内容（未闭合时取到末尾）；没有代码块时返回原文
+fn strip_fences(content: &str) -> String {
+    let Some(open) = content.find("```") else {
+        return content.to_string();
+    };
+    let after = &content[open + 3..];
+    // 跳过语言标记所在行
+    let body = match after.find('\n') {
+        Some(pos) => &after[pos + 1..],
+        None => after.trim_start_matches("json"),
+    };
+    let body = match body.find("```") {
+        Some(close) => &body[..close],
+        None => body,
+    };
+    if body.contains('{') || body.contains('[') {
+        body.to_string()
+    } else {
+        content.to_string()
+    }
+}
+
+/// 从起始括号开始扫描并修复，返回第一个可解析的值
+fn parse_from(text: &str) -> Option<ParsedJson> {
+    let scan = Scan::run(text);
+
+    if scan.complete {
+        if let Ok(value) = serde_json::from_str(&text[..scan.end]) {
+            return Some(ParsedJson {
+                value,
+                repaired: false,
+            });
+        }
+        return serde_json::from_str(&scan.out)
+            .ok()
+            .map(|value| ParsedJson {
+                value,
+                repaired: true,
+            });
+    }
+
+    // 被截断：补齐字符串与括号；失败时回退到之前的逗号处
+    let mut tail = scan.out.clone();
+    if scan.in_string {
+        tail.push('"');
+    }
+    if let Some(value) = close_and_parse(&tail, &scan.stack) {
+        return Some(ParsedJson {
+            value,
+            repaired: true,
+        });
+    }
+    scan.cuts
+        .iter()
+        .rev()
+        .take(MAX_CUT_ATTEMPTS)
+        .find_map(|(pos, stack)| close_and_parse(&scan.out[..*pos], stack))
+        .map(|value| ParsedJson {
+            value,
+            repaired: true,
+        })
+}
+
+/// 去掉末尾悬空的逗号 / 冒号后按栈补齐括号并解析
+fn close_and_parse(text: &str, stack: &[char]) -> Option<Value> {
+    let mut out = text.trim_end().trim_end_matches([',', ':']).to_string();
+    for closer in stack.iter().rev() {
+        out.push(*closer);
+    }
+    serde_json::from_str(&out).ok()
+}
+
+/// 单次扫描的结果
+struct Scan {
+    /// 修复后的文本
+    out: String,
+    /// 原文中配平结束的位置（字节）
+    end: usize,
+    complete: bool,
+    in_string: bool,
+    /// 未闭合的括号（对应的闭合字符）
+    stack: Vec<char>,
+    /// 字符串外的逗号位置（修复后文本中的位置，及当时的括号栈）
+    cuts: Vec<(usize, Vec<char>)>,
+}
+
+impl Scan {
+    fn run(text: &str) -> Self {
+        let mut scan = Scan {
+            out: String::with_capacity(text.len()),
+            end: text.len(),
+            complete: false,
+            in_string: false,
+            stack: Vec::new(),
+            cuts: Vec::new(),
+        };
+        // 当前字符串的结束定界符：ASCII 引号或中文右引号
+        let mut closer: Option<char> = None;
+        let mut escape = false;
+
+        for (i, ch) in text.char_indices() {
+            if let Some(end_quote) = closer {
+                if escape {
+                    scan.out.push(ch);
+                    escape = false;
+                    continue;
+                }
+                match ch {
+                    '\\' => {
+                        scan.out.push(ch);
+                        escape = true;
+                    }
+                    c if c == end_quote => {
+                        scan.out.push('"');
+                        closer = None;
+                    }
+                    '"' => scan.out.push_str("\\\""),
+                    '\n' => scan.out.push_str("\\n"),
+                    '\r' => scan.out.push_str("\\r"),
+                    '\t' => scan.out.push_str("\\t"),
+                    _ => scan.out.push(ch),
+                }
+                continue;
+            }
+
+            match ch {
+                '"' => {
+                    scan.out.push('"');
+                    closer = Some('"');
+                }
+                '“' | '”' => {
+                    scan.out.push('"');
+                    closer = Some('”');
+                }
+                '{' => {
+                    scan.stack.push('}');
+                    scan.out.push(ch);
+                }
+                '[' => {
+                    scan.stack.push(']');
+                    scan.out.push(ch);
+                }
+                '}' | ']' => {
+                    // 去掉尾随逗号
+                    let trimmed = scan.out.trim_end().len();
+                    scan.out.truncate(trimmed);
+                    if scan.out.ends_with(',') {
+                        scan.out.pop();
+                    }
+                    scan.out.push(ch);
+                    if scan.stack.last() == Some(&ch) {
+                        scan.stack.pop();
+                    }
+                    if scan.stack.is_empty() {
+                        scan.end = i + ch.len_utf8();
+                        scan.complete = true;
+                        break;
+                    }
+                }
+                ',' => {
+                    scan.cuts.push((scan.out.len(), scan.stack.clone()));
+                    scan.out.push(ch);
+                }
+                _ => scan.out.push(ch),
+            }
+        }
+
+        scan.in_string = closer.is_some();
+        scan
+    }
+}
+
+/// 字段类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    String,
+    StringArray,
+    Array,
+    Object,
+    Number,
+    Bool,
+}
+
+impl FieldKind {
+    fn json_schema(self) -> Value {
+        match self {
+            FieldKind::String => json!({"type": "string"}),
+            FieldKind::StringArray => json!({"type": "array", "items": {"type": "string"}}),
+            FieldKind::Array => json!({"type": "array"}),
+            FieldKind::Object => json!({"type": "object"}),
+            FieldKind::Number => json!({"type": "number"}),
+            FieldKind::Bool => json!({"type": "boolean"}),
+        }
+    }
+
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            FieldKind::String => value.as_str().is_some_and(|s| !s.trim().is_empty()),
+            FieldKind::StringArray => value
+                .as_array()
+                .is_some_and(|arr| arr.iter().all(|v| v.is_string())),
+            FieldKind::Array => value.is_array(),
+            FieldKind::Object => value.is_object(),
+            FieldKind::Number => value.is_number(),
+            FieldKind::Bool => value.is_boolean(),
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            FieldKind::String => "非空字符串",
+            FieldKind::StringArray => "字符串数组",
+            FieldKind::Array => "数组",
+            FieldKind::Object => "对象",
+            FieldKind::Number => "数字",
+            FieldKind::Bool => "布尔值",
+        }
+    }
+}
+
+/// 顶层字段
+#[derive(Debug, Clone, Copy)]
+pub struct Field {
+    pub name: &'static str,
+    pub kind: FieldKind,
+    pub required: bool,
+}
+
+/// 预期的输出结构（顶层为对象）
+#[derive(Debug, Clone, Copy)]
+pub struct Schema {
+    /// json_schema 模式下的名称（仅字母数字与下划线）
+    pub name: &'static str,
+    pub fields: &'static [Field],
+}
+
+/// 概览：summary 必填，tags 可选
+pub const OVERVIEW_SCHEMA: Schema = Schema {
+    name: "card_overview",
+    fields: &[
+        Field {
+            name: "summary",
+            kind: FieldKind::String,
+            required: true,
+        },
+        Field {
+            name: "tags",
+            kind: FieldKind::StringArray,
+            required: false,
+        },
+    ],
+};
+
+impl Schema {
+    /// 转为 JSON Schema（用于 response_format 的 json_schema 模式）
+    pub fn json_schema(&self) -> Value {
+        let properties: serde_json::Map<String, Value> = self
+            .fields
+            .iter()
+            .map(|f| (f.name.to_string(), f.kind.json_schema()))
+            .collect();
+        let required: Vec<&str> = self
+            .fields
+            .iter()
+            .filter(|f| f.required)
+            .map(|f| f.name)
+            .collect();
+        json!({"type": "object", "properties": properties, "required": required})
+    }
+
+    /// 校验顶层结构，返回全部问题
+    pub fn validate(&self, value: &Value) -> Result<(), Vec<String>> {
+        let Some(object) = value.as_object() else {
+            return Err(vec!["顶层必须是 JSON 对象".to_string()]);
+        };
+        let mut issues = Vec::new();
+        for field in self.fields {
+            match object.get(field.name).filter(|v| !v.is_null()) {
+                None if field.required => issues.push(format!("缺少字段 {}", field.name)),
+                None => {}
+                Some(v) if !field.kind.matches(v) => {
+                    issues.push(format!("字段 {} 应为{}", field.name, field.kind.label()))
+                }
+                Some(_) => {}
+            }
+        }
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+
+    /// 纠正提示中使用的字段说明
+    fn describe(&self) -> String {
+        self.fields
+            .iter()
+            .map(|f| {
+                format!(
+                    "\"{}\"（{}{}）",
+                    f.name,
+                    f.kind.label(),
+                    if f.required { "，必填" } else { "" }
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("、")
+    }
+}
+
+/// 渠道对 response_format 的支持程度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FormatLevel {
+    JsonSchema,
+    JsonObject,
+    Plain,
+}
+
+impl FormatLevel {
+    fn downgrade(self) -> Self {
+        match self {
+            FormatLevel::JsonSchema => FormatLevel::JsonObject,
+            _ => FormatLevel::Plain,
+        }
+    }
+}
+
+/// 已知的渠道支持情况（未记录的渠道先尝试最严格的模式，服务重启后重置）
+static FORMAT_SUPPORT: Lazy<RwLock<HashMap<Uuid, FormatLevel>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+fn format_level(channel_id: Uuid) -> FormatLevel {
+    FORMAT_SUPPORT
+        .read()
+        .unwrap()
+        .get(&channel_id)
+        .copied()
+        .unwrap_or(FormatLevel::JsonSchema)
+}
+
+fn format_for(level: FormatLevel, schema: Option<&Schema>) -> Option<Value> {
+    match (level, schema) {
+        (FormatLevel::JsonSchema, Some(schema)) => Some(json!({
+            "type": "json_schema",
+            "json_schema": {"name": schema.name, "schema": schema.json_schema()}
+        })),
+        (FormatLevel::JsonSchema | FormatLevel::JsonObject, _) => {
+            Some(json!({"type": "json_object"}))
+        }
+        (FormatLevel::Plain, _) => None,
+    }
+}
+
+/// 错误信息是否明确指向 response_format 不受支持
+///
+/// 只匹配各家接口在拒绝该参数时会提到的字段名，不匹配 "schema" 等宽泛词，
+/// 避免上下文超长、参数错误等无关的 400 触发降级重试
+fn is_format_error(message: &str) -> bool {
+    let lower = message.to_lowercase();
+    [
+        "response_format",
+        "response format",
+        "json_schema",
+        "json_object",
+        "json mode",
+        "structured output",
+    ]
+    .iter()
+    .any(|k| lower.contains(k))
+}
+
+/// 结构化调用结果
+#[derive(Debug, Clone)]
+pub struct JsonCompletion {
+    pub value: Value,
+    /// 最后一次的原始输出
+    pub content: String,
+    /// 实际请求次数（含纠正重试，不含格式降级）
+    pub attempts: usize,
+    pub repaired: bool,
+}
+
+/// 按渠道支持的 response_format 请求一次；错误明确指向 response_format 不受支持时逐级降级重试
+///
+/// body 需已包含 model 与 messages；降级记录在渠道上，之后的请求直接使用降级后的格式
+pub async fn request_json(
+    channel: &ai_channel::Model,
+    body: &Value,
+    schema: Option<&Schema>,
+) -> Result<String, AiError> {
+    let mut level = format_level(channel.id);
+    loop {
+        let mut body = body.clone();
+        if let Some(format) = format_for(level, schema) {
+            body["response_format"] = format;
+        }
+
+        match ai::chat_completion_body(channel, &body).await {
+            // 只有错误明确指向 response_format 时才降级重试，其他 400 直接返回，避免额外的付费请求
+            Err(AiError::Http(400 | 422, message))
+                if level != FormatLevel::Plain && is_format_error(&message) =>
+            {
+                // 跳过与当前请求相同的格式（无 schema 时 json_schema 与 json_object 一致）
+                let mut next = level.downgrade();
+                while next != FormatLevel::Plain
+                    && format_for(next, schema) == format_for(level, schema)
+                {
+                    next = next.downgrade();
+                }
+                FORMAT_SUPPORT.write().unwrap().insert(channel.id, next);
+                tracing::info!(
+                    "渠道 {} 拒绝 {:?} 输出格式，降级为 {:?} 重试",
+                    channel.name,
+                    level,
+                    next
+                );
+                level = next;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// 请求结构化 JSON 输出：解析、修复并按结构校验，失败时附带纠正说明重试 retries 次
+///
+/// params 为额外请求参数（如 temperature / max_tokens），model、messages、response_format 由本函数填写
+pub async fn complete_json(
+    channel: &ai_channel::Model,
+    messages: &[Value],
+    params: Value,
+    schema: &Schema,
+    retries: usize,
+) -> Result<JsonCompletion, AiError> {
+    let mut messages = messages.to_vec();
+    let mut problem = String::new();
+
+    for attempt in 1..=retries + 1 {
+        let mut body = params.clone();
+        body["model"] = json!(channel.model_id);
+        body["messages"] = json!(messages);
+        let content = request_json(channel, &body, Some(schema)).await?;
+        match parse_json(&content) {
+            Some(parsed) => match schema.validate(&parsed.value) {
+                Ok(()) => {
+                    return Ok(JsonCompletion {
+                        value: parsed.value,
+                        content,
+                        attempts: attempt,
+                        repaired: parsed.repaired,
+                    })
+                }
+                Err(issues) => problem = issues.join("；"),
+            },
+            None => problem = "输出不是合法的 JSON".to_string(),
+        }
+
+        tracing::warn!("AI 结构化输出第 {} 次不符合要求: {}", attempt, problem);
+        messages.push(json!({"role": "assistant", "content": content}));
+        messages.push(json!({
+            "role": "user",
+            "content": format!(
+                "上一次输出不符合要求：{}。请重新输出，只返回一个 JSON 对象，不要包含代码块或任何说明文字。字段要求：{}",
+                problem,
+                schema.describe()
+            )
+        }));
+    }
+
+    Err(AiError::Request(format!(
+        "AI 返回的 JSON 不符合要求：{}",
+        problem
+    )))
+}
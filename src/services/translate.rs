@@ -0,0 +1,195 @@
+//! 角色卡翻译
+//!
+//! - 翻译前将宏（`{{char}}` 等）、HTML 标签、代码块替换为占位符，译后还原并校验
+//! - 片段 ID 即 JSON Pointer 路径，译文以 replace 补丁写回，正则脚本等扩展字段不参与翻译
+
+use crate::services::prompt::{self, PromptVars};
+use crate::utils::json_patch::{PatchOp, PatchOpKind};
+use crate::utils::token::count_tokens;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+
+/// 每次请求的原文 Token 预算
+pub const TRANSLATE_CHUNK_TOKENS: usize = 2500;
+
+/// 代码块、行内代码、script/style 块、ST 宏、HTML 标签（按顺序优先匹配）
+static PROTECT_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?s)```.*?```|`[^`\n]+`|<(?:script|style)\b.*?</(?:script|style)>|\{\{.*?\}\}|<[^>]+>",
+    )
+    .unwrap()
+});
+
+/// 参与翻译的文本字段
+const TEXT_FIELDS: &[&str] = &[
+    "description",
+    "personality",
+    "scenario",
+    "first_mes",
+    "mes_example",
+    "creator_notes",
+];
+
+fn placeholder(i: usize) -> String {
+    format!("⟦P{}⟧", i)
+}
+
+/// 替换受保护内容为占位符
+pub fn protect(text: &str) -> (String, Vec<String>) {
+    let mut tokens = Vec::new();
+    let replaced = PROTECT_RE.replace_all(text, |caps: &regex::Captures| {
+        tokens.push(caps[0].to_string());
+        placeholder(tokens.len() - 1)
+    });
+    (replaced.into_owned(), tokens)
+}
+
+/// 还原占位符；任一占位符丢失则返回 Err
+pub fn restore(text: &str, tokens: &[String]) -> Result<String, String> {
+    let mut result = text.to_string();
+    for (i, token) in tokens.iter().enumerate() {
+        let ph = placeholder(i);
+        if !result.contains(&ph) {
+            return Err(format!("译文丢失了占位符 {}", ph));
+        }
+        result = result.replace(&ph, token);
+    }
+    Ok(result)
+}
+
+/// 待翻译片段
+#[derive(Debug, Clone)]
+pub struct Segment {
+    /// JSON Pointer 路径
+    pub path: String,
+    pub protected: String,
+    pub tokens: Vec<String>,
+}
+
+/// 收集角色卡中需要翻译的片段（跳过空文本与纯宏/标签文本）
+pub fn collect_segments(card_json: &Value, include_worldbook: bool) -> Vec<Segment> {
+    let prefix = crate::services::card::field_prefix(card_json);
+    let card_data = card_json
+        .get("data")
+        .filter(|d| d.is_object())
+        .unwrap_or(card_json);
+
+    let mut texts: Vec<(String, &str)> = Vec::new();
+    for field in TEXT_FIELDS {
+        if let Some(s) = card_data.get(*field).and_then(|v| v.as_str()) {
+            texts.push((format!("{}/{}", prefix, field), s));
+        }
+    }
+    if let Some(arr) = card_data
+        .get("alternate_greetings")
+        .and_then(|v| v.as_array())
+    {
+        for (i, v) in arr.iter().enumerate() {
+            if let Some(s) = v.as_str() {
+                texts.push((format!("{}/alternate_greetings/{}", prefix, i), s));
+            }
+        }
+    }
+    if include_worldbook {
+        if let Some(entries) = card_data
+            .get("character_book")
+            .and_then(|cb| cb.get("entries"))
+            .and_then(|e| e.as_array())
+        {
+            for (i, entry) in entries.iter().enumerate() {
+                if let Some(s) = entry.get("content").and_then(|v| v.as_str()) {
+                    texts.push((
+                        format!("{}/character_book/entries/{}/content", prefix, i),
+                        s,
+                    ));
+                }
+            }
+        }
+    }
+
+    texts
+        .into_iter()
+        .filter_map(|(path, text)| {
+            let (protected, tokens) = protect(text);
+            let has_text = PLACEHOLDER_RE
+                .replace_all(&protected, "")
+                .chars()
+                .any(|c| c.is_alphabetic());
+            has_text.then_some(Segment {
+                path,
+                protected,
+                tokens,
+            })
+        })
+        .collect()
+}
+
+static PLACEHOLDER_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"⟦P\d+⟧").unwrap());
+
+/// 按 Token 预算分组（超长片段独占一组）
+pub fn chunk_segments(segments: Vec<Segment>, budget: usize) -> Vec<Vec<Segment>> {
+    let mut chunks = Vec::new();
+    let mut current = Vec::new();
+    let mut current_tokens = 0;
+    for seg in segments {
+        let tokens = count_tokens(&seg.protected);
+        if !current.is_empty() && current_tokens + tokens > budget {
+            chunks.push(std::mem::take(&mut current));
+            current_tokens = 0;
+        }
+        current_tokens += tokens;
+        current.push(seg);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// 渲染一组片段的翻译请求（片段 ID 为组内序号 s0、s1...）
+pub fn chunk_messages(
+    template: &prompt::ResolvedTemplate,
+    global_prompt: &str,
+    target_language: &str,
+    chunk: &[Segment],
+) -> Vec<Value> {
+    let segments: serde_json::Map<String, Value> = chunk
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (format!("s{}", i), Value::String(s.protected.clone())))
+        .collect();
+
+    let mut vars = PromptVars::new();
+    vars.insert("global_prompt".into(), global_prompt.to_string());
+    vars.insert("target_language".into(), target_language.to_string());
+    vars.insert(
+        "segments".into(),
+        serde_json::to_string_pretty(&segments).unwrap_or_default(),
+    );
+    prompt::render_messages(template, &vars)
+}
+
+/// 将模型返回的译文转为补丁；缺失或占位符损坏的片段保留原文并记录警告
+pub fn chunk_patch(response: &Value, chunk: &[Segment]) -> (Vec<PatchOp>, Vec<String>) {
+    let translated = response.get("segments").unwrap_or(response);
+    let mut ops = Vec::new();
+    let mut warnings = Vec::new();
+
+    for (i, seg) in chunk.iter().enumerate() {
+        let Some(text) = translated.get(format!("s{}", i)).and_then(|v| v.as_str()) else {
+            warnings.push(format!("{}: 未返回译文，保留原文", seg.path));
+            continue;
+        };
+        match restore(text, &seg.tokens) {
+            Ok(restored) => ops.push(PatchOp {
+                op: PatchOpKind::Replace,
+                path: seg.path.clone(),
+                value: Some(Value::String(restored)),
+                reason: None,
+            }),
+            Err(e) => warnings.push(format!("{}: {}，保留原文", seg.path, e)),
+        }
+    }
+    (ops, warnings)
+}
@@ -0,0 +1,252 @@
+//! 视觉模型图片描述
+//!
+//! 将图库缩略图以 OpenAI 兼容的 image_url 内容块发送给视觉渠道，
+//! 生成描述与标签并写回 image 行（AI 标签单独记录，重新生成时替换而不影响手动标签）
+
+use crate::entities::{ai_channel, image};
+use crate::services::ai::{self, AiError};
+use crate::services::jobs;
+use crate::services::prompt::{self, ResolvedTemplate};
+use base64::{engine::general_purpose, Engine as _};
+use sea_orm::{
+    ActiveModelTrait, DatabaseConnection, DbErr, EntityTrait, QuerySelect, Set, TransactionTrait,
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// 提供给模型复用的已有标签数量上限
+const VOCABULARY_LIMIT: usize = 150;
+
+/// 一次描述任务共享的上下文
+pub struct CaptionContext {
+    pub channel: ai_channel::Model,
+    template: ResolvedTemplate,
+    global_prompt: String,
+    /// 图库已有标签（按使用次数排序）
+    vocabulary: String,
+}
+
+impl CaptionContext {
+    /// 加载视觉渠道与模板；未配置视觉渠道时返回 None
+    pub async fn load(db: &DatabaseConnection) -> Result<Option<Self>, AiError> {
+        let Some(channel) = ai::resolve_vision_channel(db).await? else {
+            return Ok(None);
+        };
+        let template = prompt::resolve_template(db, "image_caption")
+            .await?
+            .ok_or_else(|| AiError::Config("缺少图片描述提示词模板".to_string()))?;
+        Ok(Some(Self {
+            channel,
+            template,
+            global_prompt: prompt::global_prompt(db).await?,
+            vocabulary: gallery_tags(db).await?.join("、"),
+        }))
+    }
+}
+
+/// 图库中已使用的标签（按使用次数降序）
+async fn gallery_tags(db: &DatabaseConnection) -> Result<Vec<String>, DbErr> {
+    let rows: Vec<String> = image::Entity::find()
+        .select_only()
+        .column(image::Column::Tags)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for row in rows {
+        let tags: Vec<String> = serde_json::from_str(&row).unwrap_or_default();
+        for tag in tags {
+            *counts.entry(tag).or_default() += 1;
+        }
+    }
+    let mut tags: Vec<(String, usize)> = counts.into_iter().collect();
+    tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(tags
+        .into_iter()
+        .take(VOCABULARY_LIMIT)
+        .map(|(t, _)| t)
+        .collect())
+}
+
+/// 图片缩略图的磁盘路径
+fn thumbnail_file(model: &image::Model) -> std::path::PathBuf {
+    crate::utils::paths::get_data_dir().join(model.thumbnail_path.trim_start_matches('/'))
+}
+
+/// 重新读取图片行；图片已被删除（行或缩略图文件不存在）时返回 None
+///
+/// 批量任务可能在图片删除之后才轮到它，描述前需重新确认，避免为已删除的图片发起请求
+pub async fn live_image(
+    db: &DatabaseConnection,
+    id: uuid::Uuid,
+) -> Result<Option<image::Model>, DbErr> {
+    let Some(model) = image::Entity::find_by_id(id).one(db).await? else {
+        return Ok(None);
+    };
+    let exists = tokio::fs::try_exists(thumbnail_file(&model))
+        .await
+        .unwrap_or(false);
+    Ok(exists.then_some(model))
+}
+
+/// 读取图片并编码为 data URL（优先缩略图；GIF 取首帧转 PNG）
+async fn image_data_url(model: &image::Model) -> Result<String, String> {
+    let relative = model.thumbnail_path.trim_start_matches('/');
+    let data = tokio::fs::read(thumbnail_file(model))
+        .await
+        .map_err(|e| format!("读取图片失败: {}", e))?;
+
+    let ext = relative.rsplit('.').next().unwrap_or("").to_lowercase();
+    let (mime, bytes) = match ext.as_str() {
+        "webp" => ("image/webp", data),
+        "png" => ("image/png", data),
+        "jpg" | "jpeg" => ("image/jpeg", data),
+        _ => {
+            let img = ::image::load_from_memory(&data)
+                .map_err(|e| format!("解析图片失败: {}", e))?
+                .thumbnail(512, 768);
+            let mut png = Vec::new();
+            img.write_to(
+                &mut std::io::Cursor::new(&mut png),
+                ::image::ImageOutputFormat::Png,
+            )
+            .map_err(|e| format!("PNG 转换失败: {}", e))?;
+            ("image/png", png)
+        }
+    };
+
+    Ok(format!(
+        "data:{};base64,{}",
+        mime,
+        general_purpose::STANDARD.encode(bytes)
+    ))
+}
+
+/// 将最后一条 user 消息改写为「文本 + 图片」内容块
+fn attach_image(messages: &mut [Value], data_url: String) {
+    if let Some(user) = messages.iter_mut().rev().find(|m| m["role"] == "user") {
+        let text = user["content"].as_str().unwrap_or("").to_string();
+        user["content"] = json!([
+            {"type": "text", "text": text},
+            {"type": "image_url", "image_url": {"url": data_url}}
+        ]);
+    }
+}
+
+/// 读取字符串数组 JSON
+fn parse_tags(text: Option<&str>) -> Vec<String> {
+    text.and_then(|t| serde_json::from_str(t).ok())
+        .unwrap_or_default()
+}
+
+/// 为单张图片生成描述与标签并写回数据库
+pub async fn caption_image(
+    db: &DatabaseConnection,
+    ctx: &CaptionContext,
+    model: image::Model,
+) -> Result<image::Model, String> {
+    let mut vars = prompt::PromptVars::new();
+    vars.insert("global_prompt".into(), ctx.global_prompt.clone());
+    vars.insert(
+        "ai_prompt".into(),
+        model.ai_prompt.clone().unwrap_or_default(),
+    );
+    vars.insert("existing_tags".into(), ctx.vocabulary.clone());
+
+    let mut messages = prompt::render_messages(&ctx.template, &vars);
+    attach_image(&mut messages, image_data_url(&model).await?);
+
+    let content = ai::chat_completion(&ctx.channel, &messages, 0.3, false)
+        .await
+        .map_err(|e| e.to_string())?;
+    let output = ai::extract_json(&content).ok_or_else(|| "无法解析 AI 返回的 JSON".to_string())?;
+
+    let caption = output["caption"]
+        .as_str()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| "AI 未返回图片描述".to_string())?;
+    let mut new_ai_tags: Vec<String> = Vec::new();
+    for tag in output["tags"].as_array().into_iter().flatten() {
+        if let Some(tag) = tag.as_str().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+            if !new_ai_tags.iter().any(|t| t == tag) {
+                new_ai_tags.push(tag.to_string());
+            }
+        }
+    }
+
+    // 调用模型期间标签可能被手动修改：在事务中重新读取当前标签后合并
+    let txn = db.begin().await.map_err(|e| e.to_string())?;
+    let current = image::Entity::find_by_id(model.id)
+        .one(&txn)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "图片已删除".to_string())?;
+
+    // 替换上一次的 AI 标签，保留手动标签
+    let old_ai_tags = parse_tags(current.ai_tags.as_deref());
+    let mut tags: Vec<String> = parse_tags(Some(&current.tags))
+        .into_iter()
+        .filter(|t| !old_ai_tags.contains(t))
+        .collect();
+    for tag in &new_ai_tags {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+
+    let mut active: image::ActiveModel = current.into();
+    active.ai_caption = Set(Some(caption));
+    active.ai_tags = Set(Some(
+        serde_json::to_string(&new_ai_tags).unwrap_or_else(|_| "[]".to_string()),
+    ));
+    active.tags = Set(serde_json::to_string(&tags).unwrap_or_else(|_| "[]".to_string()));
+    active.captioned_at = Set(Some(chrono::Utc::now().naive_utc()));
+    let updated = active.update(&txn).await.map_err(|e| e.to_string())?;
+    txn.commit().await.map_err(|e| e.to_string())?;
+    Ok(updated)
+}
+
+/// 后台逐张生成描述（视觉接口通常限流，按顺序执行），返回任务 ID
+pub fn spawn_caption_job(
+    db: DatabaseConnection,
+    ctx: CaptionContext,
+    images: Vec<image::Model>,
+) -> uuid::Uuid {
+    let total = images.len();
+    let job_id = jobs::create("image_caption", total);
+
+    tokio::spawn(async move {
+        for (i, model) in images.into_iter().enumerate() {
+            if jobs::is_cancelled(job_id) {
+                jobs::finish(job_id, "已取消");
+                return;
+            }
+            jobs::set_message(job_id, format!("正在描述第 {}/{} 张", i + 1, total));
+            let title = model.title.clone();
+            let model = match live_image(&db, model.id).await {
+                Ok(Some(model)) => model,
+                Ok(None) => {
+                    jobs::item_failed(job_id, format!("{}: 图片已删除，已跳过", title));
+                    continue;
+                }
+                Err(e) => {
+                    jobs::item_failed(job_id, format!("{}: {}", title, e));
+                    continue;
+                }
+            };
+            match caption_image(&db, &ctx, model).await {
+                Ok(_) => jobs::item_done(job_id),
+                Err(e) => jobs::item_failed(job_id, format!("{}: {}", title, e)),
+            }
+        }
+
+        let (completed, failed) = jobs::get(job_id)
+            .map(|j| (j.completed, j.failed))
+            .unwrap_or((0, 0));
+        jobs::finish(job_id, format!("完成 {} 张，失败 {} 张", completed, failed));
+    });
+
+    job_id
+}
@@ -0,0 +1,125 @@
+//! 敏感字段加密
+//!
+//! 使用 AES-256-GCM 加密 AI 渠道 API Key 等敏感数据：
+//! - 本地密钥由数据目录下的 .ai_secret 派生，用于数据库落盘
+//! - 口令密钥由用户口令经 PBKDF2 派生，用于备份中的密钥重加密
+//!
+//! 密文格式：`enc:v1:` + base64(nonce || ciphertext || tag)
+
+use base64::{engine::general_purpose, Engine as _};
+use once_cell::sync::OnceCell;
+use rand::{thread_rng, Rng};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use sha2::{Digest, Sha256};
+use std::num::NonZeroU32;
+
+const PREFIX: &str = "enc:v1:";
+/// PBKDF2 迭代次数
+const PBKDF2_ITERATIONS: u32 = 210_000;
+
+/// 本地密钥（进程内只派生一次）
+static LOCAL_KEY: OnceCell<[u8; 32]> = OnceCell::new();
+
+/// 加载本地密钥；.ai_secret 存在但无法读取时返回错误（启动时调用，失败则中止启动）
+pub fn init_local_key() -> std::io::Result<()> {
+    LOCAL_KEY.get_or_try_init(derive_local_key).map(|_| ())
+}
+
+fn derive_local_key() -> std::io::Result<[u8; 32]> {
+    let secret = crate::utils::secret::get_ai_secret()?;
+    let mut hasher = Sha256::new();
+    hasher.update(b"piney-ai-channel-key:");
+    hasher.update(secret.as_bytes());
+    Ok(hasher.finalize().into())
+}
+
+fn local_key() -> &'static [u8; 32] {
+    LOCAL_KEY
+        .get_or_try_init(derive_local_key)
+        .expect("无法加载 AI 渠道密钥的本地 Secret")
+}
+
+/// 是否为本模块生成的密文
+pub fn is_encrypted(text: &str) -> bool {
+    text.starts_with(PREFIX)
+}
+
+/// 由用户口令派生密钥
+pub fn derive_passphrase_key(passphrase: &str, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    ring::pbkdf2::derive(
+        ring::pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    key
+}
+
+/// 生成随机盐
+pub fn random_salt() -> [u8; 16] {
+    let mut salt = [0u8; 16];
+    thread_rng().fill(&mut salt);
+    salt
+}
+
+fn aead_key(key: &[u8; 32]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key).expect("AES-256 密钥长度固定为 32 字节"))
+}
+
+/// 使用指定密钥加密
+pub fn encrypt_with(key: &[u8; 32], plaintext: &str) -> String {
+    let mut nonce = [0u8; NONCE_LEN];
+    thread_rng().fill(&mut nonce);
+
+    let mut in_out = plaintext.as_bytes().to_vec();
+    aead_key(key)
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut in_out,
+        )
+        .expect("AES-GCM 加密失败");
+
+    let mut payload = nonce.to_vec();
+    payload.extend_from_slice(&in_out);
+    format!("{}{}", PREFIX, general_purpose::STANDARD.encode(payload))
+}
+
+/// 使用指定密钥解密
+pub fn decrypt_with(key: &[u8; 32], text: &str) -> Result<String, String> {
+    let encoded = text
+        .strip_prefix(PREFIX)
+        .ok_or_else(|| "不是有效的加密数据".to_string())?;
+    let payload = general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| format!("加密数据格式错误: {}", e))?;
+    if payload.len() <= NONCE_LEN {
+        return Err("加密数据长度错误".to_string());
+    }
+
+    let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "加密数据格式错误")?;
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = aead_key(key)
+        .open_in_place(nonce, Aad::empty(), &mut in_out)
+        .map_err(|_| "解密失败：密钥不匹配或数据已损坏".to_string())?;
+    String::from_utf8(plaintext.to_vec()).map_err(|e| e.to_string())
+}
+
+/// 使用本地密钥加密（空字符串原样保留）
+pub fn encrypt_local(plaintext: &str) -> String {
+    if plaintext.is_empty() {
+        return String::new();
+    }
+    encrypt_with(local_key(), plaintext)
+}
+
+/// 使用本地密钥解密（兼容尚未加密的旧数据）
+pub fn decrypt_local(text: &str) -> Result<String, String> {
+    if !is_encrypted(text) {
+        return Ok(text.to_string());
+    }
+    decrypt_with(local_key(), text)
+}
@@ -0,0 +1,142 @@
+//! JSON Patch（RFC 6902 子集）
+//!
+//! 仅支持 add / replace / remove，路径为 JSON Pointer（如 `/data/character_book/entries/0/content`）
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PatchOpKind {
+    Add,
+    Replace,
+    Remove,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchOp {
+    pub op: PatchOpKind,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+    /// 修改理由（非 RFC 6902 字段，仅用于展示）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// 单条修改的前后对比
+#[derive(Debug, Clone, Serialize)]
+pub struct PatchDiff {
+    pub op: PatchOpKind,
+    pub path: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub reason: Option<String>,
+}
+
+/// 解析 JSON Pointer 为路径片段
+fn parse_pointer(path: &str) -> Result<Vec<String>, String> {
+    if !path.starts_with('/') {
+        return Err(format!("无效路径: {}", path));
+    }
+    Ok(path[1..]
+        .split('/')
+        .map(|s| s.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+/// 定位父节点，返回 (父节点, 最后一段 key)
+fn locate_parent<'a>(doc: &'a mut Value, tokens: &[String]) -> Result<&'a mut Value, String> {
+    let mut current = doc;
+    for token in &tokens[..tokens.len() - 1] {
+        current = match current {
+            Value::Object(map) => map
+                .get_mut(token)
+                .ok_or_else(|| format!("路径不存在: {}", token))?,
+            Value::Array(arr) => {
+                let idx: usize = token
+                    .parse()
+                    .map_err(|_| format!("无效数组下标: {}", token))?;
+                arr.get_mut(idx)
+                    .ok_or_else(|| format!("数组下标越界: {}", idx))?
+            }
+            _ => return Err(format!("路径无法继续深入: {}", token)),
+        };
+    }
+    Ok(current)
+}
+
+/// 应用单条操作，返回修改前的值
+pub fn apply_op(doc: &mut Value, op: &PatchOp) -> Result<Option<Value>, String> {
+    let tokens = parse_pointer(&op.path)?;
+    if tokens.is_empty() || tokens.iter().all(|t| t.is_empty()) {
+        return Err("不允许替换整个文档".to_string());
+    }
+    let key = tokens.last().cloned().unwrap_or_default();
+    let parent = locate_parent(doc, &tokens)?;
+
+    match (&op.op, parent) {
+        (PatchOpKind::Add, Value::Object(map)) => {
+            let value = op.value.clone().ok_or("add 操作缺少 value")?;
+            Ok(map.insert(key, value))
+        }
+        (PatchOpKind::Add, Value::Array(arr)) => {
+            let value = op.value.clone().ok_or("add 操作缺少 value")?;
+            if key == "-" {
+                arr.push(value);
+            } else {
+                let idx: usize = key.parse().map_err(|_| format!("无效数组下标: {}", key))?;
+                if idx > arr.len() {
+                    return Err(format!("数组下标越界: {}", idx));
+                }
+                arr.insert(idx, value);
+            }
+            Ok(None)
+        }
+        (PatchOpKind::Replace, Value::Object(map)) => {
+            let value = op.value.clone().ok_or("replace 操作缺少 value")?;
+            let slot = map
+                .get_mut(&key)
+                .ok_or_else(|| format!("路径不存在: {}", op.path))?;
+            Ok(Some(std::mem::replace(slot, value)))
+        }
+        (PatchOpKind::Replace, Value::Array(arr)) => {
+            let value = op.value.clone().ok_or("replace 操作缺少 value")?;
+            let idx: usize = key.parse().map_err(|_| format!("无效数组下标: {}", key))?;
+            let slot = arr
+                .get_mut(idx)
+                .ok_or_else(|| format!("数组下标越界: {}", idx))?;
+            Ok(Some(std::mem::replace(slot, value)))
+        }
+        (PatchOpKind::Remove, Value::Object(map)) => map
+            .remove(&key)
+            .map(Some)
+            .ok_or_else(|| format!("路径不存在: {}", op.path)),
+        (PatchOpKind::Remove, Value::Array(arr)) => {
+            let idx: usize = key.parse().map_err(|_| format!("无效数组下标: {}", key))?;
+            if idx >= arr.len() {
+                return Err(format!("数组下标越界: {}", idx));
+            }
+            Ok(Some(arr.remove(idx)))
+        }
+        _ => Err(format!("路径的父节点不是对象或数组: {}", op.path)),
+    }
+}
+
+/// 依次应用补丁（在副本上操作，任何一步失败则整体失败）
+pub fn apply_patch(doc: &Value, ops: &[PatchOp]) -> Result<(Value, Vec<PatchDiff>), String> {
+    let mut result = doc.clone();
+    let mut diffs = Vec::with_capacity(ops.len());
+    for (i, op) in ops.iter().enumerate() {
+        let before =
+            apply_op(&mut result, op).map_err(|e| format!("第 {} 条修改失败: {}", i + 1, e))?;
+        diffs.push(PatchDiff {
+            op: op.op.clone(),
+            path: op.path.clone(),
+            before,
+            after: op.value.clone(),
+            reason: op.reason.clone(),
+        });
+    }
+    Ok((result, diffs))
+}
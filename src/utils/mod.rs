@@ -1,8 +1,10 @@
 //! 工具模块入口
 
 pub mod auth_middleware;
+pub mod crypto;
 pub mod error;
 pub mod hash;
+pub mod json_patch;
 pub mod mode_detect;
 pub mod paths;
 pub mod secret;
@@ -10,7 +10,53 @@ use tracing::info;
 /// 2. 如果存在，直接读取
 /// 3. 如果不存在，生成一个随机 32 位字符串，写入文件并返回
 pub fn get_jwt_secret() -> String {
-    let secret_path = crate::utils::paths::get_data_path(".jwt_secret");
+    read_or_create_secret(".jwt_secret", 32)
+}
+
+/// 获取 AI 渠道密钥加密用的本地 Secret（.ai_secret）
+///
+/// 该文件不会被打包进备份，也不会在恢复备份时被覆盖。
+/// 与 JWT Secret 不同，只在文件不存在时生成：文件存在但无法读取或为空时返回错误，
+/// 避免覆盖后已加密的渠道密钥全部无法解密
+pub fn get_ai_secret() -> std::io::Result<String> {
+    const FILE_NAME: &str = ".ai_secret";
+    let secret_path = crate::utils::paths::get_data_path(FILE_NAME);
+
+    match fs::read_to_string(&secret_path) {
+        Ok(s) => {
+            let trimmed = s.trim();
+            if trimmed.is_empty() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{:?} 为空，请从备份恢复该文件", secret_path),
+                ));
+            }
+            Ok(trimmed.to_string())
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let secret = random_secret(64);
+            fs::write(&secret_path, &secret)?;
+            info!("已生成新的 {} 并保存至 {:?}", FILE_NAME, secret_path);
+            Ok(secret)
+        }
+        Err(e) => Err(std::io::Error::new(
+            e.kind(),
+            format!("无法读取 {:?}: {}", secret_path, e),
+        )),
+    }
+}
+
+fn random_secret(len: usize) -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+/// 读取数据目录下的密钥文件，不存在或为空时生成指定长度的随机字符串并写入
+fn read_or_create_secret(file_name: &str, len: usize) -> String {
+    let secret_path = crate::utils::paths::get_data_path(file_name);
 
     if secret_path.exists() {
         match fs::read_to_string(&secret_path) {
@@ -21,22 +67,18 @@ pub fn get_jwt_secret() -> String {
                 }
             }
             Err(e) => {
-                tracing::warn!("无法读取 JWT Secret 文件: {}, 将重新生成", e);
+                tracing::warn!("无法读取 {} 文件: {}, 将重新生成", file_name, e);
             }
         }
     }
 
     // 生成新密钥
-    let secret: String = thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(32)
-        .map(char::from)
-        .collect();
+    let secret = random_secret(len);
 
     if let Err(e) = fs::write(&secret_path, &secret) {
-        tracing::error!("无法写入 JWT Secret 文件: {}", e);
+        tracing::error!("无法写入 {} 文件: {}", file_name, e);
     } else {
-        info!("已生成新的 JWT Secret 并保存至 {:?}", secret_path);
+        info!("已生成新的 {} 并保存至 {:?}", file_name, secret_path);
     }
 
     secret
@@ -17,6 +17,14 @@ static BPE: Lazy<Result<CoreBPE, anyhow::Error>> = Lazy::new(|| {
     cl100k_base().map_err(|e| anyhow::anyhow!("Failed to load cl100k_base tokenizer: {}", e))
 });
 
+/// Count tokens of a single text (falls back to char count if the tokenizer is unavailable)
+pub fn count_tokens(text: &str) -> usize {
+    match BPE.as_ref() {
+        Ok(bpe) => bpe.encode_with_special_tokens(text).len(),
+        Err(_) => text.chars().count(),
+    }
+}
+
 pub fn calculate_card_tokens(json: &Value) -> TokenCounts {
     let mut counts = TokenCounts::default();
 
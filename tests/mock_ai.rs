@@ -0,0 +1,385 @@
+//! 使用离线模拟渠道（mock://）测试 AI 相关接口，无需网络与真实模型
+
+use axum::{extract::State, response::IntoResponse, Json};
+use migration::MigratorTrait;
+use piney::api::ai::{
+    doctor_analyze, generate_overview, DoctorAnalyzeRequest, GenerateOverviewRequest,
+};
+use piney::entities::{ai_channel, character_card, doctor_task, setting};
+use piney::services::ai::{self, AiError};
+use piney::services::mock_ai;
+use sea_orm::{
+    ColumnTrait, ConnectOptions, Database, DatabaseConnection, EntityTrait, QueryFilter, Set,
+};
+use serde_json::{json, Value};
+use std::sync::Once;
+use uuid::Uuid;
+
+/// 所有测试共用一个临时数据目录（模拟脚本从 data/mock 读取）
+fn data_dir() -> std::path::PathBuf {
+    static INIT: Once = Once::new();
+    let dir = std::env::temp_dir().join(format!("piney-mock-ai-{}", std::process::id()));
+    INIT.call_once(|| {
+        std::fs::create_dir_all(dir.join("mock")).unwrap();
+        std::env::set_var("DATA_DIR", &dir);
+    });
+    dir
+}
+
+/// 内存数据库（单连接，保证各查询看到同一个库）
+async fn setup_db() -> DatabaseConnection {
+    data_dir();
+    let mut options = ConnectOptions::new("sqlite::memory:");
+    options
+        .max_connections(1)
+        .min_connections(1)
+        .sqlx_logging(false);
+    let db = Database::connect(options).await.unwrap();
+    migration::Migrator::up(&db, None).await.unwrap();
+    db
+}
+
+fn channel_model(base_url: &str) -> ai_channel::Model {
+    let now = chrono::Utc::now().naive_utc();
+    ai_channel::Model {
+        id: Uuid::new_v4(),
+        name: "模拟渠道".to_string(),
+        base_url: base_url.to_string(),
+        api_key: "mock".to_string(),
+        model_id: "mock-chat".to_string(),
+        is_active: true,
+        created_at: now,
+        updated_at: now,
+    }
+}
+
+/// 写入模拟渠道并设为全局渠道
+async fn use_mock_channel(db: &DatabaseConnection, base_url: &str) {
+    let channel = channel_model(base_url);
+    let id = channel.id;
+    ai_channel::Entity::insert(ai_channel::ActiveModel::from(channel))
+        .exec_without_returning(db)
+        .await
+        .unwrap();
+    setting::Entity::insert(setting::ActiveModel {
+        key: Set("ai_config_global".to_string()),
+        value: Set(id.to_string()),
+        updated_at: Set(chrono::Utc::now().naive_utc()),
+    })
+    .exec_without_returning(db)
+    .await
+    .unwrap();
+}
+
+async fn insert_card(db: &DatabaseConnection, tags: &[&str]) -> Uuid {
+    let id = Uuid::new_v4();
+    let now = chrono::Utc::now().naive_utc();
+    let data = json!({
+        "spec": "chara_card_v2",
+        "spec_version": "2.0",
+        "data": {
+            "name": "林晚",
+            "description": "住在海边小镇的灯塔看守人。",
+            "personality": "沉静、细心",
+            "first_mes": "你来了。",
+            "tags": tags,
+            "character_book": {
+                "entries": [
+                    {"keys": ["灯塔"], "comment": "灯塔", "content": "镇上唯一的灯塔，已有百年历史。", "enabled": true},
+                    {"keys": ["小镇"], "comment": "小镇", "content": "以渔业为生的安静小镇。", "enabled": true}
+                ]
+            }
+        }
+    });
+    character_card::Entity::insert(character_card::ActiveModel {
+        id: Set(id),
+        name: Set("林晚".to_string()),
+        description: Set(None),
+        author: Set(None),
+        avatar: Set(None),
+        spec: Set(Some("chara_card_v2".to_string())),
+        spec_version: Set(Some("2.0".to_string())),
+        data: Set(data.to_string()),
+        created_at: Set(now),
+        updated_at: Set(now),
+        category_id: Set(None),
+        tags: Set(serde_json::to_string(tags).unwrap()),
+        rating: Set(0.0),
+        cover_blur: Set(false),
+        version: Set(None),
+        deleted_at: Set(None),
+        custom_summary: Set(None),
+        user_note: Set(None),
+        metadata_modified: Set(false),
+        data_hash: Set(None),
+        token_count_total: Set(None),
+        token_count_spec: Set(None),
+        token_count_wb: Set(None),
+        token_count_other: Set(None),
+        source: Set("local".to_string()),
+        avatar_version: Set(0),
+    })
+    .exec_without_returning(db)
+    .await
+    .unwrap();
+    id
+}
+
+/// 读取 SSE 响应中的全部事件数据
+async fn sse_events(response: axum::response::Response) -> Vec<Value> {
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    String::from_utf8(body.to_vec())
+        .unwrap()
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .filter_map(|data| serde_json::from_str(data.trim()).ok())
+        .collect()
+}
+
+#[tokio::test]
+async fn generate_overview_parses_mock_json() {
+    let db = setup_db().await;
+    use_mock_channel(&db, "mock://").await;
+    let card_id = insert_card(&db, &[]).await;
+
+    let response = generate_overview(State(db.clone()), Json(GenerateOverviewRequest { card_id }))
+        .await
+        .map_err(|(status, body)| format!("{}: {}", status, body.0))
+        .unwrap()
+        .into_response();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let result: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(result["summary"], "（模拟）这是一段自动生成的角色概览。");
+    assert_eq!(result["tags"], json!(["模拟", "测试"]));
+
+    let card = character_card::Entity::find_by_id(card_id)
+        .one(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        card.custom_summary.as_deref(),
+        Some("（模拟）这是一段自动生成的角色概览。")
+    );
+    assert!(card.tags.contains("模拟"));
+}
+
+#[tokio::test]
+async fn generate_overview_reports_upstream_error() {
+    let db = setup_db().await;
+    use_mock_channel(&db, "mock://error").await;
+    let card_id = insert_card(&db, &[]).await;
+
+    let (status, body) =
+        generate_overview(State(db.clone()), Json(GenerateOverviewRequest { card_id }))
+            .await
+            .map(|_| ())
+            .unwrap_err();
+    assert!(!status.is_success());
+    assert!(body.0["error"].as_str().unwrap().contains("500"));
+
+    let card = character_card::Entity::find_by_id(card_id)
+        .one(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(card.custom_summary, None);
+}
+
+#[tokio::test]
+async fn doctor_analyze_runs_request_entries_round() {
+    let db = setup_db().await;
+    use_mock_channel(&db, "mock://").await;
+    let card_id = insert_card(&db, &["测试"]).await;
+
+    let response = doctor_analyze(
+        State(db.clone()),
+        Json(DoctorAnalyzeRequest {
+            card_id,
+            mode: None,
+        }),
+    )
+    .await
+    .map_err(|(status, body)| format!("{}: {}", status, body.0))
+    .unwrap()
+    .into_response();
+    let events = sse_events(response).await;
+
+    let statuses: Vec<&str> = events.iter().filter_map(|e| e["status"].as_str()).collect();
+    assert_eq!(statuses, ["progress", "complete"]);
+    let report = &events[1]["report"];
+    assert_eq!(report["conclusion"], "（模拟）诊断完成。");
+
+    let tasks = doctor_task::Entity::find()
+        .filter(doctor_task::Column::CharacterId.eq(card_id))
+        .all(&db)
+        .await
+        .unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].status, "success");
+}
+
+#[tokio::test]
+async fn doctor_analyze_stops_on_upstream_error() {
+    let db = setup_db().await;
+    use_mock_channel(&db, "mock://rate_limit").await;
+    let card_id = insert_card(&db, &[]).await;
+
+    let response = doctor_analyze(
+        State(db.clone()),
+        Json(DoctorAnalyzeRequest {
+            card_id,
+            mode: None,
+        }),
+    )
+    .await
+    .map_err(|(status, body)| format!("{}: {}", status, body.0))
+    .unwrap()
+    .into_response();
+    let events = sse_events(response).await;
+
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["status"], "error");
+    assert!(events[0]["message"].as_str().unwrap().contains("429"));
+    let tasks = doctor_task::Entity::find()
+        .filter(doctor_task::Column::CharacterId.eq(card_id))
+        .all(&db)
+        .await
+        .unwrap();
+    assert!(tasks.is_empty());
+}
+
+#[tokio::test]
+async fn script_replays_steps_in_order() {
+    let dir = data_dir();
+    std::fs::write(
+        dir.join("mock").join("two_steps.json"),
+        json!([
+            {"content": "第一步"},
+            {"status": 503, "error": "暂时不可用"},
+            {"content": "最后一步"}
+        ])
+        .to_string(),
+    )
+    .unwrap();
+    let channel = channel_model("mock://script/two_steps");
+    let messages = [json!({"role": "user", "content": "你好"})];
+
+    let first = ai::chat_completion(&channel, &messages, 0.0, false)
+        .await
+        .unwrap();
+    assert_eq!(first, "第一步");
+    let second = ai::chat_completion(&channel, &messages, 0.0, false).await;
+    assert!(matches!(second, Err(AiError::Http(503, _))));
+    for _ in 0..2 {
+        let last = ai::chat_completion(&channel, &messages, 0.0, false)
+            .await
+            .unwrap();
+        assert_eq!(last, "最后一步");
+    }
+}
+
+#[tokio::test]
+async fn script_name_cannot_escape_mock_dir() {
+    let dir = data_dir();
+    std::fs::write(
+        dir.join("outside.json"),
+        json!([{"content": "不应读取"}]).to_string(),
+    )
+    .unwrap();
+    let messages = [json!({"role": "user", "content": "你好"})];
+
+    for name in ["../outside", "..\\outside", "a/b"] {
+        let channel = channel_model(&format!("mock://script/{}", name));
+        let result = ai::chat_completion(&channel, &messages, 0.0, false).await;
+        assert!(
+            matches!(result, Err(AiError::Http(400, _))),
+            "script name {:?} should be rejected",
+            name
+        );
+    }
+}
+
+#[tokio::test]
+async fn script_with_invalid_status_is_rejected() {
+    let dir = data_dir();
+    std::fs::write(
+        dir.join("mock").join("bad_status.json"),
+        json!([{"status": 1000, "error": "无效"}]).to_string(),
+    )
+    .unwrap();
+    let channel = channel_model("mock://script/bad_status");
+    let messages = [json!({"role": "user", "content": "你好"})];
+
+    let result = ai::chat_completion(&channel, &messages, 0.0, false).await;
+    assert!(matches!(result, Err(AiError::Http(400, _))));
+}
+
+#[tokio::test]
+async fn saving_channel_restarts_script() {
+    let dir = data_dir();
+    std::fs::write(
+        dir.join("mock").join("restart.json"),
+        json!([{"content": "一"}, {"content": "二"}]).to_string(),
+    )
+    .unwrap();
+    let base_url = "mock://script/restart";
+    let channel = channel_model(base_url);
+    let messages = [json!({"role": "user", "content": "你好"})];
+
+    for expected in ["一", "二"] {
+        let reply = ai::chat_completion(&channel, &messages, 0.0, false)
+            .await
+            .unwrap();
+        assert_eq!(reply, expected);
+    }
+    mock_ai::reset_script(base_url);
+    let reply = ai::chat_completion(&channel, &messages, 0.0, false)
+        .await
+        .unwrap();
+    assert_eq!(reply, "一");
+}
+
+#[tokio::test]
+async fn json_mode_downgrades_unsupported_response_format() {
+    let dir = data_dir();
+    std::fs::write(
+        dir.join("mock").join("no_json_mode.json"),
+        json!([
+            {"status": 400, "error": "response_format is not supported by this model"},
+            {"content": "{\"ok\": true}"}
+        ])
+        .to_string(),
+    )
+    .unwrap();
+    let channel = channel_model("mock://script/no_json_mode");
+    let messages = [json!({"role": "user", "content": "你好"})];
+
+    let reply = ai::chat_completion(&channel, &messages, 0.0, true)
+        .await
+        .unwrap();
+    assert_eq!(reply, "{\"ok\": true}");
+    let health = piney::services::channel_health::get(channel.id);
+    assert!(health.is_none_or(|h| h.consecutive_failures == 0));
+}
+
+#[tokio::test]
+async fn empty_reply_reports_safety_filter() {
+    let dir = data_dir();
+    std::fs::write(
+        dir.join("mock").join("empty_reply.json"),
+        json!([{"content": ""}]).to_string(),
+    )
+    .unwrap();
+    let channel = channel_model("mock://script/empty_reply");
+    let messages = [json!({"role": "user", "content": "你好"})];
+
+    let error = ai::chat_completion(&channel, &messages, 0.0, false)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("completion_tokens=0"));
+}